@@ -1,12 +1,13 @@
 use gosub_engine::html5::parser::document::DocumentBuilder;
 use gosub_engine::{
-    bytes::{CharIterator, Confidence, Encoding},
+    bytes::CharIterator,
     html5::{
-        node::{Node, NodeData},
+        node::Node,
         parser::{document::Document, Html5Parser},
     },
     types::Result,
 };
+use std::io::Read;
 use std::process::exit;
 
 fn main() -> Result<()> {
@@ -24,16 +25,12 @@ fn main() -> Result<()> {
         println!("could not get url. Status code {}", response.status());
         exit(1);
     }
-    let html = response.into_string()?;
+    let mut html = Vec::new();
+    response.into_reader().read_to_end(&mut html)?;
 
+    // The encoding is detected from the html itself
     let mut chars = CharIterator::new();
-    chars.read_from_str(&html, Some(Encoding::UTF8));
-    chars.set_confidence(Confidence::Certain);
-
-    // If the encoding confidence is not Confidence::Certain, we should detect the encoding.
-    if !chars.is_certain_encoding() {
-        chars.detect_encoding()
-    }
+    chars.read_from_bytes(&html, None);
 
     let document = DocumentBuilder::new_document();
     let parse_errors = Html5Parser::parse_document(&mut chars, Document::clone(&document), None)?;
@@ -42,7 +39,7 @@ fn main() -> Result<()> {
         None => {
             println!("[No Body Found]");
         }
-        Some(node) => display_node(&document.get(), node),
+        Some(node) => println!("{}", document.get().inner_text(node.id)),
     }
 
     for e in parse_errors {
@@ -88,16 +85,3 @@ fn get_node_by_path<'a>(document: &'a Document, path: Vec<&'a str>) -> Option<&'
     }
     Some(node)
 }
-
-fn display_node(document: &Document, node: &Node) {
-    if let NodeData::Text(text) = &node.data {
-        if !text.value().eq("\n") {
-            println!("{}", text.value());
-        }
-    }
-    for child_id in &node.children {
-        if let Some(child) = document.get_node_by_id(*child_id) {
-            display_node(document, child);
-        }
-    }
-}
@@ -1,20 +1,144 @@
 use crate::html5::tokenizer::{CHAR_CR, CHAR_LF};
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::{fmt, io};
 
-/// Encoding defines the way the buffer stream is read, as what defines a "character".
-#[derive(PartialEq)]
+mod labels;
+pub(crate) mod sniffer;
+
+/// Encoding defines the way the buffer stream is read, as what defines a "character". Apart from
+/// ASCII, these are the encodings defined in https://encoding.spec.whatwg.org/#names-and-labels
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 pub enum Encoding {
     /// Stream is of UTF8 characters
     UTF8,
     /// Stream consists of 8-bit ASCII characters
     ASCII,
+    IBM866,
+    ISO8859_2,
+    ISO8859_3,
+    ISO8859_4,
+    ISO8859_5,
+    ISO8859_6,
+    ISO8859_7,
+    ISO8859_8,
+    ISO8859_8I,
+    ISO8859_10,
+    ISO8859_13,
+    ISO8859_14,
+    ISO8859_15,
+    ISO8859_16,
+    KOI8R,
+    KOI8U,
+    Macintosh,
+    Windows874,
+    Windows1250,
+    Windows1251,
+    Windows1252,
+    Windows1253,
+    Windows1254,
+    Windows1255,
+    Windows1256,
+    Windows1257,
+    Windows1258,
+    XMacCyrillic,
+    GBK,
+    GB18030,
+    Big5,
+    EUCJP,
+    ISO2022JP,
+    ShiftJIS,
+    EUCKR,
+    Replacement,
+    UTF16BE,
+    UTF16LE,
+    XUserDefined,
+}
+
+impl Encoding {
+    /// Returns the encoding for the given label, or None when the label is unknown.
+    /// See https://encoding.spec.whatwg.org/#concept-encoding-get
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        let label = label
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .to_ascii_lowercase();
+
+        labels::ENCODING_LABELS.get(label.as_str()).copied()
+    }
+
+    /// Returns the canonical name of the encoding
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::ASCII => "ascii",
+            _ => self.decoder().expect("decoder").name(),
+        }
+    }
+
+    /// Returns the encoding that should be used when this encoding is found in a meta element.
+    /// UTF-16 encodings cannot be declared from inside the document itself, and x-user-defined
+    /// is treated as windows-1252.
+    pub fn for_meta(&self) -> Encoding {
+        match self {
+            Encoding::UTF16BE | Encoding::UTF16LE => Encoding::UTF8,
+            Encoding::XUserDefined => Encoding::Windows1252,
+            _ => *self,
+        }
+    }
+
+    /// Returns the decoder for this encoding. ASCII has no decoder, as it's handled separately.
+    fn decoder(&self) -> Option<&'static encoding_rs::Encoding> {
+        let decoder = match self {
+            Encoding::ASCII => return None,
+            Encoding::UTF8 => encoding_rs::UTF_8,
+            Encoding::IBM866 => encoding_rs::IBM866,
+            Encoding::ISO8859_2 => encoding_rs::ISO_8859_2,
+            Encoding::ISO8859_3 => encoding_rs::ISO_8859_3,
+            Encoding::ISO8859_4 => encoding_rs::ISO_8859_4,
+            Encoding::ISO8859_5 => encoding_rs::ISO_8859_5,
+            Encoding::ISO8859_6 => encoding_rs::ISO_8859_6,
+            Encoding::ISO8859_7 => encoding_rs::ISO_8859_7,
+            Encoding::ISO8859_8 => encoding_rs::ISO_8859_8,
+            Encoding::ISO8859_8I => encoding_rs::ISO_8859_8_I,
+            Encoding::ISO8859_10 => encoding_rs::ISO_8859_10,
+            Encoding::ISO8859_13 => encoding_rs::ISO_8859_13,
+            Encoding::ISO8859_14 => encoding_rs::ISO_8859_14,
+            Encoding::ISO8859_15 => encoding_rs::ISO_8859_15,
+            Encoding::ISO8859_16 => encoding_rs::ISO_8859_16,
+            Encoding::KOI8R => encoding_rs::KOI8_R,
+            Encoding::KOI8U => encoding_rs::KOI8_U,
+            Encoding::Macintosh => encoding_rs::MACINTOSH,
+            Encoding::Windows874 => encoding_rs::WINDOWS_874,
+            Encoding::Windows1250 => encoding_rs::WINDOWS_1250,
+            Encoding::Windows1251 => encoding_rs::WINDOWS_1251,
+            Encoding::Windows1252 => encoding_rs::WINDOWS_1252,
+            Encoding::Windows1253 => encoding_rs::WINDOWS_1253,
+            Encoding::Windows1254 => encoding_rs::WINDOWS_1254,
+            Encoding::Windows1255 => encoding_rs::WINDOWS_1255,
+            Encoding::Windows1256 => encoding_rs::WINDOWS_1256,
+            Encoding::Windows1257 => encoding_rs::WINDOWS_1257,
+            Encoding::Windows1258 => encoding_rs::WINDOWS_1258,
+            Encoding::XMacCyrillic => encoding_rs::X_MAC_CYRILLIC,
+            Encoding::GBK => encoding_rs::GBK,
+            Encoding::GB18030 => encoding_rs::GB18030,
+            Encoding::Big5 => encoding_rs::BIG5,
+            Encoding::EUCJP => encoding_rs::EUC_JP,
+            Encoding::ISO2022JP => encoding_rs::ISO_2022_JP,
+            Encoding::ShiftJIS => encoding_rs::SHIFT_JIS,
+            Encoding::EUCKR => encoding_rs::EUC_KR,
+            Encoding::Replacement => encoding_rs::REPLACEMENT,
+            Encoding::UTF16BE => encoding_rs::UTF_16BE,
+            Encoding::UTF16LE => encoding_rs::UTF_16LE,
+            Encoding::XUserDefined => encoding_rs::X_USER_DEFINED,
+        };
+
+        Some(decoder)
+    }
 }
 
 /// The confidence decides how confident we are that the input stream is of this encoding
-#[derive(PartialEq)]
+#[derive(PartialEq, Debug, Copy, Clone)]
 pub enum Confidence {
     /// This encoding might be the one we need
     Tentative,
@@ -47,6 +171,28 @@ impl fmt::Display for Position {
     }
 }
 
+/// Range in the stream, from the start position up to (but not including) the end position
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Span {
+    /// Position of the first character
+    pub start: Position,
+    /// Position directly after the last character
+    pub end: Position,
+}
+
+impl Span {
+    /// Create a new span
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
 /// Defines a single character/element in the stream. This is either a UTF8 character, or
 /// a surrogate characters since these cannot be stored in a single char.
 /// Eof is denoted as a separate element.
@@ -94,6 +240,30 @@ impl Bytes {
     }
 }
 
+/// Converts decoded characters into the characters of the stream. Newlines are normalized
+/// (CRLF/CR => LF), where last_was_cr tells whether the previous character was a CR, so a CRLF
+/// pair split over two calls is normalized as well.
+fn stream_chars(decoded: &str, last_was_cr: &mut bool) -> Vec<Bytes> {
+    let mut chars = Vec::with_capacity(decoded.len());
+    for c in decoded.chars() {
+        if c == CHAR_LF && *last_was_cr {
+            *last_was_cr = false;
+            continue;
+        }
+
+        *last_was_cr = c == CHAR_CR;
+        if c == CHAR_CR {
+            chars.push(Ch(CHAR_LF));
+        } else if (0xD800..=0xDFFF).contains(&(c as u32)) {
+            chars.push(Bytes::Surrogate(c as u16));
+        } else {
+            chars.push(Ch(c));
+        }
+    }
+
+    chars
+}
+
 /// Buffered UTF-8 iterator
 /// TODO: Implement `Peekable` and `Iterator<Item = char>`
 pub struct CharIterator {
@@ -113,6 +283,21 @@ pub struct CharIterator {
     u8_buffer: Vec<u8>,
     /// If all things are ok, both buffer and u8_buffer should refer to the same memory location (?)
     pub has_read_eof: bool, // True when we just read an EOF
+    /// Decoder for the current encoding. It keeps its state between the chunks of a stream.
+    decoder: Option<encoding_rs::Decoder>,
+    /// True when the last decoded character was a CR, so a directly following LF can be skipped
+    last_was_cr: bool,
+    /// True when the encoding must still be detected once enough bytes have been appended
+    detect_pending: bool,
+    /// True when no more bytes will be appended to the stream
+    closed: bool,
+    /// Set when a read or look-ahead went past the end of a stream that is not yet closed
+    needs_input: Cell<bool>,
+    /// When set, characters at or after this offset cannot be read yet
+    read_limit: Option<usize>,
+    /// True when characters were inserted with insert_str(). These are not in the u8_buffer, so
+    /// decoding the bytes again would lose them.
+    has_inserted_chars: bool,
 }
 
 impl Default for CharIterator {
@@ -137,6 +322,13 @@ impl CharIterator {
             buffer: Vec::new(),
             u8_buffer: Vec::new(),
             has_read_eof: false,
+            decoder: None,
+            last_was_cr: false,
+            detect_pending: false,
+            closed: true,
+            needs_input: Cell::new(false),
+            read_limit: None,
+            has_inserted_chars: false,
         }
     }
 
@@ -145,14 +337,24 @@ impl CharIterator {
         self.confidence == Confidence::Certain
     }
 
-    /// Detect the given encoding from stream analysis
-    pub fn detect_encoding(&self) {
-        todo!()
+    /// Detect the encoding from stream analysis (BOM sniffing, meta prescan and autodetection) and
+    /// decode the stream with the detected encoding.
+    pub fn detect_encoding(&mut self) {
+        let (encoding, confidence) = sniffer::sniff(&self.u8_buffer, self.closed);
+        if let Some((bom_encoding, bom_len)) = sniffer::sniff_bom(&self.u8_buffer) {
+            if bom_encoding == encoding {
+                self.u8_buffer.drain(..bom_len);
+            }
+        }
+
+        self.force_set_encoding(encoding);
+        self.confidence = confidence;
+        self.reset();
     }
 
     /// Returns true when the stream pointer is at the end of the stream
     pub fn eof(&self) -> bool {
-        self.has_read_eof || self.position.offset >= self.length
+        self.has_read_eof || self.position.offset >= self.end()
     }
 
     /// Reset the stream reader back to the start
@@ -160,13 +362,23 @@ impl CharIterator {
         self.position.offset = 0;
         self.position.line = 1;
         self.position.col = 1;
+        self.has_read_eof = false;
+        self.needs_input.set(false);
+    }
+
+    /// Moves the stream reader back to the given position, which must have been returned by an
+    /// earlier read.
+    pub(crate) fn seek(&mut self, position: Position) {
+        self.position = position;
+        self.has_read_eof = false;
+        self.needs_input.set(false);
     }
 
     /// Skip offset characters in the stream (based on chars)
     pub fn skip(&mut self, offset: usize) {
         let mut skip_len = offset;
-        if self.position.offset + offset >= self.length {
-            skip_len = self.length - self.position.offset;
+        if self.position.offset + offset >= self.end() {
+            skip_len = self.end() - self.position.offset;
         }
 
         for _ in 0..skip_len {
@@ -211,86 +423,199 @@ impl CharIterator {
     /// Sets the encoding for this stream, and decodes the u8_buffer into the buffer with the
     /// correct encoding.
     pub fn force_set_encoding(&mut self, e: Encoding) {
-        match e {
-            Encoding::UTF8 => {
-                let str_buf;
-                unsafe {
-                    str_buf = std::str::from_utf8_unchecked(&self.u8_buffer)
-                        .replace("\u{000D}\u{000A}", "\u{000A}")
-                        .replace('\u{000D}', "\u{000A}");
-                }
-
-                // Convert the utf8 string into characters so we can use easy indexing
-                self.buffer = vec![];
-                for c in str_buf.chars() {
-                    // // Check if we have a non-bmp character. This means it's above 0x10000
-                    // let cp = c as u32;
-                    // if cp > 0x10000 && cp <= 0x10FFFF {
-                    //     let adjusted = cp - 0x10000;
-                    //     let lead = ((adjusted >> 10) & 0x3FF) as u16 + 0xD800;
-                    //     let trail = (adjusted & 0x3FF) as u16 + 0xDC00;
-                    //     self.buffer.push(Element::Surrogate(lead));
-                    //     self.buffer.push(Element::Surrogate(trail));
-                    //     continue;
-                    // }
-
-                    if (0xD800..=0xDFFF).contains(&(c as u32)) {
-                        self.buffer.push(Bytes::Surrogate(c as u16));
-                    } else {
-                        self.buffer.push(Ch(c));
-                    }
-                }
-                self.length = self.buffer.len();
-            }
-            Encoding::ASCII => {
-                // Convert the string into characters so we can use easy indexing. Any non-ascii chars (> 0x7F) are converted to '?'
-                self.buffer = self.normalize_newlines_and_ascii(&self.u8_buffer);
-                self.length = self.buffer.len();
-            }
-        }
-
         self.encoding = e;
+        self.decoder = e.decoder().map(|d| d.new_decoder_without_bom_handling());
+        self.detect_pending = false;
+        self.last_was_cr = false;
+        self.buffer = vec![];
+        self.length = 0;
+
+        let bytes = std::mem::take(&mut self.u8_buffer);
+        self.decode(&bytes, self.closed);
+        self.u8_buffer = bytes;
     }
 
-    /// Normalizes newlines (CRLF/CR => LF) and converts high ascii to '?'
-    fn normalize_newlines_and_ascii(&self, buffer: &Vec<u8>) -> Vec<Bytes> {
-        let mut result = Vec::with_capacity(buffer.len());
-
-        for i in 0..buffer.len() {
-            if buffer[i] == CHAR_CR as u8 {
-                // convert CR to LF, or CRLF to LF
-                if i + 1 < buffer.len() && buffer[i + 1] == CHAR_LF as u8 {
-                    continue;
-                }
-                result.push(Ch(CHAR_LF));
-            } else if buffer[i] >= 0x80 {
-                // Convert high ascii to ?
-                result.push(Ch('?'));
-            } else {
-                // everything else is ok
-                result.push(Ch(buffer[i] as char))
+    /// Decodes the given bytes and appends the characters to the buffer. Newlines are normalized
+    /// (CRLF/CR => LF), also when a CRLF pair is split over two chunks of a stream. When last is
+    /// set, no more bytes will follow and any incomplete byte sequence is flushed.
+    fn decode(&mut self, bytes: &[u8], last: bool) {
+        let decoded = match self.decoder.as_mut() {
+            Some(decoder) => {
+                let capacity = decoder
+                    .max_utf8_buffer_length(bytes.len())
+                    .expect("buffer too large");
+                let mut decoded = String::with_capacity(capacity);
+
+                // Invalid byte sequences are decoded into U+FFFD replacement characters
+                let _ = decoder.decode_to_string(bytes, &mut decoded, last);
+                decoded
             }
-        }
-
-        result
+            // Any non-ascii chars (> 0x7F) are converted to '?'
+            None => bytes
+                .iter()
+                .map(|&b| if b >= 0x80 { '?' } else { b as char })
+                .collect(),
+        };
+
+        let chars = stream_chars(&decoded, &mut self.last_was_cr);
+        self.buffer.extend(chars);
+        self.length = self.buffer.len();
     }
 
-    /// Populates the current buffer with the contents of given file f
+    /// Populates the current buffer with the contents of given file f. When no encoding is given,
+    /// the encoding is detected from the contents of the file.
     pub fn read_from_file(&mut self, mut f: File, e: Option<Encoding>) -> io::Result<()> {
         // First we read the u8 bytes into a buffer
-        f.read_to_end(&mut self.u8_buffer).expect("uh oh");
-        self.force_set_encoding(e.unwrap_or(Encoding::UTF8));
-        self.reset();
+        let mut bytes = Vec::new();
+        f.read_to_end(&mut bytes)?;
+        self.read_from_bytes(&bytes, e);
         Ok(())
     }
 
-    /// Populates the current buffer with the contents of the given string s
+    /// Populates the current buffer with the given bytes. When no encoding is given, the encoding
+    /// is detected from the bytes themselves and the confidence is set accordingly. A given
+    /// encoding is always used with certain confidence.
+    pub fn read_from_bytes(&mut self, bytes: &[u8], e: Option<Encoding>) {
+        self.u8_buffer = Vec::from(bytes);
+        self.has_inserted_chars = false;
+        self.closed = true;
+        match e {
+            Some(e) => {
+                self.force_set_encoding(e);
+                self.confidence = Confidence::Certain;
+                self.reset();
+            }
+            None => self.detect_encoding(),
+        }
+    }
+
+    /// Populates the current buffer with the contents of the given string s. Since the string is
+    /// already decoded, the encoding is UTF8 unless given otherwise.
     pub fn read_from_str(&mut self, s: &str, e: Option<Encoding>) {
         self.u8_buffer = Vec::from(s.as_bytes());
+        self.has_inserted_chars = false;
+        self.closed = true;
         self.force_set_encoding(e.unwrap_or(Encoding::UTF8));
+        self.confidence = Confidence::Certain;
         self.reset();
     }
 
+    /// Starts a new stream of bytes that are appended in chunks with append_bytes() as they
+    /// arrive. When no encoding is given, it is detected as soon as enough bytes have been
+    /// appended, or when the stream is closed.
+    pub fn start_stream(&mut self, e: Option<Encoding>) {
+        self.u8_buffer = vec![];
+        self.has_inserted_chars = false;
+        self.closed = false;
+
+        match e {
+            Some(e) => {
+                self.force_set_encoding(e);
+                self.confidence = Confidence::Certain;
+            }
+            None => {
+                self.buffer = vec![];
+                self.length = 0;
+                self.detect_pending = true;
+                self.confidence = Confidence::Tentative;
+            }
+        }
+
+        self.reset();
+    }
+
+    /// Appends a chunk of bytes to the stream. Bytes appended after the stream has been closed
+    /// are ignored.
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        if self.closed {
+            return;
+        }
+
+        self.u8_buffer.extend_from_slice(bytes);
+        self.needs_input.set(false);
+
+        if self.detect_pending {
+            // We can only sniff the encoding once we have enough bytes to prescan
+            if self.u8_buffer.len() >= sniffer::PRESCAN_LENGTH
+                || sniffer::sniff_bom(&self.u8_buffer).is_some()
+            {
+                self.detect_encoding();
+            }
+            return;
+        }
+
+        self.decode(bytes, false);
+    }
+
+    /// Closes the stream. No more bytes can be appended, and reading past the last character
+    /// returns EOF.
+    pub fn close(&mut self) {
+        if self.closed {
+            return;
+        }
+        self.closed = true;
+        self.needs_input.set(false);
+
+        if self.detect_pending {
+            self.detect_encoding();
+            return;
+        }
+
+        self.decode(&[], true);
+    }
+
+    /// Returns true when no more bytes will be appended to the stream
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Returns true when all input is available: the stream is closed and reading is not limited
+    pub(crate) fn is_complete(&self) -> bool {
+        self.closed && self.read_limit.is_none()
+    }
+
+    /// Limits reading to the characters before the given offset. Reading past the limit behaves
+    /// as if the end of an unfinished stream was reached.
+    pub(crate) fn set_read_limit(&mut self, limit: Option<usize>) {
+        self.read_limit = limit;
+        self.needs_input.set(false);
+    }
+
+    /// Returns the offset up to which characters can be read
+    fn end(&self) -> usize {
+        match self.read_limit {
+            Some(limit) => limit.min(self.length),
+            None => self.length,
+        }
+    }
+
+    /// Inserts the given string at the given offset in the (already decoded) stream. Newlines
+    /// are normalized in the same way as decoded bytes. Returns the number of characters
+    /// inserted. The stream cannot be decoded again afterward, see has_inserted_chars().
+    pub(crate) fn insert_str(&mut self, offset: usize, s: &str) -> usize {
+        let chars = stream_chars(s, &mut false);
+        let len = chars.len();
+        self.has_inserted_chars = true;
+
+        let offset = offset.min(self.length);
+        self.buffer.splice(offset..offset, chars);
+        self.length = self.buffer.len();
+
+        len
+    }
+
+    /// Returns true when characters were inserted into the stream that are not part of its bytes.
+    /// Changing the encoding would decode the bytes again and lose these characters.
+    pub(crate) fn has_inserted_chars(&self) -> bool {
+        self.has_inserted_chars
+    }
+
+    /// Returns true when a read went past the end of the stream while it was not yet closed. The
+    /// read returned EOF, but more characters may follow.
+    pub(crate) fn needs_input(&self) -> bool {
+        self.needs_input.get()
+    }
+
     /// Returns the number of characters left in the buffer
     #[cfg(test)]
     fn chars_left(&self) -> usize {
@@ -305,7 +630,7 @@ impl CharIterator {
         }
 
         // If we still can move forward in the stream, move forwards
-        if self.position.offset < self.length {
+        if self.position.offset < self.end() {
             let c = self.buffer[self.position.offset];
             if c == Ch('\n') {
                 // Store line offset for the given line
@@ -321,7 +646,10 @@ impl CharIterator {
             return c;
         }
 
-        // otherwise, we have reached the end of the stream
+        // otherwise, we have reached the end of the stream (or at least, of what we have so far)
+        if !self.is_complete() {
+            self.needs_input.set(true);
+        }
         self.has_read_eof = true;
 
         Eof
@@ -358,9 +686,20 @@ impl CharIterator {
         }
     }
 
+    /// Returns all characters from the current position up to the end of the buffer
+    pub(crate) fn remaining_str(&self) -> String {
+        self.buffer[self.position.offset..self.length]
+            .iter()
+            .map(|&c| char::from(c))
+            .collect()
+    }
+
     /// Looks ahead in the stream and returns len characters
     pub(crate) fn look_ahead_slice(&self, len: usize) -> String {
-        let end_pos = std::cmp::min(self.length, self.position.offset + len);
+        let end_pos = std::cmp::min(self.end(), self.position.offset + len);
+        if end_pos < self.position.offset + len && !self.is_complete() {
+            self.needs_input.set(true);
+        }
 
         let slice = &self.buffer[self.position.offset..end_pos];
         slice.iter().map(|e| e.to_string()).collect()
@@ -370,7 +709,10 @@ impl CharIterator {
     /// (or back) in the stream.
     pub(crate) fn look_ahead(&self, offset: usize) -> Bytes {
         // Trying to look after the stream
-        if self.position.offset + offset >= self.length {
+        if self.position.offset + offset >= self.end() {
+            if !self.is_complete() {
+                self.needs_input.set(true);
+            }
             return Eof;
         }
 
@@ -505,4 +847,124 @@ mod test {
         chars.unread();
         assert!(matches!(chars.read_char(), Eof));
     }
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(Encoding::for_label("utf-8"), Some(Encoding::UTF8));
+        assert_eq!(
+            Encoding::for_label(" Latin1\n"),
+            Some(Encoding::Windows1252)
+        );
+        assert_eq!(Encoding::for_label("SJIS"), Some(Encoding::ShiftJIS));
+        assert_eq!(Encoding::for_label("x-gbk"), Some(Encoding::GBK));
+        assert_eq!(Encoding::for_label("utf-16"), Some(Encoding::UTF16LE));
+        assert_eq!(Encoding::for_label("bogus"), None);
+        assert_eq!(Encoding::Windows1252.name(), "windows-1252");
+        assert_eq!(Encoding::ShiftJIS.name(), "Shift_JIS");
+    }
+
+    #[test]
+    fn test_decode_legacy_encodings() {
+        let mut chars = CharIterator::new();
+
+        chars.read_from_bytes(b"caf\xe9\x80", Some(Encoding::Windows1252));
+        assert!(chars.is_certain_encoding());
+        assert_eq!(chars.look_ahead_slice(5), "caf\u{e9}\u{20ac}");
+
+        chars.read_from_bytes(b"\x93\xfa\x96\x7b", Some(Encoding::ShiftJIS));
+        assert_eq!(chars.look_ahead_slice(2), "\u{65e5}\u{672c}");
+
+        chars.read_from_bytes(b"\xc4\xe3\xba\xc3", Some(Encoding::GBK));
+        assert_eq!(chars.look_ahead_slice(2), "\u{4f60}\u{597d}");
+
+        // Invalid UTF-8 is decoded into replacement characters
+        chars.read_from_bytes(b"a\xffb\r\nc", Some(Encoding::UTF8));
+        assert_eq!(chars.look_ahead_slice(5), "a\u{fffd}b\nc");
+    }
+
+    #[test]
+    fn test_sniffed_encoding() {
+        let mut chars = CharIterator::new();
+
+        chars.read_from_bytes(b"<meta charset=\"shift_jis\">\x93\xfa", None);
+        assert_eq!(chars.encoding, Encoding::ShiftJIS);
+        assert!(!chars.is_certain_encoding());
+        assert_eq!(
+            chars.look_ahead_slice(30),
+            "<meta charset=\"shift_jis\">\u{65e5}"
+        );
+
+        chars.read_from_bytes(b"\xEF\xBB\xBFfoo", None);
+        assert_eq!(chars.encoding, Encoding::UTF8);
+        assert!(chars.is_certain_encoding());
+        assert_eq!(chars.length, 3);
+
+        chars.read_from_bytes(b"<p>\xe9</p>", None);
+        assert_eq!(chars.encoding, Encoding::Windows1252);
+        assert_eq!(chars.look_ahead_slice(8), "<p>\u{e9}</p>");
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let mut chars = CharIterator::new();
+        chars.start_stream(Some(Encoding::UTF8));
+        assert!(!chars.is_closed());
+
+        // Multibyte characters and CRLF pairs can be split over chunks
+        chars.append_bytes(b"a\xe2\x82");
+        chars.append_bytes(b"\xacb\r");
+        assert_eq!(chars.read_char(), Ch('a'));
+        assert_eq!(chars.read_char(), Ch('\u{20ac}'));
+        assert_eq!(chars.read_char(), Ch('b'));
+        assert_eq!(chars.read_char(), Ch('\n'));
+        assert!(!chars.needs_input());
+        assert_eq!(chars.read_char(), Eof);
+        assert!(chars.needs_input());
+
+        chars.unread();
+        chars.append_bytes(b"\nc");
+        chars.close();
+        assert!(!chars.needs_input());
+        assert_eq!(chars.read_char(), Ch('c'));
+        assert_eq!(chars.read_char(), Eof);
+        assert!(!chars.needs_input());
+    }
+
+    #[test]
+    fn test_stream_detect_encoding() {
+        let mut chars = CharIterator::new();
+        chars.start_stream(None);
+
+        // Nothing can be decoded until we have enough bytes to prescan
+        chars.append_bytes(b"<meta charset=\"iso-8859-2\">\xb1");
+        assert_eq!(chars.length, 0);
+
+        chars.close();
+        assert_eq!(chars.encoding, Encoding::ISO8859_2);
+        assert_eq!(
+            chars.look_ahead_slice(30),
+            "<meta charset=\"iso-8859-2\">\u{105}"
+        );
+
+        chars.start_stream(None);
+        chars.append_bytes(b"\xEF\xBB\xBFfoo");
+        assert_eq!(chars.encoding, Encoding::UTF8);
+        assert!(chars.is_certain_encoding());
+        assert_eq!(chars.length, 3);
+    }
+
+    #[test]
+    fn test_insert_str() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("ad", None);
+        assert!(!chars.has_inserted_chars());
+
+        // Inserted characters are normalized like decoded ones
+        assert_eq!(chars.insert_str(1, "b\r\nc\r"), 4);
+        assert!(chars.has_inserted_chars());
+        assert_eq!(chars.look_ahead_slice(10), "ab\nc\nd");
+
+        chars.read_from_str("new", None);
+        assert!(!chars.has_inserted_chars());
+    }
 }
@@ -0,0 +1,276 @@
+//! WHATWG encoding label table
+//!
+//! Maps every label defined in https://encoding.spec.whatwg.org/#names-and-labels onto the
+//! encoding it refers to. Labels are stored in lowercase, so a label must be stripped from
+//! leading and trailing ASCII whitespace and lowercased before looking it up.
+use crate::bytes::Encoding;
+
+pub(crate) static ENCODING_LABELS: phf::Map<&'static str, Encoding> = phf::phf_map! {
+    "unicode-1-1-utf-8" => Encoding::UTF8,
+    "unicode11utf8" => Encoding::UTF8,
+    "unicode20utf8" => Encoding::UTF8,
+    "utf-8" => Encoding::UTF8,
+    "utf8" => Encoding::UTF8,
+    "x-unicode20utf8" => Encoding::UTF8,
+
+    "866" => Encoding::IBM866,
+    "cp866" => Encoding::IBM866,
+    "csibm866" => Encoding::IBM866,
+    "ibm866" => Encoding::IBM866,
+
+    "csisolatin2" => Encoding::ISO8859_2,
+    "iso-8859-2" => Encoding::ISO8859_2,
+    "iso-ir-101" => Encoding::ISO8859_2,
+    "iso8859-2" => Encoding::ISO8859_2,
+    "iso88592" => Encoding::ISO8859_2,
+    "iso_8859-2" => Encoding::ISO8859_2,
+    "iso_8859-2:1987" => Encoding::ISO8859_2,
+    "l2" => Encoding::ISO8859_2,
+    "latin2" => Encoding::ISO8859_2,
+
+    "csisolatin3" => Encoding::ISO8859_3,
+    "iso-8859-3" => Encoding::ISO8859_3,
+    "iso-ir-109" => Encoding::ISO8859_3,
+    "iso8859-3" => Encoding::ISO8859_3,
+    "iso88593" => Encoding::ISO8859_3,
+    "iso_8859-3" => Encoding::ISO8859_3,
+    "iso_8859-3:1988" => Encoding::ISO8859_3,
+    "l3" => Encoding::ISO8859_3,
+    "latin3" => Encoding::ISO8859_3,
+
+    "csisolatin4" => Encoding::ISO8859_4,
+    "iso-8859-4" => Encoding::ISO8859_4,
+    "iso-ir-110" => Encoding::ISO8859_4,
+    "iso8859-4" => Encoding::ISO8859_4,
+    "iso88594" => Encoding::ISO8859_4,
+    "iso_8859-4" => Encoding::ISO8859_4,
+    "iso_8859-4:1988" => Encoding::ISO8859_4,
+    "l4" => Encoding::ISO8859_4,
+    "latin4" => Encoding::ISO8859_4,
+
+    "csisolatincyrillic" => Encoding::ISO8859_5,
+    "cyrillic" => Encoding::ISO8859_5,
+    "iso-8859-5" => Encoding::ISO8859_5,
+    "iso-ir-144" => Encoding::ISO8859_5,
+    "iso8859-5" => Encoding::ISO8859_5,
+    "iso88595" => Encoding::ISO8859_5,
+    "iso_8859-5" => Encoding::ISO8859_5,
+    "iso_8859-5:1988" => Encoding::ISO8859_5,
+
+    "arabic" => Encoding::ISO8859_6,
+    "asmo-708" => Encoding::ISO8859_6,
+    "csiso88596e" => Encoding::ISO8859_6,
+    "csiso88596i" => Encoding::ISO8859_6,
+    "csisolatinarabic" => Encoding::ISO8859_6,
+    "ecma-114" => Encoding::ISO8859_6,
+    "iso-8859-6" => Encoding::ISO8859_6,
+    "iso-8859-6-e" => Encoding::ISO8859_6,
+    "iso-8859-6-i" => Encoding::ISO8859_6,
+    "iso-ir-127" => Encoding::ISO8859_6,
+    "iso8859-6" => Encoding::ISO8859_6,
+    "iso88596" => Encoding::ISO8859_6,
+    "iso_8859-6" => Encoding::ISO8859_6,
+    "iso_8859-6:1987" => Encoding::ISO8859_6,
+
+    "csisolatingreek" => Encoding::ISO8859_7,
+    "ecma-118" => Encoding::ISO8859_7,
+    "elot_928" => Encoding::ISO8859_7,
+    "greek" => Encoding::ISO8859_7,
+    "greek8" => Encoding::ISO8859_7,
+    "iso-8859-7" => Encoding::ISO8859_7,
+    "iso-ir-126" => Encoding::ISO8859_7,
+    "iso8859-7" => Encoding::ISO8859_7,
+    "iso88597" => Encoding::ISO8859_7,
+    "iso_8859-7" => Encoding::ISO8859_7,
+    "iso_8859-7:1987" => Encoding::ISO8859_7,
+    "sun_eu_greek" => Encoding::ISO8859_7,
+
+    "csiso88598e" => Encoding::ISO8859_8,
+    "csisolatinhebrew" => Encoding::ISO8859_8,
+    "hebrew" => Encoding::ISO8859_8,
+    "iso-8859-8" => Encoding::ISO8859_8,
+    "iso-8859-8-e" => Encoding::ISO8859_8,
+    "iso-ir-138" => Encoding::ISO8859_8,
+    "iso8859-8" => Encoding::ISO8859_8,
+    "iso88598" => Encoding::ISO8859_8,
+    "iso_8859-8" => Encoding::ISO8859_8,
+    "iso_8859-8:1988" => Encoding::ISO8859_8,
+    "visual" => Encoding::ISO8859_8,
+
+    "csiso88598i" => Encoding::ISO8859_8I,
+    "iso-8859-8-i" => Encoding::ISO8859_8I,
+    "logical" => Encoding::ISO8859_8I,
+
+    "csisolatin6" => Encoding::ISO8859_10,
+    "iso-8859-10" => Encoding::ISO8859_10,
+    "iso-ir-157" => Encoding::ISO8859_10,
+    "iso8859-10" => Encoding::ISO8859_10,
+    "iso885910" => Encoding::ISO8859_10,
+    "l6" => Encoding::ISO8859_10,
+    "latin6" => Encoding::ISO8859_10,
+
+    "iso-8859-13" => Encoding::ISO8859_13,
+    "iso8859-13" => Encoding::ISO8859_13,
+    "iso885913" => Encoding::ISO8859_13,
+
+    "iso-8859-14" => Encoding::ISO8859_14,
+    "iso8859-14" => Encoding::ISO8859_14,
+    "iso885914" => Encoding::ISO8859_14,
+
+    "csisolatin9" => Encoding::ISO8859_15,
+    "iso-8859-15" => Encoding::ISO8859_15,
+    "iso8859-15" => Encoding::ISO8859_15,
+    "iso885915" => Encoding::ISO8859_15,
+    "iso_8859-15" => Encoding::ISO8859_15,
+    "l9" => Encoding::ISO8859_15,
+
+    "iso-8859-16" => Encoding::ISO8859_16,
+
+    "cskoi8r" => Encoding::KOI8R,
+    "koi" => Encoding::KOI8R,
+    "koi8" => Encoding::KOI8R,
+    "koi8-r" => Encoding::KOI8R,
+    "koi8_r" => Encoding::KOI8R,
+
+    "koi8-ru" => Encoding::KOI8U,
+    "koi8-u" => Encoding::KOI8U,
+
+    "csmacintosh" => Encoding::Macintosh,
+    "mac" => Encoding::Macintosh,
+    "macintosh" => Encoding::Macintosh,
+    "x-mac-roman" => Encoding::Macintosh,
+
+    "dos-874" => Encoding::Windows874,
+    "iso-8859-11" => Encoding::Windows874,
+    "iso8859-11" => Encoding::Windows874,
+    "iso885911" => Encoding::Windows874,
+    "tis-620" => Encoding::Windows874,
+    "windows-874" => Encoding::Windows874,
+
+    "cp1250" => Encoding::Windows1250,
+    "windows-1250" => Encoding::Windows1250,
+    "x-cp1250" => Encoding::Windows1250,
+
+    "cp1251" => Encoding::Windows1251,
+    "windows-1251" => Encoding::Windows1251,
+    "x-cp1251" => Encoding::Windows1251,
+
+    "ansi_x3.4-1968" => Encoding::Windows1252,
+    "ascii" => Encoding::Windows1252,
+    "cp1252" => Encoding::Windows1252,
+    "cp819" => Encoding::Windows1252,
+    "csisolatin1" => Encoding::Windows1252,
+    "ibm819" => Encoding::Windows1252,
+    "iso-8859-1" => Encoding::Windows1252,
+    "iso-ir-100" => Encoding::Windows1252,
+    "iso8859-1" => Encoding::Windows1252,
+    "iso88591" => Encoding::Windows1252,
+    "iso_8859-1" => Encoding::Windows1252,
+    "iso_8859-1:1987" => Encoding::Windows1252,
+    "l1" => Encoding::Windows1252,
+    "latin1" => Encoding::Windows1252,
+    "us-ascii" => Encoding::Windows1252,
+    "windows-1252" => Encoding::Windows1252,
+    "x-cp1252" => Encoding::Windows1252,
+
+    "cp1253" => Encoding::Windows1253,
+    "windows-1253" => Encoding::Windows1253,
+    "x-cp1253" => Encoding::Windows1253,
+
+    "cp1254" => Encoding::Windows1254,
+    "csisolatin5" => Encoding::Windows1254,
+    "iso-8859-9" => Encoding::Windows1254,
+    "iso-ir-148" => Encoding::Windows1254,
+    "iso8859-9" => Encoding::Windows1254,
+    "iso88599" => Encoding::Windows1254,
+    "iso_8859-9" => Encoding::Windows1254,
+    "iso_8859-9:1989" => Encoding::Windows1254,
+    "l5" => Encoding::Windows1254,
+    "latin5" => Encoding::Windows1254,
+    "windows-1254" => Encoding::Windows1254,
+    "x-cp1254" => Encoding::Windows1254,
+
+    "cp1255" => Encoding::Windows1255,
+    "windows-1255" => Encoding::Windows1255,
+    "x-cp1255" => Encoding::Windows1255,
+
+    "cp1256" => Encoding::Windows1256,
+    "windows-1256" => Encoding::Windows1256,
+    "x-cp1256" => Encoding::Windows1256,
+
+    "cp1257" => Encoding::Windows1257,
+    "windows-1257" => Encoding::Windows1257,
+    "x-cp1257" => Encoding::Windows1257,
+
+    "cp1258" => Encoding::Windows1258,
+    "windows-1258" => Encoding::Windows1258,
+    "x-cp1258" => Encoding::Windows1258,
+
+    "x-mac-cyrillic" => Encoding::XMacCyrillic,
+    "x-mac-ukrainian" => Encoding::XMacCyrillic,
+
+    "chinese" => Encoding::GBK,
+    "csgb2312" => Encoding::GBK,
+    "csiso58gb231280" => Encoding::GBK,
+    "gb2312" => Encoding::GBK,
+    "gb_2312" => Encoding::GBK,
+    "gb_2312-80" => Encoding::GBK,
+    "gbk" => Encoding::GBK,
+    "iso-ir-58" => Encoding::GBK,
+    "x-gbk" => Encoding::GBK,
+
+    "gb18030" => Encoding::GB18030,
+
+    "big5" => Encoding::Big5,
+    "big5-hkscs" => Encoding::Big5,
+    "cn-big5" => Encoding::Big5,
+    "csbig5" => Encoding::Big5,
+    "x-x-big5" => Encoding::Big5,
+
+    "cseucpkdfmtjapanese" => Encoding::EUCJP,
+    "euc-jp" => Encoding::EUCJP,
+    "x-euc-jp" => Encoding::EUCJP,
+
+    "csiso2022jp" => Encoding::ISO2022JP,
+    "iso-2022-jp" => Encoding::ISO2022JP,
+
+    "csshiftjis" => Encoding::ShiftJIS,
+    "ms932" => Encoding::ShiftJIS,
+    "ms_kanji" => Encoding::ShiftJIS,
+    "shift-jis" => Encoding::ShiftJIS,
+    "shift_jis" => Encoding::ShiftJIS,
+    "sjis" => Encoding::ShiftJIS,
+    "windows-31j" => Encoding::ShiftJIS,
+    "x-sjis" => Encoding::ShiftJIS,
+
+    "cseuckr" => Encoding::EUCKR,
+    "csksc56011987" => Encoding::EUCKR,
+    "euc-kr" => Encoding::EUCKR,
+    "iso-ir-149" => Encoding::EUCKR,
+    "korean" => Encoding::EUCKR,
+    "ks_c_5601-1987" => Encoding::EUCKR,
+    "ks_c_5601-1989" => Encoding::EUCKR,
+    "ksc5601" => Encoding::EUCKR,
+    "ksc_5601" => Encoding::EUCKR,
+    "windows-949" => Encoding::EUCKR,
+
+    "csiso2022kr" => Encoding::Replacement,
+    "hz-gb-2312" => Encoding::Replacement,
+    "iso-2022-cn" => Encoding::Replacement,
+    "iso-2022-cn-ext" => Encoding::Replacement,
+    "iso-2022-kr" => Encoding::Replacement,
+    "replacement" => Encoding::Replacement,
+
+    "unicodefffe" => Encoding::UTF16BE,
+    "utf-16be" => Encoding::UTF16BE,
+
+    "csunicode" => Encoding::UTF16LE,
+    "iso-10646-ucs-2" => Encoding::UTF16LE,
+    "ucs-2" => Encoding::UTF16LE,
+    "unicode" => Encoding::UTF16LE,
+    "unicodefeff" => Encoding::UTF16LE,
+    "utf-16" => Encoding::UTF16LE,
+    "utf-16le" => Encoding::UTF16LE,
+
+    "x-user-defined" => Encoding::XUserDefined,
+};
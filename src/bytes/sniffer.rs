@@ -0,0 +1,392 @@
+//! Encoding sniffing
+//!
+//! Implements the parts of https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding
+//! that can be done by looking at the raw bytes of the stream: BOM sniffing, the meta prescan and
+//! a final autodetection step.
+use crate::bytes::{Confidence, Encoding};
+
+/// Number of bytes the prescan algorithm will look at
+pub(crate) const PRESCAN_LENGTH: usize = 1024;
+
+/// Default encoding that is used when nothing else could be detected
+pub(crate) const DEFAULT_ENCODING: Encoding = Encoding::Windows1252;
+
+/// Runs the encoding sniffing algorithm on the given bytes and returns the encoding together with
+/// the confidence we have in it. Complete is false when the bytes are only the start of a stream.
+pub(crate) fn sniff(bytes: &[u8], complete: bool) -> (Encoding, Confidence) {
+    if let Some((encoding, _)) = sniff_bom(bytes) {
+        return (encoding, Confidence::Certain);
+    }
+
+    if let Some(encoding) = prescan(bytes) {
+        return (encoding, Confidence::Tentative);
+    }
+
+    (autodetect(bytes, complete), Confidence::Tentative)
+}
+
+/// Checks if the stream starts with a byte order mark. Returns the encoding and the length of the
+/// BOM when found.
+pub(crate) fn sniff_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some((Encoding::UTF8, 3));
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Some((Encoding::UTF16BE, 2));
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Some((Encoding::UTF16LE, 2));
+    }
+
+    None
+}
+
+/// When no BOM or meta element is found, we check if the stream is valid UTF-8 with at least one
+/// non-ASCII character. If so, we assume it's UTF-8, otherwise we fall back to the default encoding.
+/// When the stream is not complete, a multibyte sequence can be cut off at the end, which is fine.
+fn autodetect(bytes: &[u8], complete: bool) -> Encoding {
+    let valid = match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => !complete && e.error_len().is_none(),
+    };
+
+    if valid && !bytes.is_ascii() {
+        return Encoding::UTF8;
+    }
+
+    DEFAULT_ENCODING
+}
+
+/// Returns true when the byte is considered whitespace by the prescan algorithm
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Prescans the first bytes of the stream for a meta element that defines the encoding.
+/// See https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+pub(crate) fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+
+        if rest.starts_with(b"<!--") {
+            // Comments can end with the dashes of the opening sequence (ie: <!-->)
+            match find(&bytes[pos + 2..], b"-->") {
+                Some(idx) => pos += 2 + idx + 3,
+                None => return None,
+            }
+            continue;
+        }
+
+        if rest.len() > 5
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && (is_whitespace(rest[5]) || rest[5] == b'/')
+        {
+            pos += 6;
+            match prescan_meta(bytes, &mut pos) {
+                MetaResult::Found(encoding) => return Some(encoding),
+                MetaResult::NotFound => continue,
+                MetaResult::OutOfBytes => return None,
+            }
+        }
+
+        if rest.len() > 2
+            && ((rest[0] == b'<' && rest[1].is_ascii_alphabetic())
+                || (rest[0] == b'<' && rest[1] == b'/' && rest[2].is_ascii_alphabetic()))
+        {
+            while pos < bytes.len() && !is_whitespace(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            loop {
+                match get_attribute(bytes, &mut pos) {
+                    AttributeResult::Attribute(..) => {}
+                    AttributeResult::None => break,
+                    AttributeResult::OutOfBytes => return None,
+                }
+            }
+            pos += 1;
+            continue;
+        }
+
+        if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            match find(rest, b">") {
+                Some(idx) => pos += idx + 1,
+                None => return None,
+            }
+            continue;
+        }
+
+        pos += 1;
+    }
+
+    None
+}
+
+/// Result of prescanning a single meta element
+enum MetaResult {
+    Found(Encoding),
+    NotFound,
+    OutOfBytes,
+}
+
+/// Handles the attributes of a <meta> element. Position points to the first byte after "<meta ".
+fn prescan_meta(bytes: &[u8], pos: &mut usize) -> MetaResult {
+    let mut attribute_list: Vec<String> = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    // None when no charset has been seen yet, Some(None) when the charset label was invalid
+    let mut charset: Option<Option<Encoding>> = None;
+
+    loop {
+        let (name, value) = match get_attribute(bytes, pos) {
+            AttributeResult::Attribute(name, value) => (name, value),
+            AttributeResult::None => break,
+            AttributeResult::OutOfBytes => return MetaResult::OutOfBytes,
+        };
+
+        if attribute_list.contains(&name) {
+            continue;
+        }
+
+        match name.as_str() {
+            "http-equiv" if value == "content-type" => got_pragma = true,
+            "content" if charset.is_none() => {
+                if let Some(encoding) = extract_encoding_from_content(&value) {
+                    charset = Some(Some(encoding));
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Some(Encoding::for_label(&value));
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+
+        attribute_list.push(name);
+    }
+
+    match (need_pragma, charset.flatten()) {
+        (None, _) | (_, None) => MetaResult::NotFound,
+        (Some(true), _) if !got_pragma => MetaResult::NotFound,
+        (_, Some(encoding)) => MetaResult::Found(encoding.for_meta()),
+    }
+}
+
+/// Result of the "get an attribute" algorithm
+enum AttributeResult {
+    Attribute(String, String),
+    None,
+    OutOfBytes,
+}
+
+/// Implements the "get an attribute" algorithm from the prescan. Position is advanced past the
+/// attribute that is returned.
+fn get_attribute(bytes: &[u8], pos: &mut usize) -> AttributeResult {
+    macro_rules! byte {
+        () => {
+            match bytes.get(*pos) {
+                Some(b) => *b,
+                None => return AttributeResult::OutOfBytes,
+            }
+        };
+    }
+
+    // 1.
+    while is_whitespace(byte!()) || byte!() == b'/' {
+        *pos += 1;
+    }
+
+    // 2.
+    if byte!() == b'>' {
+        return AttributeResult::None;
+    }
+
+    // 3.
+    let mut name = String::new();
+    let mut value = String::new();
+
+    // 4. / 5.
+    loop {
+        match byte!() {
+            b'=' if !name.is_empty() => {
+                *pos += 1;
+                break;
+            }
+            b if is_whitespace(b) => {
+                // 6.
+                while is_whitespace(byte!()) {
+                    *pos += 1;
+                }
+
+                // 7.
+                if byte!() != b'=' {
+                    return AttributeResult::Attribute(name, value);
+                }
+
+                // 8.
+                *pos += 1;
+                break;
+            }
+            b'/' | b'>' => return AttributeResult::Attribute(name, value),
+            b => name.push(b.to_ascii_lowercase() as char),
+        }
+        *pos += 1;
+    }
+
+    // 9.
+    while is_whitespace(byte!()) {
+        *pos += 1;
+    }
+
+    // 10.
+    match byte!() {
+        quote @ (b'"' | b'\'') => loop {
+            *pos += 1;
+            match byte!() {
+                b if b == quote => {
+                    *pos += 1;
+                    return AttributeResult::Attribute(name, value);
+                }
+                b => value.push(b.to_ascii_lowercase() as char),
+            }
+        },
+        b'>' => return AttributeResult::Attribute(name, value),
+        b => {
+            value.push(b.to_ascii_lowercase() as char);
+            *pos += 1;
+        }
+    }
+
+    // 11. / 12.
+    loop {
+        match byte!() {
+            b if is_whitespace(b) || b == b'>' => return AttributeResult::Attribute(name, value),
+            b => value.push(b.to_ascii_lowercase() as char),
+        }
+        *pos += 1;
+    }
+}
+
+/// Extracts a character encoding from the content attribute of a meta element.
+/// See https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+pub(crate) fn extract_encoding_from_content(content: &str) -> Option<Encoding> {
+    let bytes = content.as_bytes();
+    let mut pos = 0;
+
+    loop {
+        // Find the next "charset" (case-insensitive)
+        let idx = bytes[pos..]
+            .windows(7)
+            .position(|w| w.eq_ignore_ascii_case(b"charset"))?;
+        pos += idx + 7;
+
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        if pos < bytes.len() && bytes[pos] == b'=' {
+            pos += 1;
+            break;
+        }
+    }
+
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+
+    match bytes.get(pos) {
+        None => None,
+        Some(quote @ (b'"' | b'\'')) => {
+            let end = find(&bytes[pos + 1..], &[*quote])?;
+            Encoding::for_label(&content[pos + 1..pos + 1 + end])
+        }
+        Some(_) => {
+            let end = bytes[pos..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace() || *b == b';')
+                .unwrap_or(bytes.len() - pos);
+            Encoding::for_label(&content[pos..pos + end])
+        }
+    }
+}
+
+/// Returns the index of the first occurrence of needle in the haystack
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bom() {
+        assert_eq!(sniff_bom(b"\xEF\xBB\xBFfoo"), Some((Encoding::UTF8, 3)));
+        assert_eq!(sniff_bom(b"\xFE\xFFfoo"), Some((Encoding::UTF16BE, 2)));
+        assert_eq!(sniff_bom(b"\xFF\xFEfoo"), Some((Encoding::UTF16LE, 2)));
+        assert_eq!(sniff_bom(b"foo"), None);
+    }
+
+    #[test]
+    fn prescan_meta_charset() {
+        assert_eq!(
+            prescan(b"<!DOCTYPE html><meta charset=\"shift_jis\">"),
+            Some(Encoding::ShiftJIS)
+        );
+        assert_eq!(
+            prescan(b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=gbk\">"),
+            Some(Encoding::GBK)
+        );
+        assert_eq!(prescan(b"<meta content=\"text/html; charset=gbk\">"), None);
+        assert_eq!(prescan(b"<!-- <meta charset=gbk> -->"), None);
+        assert_eq!(
+            prescan(b"<meta charset=\"utf-16le\">"),
+            Some(Encoding::UTF8)
+        );
+        assert_eq!(
+            prescan(b"<meta charset=\"x-user-defined\">"),
+            Some(Encoding::Windows1252)
+        );
+        assert_eq!(prescan(b"<meta charset=gbk"), None);
+    }
+
+    #[test]
+    fn extract_from_content() {
+        assert_eq!(
+            extract_encoding_from_content("text/html; charset=euc-jp"),
+            Some(Encoding::EUCJP)
+        );
+        assert_eq!(
+            extract_encoding_from_content("text/html; CHARSET = 'big5'"),
+            Some(Encoding::Big5)
+        );
+        assert_eq!(
+            extract_encoding_from_content("text/html; charset='big5"),
+            None
+        );
+        assert_eq!(extract_encoding_from_content("text/html"), None);
+    }
+
+    #[test]
+    fn sniff_defaults() {
+        assert_eq!(
+            sniff(b"<p>plain ascii</p>", true),
+            (Encoding::Windows1252, Confidence::Tentative)
+        );
+        assert_eq!(
+            sniff("<p>caf\u{e9}</p>".as_bytes(), true),
+            (Encoding::UTF8, Confidence::Tentative)
+        );
+        assert_eq!(
+            sniff(b"\xEF\xBB\xBF<meta charset=gbk>", true),
+            (Encoding::UTF8, Confidence::Certain)
+        );
+
+        // A multibyte sequence cut off at the end is only valid when more bytes can follow
+        assert_eq!(sniff(b"caf\xc3\xa9 caf\xc3", false).0, Encoding::UTF8);
+        assert_eq!(sniff(b"caf\xc3\xa9 caf\xc3", true).0, Encoding::Windows1252);
+    }
+}
@@ -5,8 +5,27 @@ use std::fmt::{self, Debug, Formatter};
 /// Used for the [An+B microsyntax](https://drafts.csswg.org/css-syntax/#anb-microsyntax).
 #[derive(Debug, PartialEq)]
 pub struct AnPlusB {
-    a: Option<String>,
-    b: Option<String>,
+    a: i32,
+    b: i32,
+}
+
+impl AnPlusB {
+    pub fn new(a: i32, b: i32) -> AnPlusB {
+        AnPlusB { a, b }
+    }
+
+    /// Returns true when there is a non-negative integer n for which An+B equals the given
+    /// 1-based index
+    pub fn matches(&self, index: usize) -> bool {
+        let offset = index as i64 - self.b as i64;
+        let a = self.a as i64;
+
+        if a == 0 {
+            return offset == 0;
+        }
+
+        offset % a == 0 && offset / a >= 0
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -92,6 +111,10 @@ impl IdSelector {
     pub fn new<S: Into<String>>(name: S) -> IdSelector {
         IdSelector { name: name.into() }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 /// [Class Selector](https://drafts.csswg.org/selectors/#class-html)
@@ -104,6 +127,10 @@ impl ClassSelector {
     pub fn new(name: String) -> ClassSelector {
         ClassSelector { name }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 /// [TypeSelector](https://drafts.csswg.org/selectors/#type-selectors)
@@ -122,6 +149,15 @@ impl TypeSelector {
     pub fn new<S: Into<String>>(name: S) -> TypeSelector {
         TypeSelector { name: name.into() }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns true for the universal selector (`*`)
+    pub fn is_universal(&self) -> bool {
+        self.name == "*"
+    }
 }
 
 /// [Nesting Selector](https://drafts.csswg.org/css-nesting/#nest-selector)
@@ -165,6 +201,10 @@ impl Identifier {
     pub fn new<S: Into<String>>(name: S) -> Identifier {
         Identifier { name: name.into() }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -328,12 +368,43 @@ pub enum NthValue {
     AnPlusB(AnPlusB),
     Identifier(Identifier),
 }
+
+impl NthValue {
+    /// Returns true when the given 1-based index matches. Identifiers are either `odd` or `even`.
+    pub fn matches(&self, index: usize) -> bool {
+        match self {
+            NthValue::AnPlusB(anb) => anb.matches(index),
+            NthValue::Identifier(ident) if ident.name.eq_ignore_ascii_case("odd") => index % 2 == 1,
+            NthValue::Identifier(ident) if ident.name.eq_ignore_ascii_case("even") => {
+                index % 2 == 0
+            }
+            NthValue::Identifier(_) => false,
+        }
+    }
+}
+
+/// Argument of the `:nth-*()` pseudo-classes, like `2n+1 of .item`
 #[derive(Debug, PartialEq)]
 pub struct Nth {
     nth: NthValue,
     selector: Option<SelectorList>,
 }
 
+impl Nth {
+    pub fn new(nth: NthValue, selector: Option<SelectorList>) -> Nth {
+        Nth { nth, selector }
+    }
+
+    pub fn value(&self) -> &NthValue {
+        &self.nth
+    }
+
+    /// Returns the `of S` selector list, if any
+    pub fn selector(&self) -> Option<&SelectorList> {
+        self.selector.as_ref()
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct CssNumber {
     value: String,
@@ -350,6 +421,10 @@ impl CssString {
             value: value.into(),
         }
     }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
 
 // todo: should be "enum"
@@ -363,11 +438,40 @@ pub struct Percentage {
     value: String,
 }
 
+/// Argument of a functional pseudo-class
+#[derive(Debug, PartialEq)]
+pub enum PseudoClassArgument {
+    /// Selector list of `:is()`, `:where()`, `:not()` and `:has()`. The selectors of `:has()`
+    /// are relative, so they can start with a combinator.
+    SelectorList(SelectorList),
+    /// Argument of the `:nth-*()` pseudo-classes
+    Nth(Nth),
+    /// Argument of for instance `:lang()` and `:dir()`
+    Identifier(Identifier),
+}
+
 /// [Pseudo-classes](https://drafts.csswg.org/selectors/#pseudo-classes)
 #[derive(Debug, PartialEq)]
 pub struct PseudoClassSelector {
     name: String,
-    children: Option<SelectorList>,
+    argument: Option<PseudoClassArgument>,
+}
+
+impl PseudoClassSelector {
+    pub fn new<S: Into<String>>(name: S, argument: Option<PseudoClassArgument>) -> Self {
+        PseudoClassSelector {
+            name: name.into(),
+            argument,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn argument(&self) -> Option<&PseudoClassArgument> {
+        self.argument.as_ref()
+    }
 }
 
 /// [Pseudo-elements](https://drafts.csswg.org/selectors/#pseudo-elements)
@@ -377,6 +481,19 @@ pub struct PseudoElementSelector {
     children: Option<SelectorList>,
 }
 
+impl PseudoElementSelector {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        PseudoElementSelector {
+            name: name.into(),
+            children: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Ratio {
     left: CssNumber,
@@ -407,6 +524,8 @@ pub enum Selector {
     AttributeSelector(AttributeSelector),
     TypeSelector(TypeSelector),
     NestingSelector(NestingSelector),
+    PseudoClassSelector(PseudoClassSelector),
+    PseudoElementSelector(PseudoElementSelector),
     Combinator(Combinator),
 }
 
@@ -446,6 +565,12 @@ impl SelectorList {
         self.children.last()
     }
 
+    /// Returns the selectors and combinators in this list. Complex selectors are separated by a
+    /// [`Combinator::SelectorListCombinator`].
+    pub fn children(&self) -> &[Selector] {
+        &self.children
+    }
+
     pub fn is_last_child_descendant_combinator(&self) -> bool {
         let selector_list = &self.children;
 
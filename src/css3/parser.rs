@@ -1,27 +1,83 @@
 use crate::css3::node::{
-    Block, BlockChild, Declaration, DeclarationList, Dimension, IdSelector, Identifier, Rule,
-    Selector, SelectorList, StyleSheet, StyleSheetRule, Value, ValueList,
+    AnPlusB, Block, BlockChild, Declaration, DeclarationList, Dimension, IdSelector, Identifier,
+    Nth, NthValue, PseudoClassArgument, PseudoClassSelector, PseudoElementSelector, Rule, Selector,
+    SelectorList, StyleSheet, StyleSheetRule, Value, ValueList,
 };
 use crate::css3::tokenizer::Tokenizer;
 use crate::css3::tokens::{Token, TokenType};
+use crate::types::{Error, Result};
 
 use super::node::{
     AttributeMatcher, AttributeSelector, ClassSelector, Combinator, CssString, TypeSelector,
 };
 
 macro_rules! unexpected_token {
-    ($expecting:expr, $received:expr) => {
-        panic!(
+    ($received:expr, $expecting:expr) => {
+        return Err(Error::CssSyntax(format!(
             "Unexpected token: {:?}, Expecting: {:?}",
-            $expecting, $received
-        )
+            $received, $expecting
+        )))
     };
 
     ($expecting:expr) => {
-        panic!("Unexpecting end of input. Expecting a {:?}", $expecting)
+        return Err(Error::CssSyntax(format!(
+            "Unexpected end of input. Expecting a {:?}",
+            $expecting
+        )))
     };
 }
 
+/// Pseudo-classes without arguments that are recognized by the parser
+const PSEUDO_CLASSES: [&str; 44] = [
+    "active",
+    "any-link",
+    "autofill",
+    "blank",
+    "checked",
+    "current",
+    "default",
+    "defined",
+    "disabled",
+    "empty",
+    "enabled",
+    "first-child",
+    "first-of-type",
+    "focus",
+    "focus-visible",
+    "focus-within",
+    "future",
+    "hover",
+    "in-range",
+    "indeterminate",
+    "invalid",
+    "last-child",
+    "last-of-type",
+    "link",
+    "local-link",
+    "only-child",
+    "only-of-type",
+    "optional",
+    "out-of-range",
+    "past",
+    "paused",
+    "placeholder-shown",
+    "playing",
+    "read-only",
+    "read-write",
+    "required",
+    "root",
+    "scope",
+    "target",
+    "target-within",
+    "user-invalid",
+    "user-valid",
+    "valid",
+    "visited",
+];
+
+/// Pseudo-elements that may be written with a single colon
+const LEGACY_PSEUDO_ELEMENTS: [&str; 4] = ["after", "before", "first-letter", "first-line"];
+
 /// # CSS3 Parser
 /// The parser using the Recursive Descent Parser algorithm (predictive parser).
 /// The grammer rules is defined using Backus–Naur form (BNF)
@@ -47,12 +103,44 @@ impl CSS3Parser {
         }
     }
 
-    pub fn parse(&mut self, raw: &str) -> StyleSheet {
+    pub fn parse(&mut self, raw: &str) -> Result<StyleSheet> {
+        self.init(raw);
+        self.skip_whitespace()?;
+        self.style_sheet()
+    }
+
+    /// Parses a selector list on its own, as used by for instance `querySelector()`
+    pub fn parse_selectors(&mut self, raw: &str) -> Result<SelectorList> {
+        self.init(raw);
+        let selectors = self.selector_list(false, false)?;
+
+        // Namespace prefixes cannot be declared for these selectors
+        if selectors.children().iter().any(|selector| {
+            matches!(
+                selector,
+                Selector::Combinator(Combinator::NamespaceSeparator)
+            )
+        }) {
+            return Err(Error::CssSyntax("Unknown namespace prefix".to_string()));
+        }
+
+        if let Some(token) = self.lookahead.clone() {
+            unexpected_token!(token, "end of selector");
+        }
+        if !self.tokenizer.is_eof() {
+            return Err(Error::CssSyntax(format!(
+                "Unexpected character at '{}'",
+                self.tokenizer.cursor
+            )));
+        }
+
+        Ok(selectors)
+    }
+
+    fn init(&mut self, raw: &str) {
         self.raw = raw.to_string();
         self.tokenizer.init(raw);
         self.lookahead = self.tokenizer.get_next_token();
-        self.skip_whitespace();
-        self.style_sheet()
     }
 
     /// ```txt
@@ -60,8 +148,8 @@ impl CSS3Parser {
     ///     : RulesList
     ///     ;
     /// ```
-    fn style_sheet(&mut self) -> StyleSheet {
-        StyleSheet::new(self.rules_list())
+    fn style_sheet(&mut self) -> Result<StyleSheet> {
+        Ok(StyleSheet::new(self.rules_list()?))
     }
 
     /// ```txt
@@ -69,15 +157,15 @@ impl CSS3Parser {
     ///     : [Rule | AtRule]+
     ///     ;
     /// ```
-    fn rules_list(&mut self) -> Vec<StyleSheetRule> {
+    fn rules_list(&mut self) -> Result<Vec<StyleSheetRule>> {
         // note: support only "Rule" for now
         let mut rules: Vec<StyleSheetRule> = Vec::new();
 
         while !self.is_next_token(TokenType::LCurly) & self.lookahead.is_some() {
-            rules.push(StyleSheetRule::Rule(self.rule()));
+            rules.push(StyleSheetRule::Rule(self.rule()?));
         }
 
-        rules
+        Ok(rules)
     }
 
     /// ```txt
@@ -85,50 +173,147 @@ impl CSS3Parser {
     ///     : SelectorList Block
     ///     ;
     /// ```
-    fn rule(&mut self) -> Rule {
-        self.skip_whitespace();
+    fn rule(&mut self) -> Result<Rule> {
+        self.skip_whitespace()?;
 
-        let selectors = self.selector_list();
-        let block = self.block();
+        let selectors = self.selector_list(false, false)?;
+        let block = self.block()?;
 
-        self.skip_whitespace();
-        Rule::new(selectors, block)
+        self.skip_whitespace()?;
+        Ok(Rule::new(selectors, block))
     }
 
     ///```bnf
     /// SelectorList
-    ///     : [Selector]*
+    ///     : ComplexSelector [SELECTOR_LIST_COMBINATOR ComplexSelector]*
     ///     ;
     /// ```
-    fn selector_list(&mut self) -> SelectorList {
+    ///
+    /// Relative selectors, like the ones of `:has()`, may start with a combinator. Invalid
+    /// complex selectors are dropped from a forgiving list, like the ones of `:is()`.
+    fn selector_list(&mut self, relative: bool, forgiving: bool) -> Result<SelectorList> {
         let mut selector_list = SelectorList::default();
 
-        while !self.is_next_token(TokenType::LCurly) {
-            let curr_selector = self.selector();
+        loop {
+            match self.complex_selector(relative) {
+                Ok(selectors) => {
+                    if selector_list.last().is_some() {
+                        selector_list
+                            .push(Selector::Combinator(Combinator::SelectorListCombinator));
+                    }
+                    for selector in selectors {
+                        selector_list.push(selector);
+                    }
+                }
+                Err(_) if forgiving => self.skip_complex_selector()?,
+                Err(err) => return Err(err),
+            }
+
+            if !self.is_next_token(TokenType::SelectorListCombinator) {
+                break;
+            }
+            self.consume(TokenType::SelectorListCombinator)?;
+        }
+
+        if selector_list.last().is_none() && !forgiving {
+            return Err(Error::CssSyntax("Empty selector list".to_string()));
+        }
 
-            // todo: refactor this nested if else statements
-            if let Some(prev_selector) = selector_list.last() {
-                if curr_selector.is_combinator() && prev_selector.is_descendant_combinator() {
-                    selector_list.pop();
-                    selector_list.push(curr_selector);
-                } else if curr_selector.is_descendant_combinator() && prev_selector.is_combinator()
+        Ok(selector_list)
+    }
+
+    ///```bnf
+    /// ComplexSelector
+    ///     : [Combinator]? [Selector]+ [Combinator [Selector]+]*
+    ///     ;
+    /// ```
+    fn complex_selector(&mut self, relative: bool) -> Result<Vec<Selector>> {
+        let mut selectors: Vec<Selector> = Vec::new();
+        self.skip_whitespace()?;
+
+        while !self.is_complex_selector_end() {
+            let curr_selector = self.selector()?;
+
+            match selectors.last() {
+                Some(prev_selector)
+                    if curr_selector.is_combinator()
+                        && prev_selector.is_descendant_combinator() =>
                 {
-                    continue;
-                } else {
-                    selector_list.push(curr_selector);
+                    selectors.pop();
+                    selectors.push(curr_selector);
                 }
-            } else {
-                selector_list.push(curr_selector);
+                Some(prev_selector)
+                    if curr_selector.is_descendant_combinator()
+                        && prev_selector.is_combinator() => {}
+                Some(prev_selector)
+                    if curr_selector.is_combinator() && prev_selector.is_combinator() =>
+                {
+                    unexpected_token!(curr_selector, "Selector")
+                }
+                // Without a default namespace, the `*|` prefix is the same as no prefix
+                Some(Selector::TypeSelector(prev_selector))
+                    if prev_selector.is_universal()
+                        && matches!(
+                            curr_selector,
+                            Selector::Combinator(Combinator::NamespaceSeparator)
+                        ) =>
+                {
+                    selectors.pop();
+                }
+                // A type selector must come first in a compound selector
+                Some(prev_selector)
+                    if matches!(curr_selector, Selector::TypeSelector(_))
+                        && !prev_selector.is_combinator() =>
+                {
+                    unexpected_token!(curr_selector, "Selector")
+                }
+                _ => selectors.push(curr_selector),
             }
         }
 
-        if selector_list.is_last_child_descendant_combinator() {
-            selector_list.pop();
+        if selectors
+            .last()
+            .is_some_and(Selector::is_descendant_combinator)
+        {
+            selectors.pop();
         }
 
-        println!("Selector List: {:#?}", selector_list);
+        match (selectors.first(), selectors.last()) {
+            (None, _) => unexpected_token!(self.get_next_token_type(), "Selector"),
+            (_, Some(last)) if last.is_combinator() => unexpected_token!("Selector"),
+            (Some(first), _) if first.is_combinator() && !relative => {
+                unexpected_token!(first, "Selector")
+            }
+            _ => {}
+        }
 
-        selector_list
+        // A relative selector without combinator matches the descendants
+        if relative && !selectors[0].is_combinator() {
+            selectors.insert(0, Selector::Combinator(Combinator::DescendantCombinator));
+        }
+
+        Ok(selectors)
+    }
+
+    /// Skips the rest of an invalid complex selector in a forgiving selector list
+    fn skip_complex_selector(&mut self) -> Result<()> {
+        let mut depth = 0;
+
+        while let Some(token) = self.lookahead.clone() {
+            match token.token_type {
+                TokenType::SelectorListCombinator | TokenType::RParen | TokenType::LCurly
+                    if depth == 0 =>
+                {
+                    break
+                }
+                TokenType::LParen => depth += 1,
+                TokenType::RParen => depth -= 1,
+                _ => {}
+            }
+            self.consume(token.token_type)?;
+        }
+
+        Ok(())
     }
 
     ///```txt
@@ -137,41 +322,57 @@ impl CSS3Parser {
     ///     | ClassSelector
     ///     | AttributeSelector
     ///     | TypeSelector
+    ///     | PseudoSelector
     ///     | NestingSelector
     ///     | Combinator
     ///     ;
     /// ```
-    fn selector(&mut self) -> Selector {
+    fn selector(&mut self) -> Result<Selector> {
         if self.is_next_token(TokenType::Hash) {
-            return Selector::IdSelector(self.id_selector());
+            return Ok(Selector::IdSelector(self.id_selector()?));
         }
 
         if self.is_next_token(TokenType::Dot) {
-            return Selector::ClassSelector(self.class_selector());
+            return Ok(Selector::ClassSelector(self.class_selector()?));
         }
 
         if self.is_next_token(TokenType::LBracket) {
-            return Selector::AttributeSelector(self.attribute_selector());
+            return Ok(Selector::AttributeSelector(self.attribute_selector()?));
         }
 
-        if self.is_next_token(TokenType::Ident) {
-            return Selector::TypeSelector(self.type_selector());
+        if self.is_next_tokens(vec![TokenType::Ident, TokenType::Asterisk]) {
+            return Ok(Selector::TypeSelector(self.type_selector()?));
+        }
+
+        if self.is_next_token(TokenType::Colon) {
+            return self.pseudo_selector();
         }
 
         if self.is_combinator_next() {
-            return Selector::Combinator(self.combinator());
+            return Ok(Selector::Combinator(self.combinator()?));
         }
 
-        unexpected_token!(self.get_next_token_type(), "Selector")
+        match self.get_next_token_type() {
+            Some(token_type) => unexpected_token!(token_type, "Selector"),
+            None => unexpected_token!("Selector"),
+        }
     }
 
     /// ```bnf
     ///  TypeSelector
     ///     : IDENT
+    ///     | ASTERISK
     ///     ;   
     /// ```
-    fn type_selector(&mut self) -> TypeSelector {
-        TypeSelector::new(self.consume_token(TokenType::Ident).value)
+    fn type_selector(&mut self) -> Result<TypeSelector> {
+        if self.is_next_token(TokenType::Asterisk) {
+            self.consume(TokenType::Asterisk)?;
+            return Ok(TypeSelector::new("*"));
+        }
+
+        Ok(TypeSelector::new(
+            self.consume_token(TokenType::Ident)?.value,
+        ))
     }
 
     /// ```bnf
@@ -179,10 +380,10 @@ impl CSS3Parser {
     ///     : HASH IDENT
     ///     ;   
     /// ```
-    fn id_selector(&mut self) -> IdSelector {
-        self.consume_token(TokenType::Hash);
-        let name = self.consume_token(TokenType::Ident).value;
-        IdSelector::new(name)
+    fn id_selector(&mut self) -> Result<IdSelector> {
+        self.consume_token(TokenType::Hash)?;
+        let name = self.consume_token(TokenType::Ident)?.value;
+        Ok(IdSelector::new(name))
     }
 
     /// ```bnf
@@ -190,51 +391,60 @@ impl CSS3Parser {
     ///     : DOT IDENT
     ///     ;   
     /// ```
-    fn class_selector(&mut self) -> ClassSelector {
-        self.consume_token(TokenType::Dot);
-        let name = self.consume_token(TokenType::Ident).value;
-        ClassSelector::new(name)
+    fn class_selector(&mut self) -> Result<ClassSelector> {
+        self.consume_token(TokenType::Dot)?;
+        let name = self.consume_token(TokenType::Ident)?.value;
+        Ok(ClassSelector::new(name))
     }
 
     /// ```bnf
     ///  AttributeSelector
-    ///     : LBRACKET IDENT [AttributeMatcher String]? [IDENT]? RBRACKET
+    ///     : LBRACKET IDENT [AttributeMatcher [String | IDENT]]? [IDENT]? RBRACKET
     ///     ;   
     /// ```
-    fn attribute_selector(&mut self) -> AttributeSelector {
-        self.consume_token(TokenType::LBracket);
-        let name = self.identifier();
+    fn attribute_selector(&mut self) -> Result<AttributeSelector> {
+        self.consume_token(TokenType::LBracket)?;
+        let name = self.identifier()?;
 
-        self.skip_whitespace();
+        self.skip_whitespace()?;
 
         let matcher = if !self.is_next_token(TokenType::RBracket) {
-            Some(self.attribute_matcher())
+            Some(self.attribute_matcher()?)
         } else {
             None
         };
 
         let value = if matcher.is_some() {
-            Some(self.string())
+            self.skip_whitespace()?;
+            if self.is_next_token(TokenType::Ident) {
+                Some(CssString::new(self.consume(TokenType::Ident)?.value))
+            } else {
+                Some(self.string()?)
+            }
         } else {
             None
         };
 
-        self.skip_whitespace();
+        self.skip_whitespace()?;
 
-        let flag = if !self.is_next_token(TokenType::RBracket) {
-            Some(self.identifier())
+        let flag = if value.is_some() && !self.is_next_token(TokenType::RBracket) {
+            let flag = self.consume_token(TokenType::Ident)?.value;
+            if !flag.eq_ignore_ascii_case("i") && !flag.eq_ignore_ascii_case("s") {
+                unexpected_token!(flag, "AttributeFlag");
+            }
+            Some(Identifier::new(flag.to_ascii_lowercase()))
         } else {
             None
         };
 
-        self.consume_token(TokenType::RBracket);
+        self.consume_token(TokenType::RBracket)?;
 
-        AttributeSelector {
+        Ok(AttributeSelector {
             name,
             matcher,
             value,
             flag,
-        }
+        })
     }
 
     /// ```bnf
@@ -247,7 +457,7 @@ impl CSS3Parser {
     ///     |  EQUAL
     ///     ;   
     /// ```
-    fn attribute_matcher(&mut self) -> AttributeMatcher {
+    fn attribute_matcher(&mut self) -> Result<AttributeMatcher> {
         if let Some(next_token_type) = self.get_next_token_type() {
             let matcher = match next_token_type {
                 TokenType::IncludeMatch => AttributeMatcher::IncludeMatch,
@@ -259,13 +469,149 @@ impl CSS3Parser {
                 _ => unexpected_token!(next_token_type, "AttributeMatcher"),
             };
 
-            self.consume_token(self.get_next_token_type().unwrap());
-            return matcher;
+            self.consume(next_token_type)?;
+            return Ok(matcher);
         }
 
         unexpected_token!("AttributeMatcher")
     }
 
+    /// ```bnf
+    ///  PseudoSelector
+    ///     : COLON COLON IDENT
+    ///     | COLON IDENT [LPAREN PseudoClassArgument RPAREN]?
+    ///     ;
+    /// ```
+    fn pseudo_selector(&mut self) -> Result<Selector> {
+        self.consume(TokenType::Colon)?;
+
+        if self.is_next_token(TokenType::Colon) {
+            self.consume(TokenType::Colon)?;
+            let name = self.consume(TokenType::Ident)?.value.to_ascii_lowercase();
+            return Ok(Selector::PseudoElementSelector(PseudoElementSelector::new(
+                name,
+            )));
+        }
+
+        let name = self.consume(TokenType::Ident)?.value.to_ascii_lowercase();
+
+        if !self.is_next_token(TokenType::LParen) {
+            if LEGACY_PSEUDO_ELEMENTS.contains(&name.as_str()) {
+                return Ok(Selector::PseudoElementSelector(PseudoElementSelector::new(
+                    name,
+                )));
+            }
+            if !PSEUDO_CLASSES.contains(&name.as_str()) {
+                unexpected_token!(name, "PseudoClass");
+            }
+
+            return Ok(Selector::PseudoClassSelector(PseudoClassSelector::new(
+                name, None,
+            )));
+        }
+
+        self.consume(TokenType::LParen)?;
+        let argument = self.pseudo_class_argument(&name).and_then(|argument| {
+            self.consume_token(TokenType::RParen)?;
+            Ok(argument)
+        });
+
+        match argument {
+            Ok(argument) => Ok(Selector::PseudoClassSelector(PseudoClassSelector::new(
+                name,
+                Some(argument),
+            ))),
+            Err(err) => {
+                // A forgiving selector list continues after the invalid pseudo-class
+                self.skip_arguments()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Skips the rest of the arguments of an invalid pseudo-class, up to and including the
+    /// closing parenthesis
+    fn skip_arguments(&mut self) -> Result<()> {
+        let mut depth = 0;
+
+        while let Some(token) = self.lookahead.clone() {
+            self.consume(token.token_type)?;
+            match token.token_type {
+                TokenType::LParen => depth += 1,
+                TokenType::RParen if depth == 0 => break,
+                TokenType::RParen => depth -= 1,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// ```bnf
+    ///  PseudoClassArgument
+    ///     : SelectorList
+    ///     | Nth
+    ///     | [IDENT | STRING]
+    ///     ;
+    /// ```
+    fn pseudo_class_argument(&mut self, name: &str) -> Result<PseudoClassArgument> {
+        let argument = match name {
+            "is" | "where" => PseudoClassArgument::SelectorList(self.selector_list(false, true)?),
+            "not" => PseudoClassArgument::SelectorList(self.selector_list(false, false)?),
+            "has" => PseudoClassArgument::SelectorList(self.selector_list(true, false)?),
+            "nth-child" | "nth-last-child" => PseudoClassArgument::Nth(self.nth(true)?),
+            "nth-of-type" | "nth-last-of-type" => PseudoClassArgument::Nth(self.nth(false)?),
+            "lang" | "dir" => {
+                self.skip_whitespace()?;
+                if self.is_next_token(TokenType::String) {
+                    PseudoClassArgument::Identifier(Identifier::new(self.string()?.value()))
+                } else {
+                    PseudoClassArgument::Identifier(self.identifier()?)
+                }
+            }
+            _ => unexpected_token!(name, "PseudoClass"),
+        };
+
+        Ok(argument)
+    }
+
+    /// ```bnf
+    ///  Nth
+    ///     : AnPlusB [IDENT(of) SelectorList]?
+    ///     ;
+    /// ```
+    ///
+    /// The `of` selector list is only allowed for `:nth-child()` and `:nth-last-child()`.
+    fn nth(&mut self, allow_selector: bool) -> Result<Nth> {
+        // The An+B microsyntax does not line up with the tokens, so it is parsed from the raw
+        // value of the tokens up to the closing parenthesis or "of"
+        let mut raw = String::new();
+        while let Some(token) = self.lookahead.clone() {
+            if token.token_type == TokenType::RParen
+                || (token.token_type == TokenType::Ident && token.value.eq_ignore_ascii_case("of"))
+            {
+                break;
+            }
+            raw.push_str(&token.value);
+            self.consume(token.token_type)?;
+        }
+
+        let Some(value) = an_plus_b(raw.trim()) else {
+            unexpected_token!(raw, "AnPlusB")
+        };
+
+        if !self.is_next_token(TokenType::Ident) {
+            return Ok(Nth::new(value, None));
+        }
+        if !allow_selector {
+            unexpected_token!(self.get_next_token_type(), "RParen")
+        }
+
+        self.consume(TokenType::Ident)?;
+        let selector = self.selector_list(false, false)?;
+        Ok(Nth::new(value, Some(selector)))
+    }
+
     /// ```bnf
     /// Combinator:
     ///     : CHILD_COMBINATOR
@@ -276,7 +622,7 @@ impl CSS3Parser {
     ///     | SUBSEQUENT_SIBLING_COMBINATOR
     ///     ;
     /// ```
-    fn combinator(&mut self) -> Combinator {
+    fn combinator(&mut self) -> Result<Combinator> {
         if let Some(next_token_type) = self.get_next_token_type() {
             let combinator = match next_token_type {
                 TokenType::ChildCombinator => Combinator::ChildCombinator,
@@ -291,8 +637,8 @@ impl CSS3Parser {
                 }
             };
 
-            self.consume_token(self.get_next_token_type().unwrap());
-            return combinator;
+            self.consume(next_token_type)?;
+            return Ok(combinator);
         };
 
         unexpected_token!("Combinator");
@@ -303,8 +649,8 @@ impl CSS3Parser {
     ///     : STRING
     ///     ;
     /// ```
-    fn string(&mut self) -> CssString {
-        let mut value = self.consume_token(TokenType::String).value;
+    fn string(&mut self) -> Result<CssString> {
+        let mut value = self.consume_token(TokenType::String)?.value;
 
         // Remove starting and ending quotes
         value.pop();
@@ -312,7 +658,7 @@ impl CSS3Parser {
             value.remove(0);
         }
 
-        CssString::new(value)
+        Ok(CssString::new(value))
     }
 
     /// ```bnf
@@ -320,19 +666,19 @@ impl CSS3Parser {
     ///     : LCURLY [Rule | AtRule | DeclarationList]* RCURLY
     ///     ;   
     /// ```
-    fn block(&mut self) -> Block {
+    fn block(&mut self) -> Result<Block> {
         // note: add support for 'DeclarationList' for now
         let mut block = Block::default();
 
-        self.consume_token(TokenType::LCurly);
+        self.consume_token(TokenType::LCurly)?;
 
         while !self.is_next_token(TokenType::RCurly) {
-            block.add_child(BlockChild::DeclarationList(self.declaration_list()))
+            block.add_child(BlockChild::DeclarationList(self.declaration_list()?))
         }
 
-        self.consume_token(TokenType::RCurly);
+        self.consume_token(TokenType::RCurly)?;
 
-        block
+        Ok(block)
     }
 
     /// ```bnf
@@ -340,14 +686,14 @@ impl CSS3Parser {
     ///     : [Declaration]*
     ///     ;   
     /// ```
-    fn declaration_list(&mut self) -> DeclarationList {
+    fn declaration_list(&mut self) -> Result<DeclarationList> {
         let mut declaration_list = DeclarationList::default();
 
         while !self.is_next_token(TokenType::RCurly) {
-            declaration_list.add_child(self.declaration());
+            declaration_list.add_child(self.declaration()?);
         }
 
-        declaration_list
+        Ok(declaration_list)
     }
 
     /// ```bnf
@@ -355,23 +701,23 @@ impl CSS3Parser {
     ///     : IDENT COLON ValueList IMPORTANT SEMICOLON
     ///     ;   
     /// ```
-    fn declaration(&mut self) -> Declaration {
+    fn declaration(&mut self) -> Result<Declaration> {
         let mut declaration = Declaration::default();
 
-        declaration.set_property(self.consume_token(TokenType::Ident).value);
-        self.consume_token(TokenType::Colon);
-        declaration.set_value(self.value_list());
+        declaration.set_property(self.consume_token(TokenType::Ident)?.value);
+        self.consume_token(TokenType::Colon)?;
+        declaration.set_value(self.value_list()?);
 
-        self.skip_whitespace();
+        self.skip_whitespace()?;
         if self.is_next_token(TokenType::Important) {
-            self.consume_token(TokenType::Important);
+            self.consume_token(TokenType::Important)?;
             declaration.set_important_as(true);
         }
 
-        self.consume_token(TokenType::Semicolon);
-        self.skip_whitespace();
+        self.consume_token(TokenType::Semicolon)?;
+        self.skip_whitespace()?;
 
-        declaration
+        Ok(declaration)
     }
 
     /// ```bnf
@@ -379,16 +725,16 @@ impl CSS3Parser {
     ///     : [Value]*
     ///     ;   
     /// ```
-    fn value_list(&mut self) -> ValueList {
+    fn value_list(&mut self) -> Result<ValueList> {
         let mut value_list = ValueList::default();
 
         while !self.is_next_tokens(vec![TokenType::Semicolon, TokenType::Important]) {
-            let value = self.value();
+            let value = self.value()?;
             value_list.add_child(value);
-            self.skip_whitespace();
+            self.skip_whitespace()?;
         }
 
-        value_list
+        Ok(value_list)
     }
 
     /// ```bnf
@@ -396,15 +742,15 @@ impl CSS3Parser {
     ///     : [Dimension | Identifier | Function]
     ///     ;   
     /// ```
-    fn value(&mut self) -> Value {
+    fn value(&mut self) -> Result<Value> {
         // note: support only "Identifier" and "Dimension" for now
-        self.skip_whitespace();
+        self.skip_whitespace()?;
 
         if self.is_next_token(TokenType::Ident) {
-            return Value::Identifier(self.identifier());
+            return Ok(Value::Identifier(self.identifier()?));
         }
 
-        Value::Dimension(self.dimension())
+        Ok(Value::Dimension(self.dimension()?))
     }
 
     /// ```bnf
@@ -412,8 +758,8 @@ impl CSS3Parser {
     ///     : IDENT
     ///     ;   
     /// ```
-    fn identifier(&mut self) -> Identifier {
-        Identifier::new(self.consume_token(TokenType::Ident).value)
+    fn identifier(&mut self) -> Result<Identifier> {
+        Ok(Identifier::new(self.consume_token(TokenType::Ident)?.value))
     }
 
     /// ```bnf
@@ -421,50 +767,54 @@ impl CSS3Parser {
     ///     : NUMBER IDENT
     ///     ;   
     /// ```
-    fn dimension(&mut self) -> Dimension {
-        let value = self.consume_token(TokenType::Number).value;
+    fn dimension(&mut self) -> Result<Dimension> {
+        let value = self.consume_token(TokenType::Number)?.value;
 
         let unit = if self.is_next_token(TokenType::Ident) {
-            Some(self.consume_token(TokenType::Ident).value)
+            Some(self.consume_token(TokenType::Ident)?.value)
         } else {
             None
         };
 
-        Dimension::new(value, unit)
+        Ok(Dimension::new(value, unit))
     }
 
-    fn consume(&mut self, token_type: TokenType) -> Token {
+    fn consume(&mut self, token_type: TokenType) -> Result<Token> {
         if let Some(token) = self.lookahead.clone() {
             if token.token_type != token_type {
-                panic!(
+                return Err(Error::CssSyntax(format!(
                     "Unexpected token: '{:?}', expected: '{:?}'. Got '{}' at '{}'",
                     token.token_type, token_type, token.value, self.tokenizer.cursor
-                )
+                )));
             }
 
             // Advance to the next token
             self.lookahead = self.tokenizer.get_next_token();
 
-            println!("next token: {:#?}", self.lookahead);
-            return token.clone();
+            return Ok(token);
         }
 
-        panic!("Unexpected end of input, expected: {:?}", token_type)
+        Err(Error::CssSyntax(format!(
+            "Unexpected end of input, expected: {:?}",
+            token_type
+        )))
     }
 
-    fn consume_token(&mut self, token_type: TokenType) -> Token {
+    fn consume_token(&mut self, token_type: TokenType) -> Result<Token> {
         if self.is_next_token(token_type) {
             return self.consume(token_type);
         }
 
-        self.skip_whitespace();
+        self.skip_whitespace()?;
         self.consume(token_type)
     }
 
-    fn skip_whitespace(&mut self) {
+    fn skip_whitespace(&mut self) -> Result<()> {
         while self.is_next_token(TokenType::WhiteSpace) {
-            self.consume(TokenType::WhiteSpace);
+            self.consume(TokenType::WhiteSpace)?;
         }
+
+        Ok(())
     }
 
     fn is_next_token(&self, token_type: TokenType) -> bool {
@@ -503,6 +853,65 @@ impl CSS3Parser {
             TokenType::SubsequentSiblingCombinator,
         ])
     }
+
+    /// Returns true at the end of the input, or at the token after a complex selector
+    fn is_complex_selector_end(&self) -> bool {
+        self.lookahead.is_none()
+            || self.is_next_tokens(vec![
+                TokenType::LCurly,
+                TokenType::RParen,
+                TokenType::SelectorListCombinator,
+            ])
+    }
+}
+
+/// Parses the [An+B microsyntax](https://drafts.csswg.org/css-syntax/#anb-microsyntax) of the
+/// `:nth-*()` pseudo-classes
+fn an_plus_b(value: &str) -> Option<NthValue> {
+    let value = value.to_ascii_lowercase();
+    if value == "odd" || value == "even" {
+        return Some(NthValue::Identifier(Identifier::new(value)));
+    }
+
+    let Some((a, b)) = value.split_once('n') else {
+        return Some(NthValue::AnPlusB(AnPlusB::new(0, integer(&value)?)));
+    };
+
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        a => integer(a)?,
+    };
+
+    // Whitespace is allowed around the sign of B, but not between the sign and A
+    let b = b.trim_start();
+    let b = match b.chars().next() {
+        None => 0,
+        Some(sign @ ('+' | '-')) => {
+            let digits = b[1..].trim_start();
+            if digits.starts_with(['+', '-']) {
+                return None;
+            }
+            if sign == '-' {
+                -integer(digits)?
+            } else {
+                integer(digits)?
+            }
+        }
+        Some(_) => return None,
+    };
+
+    Some(NthValue::AnPlusB(AnPlusB::new(a, b)))
+}
+
+/// Parses an integer with an optional sign
+fn integer(value: &str) -> Option<i32> {
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok()
 }
 
 #[cfg(test)]
@@ -512,8 +921,9 @@ mod test {
     #[test]
     fn parse_css() {
         let mut parser = CSS3Parser::new();
-        let style_sheet = parser.parse(
-            r#"
+        let style_sheet = parser
+            .parse(
+                r#"
             
                 #header div > p {
                     display: flex;
@@ -521,7 +931,8 @@ mod test {
                     font-size: 1rem !important;
                 }
             "#,
-        );
+            )
+            .unwrap();
 
         assert_eq!(
             style_sheet,
@@ -565,13 +976,15 @@ mod test {
         let mut parser = CSS3Parser::new();
 
         assert_eq!(
-            parser.parse(
-                r##"
+            parser
+                .parse(
+                    r##"
             a {
                 color: blue;
             }
         "##
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![Selector::TypeSelector(TypeSelector::new("a"))]),
                 Block::new(vec![BlockChild::DeclarationList(DeclarationList::new(
@@ -584,14 +997,16 @@ mod test {
         );
 
         assert_eq!(
-            parser.parse(
-                r##"
+            parser
+                .parse(
+                    r##"
             /* Internal links, beginning with "#" */
             a[href^="#"] {
                 background-color: gold;
             }
         "##,
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![
                     Selector::TypeSelector(TypeSelector::new("a")),
@@ -612,14 +1027,16 @@ mod test {
         );
 
         assert_eq!(
-            parser.parse(
-                r#"
+            parser
+                .parse(
+                    r#"
             /* Links with "example" anywhere in the URL */
             a[href*="example"] {
                 background-color: silver;
             }
         "#
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![
                     Selector::TypeSelector(TypeSelector::new("a")),
@@ -640,15 +1057,17 @@ mod test {
         );
 
         assert_eq!(
-            parser.parse(
-                r#"
+            parser
+                .parse(
+                    r#"
             /* Links with "insensitive" anywhere in the URL,
             regardless of capitalization */
             a[href *= "insensitive" i] {
                 color: cyan;
             }
         "#
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![
                     Selector::TypeSelector(TypeSelector::new("a")),
@@ -669,15 +1088,17 @@ mod test {
         );
 
         assert_eq!(
-            parser.parse(
-                r#"
+            parser
+                .parse(
+                    r#"
                     /* Links with "cAsE" anywhere in the URL,
                     with matching capitalization */
                     a[href*="cAsE" s] {
                         color: pink;
                     }
                 "#,
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![
                     Selector::TypeSelector(TypeSelector::new("a")),
@@ -698,8 +1119,9 @@ mod test {
         );
 
         assert_eq!(
-            parser.parse(
-                r#"
+            parser
+                .parse(
+                    r#"
             
             /* Links that end in ".org" */
             a[href$=".org"] {
@@ -707,7 +1129,8 @@ mod test {
             }
 
             "#
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![
                     Selector::TypeSelector(TypeSelector::new("a")),
@@ -728,14 +1151,16 @@ mod test {
         );
 
         assert_eq!(
-            parser.parse(
-                r#"
+            parser
+                .parse(
+                    r#"
             /* Links that start with "https://" and end in ".org" */
             a[href^="https://"][href$=".org"] {
                 color: green;
             } 
             "#
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(vec![StyleSheetRule::Rule(Rule::new(
                 SelectorList::new(vec![
                     Selector::TypeSelector(TypeSelector::new("a")),
@@ -790,8 +1215,9 @@ mod test {
         }
 
         assert_eq!(
-            parser.parse(
-                r##"
+            parser
+                .parse(
+                    r##"
             ul > li {}  
 
             ul || li {}
@@ -806,8 +1232,172 @@ mod test {
 
             ul li {}
         "##
-            ),
+                )
+                .unwrap(),
             StyleSheet::new(rules)
         );
     }
+
+    /// Returns the indexes in 1..=10 matched by the given An+B argument
+    fn nth_matches(input: &str) -> Option<Vec<usize>> {
+        let selectors = CSS3Parser::new()
+            .parse_selectors(&format!(":nth-child({input})"))
+            .ok()?;
+        let [Selector::PseudoClassSelector(pseudo)] = selectors.children() else {
+            return None;
+        };
+        let Some(PseudoClassArgument::Nth(nth)) = pseudo.argument() else {
+            return None;
+        };
+
+        Some((1..=10).filter(|&idx| nth.value().matches(idx)).collect())
+    }
+
+    #[test]
+    fn parse_selectors() {
+        let mut parser = CSS3Parser::new();
+
+        assert_eq!(
+            parser
+                .parse_selectors("div > p.note, #main a[href^='http' I]")
+                .unwrap(),
+            SelectorList::new(vec![
+                Selector::TypeSelector(TypeSelector::new("div")),
+                Selector::Combinator(Combinator::ChildCombinator),
+                Selector::TypeSelector(TypeSelector::new("p")),
+                Selector::ClassSelector(ClassSelector::new("note".into())),
+                Selector::Combinator(Combinator::SelectorListCombinator),
+                Selector::IdSelector(IdSelector::new("main")),
+                Selector::Combinator(Combinator::DescendantCombinator),
+                Selector::TypeSelector(TypeSelector::new("a")),
+                Selector::AttributeSelector(AttributeSelector {
+                    name: Identifier::new("href"),
+                    matcher: Some(AttributeMatcher::PrefixMatch),
+                    value: Some(CssString::new("http")),
+                    flag: Some(Identifier::new("i")),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_pseudo_selectors() {
+        let mut parser = CSS3Parser::new();
+
+        assert_eq!(
+            parser
+                .parse_selectors("*:has(> img):not(.a, .b)::before")
+                .unwrap(),
+            SelectorList::new(vec![
+                Selector::TypeSelector(TypeSelector::new("*")),
+                Selector::PseudoClassSelector(PseudoClassSelector::new(
+                    "has",
+                    Some(PseudoClassArgument::SelectorList(SelectorList::new(vec![
+                        Selector::Combinator(Combinator::ChildCombinator),
+                        Selector::TypeSelector(TypeSelector::new("img")),
+                    ])))
+                )),
+                Selector::PseudoClassSelector(PseudoClassSelector::new(
+                    "not",
+                    Some(PseudoClassArgument::SelectorList(SelectorList::new(vec![
+                        Selector::ClassSelector(ClassSelector::new("a".into())),
+                        Selector::Combinator(Combinator::SelectorListCombinator),
+                        Selector::ClassSelector(ClassSelector::new("b".into())),
+                    ])))
+                )),
+                Selector::PseudoElementSelector(PseudoElementSelector::new("before")),
+            ])
+        );
+
+        // Invalid selectors are dropped from forgiving selector lists
+        assert_eq!(
+            parser
+                .parse_selectors(":is(p, :unknown, :nth-child(x), 12)")
+                .unwrap(),
+            SelectorList::new(vec![Selector::PseudoClassSelector(
+                PseudoClassSelector::new(
+                    "is",
+                    Some(PseudoClassArgument::SelectorList(SelectorList::new(vec![
+                        Selector::TypeSelector(TypeSelector::new("p")),
+                    ])))
+                )
+            )])
+        );
+
+        assert_eq!(
+            parser.parse_selectors("li:nth-child(2n+1 of .a)").unwrap(),
+            SelectorList::new(vec![
+                Selector::TypeSelector(TypeSelector::new("li")),
+                Selector::PseudoClassSelector(PseudoClassSelector::new(
+                    "nth-child",
+                    Some(PseudoClassArgument::Nth(Nth::new(
+                        NthValue::AnPlusB(AnPlusB::new(2, 1)),
+                        Some(SelectorList::new(vec![Selector::ClassSelector(
+                            ClassSelector::new("a".into())
+                        )]))
+                    )))
+                )),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_an_plus_b() {
+        let cases: [(&str, &[usize]); 13] = [
+            ("odd", &[1, 3, 5, 7, 9]),
+            ("even", &[2, 4, 6, 8, 10]),
+            ("5", &[5]),
+            ("2n+1", &[1, 3, 5, 7, 9]),
+            ("2n + 4", &[4, 6, 8, 10]),
+            ("2n-1", &[1, 3, 5, 7, 9]),
+            ("3n- 1", &[2, 5, 8]),
+            ("-n+3", &[1, 2, 3]),
+            ("+n", &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]),
+            ("N-8", &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]),
+            ("-2n+5", &[1, 3, 5]),
+            ("-2", &[]),
+            ("0n+0", &[]),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(nth_matches(input).as_deref(), Some(expected), "{input}");
+        }
+
+        for input in [
+            "", "2n+", "n+-1", "2.5n", "foo", "+ n", "- 2", "2 n", "2n 1",
+        ] {
+            assert!(nth_matches(input).is_none(), "{input} should not parse");
+        }
+    }
+
+    #[test]
+    fn parse_invalid_selectors() {
+        for input in [
+            "",
+            "div,",
+            "> p",
+            "p >",
+            "p > > a",
+            "#1a",
+            ".",
+            ".a*",
+            "[href",
+            "a[href=]",
+            "a[href=x y]",
+            ":unknown",
+            ":not()",
+            "p:nth-of-type(2n of .a)",
+            "div)",
+            "div!",
+            "svg|rect",
+        ] {
+            assert!(
+                matches!(
+                    CSS3Parser::new().parse_selectors(input),
+                    Err(Error::CssSyntax(_))
+                ),
+                "'{input}' should be invalid"
+            );
+        }
+    }
 }
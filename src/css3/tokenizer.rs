@@ -1,6 +1,15 @@
-use crate::css3::tokens::{Token, TOKEN_REFS};
+use crate::css3::tokens::{Token, TokenType, TOKEN_REFS};
+use lazy_static::lazy_static;
 use regex::{self, Regex};
 
+lazy_static! {
+    /// Compiled regular expressions of the token references
+    static ref TOKEN_REGEXES: Vec<(Regex, Option<TokenType>)> = TOKEN_REFS
+        .iter()
+        .map(|(regex, token_type)| (Regex::new(regex).unwrap(), *token_type))
+        .collect();
+}
+
 /// CSS Tokenizer
 #[derive(Debug, Default, PartialEq)]
 pub struct Tokenizer {
@@ -35,8 +44,7 @@ impl Tokenizer {
         }
 
         let raw = &self.raw[self.cursor..];
-        for (regex, token_type) in TOKEN_REFS.iter() {
-            let re = Regex::new(regex).unwrap();
+        for (re, token_type) in TOKEN_REGEXES.iter() {
             let result = re.captures(raw);
 
             // println!(
@@ -64,8 +72,6 @@ impl Tokenizer {
 
 #[cfg(test)]
 mod test {
-    use crate::css3::tokens::TokenType;
-
     use super::*;
 
     macro_rules! assert_next_token {
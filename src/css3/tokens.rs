@@ -43,6 +43,10 @@ pub enum TokenType {
     /// A `~`
     SubsequentSiblingCombinator,
     Equal,
+    /// A `*`, like in the universal selector
+    Asterisk,
+    /// A `-` that does not start an identifier
+    Minus,
     Ident,
     Important,
     // todo: add unicode & ascii
@@ -80,6 +84,8 @@ static SEMICOLON: &str = r"^;";
 static COLON: &str = r"^:";
 static LCURLY: &str = r"^\{";
 static RCURLY: &str = r"^\}";
+static LPAREN: &str = r"^\(";
+static RPAREN: &str = r"^\)";
 static LBRACKET: &str = r"^\[";
 static RBRACKET: &str = r"^\]";
 static INCLUDE_MATCH: &str = r"^\~=";
@@ -88,6 +94,8 @@ static PREFIX_MATCH: &str = r"^\^=";
 static SUFFIX_MATCH: &str = r"^\$=";
 static SUBSTRING_MATCH: &str = r"^\*=";
 static EQUAL: &str = r"^=";
+static ASTERISK: &str = r"^\*";
+static MINUS: &str = r"^-";
 static SPACE: &str = r"^[ \t\r\n\f]+";
 static IMPORTANT: &str = "^!important";
 static COMMENT: &str = r"^\/\*[^*]*\*+([^/*][^*]*\*+)*\/";
@@ -100,7 +108,7 @@ static NEXT_SIBLING_COMBINATOR: &str = r"^\+";
 static SELECTOR_LIST_COMBINATOR: &str = r"^,";
 static SUBSEQUENT_SIBLING_COMBINATOR: &str = r"^\~";
 
-pub static TOKEN_REFS: [(&str, Option<TokenType>); 27] = [
+pub static TOKEN_REFS: [(&str, Option<TokenType>); 31] = [
     (NUMBER, Some(TokenType::Number)),
     (IDENT, Some(TokenType::Ident)),
     (HASH, Some(TokenType::Hash)),
@@ -109,6 +117,8 @@ pub static TOKEN_REFS: [(&str, Option<TokenType>); 27] = [
     (SEMICOLON, Some(TokenType::Semicolon)),
     (LCURLY, Some(TokenType::LCurly)),
     (RCURLY, Some(TokenType::RCurly)),
+    (LPAREN, Some(TokenType::LParen)),
+    (RPAREN, Some(TokenType::RParen)),
     (LBRACKET, Some(TokenType::LBracket)),
     (RBRACKET, Some(TokenType::RBracket)),
     (COLON, Some(TokenType::Colon)),
@@ -120,6 +130,8 @@ pub static TOKEN_REFS: [(&str, Option<TokenType>); 27] = [
     (SUFFIX_MATCH, Some(TokenType::SuffixMatch)),
     (SUBSTRING_MATCH, Some(TokenType::SubstringMatch)),
     (EQUAL, Some(TokenType::Equal)),
+    (ASTERISK, Some(TokenType::Asterisk)),
+    (MINUS, Some(TokenType::Minus)),
     (CHILD_COMBINATOR, Some(TokenType::ChildCombinator)),
     // (DESCENDANT_COMBINATOR, Some(TokenType::DescendantCombinator)), // should be skipped if needed
     (COLUMN_COMBINATOR, Some(TokenType::ColumnCombinator)),
@@ -5,7 +5,10 @@
 pub mod dom;
 pub mod element_class;
 pub mod error_logger;
+pub mod mutation_observer;
 pub mod node;
 pub mod parser;
+pub mod preload_scanner;
+pub mod serializer;
 pub mod tokenizer;
 pub mod util;
@@ -1,65 +1,774 @@
+//! Typed element interfaces
+//!
+//! The parser stores every element as a [`Node`] with [`ElementData`]. The types in this module
+//! are lightweight views over such a node that implement the [Element](https://dom.spec.whatwg.org/#interface-element)
+//! and [HTMLElement](https://html.spec.whatwg.org/multipage/dom.html#htmlelement) interfaces,
+//! and the interfaces of specific elements in [`elements`]. A view holds a handle to the document
+//! and the id of the node, so it does not keep the document borrowed. The node can be deleted
+//! while a view of it exists, after which the methods of the view return
+//! [`DomError::NotFound`].
+//!
+//! Attributes are [reflected](https://html.spec.whatwg.org/multipage/common-dom-interfaces.html#reflecting-content-attributes-in-idl-attributes):
+//! getters read the content attributes of the element, and setters update them through the
+//! document, so the id index, the element classes and mutation observers stay in sync.
+use crate::html5::node::data::element::ElementData;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::parser::tree_builder::TreeBuilder;
+use crate::types::{DomError, Result};
 use std::collections::HashMap;
+use std::ops::Deref;
 
+pub mod collection;
+pub mod elements;
+pub mod markup;
+pub mod shadow;
+pub mod text;
+
+/// Data of a shadow root node, see [ShadowRoot](https://dom.spec.whatwg.org/#interface-shadowroot).
+///
+/// A shadow root is a node without a parent. Its children form the shadow tree of the host,
+/// which the host refers to from its element data.
+#[derive(Debug, Clone, PartialEq)]
 pub struct ShadowRoot {
     pub mode: ShadowRootMode,
     pub delegates_focus: bool,
     pub slot_assignment: SlotAssignmentMode,
-    pub host: Box<Element>,
+    /// The shadow root is copied when the host is cloned
+    pub clonable: bool,
+    /// The shadow root is included when the host is serialized
+    pub serializable: bool,
+    /// The shadow root was created by a template element with a shadowrootmode attribute
+    pub declarative: bool,
+    /// Element the shadow root is attached to
+    pub host: NodeId,
     // pub onslotchange: Option<EventHandler>,
 }
 
+/// How the children of a shadow host are assigned to slots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SlotAssignmentMode {
+    /// Children are assigned with [`HtmlSlotElement::assign`](elements::HtmlSlotElement::assign)
     Manual,
+    /// Children are assigned to the first slot whose name matches their slot attribute
     Named,
 }
 
+/// Whether the shadow root can be accessed from outside the shadow tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShadowRootMode {
     Open,
     Closed,
 }
 
+/// Options for attaching a shadow root, see [ShadowRootInit](https://dom.spec.whatwg.org/#dictdef-shadowrootinit)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowRootInit {
+    pub mode: ShadowRootMode,
+    pub delegates_focus: bool,
+    pub slot_assignment: SlotAssignmentMode,
+    pub clonable: bool,
+    pub serializable: bool,
+}
+
+impl ShadowRootInit {
+    /// Returns the options for a shadow root with the given mode and named slot assignment
+    pub fn new(mode: ShadowRootMode) -> Self {
+        ShadowRootInit {
+            mode,
+            delegates_focus: false,
+            slot_assignment: SlotAssignmentMode::Named,
+            clonable: false,
+            serializable: false,
+        }
+    }
+}
+
+/// View of an element node, see [Element](https://dom.spec.whatwg.org/#interface-element)
+#[derive(Debug, Clone, PartialEq)]
 pub struct Element {
-    pub namespace_uri: Option<String>,
-    pub prefix: Option<String>,
-    pub local_name: String,
-    pub tag_name: String,
-    pub id: String,
-    pub class_name: String,
-    pub class_list: Vec<String>,
-    pub slot: String,
-    pub attributes: HashMap<String, String>,
-    pub shadow_root: Option<Box<ShadowRoot>>,
+    document: DocumentHandle,
+    node_id: NodeId,
+}
+
+impl Element {
+    /// Returns a view of the node, or None when the node is not an element
+    pub fn new(document: &DocumentHandle, node_id: NodeId) -> Option<Self> {
+        let is_element = matches!(
+            document.get().get_node_by_id(node_id)?.data,
+            NodeData::Element(_)
+        );
+
+        is_element.then(|| Element {
+            document: Document::clone(document),
+            node_id,
+        })
+    }
+
+    /// Returns the id of the element node
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    /// Returns the document of the element
+    pub fn document(&self) -> &DocumentHandle {
+        &self.document
+    }
+
+    /// Calls the function with the node of the element. Fails when the node has been deleted.
+    fn with_node<R>(&self, f: impl FnOnce(&Node) -> R) -> Result<R> {
+        let document = self.document.get();
+        Ok(f(document.dom_node(self.node_id)?))
+    }
+
+    /// Calls the function with the element data of the element
+    fn with_data<R>(&self, f: impl FnOnce(&ElementData) -> R) -> Result<R> {
+        self.with_node(|node| match &node.data {
+            NodeData::Element(element) => Ok(f(element)),
+            _ => Err(DomError::NotFound(format!("node {} is not an element", node.id)).into()),
+        })?
+    }
+
+    /// Returns the namespace of the element
+    pub fn namespace_uri(&self) -> Result<Option<String>> {
+        self.with_node(|node| node.namespace.clone())
+    }
+
+    /// Returns the local name of the element
+    pub fn local_name(&self) -> Result<String> {
+        self.with_data(|element| element.name().to_owned())
+    }
+
+    /// Returns the tag name of the element, which is uppercased for HTML elements
+    pub fn tag_name(&self) -> Result<String> {
+        let name = self.local_name()?;
+        if self.is_html()? {
+            Ok(name.to_ascii_uppercase())
+        } else {
+            Ok(name)
+        }
+    }
+
+    /// Returns true when the element is in the HTML namespace
+    pub fn is_html(&self) -> Result<bool> {
+        Ok(self.namespace_uri()?.as_deref() == Some(HTML_NAMESPACE))
+    }
+
+    /// Returns a copy of all attributes of the element
+    pub fn attributes(&self) -> Result<HashMap<String, String>> {
+        self.with_data(|element| element.attributes.clone())
+    }
+
+    /// Returns the value of the attribute
+    pub fn get_attribute(&self, name: &str) -> Result<Option<String>> {
+        self.with_data(|element| element.attributes.get(name).cloned())
+    }
+
+    /// Returns true when the attribute is set
+    pub fn has_attribute(&self, name: &str) -> Result<bool> {
+        self.with_data(|element| element.attributes.contains_key(name))
+    }
+
+    /// Sets the value of the attribute
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> Result<()> {
+        self.document.insert_attribute(name, value, self.node_id)
+    }
+
+    /// Removes the attribute
+    pub fn remove_attribute(&mut self, name: &str) -> Result<()> {
+        self.document.remove_attribute(name, self.node_id)
+    }
+
+    /// Adds the attribute when force is true, and removes it when force is false. Without force
+    /// the attribute is toggled. Returns whether the attribute is set afterward.
+    pub fn toggle_attribute(&mut self, name: &str, force: Option<bool>) -> Result<bool> {
+        let has_attribute = self.has_attribute(name)?;
+        let set = force.unwrap_or(!has_attribute);
+        match (set, has_attribute) {
+            (true, false) => self.set_attribute(name, "")?,
+            (false, true) => self.remove_attribute(name)?,
+            _ => {}
+        }
+
+        Ok(set)
+    }
+
+    /// Returns the value of a reflected string attribute, which is empty when not set
+    pub(crate) fn string_attribute(&self, name: &str) -> Result<String> {
+        Ok(self.get_attribute(name)?.unwrap_or_default())
+    }
+
+    /// Returns the value of a reflected boolean attribute, which is true when the attribute is set
+    pub(crate) fn bool_attribute(&self, name: &str) -> Result<bool> {
+        self.has_attribute(name)
+    }
+
+    /// Sets a reflected boolean attribute
+    pub(crate) fn set_bool_attribute(&mut self, name: &str, value: bool) -> Result<()> {
+        self.toggle_attribute(name, Some(value)).map(|_| ())
+    }
+
+    /// Returns the value of a reflected [enumerated attribute](https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#enumerated-attribute).
+    /// Keywords are matched ASCII case-insensitively. The missing value default is used when the
+    /// attribute is not set, and the invalid value default when it has an unknown value.
+    pub(crate) fn enumerated_attribute(
+        &self,
+        name: &str,
+        keywords: &[&str],
+        missing: &str,
+        invalid: &str,
+    ) -> Result<String> {
+        let Some(value) = self.get_attribute(name)? else {
+            return Ok(missing.to_owned());
+        };
+
+        Ok(keywords
+            .iter()
+            .find(|keyword| keyword.eq_ignore_ascii_case(&value))
+            .map_or(invalid, |keyword| keyword)
+            .to_owned())
+    }
+
+    /// Returns the value of a reflected `long` attribute, or the default when the attribute is
+    /// not a valid integer
+    pub(crate) fn integer_attribute(&self, name: &str, default: i32) -> Result<i32> {
+        Ok(self
+            .get_attribute(name)?
+            .and_then(|value| parse_integer(&value))
+            .unwrap_or(default))
+    }
+
+    /// Returns the value of a reflected `unsigned long` attribute, or the default when the
+    /// attribute is not a valid non-negative integer
+    pub(crate) fn unsigned_attribute(&self, name: &str, default: u32) -> Result<u32> {
+        Ok(self
+            .get_attribute(name)?
+            .and_then(|value| parse_integer(&value))
+            .and_then(|value| u32::try_from(value).ok())
+            .unwrap_or(default))
+    }
+
+    /// Returns the id of the element
+    pub fn id(&self) -> Result<String> {
+        self.string_attribute("id")
+    }
+
+    /// Sets the id of the element
+    pub fn set_id(&mut self, id: &str) -> Result<()> {
+        self.set_attribute("id", id)
+    }
+
+    /// Returns the value of the class attribute
+    pub fn class_name(&self) -> Result<String> {
+        self.string_attribute("class")
+    }
+
+    /// Sets the value of the class attribute
+    pub fn set_class_name(&mut self, class_name: &str) -> Result<()> {
+        self.set_attribute("class", class_name)
+    }
+
+    /// Returns the classes of the element
+    pub fn class_list(&self) -> ClassList {
+        ClassList {
+            element: self.clone(),
+        }
+    }
+
+    /// Returns the name of the slot the element is assigned to
+    pub fn slot(&self) -> Result<String> {
+        self.string_attribute("slot")
+    }
+
+    /// Sets the name of the slot the element is assigned to
+    pub fn set_slot(&mut self, slot: &str) -> Result<()> {
+        self.set_attribute("slot", slot)
+    }
+
+    /// Attaches a shadow root to the element, see [attachShadow()](https://dom.spec.whatwg.org/#dom-element-attachshadow).
+    /// Returns the id of the shadow root node.
+    pub fn attach_shadow(&mut self, init: ShadowRootInit) -> Result<NodeId> {
+        self.document.get_mut().attach_shadow(self.node_id, init)
+    }
+
+    /// Returns the shadow root of the element, unless it is closed
+    pub fn shadow_root(&self) -> Option<NodeId> {
+        let document = self.document.get();
+        let shadow_root_id = document.shadow_root(self.node_id)?;
+        let shadow_root = document.shadow_root_data(shadow_root_id)?;
+
+        (shadow_root.mode == ShadowRootMode::Open).then_some(shadow_root_id)
+    }
+
+    /// Returns the slot the element is assigned to, unless the slot is in a closed shadow tree
+    pub fn assigned_slot(&self) -> Option<NodeId> {
+        self.document.get().find_slot(self.node_id, true)
+    }
+
+    /// Returns the concatenation of all text in the element
+    pub fn text_content(&self) -> String {
+        self.document
+            .get()
+            .text_content(self.node_id)
+            .unwrap_or_default()
+    }
+
+    /// Replaces the children of the element with a single text node
+    pub fn set_text_content(&mut self, value: &str) -> Result<()> {
+        self.document
+            .get_mut()
+            .set_text_content(self.node_id, value)
+    }
+
+    /// Merges adjacent text nodes in the subtree of the element
+    pub fn normalize(&mut self) -> Result<()> {
+        self.document.get_mut().normalize(self.node_id)
+    }
+
+    /// Returns the element as an HTML element, or None when it is not in the HTML namespace or
+    /// the node has been deleted
+    pub fn as_html(&self) -> Option<HtmlElement> {
+        matches!(self.is_html(), Ok(true)).then(|| HtmlElement(self.clone()))
+    }
+}
+
+/// Parses an integer with the [rules for parsing integers](https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-integers)
+fn parse_integer(value: &str) -> Option<i32> {
+    let value = value.trim_start_matches(['\t', '\n', '\x0C', '\r', ' ']);
+    let (negative, value) = match value.as_bytes().first()? {
+        b'-' => (true, &value[1..]),
+        b'+' => (false, &value[1..]),
+        _ => (false, value),
+    };
+
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(value, |end| &value[..end]);
+    if digits.is_empty() {
+        return None;
+    }
+
+    let value = digits.parse::<i64>().ok()?;
+    i32::try_from(if negative { -value } else { value }).ok()
+}
+
+/// The classes of an element, see [DOMTokenList](https://dom.spec.whatwg.org/#interface-domtokenlist).
+///
+/// The list is live: it reads the classes from the [`ElementClass`](crate::html5::element_class::ElementClass) of the element, and changes
+/// are written to the class attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassList {
+    element: Element,
+}
+
+impl ClassList {
+    /// Returns the classes in the order of the class attribute, without duplicates
+    pub fn tokens(&self) -> Result<Vec<String>> {
+        let mut tokens: Vec<String> = Vec::new();
+        for token in self.element.class_name()?.split_ascii_whitespace() {
+            if !tokens.iter().any(|t| t == token) {
+                tokens.push(token.to_owned());
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    /// Returns the value of the class attribute
+    pub fn value(&self) -> Result<String> {
+        self.element.class_name()
+    }
+
+    /// Returns the number of classes
+    pub fn len(&self) -> Result<usize> {
+        self.element.with_data(|element| element.classes.len())
+    }
+
+    /// Returns true when the element has no classes
+    pub fn is_empty(&self) -> Result<bool> {
+        self.element.with_data(|element| element.classes.is_empty())
+    }
+
+    /// Returns the class at the index in the class attribute
+    pub fn item(&self, index: usize) -> Result<Option<String>> {
+        Ok(self.tokens()?.into_iter().nth(index))
+    }
+
+    /// Returns true when the element has the class
+    pub fn contains(&self, token: &str) -> Result<bool> {
+        self.element
+            .with_data(|element| element.classes.contains(token))
+    }
+
+    /// Adds the classes that are not present yet
+    pub fn add(&mut self, tokens: &[&str]) -> Result<()> {
+        validate_tokens(tokens)?;
+
+        let mut classes = self.tokens()?;
+        for token in tokens {
+            if !classes.iter().any(|class| class == token) {
+                classes.push(token.to_string());
+            }
+        }
+
+        self.update(classes)
+    }
+
+    /// Removes the classes
+    pub fn remove(&mut self, tokens: &[&str]) -> Result<()> {
+        validate_tokens(tokens)?;
+
+        let mut classes = self.tokens()?;
+        classes.retain(|class| !tokens.contains(&class.as_str()));
+
+        self.update(classes)
+    }
+
+    /// Removes the class when it is present and adds it otherwise. With force the class is only
+    /// added (true) or only removed (false). Returns whether the class is present afterward.
+    pub fn toggle(&mut self, token: &str, force: Option<bool>) -> Result<bool> {
+        validate_tokens(&[token])?;
+
+        let present = self.contains(token)?;
+        match (force.unwrap_or(!present), present) {
+            (true, false) => self.add(&[token])?,
+            (false, true) => self.remove(&[token])?,
+            _ => {}
+        }
+
+        self.contains(token)
+    }
+
+    /// Replaces a class with another one. Returns false when the class is not present.
+    pub fn replace(&mut self, token: &str, new_token: &str) -> Result<bool> {
+        validate_tokens(&[token, new_token])?;
+
+        if !self.contains(token)? {
+            return Ok(false);
+        }
+
+        let mut classes = Vec::new();
+        for class in self.tokens()? {
+            let class = if class == token {
+                new_token.to_owned()
+            } else {
+                class
+            };
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+
+        self.update(classes)?;
+        Ok(true)
+    }
+
+    /// Runs the [update steps](https://dom.spec.whatwg.org/#concept-dtl-update), which write
+    /// the classes back to the class attribute
+    fn update(&mut self, classes: Vec<String>) -> Result<()> {
+        if classes.is_empty() && !self.element.has_attribute("class")? {
+            return Ok(());
+        }
+
+        self.element.set_class_name(&classes.join(" "))
+    }
 }
 
-pub struct HtmlElement {
-    // Element fields
-    pub namespace_uri: Option<String>,
-    pub prefix: Option<String>,
-    pub local_name: String,
-    pub tag_name: String,
-    pub id: String,
-    pub class_name: String,
-    pub class_list: Vec<String>,
-    pub slot: String,
-    pub attributes: HashMap<String, String>,
-    pub shadow_root: Option<ShadowRoot>,
-
-    // HTML Element
-    pub title: String,
-    pub lang: String,
-    pub translate: bool,
-    pub dir: String,
-
-    pub hidden: Option<bool>,
-    pub insert: bool,
-    pub access_key: String,
-    pub access_key_label: String,
-    pub draggable: bool,
-    pub spellcheck: bool,
-    pub autocapitalize: String,
-
-    pub inner_text: String,
-    pub outer_text: String,
-
-    pub popover: Option<String>,
+/// Checks that tokens are not empty and contain no whitespace
+fn validate_tokens(tokens: &[&str]) -> Result<()> {
+    for token in tokens {
+        if token.is_empty() {
+            return Err(DomError::Syntax("token is empty".into()).into());
+        }
+        if token.contains(|c: char| c.is_ascii_whitespace()) {
+            return Err(
+                DomError::InvalidCharacter(format!("token '{token}' contains whitespace")).into(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// View of an HTML element, see [HTMLElement](https://html.spec.whatwg.org/multipage/dom.html#htmlelement)
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlElement(Element);
+
+impl Deref for HtmlElement {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl HtmlElement {
+    /// Returns a view of the node, or None when the node is not an HTML element
+    pub fn new(document: &DocumentHandle, node_id: NodeId) -> Option<Self> {
+        Element::new(document, node_id)?.as_html()
+    }
+
+    /// Returns the view as a plain element
+    pub fn element(&self) -> &Element {
+        &self.0
+    }
+
+    /// Returns the view as a mutable plain element, for setting attributes
+    pub fn element_mut(&mut self) -> &mut Element {
+        &mut self.0
+    }
+
+    /// Returns a view of the element when it is an HTML element with the given local name
+    pub(crate) fn with_name(
+        document: &DocumentHandle,
+        node_id: NodeId,
+        name: &str,
+    ) -> Option<Self> {
+        Self::new(document, node_id).filter(|element| {
+            element
+                .local_name()
+                .is_ok_and(|local_name| local_name == name)
+        })
+    }
+
+    /// Returns the text of the element as it is rendered, see [`Document::inner_text`]
+    pub fn inner_text(&self) -> String {
+        self.document().get().inner_text(self.node_id())
+    }
+
+    /// Returns the advisory title of the element
+    pub fn title(&self) -> Result<String> {
+        self.string_attribute("title")
+    }
+
+    /// Sets the advisory title of the element
+    pub fn set_title(&mut self, title: &str) -> Result<()> {
+        self.0.set_attribute("title", title)
+    }
+
+    /// Returns the language of the element as set on the element itself
+    pub fn lang(&self) -> Result<String> {
+        self.string_attribute("lang")
+    }
+
+    /// Sets the language of the element
+    pub fn set_lang(&mut self, lang: &str) -> Result<()> {
+        self.0.set_attribute("lang", lang)
+    }
+
+    /// Returns the text direction of the element: "ltr", "rtl", "auto" or empty
+    pub fn dir(&self) -> Result<String> {
+        self.enumerated_attribute("dir", &["ltr", "rtl", "auto"], "", "")
+    }
+
+    /// Sets the text direction of the element
+    pub fn set_dir(&mut self, dir: &str) -> Result<()> {
+        self.0.set_attribute("dir", dir)
+    }
+
+    /// Returns true when the element is hidden with the hidden attribute
+    pub fn hidden(&self) -> Result<bool> {
+        self.bool_attribute("hidden")
+    }
+
+    /// Sets or removes the hidden attribute
+    pub fn set_hidden(&mut self, hidden: bool) -> Result<()> {
+        self.0.set_bool_attribute("hidden", hidden)
+    }
+
+    /// Returns whether the element is [draggable](https://html.spec.whatwg.org/multipage/dnd.html#dom-draggable).
+    /// Without a valid draggable attribute, images and links with an href are draggable.
+    pub fn draggable(&self) -> Result<bool> {
+        match self
+            .enumerated_attribute("draggable", &["true", "false"], "", "")?
+            .as_str()
+        {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => match self.local_name()?.as_str() {
+                "img" => Ok(true),
+                "a" => self.has_attribute("href"),
+                _ => Ok(false),
+            },
+        }
+    }
+
+    /// Sets whether the element is draggable
+    pub fn set_draggable(&mut self, draggable: bool) -> Result<()> {
+        self.0.set_attribute("draggable", &draggable.to_string())
+    }
+
+    /// Returns whether the contents of the element are translated. Without a valid translate
+    /// attribute, the element inherits this from its parent.
+    pub fn translate(&self) -> bool {
+        let document = self.document().get();
+        let mut node_id = Some(self.node_id());
+        while let Some(node) = node_id.and_then(|id| document.get_node_by_id(id)) {
+            if let NodeData::Element(element) = &node.data {
+                match element.attributes.get("translate").map(String::as_str) {
+                    Some("" | "yes") => return true,
+                    Some("no") => return false,
+                    _ => {}
+                }
+            }
+            node_id = node.parent;
+        }
+
+        true
+    }
+
+    /// Sets whether the contents of the element are translated
+    pub fn set_translate(&mut self, translate: bool) -> Result<()> {
+        self.0
+            .set_attribute("translate", if translate { "yes" } else { "no" })
+    }
+
+    /// Returns the access key of the element
+    pub fn access_key(&self) -> Result<String> {
+        self.string_attribute("accesskey")
+    }
+
+    /// Sets the access key of the element
+    pub fn set_access_key(&mut self, access_key: &str) -> Result<()> {
+        self.0.set_attribute("accesskey", access_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::document::parse_document;
+    use crate::types::Error;
+
+    fn element(document: &DocumentHandle, id: &str) -> HtmlElement {
+        let node_id = document.get().get_node_by_named_id(id).unwrap().id;
+        HtmlElement::new(document, node_id).unwrap()
+    }
+
+    #[test]
+    fn element_view() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><div id="main" class="a b"><svg><rect/></svg>text</div>"#,
+        );
+        let div = element(&document, "main");
+        assert_eq!(div.tag_name().unwrap(), "DIV");
+        assert_eq!(div.local_name().unwrap(), "div");
+        assert_eq!(div.id().unwrap(), "main");
+        assert_eq!(div.class_name().unwrap(), "a b");
+
+        let doc = document.get();
+        let children = doc.get_node_by_id(div.node_id()).unwrap().children.clone();
+        drop(doc);
+        let svg = Element::new(&document, children[0]).unwrap();
+        assert_eq!(svg.tag_name().unwrap(), "svg");
+        assert!(svg.as_html().is_none());
+        assert!(Element::new(&document, children[1]).is_none());
+    }
+
+    #[test]
+    fn reflected_attributes() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><p id="p" title="Hi" lang="nl" dir="RTL" hidden translate="no"><img id="img">
+            <a id="a" href="/" dir="sideways" translate draggable="false"></a></p>"#,
+        );
+        let mut p = element(&document, "p");
+        assert_eq!(p.title().unwrap(), "Hi");
+        assert_eq!(p.lang().unwrap(), "nl");
+        assert_eq!(p.dir().unwrap(), "rtl");
+        assert!(p.hidden().unwrap());
+        assert!(!p.translate());
+        assert!(!p.draggable().unwrap());
+
+        let a = element(&document, "a");
+        assert_eq!(a.dir().unwrap(), "");
+        assert!(a.translate());
+        assert!(!a.draggable().unwrap());
+        let img = element(&document, "img");
+        assert!(!img.translate());
+        assert!(img.draggable().unwrap());
+
+        p.set_hidden(false).unwrap();
+        p.set_dir("ltr").unwrap();
+        p.set_translate(true).unwrap();
+        assert!(!p.hidden().unwrap());
+        assert!(!p.has_attribute("hidden").unwrap());
+        assert_eq!(p.dir().unwrap(), "ltr");
+        assert!(img.translate());
+
+        p.element_mut().set_id("paragraph").unwrap();
+        assert!(document.get().get_node_by_named_id("p").is_none());
+        assert_eq!(
+            document.get().get_node_by_named_id("paragraph").unwrap().id,
+            p.node_id()
+        );
+    }
+
+    #[test]
+    fn class_list() {
+        let document = parse_document(r#"<!DOCTYPE html><div id="div" class=" b a b "></div>"#);
+        let mut classes = element(&document, "div").class_list();
+        assert_eq!(classes.tokens().unwrap(), ["b", "a"]);
+        assert_eq!(classes.len().unwrap(), 2);
+        assert!(classes.contains("a").unwrap());
+        assert!(!classes.contains("c").unwrap());
+
+        classes.add(&["c", "a"]).unwrap();
+        assert_eq!(classes.value().unwrap(), "b a c");
+        assert!(classes.contains("c").unwrap());
+
+        assert!(!classes.toggle("b", None).unwrap());
+        assert!(classes.toggle("d", Some(true)).unwrap());
+        assert_eq!(classes.value().unwrap(), "a c d");
+
+        assert!(classes.replace("c", "a").unwrap());
+        assert!(!classes.replace("x", "y").unwrap());
+        assert_eq!(classes.value().unwrap(), "a d");
+
+        classes.remove(&["a", "d"]).unwrap();
+        assert_eq!(classes.value().unwrap(), "");
+        assert!(classes.is_empty().unwrap());
+
+        assert!(matches!(
+            classes.add(&[""]),
+            Err(Error::Dom(DomError::Syntax(_)))
+        ));
+        assert!(matches!(
+            classes.toggle("a b", None),
+            Err(Error::Dom(DomError::InvalidCharacter(_)))
+        ));
+    }
+
+    #[test]
+    fn deleted_element() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><div id="div"><p id="p" class="x" title="t">text</p></div>"#,
+        );
+        let p = element(&document, "p");
+        let classes = p.class_list();
+
+        // Replacing the text content of the div deletes the p element
+        element(&document, "div")
+            .element_mut()
+            .set_text_content("new")
+            .unwrap();
+
+        assert!(matches!(p.id(), Err(Error::Dom(DomError::NotFound(_)))));
+        assert!(matches!(
+            p.tag_name(),
+            Err(Error::Dom(DomError::NotFound(_)))
+        ));
+        assert!(p.title().is_err());
+        assert!(classes.contains("x").is_err());
+        assert!(p.as_html().is_none());
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse_integer("  42px"), Some(42));
+        assert_eq!(parse_integer("-7"), Some(-7));
+        assert_eq!(parse_integer("+3"), Some(3));
+        assert_eq!(parse_integer("px"), None);
+        assert_eq!(parse_integer(""), None);
+        assert_eq!(parse_integer("99999999999"), None);
+    }
 }
@@ -0,0 +1,653 @@
+//! Live element collections
+//!
+//! Implements [getElementsByTagName()](https://dom.spec.whatwg.org/#dom-document-getelementsbytagname),
+//! [getElementsByTagNameNS()](https://dom.spec.whatwg.org/#dom-document-getelementsbytagnamens),
+//! [getElementsByClassName()](https://dom.spec.whatwg.org/#dom-document-getelementsbyclassname)
+//! and [getElementsByName()](https://html.spec.whatwg.org/multipage/dom.html#dom-document-getelementsbyname),
+//! which return an [`HtmlCollection`].
+//!
+//! A collection is live: it is not a snapshot of the elements, but reflects the document at the
+//! moment it is read. The document keeps an [`ElementIndex`] of its elements by tag name, class
+//! and name attribute, which is updated when elements are added, deleted or get new attributes.
+//! A collection looks up its candidates in the index, and caches the result until the document
+//! changes again.
+use crate::html5::dom::Element;
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::parser::quirks::QuirksMode;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Elements of a document by tag name, class and name attribute. All elements in the arena are
+/// indexed, whether they are in the tree or not, so collections check that their candidates are
+/// descendants of their root.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ElementIndex {
+    /// Incremented on every change to the tree or to an attribute
+    version: u64,
+    /// Elements by ASCII lowercase local name
+    names: HashMap<String, HashSet<NodeId>>,
+    /// Elements by ASCII lowercase class
+    classes: HashMap<String, HashSet<NodeId>>,
+    /// Elements by the value of their name attribute
+    name_attributes: HashMap<String, HashSet<NodeId>>,
+}
+
+impl ElementIndex {
+    /// Adds the node to the index when it is an element
+    pub(crate) fn insert(&mut self, node: &Node) {
+        let NodeData::Element(element) = &node.data else {
+            return;
+        };
+
+        add_entry(&mut self.names, node.name.to_ascii_lowercase(), node.id);
+        self.attribute_changed(
+            node.id,
+            "class",
+            None,
+            element.attributes.get("class").map(String::as_str),
+        );
+        self.attribute_changed(
+            node.id,
+            "name",
+            None,
+            element.attributes.get("name").map(String::as_str),
+        );
+    }
+
+    /// Removes the node from the index
+    pub(crate) fn remove(&mut self, node: &Node) {
+        let NodeData::Element(element) = &node.data else {
+            return;
+        };
+
+        remove_entry(&mut self.names, &node.name.to_ascii_lowercase(), node.id);
+        self.attribute_changed(
+            node.id,
+            "class",
+            element.attributes.get("class").map(String::as_str),
+            None,
+        );
+        self.attribute_changed(
+            node.id,
+            "name",
+            element.attributes.get("name").map(String::as_str),
+            None,
+        );
+    }
+
+    /// Updates the index for an attribute of the element that was set or removed
+    pub(crate) fn attribute_changed(
+        &mut self,
+        element_id: NodeId,
+        name: &str,
+        old_value: Option<&str>,
+        value: Option<&str>,
+    ) {
+        self.version += 1;
+
+        match name {
+            "class" => {
+                for class in class_tokens(old_value.unwrap_or_default()) {
+                    remove_entry(&mut self.classes, &class.to_ascii_lowercase(), element_id);
+                }
+                for class in class_tokens(value.unwrap_or_default()) {
+                    add_entry(&mut self.classes, class.to_ascii_lowercase(), element_id);
+                }
+            }
+            "name" => {
+                if let Some(old_value) = old_value {
+                    remove_entry(&mut self.name_attributes, old_value, element_id);
+                }
+                if let Some(value) = value {
+                    add_entry(&mut self.name_attributes, value.to_owned(), element_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Marks that nodes were inserted into or removed from the tree
+    pub(crate) fn tree_changed(&mut self) {
+        self.version += 1;
+    }
+
+    /// Removes all elements that are not kept from the index
+    pub(crate) fn retain(&mut self, keep: impl Fn(NodeId) -> bool) {
+        self.version += 1;
+        for index in [
+            &mut self.names,
+            &mut self.classes,
+            &mut self.name_attributes,
+        ] {
+            index.retain(|_, ids| {
+                ids.retain(|&id| keep(id));
+                !ids.is_empty()
+            });
+        }
+    }
+
+    /// Removes all elements from the index. The version is kept, so existing collections do not
+    /// mistake the emptied document for the one they cached.
+    pub(crate) fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+}
+
+fn add_entry(index: &mut HashMap<String, HashSet<NodeId>>, key: String, node_id: NodeId) {
+    index.entry(key).or_default().insert(node_id);
+}
+
+fn remove_entry(index: &mut HashMap<String, HashSet<NodeId>>, key: &str, node_id: NodeId) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(&node_id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+/// Splits a class attribute or a class names argument on ASCII whitespace
+fn class_tokens(value: &str) -> impl Iterator<Item = &str> {
+    value.split_ascii_whitespace()
+}
+
+/// Which elements are in a collection
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    /// Elements with the qualified name, which is ASCII lowercased for HTML elements. "*"
+    /// matches all elements.
+    TagName(String),
+    /// Elements with the namespace and local name, where "*" matches any namespace or any local
+    /// name. None is the null namespace.
+    TagNameNs(Option<String>, String),
+    /// Elements that have all of the classes
+    ClassNames(Vec<String>),
+    /// HTML elements with the value of their name attribute
+    Name(String),
+}
+
+impl Filter {
+    /// Returns the candidates from the index, or None when all elements are candidates. Only
+    /// the smallest set of the classes is needed, as the filter checks the other classes.
+    fn candidates(&self, index: &ElementIndex) -> Option<Vec<NodeId>> {
+        let sets: Vec<Option<&HashSet<NodeId>>> = match self {
+            Filter::TagName(name) | Filter::TagNameNs(_, name) if name == "*" => return None,
+            Filter::TagName(name) | Filter::TagNameNs(_, name) => {
+                vec![index.names.get(&name.to_ascii_lowercase())]
+            }
+            Filter::ClassNames(classes) => classes
+                .iter()
+                .map(|class| index.classes.get(&class.to_ascii_lowercase()))
+                .collect(),
+            Filter::Name(name) => vec![index.name_attributes.get(name)],
+        };
+
+        // Without any class names, or when a set is missing, there are no candidates at all
+        let smallest = sets
+            .into_iter()
+            .min_by_key(|set| set.map_or(0, HashSet::len))
+            .flatten();
+        Some(
+            smallest
+                .map(|set| set.iter().copied().collect())
+                .unwrap_or_default(),
+        )
+    }
+
+    fn matches(&self, node: &Node, quirks_mode: QuirksMode) -> bool {
+        let NodeData::Element(element) = &node.data else {
+            return false;
+        };
+
+        match self {
+            Filter::TagName(name) if name == "*" => true,
+            Filter::TagName(name) if node.is_namespace(HTML_NAMESPACE) => {
+                node.name == name.to_ascii_lowercase()
+            }
+            Filter::TagName(name) => node.name == *name,
+            Filter::TagNameNs(namespace, local_name) => {
+                let namespace_matches = match namespace.as_deref() {
+                    Some("*") => true,
+                    namespace => node.namespace.as_deref() == namespace,
+                };
+                namespace_matches && (local_name == "*" || node.name == *local_name)
+            }
+            Filter::ClassNames(classes) => {
+                let Some(value) = element.attributes.get("class") else {
+                    return false;
+                };
+                let tokens: Vec<&str> = class_tokens(value).collect();
+                classes.iter().all(|class| {
+                    tokens.iter().any(|token| {
+                        if quirks_mode == QuirksMode::Quirks {
+                            token.eq_ignore_ascii_case(class)
+                        } else {
+                            token == class
+                        }
+                    })
+                })
+            }
+            Filter::Name(name) => {
+                node.is_namespace(HTML_NAMESPACE) && element.attributes.get("name") == Some(name)
+            }
+        }
+    }
+}
+
+/// Live list of elements, see [HTMLCollection](https://dom.spec.whatwg.org/#interface-htmlcollection).
+///
+/// The elements are the descendants of the root that match the filter of the collection, in
+/// tree order. The collection holds a handle to the document, so it does not keep the document
+/// borrowed.
+#[derive(Debug, Clone)]
+pub struct HtmlCollection {
+    document: DocumentHandle,
+    root: NodeId,
+    filter: Filter,
+    /// Elements of the collection, with the version of the index they were found with
+    cache: RefCell<Option<(u64, Vec<NodeId>)>>,
+}
+
+impl HtmlCollection {
+    fn new(document: &DocumentHandle, root: NodeId, filter: Filter) -> Self {
+        HtmlCollection {
+            document: Document::clone(document),
+            root,
+            filter,
+            cache: RefCell::new(None),
+        }
+    }
+
+    /// Returns the root of the collection
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Returns the number of elements in the collection
+    pub fn len(&self) -> usize {
+        self.with_elements(|elements| elements.len())
+    }
+
+    /// Returns true when the collection has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the element at the index in the collection
+    pub fn item(&self, index: usize) -> Option<NodeId> {
+        self.with_elements(|elements| elements.get(index).copied())
+    }
+
+    /// Returns the first element with the key as id, or HTML element with the key as name
+    /// attribute, see [namedItem()](https://dom.spec.whatwg.org/#dom-htmlcollection-nameditem)
+    pub fn named_item(&self, key: &str) -> Option<NodeId> {
+        if key.is_empty() {
+            return None;
+        }
+
+        let doc = self.document.get();
+        self.with_elements(|elements| {
+            elements.iter().copied().find(|&node_id| {
+                let Some(node) = doc.get_node_by_id(node_id) else {
+                    return false;
+                };
+                let NodeData::Element(element) = &node.data else {
+                    return false;
+                };
+                element.attributes.get("id").is_some_and(|id| id == key)
+                    || (node.is_namespace(HTML_NAMESPACE)
+                        && element
+                            .attributes
+                            .get("name")
+                            .is_some_and(|name| name == key))
+            })
+        })
+    }
+
+    /// Returns the elements that are currently in the collection
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        self.with_elements(|elements| elements.to_vec())
+    }
+
+    /// Calls the function with the elements of the collection, which are looked up again when
+    /// the document changed since the last call
+    fn with_elements<R>(&self, f: impl FnOnce(&[NodeId]) -> R) -> R {
+        let doc = self.document.get();
+        let version = doc.element_index.version;
+
+        let mut cache = self.cache.borrow_mut();
+        if !matches!(&*cache, Some((cached, _)) if *cached == version) {
+            *cache = Some((version, self.find_elements(&doc)));
+        }
+
+        let (_, elements) = cache.as_ref().expect("collection cache is set");
+        f(elements)
+    }
+
+    fn find_elements(&self, doc: &Document) -> Vec<NodeId> {
+        let quirks_mode = doc.quirks_mode;
+
+        let Some(candidates) = self.filter.candidates(&doc.element_index) else {
+            return doc
+                .descendants(self.root)
+                .filter(|node| self.filter.matches(node, quirks_mode))
+                .map(|node| node.id)
+                .collect();
+        };
+
+        let mut elements: Vec<(Vec<usize>, NodeId)> = candidates
+            .into_iter()
+            .filter(|&id| {
+                doc.get_node_by_id(id)
+                    .is_some_and(|node| self.filter.matches(node, quirks_mode))
+            })
+            .filter_map(|id| Some((tree_position(doc, self.root, id)?, id)))
+            .collect();
+        elements.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        elements.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+/// Returns the indexes of the node and its ancestors among their siblings, from the child of the
+/// root down to the node, or None when the node is not a descendant of the root. Comparing
+/// positions gives the tree order of the nodes.
+pub(crate) fn tree_position(doc: &Document, root: NodeId, node_id: NodeId) -> Option<Vec<usize>> {
+    let mut position = Vec::new();
+    let mut current = node_id;
+    while current != root {
+        let parent_id = doc.get_node_by_id(current)?.parent?;
+        let parent = doc.get_node_by_id(parent_id)?;
+        position.push(parent.children.iter().position(|&id| id == current)?);
+        current = parent_id;
+    }
+
+    if position.is_empty() {
+        return None;
+    }
+
+    position.reverse();
+    Some(position)
+}
+
+/// Collections of the document, which contain the elements in the tree of the document
+impl DocumentHandle {
+    /// Returns the elements with the qualified name, or all elements for "*"
+    pub fn get_elements_by_tag_name(&self, qualified_name: &str) -> HtmlCollection {
+        HtmlCollection::new(
+            self,
+            NodeId::root(),
+            Filter::TagName(qualified_name.to_owned()),
+        )
+    }
+
+    /// Returns the elements with the namespace and local name. An empty namespace is the null
+    /// namespace, and "*" matches any namespace or any local name.
+    pub fn get_elements_by_tag_name_ns(
+        &self,
+        namespace: Option<&str>,
+        local_name: &str,
+    ) -> HtmlCollection {
+        HtmlCollection::new(self, NodeId::root(), tag_name_ns(namespace, local_name))
+    }
+
+    /// Returns the elements that have all classes in the space separated class names
+    pub fn get_elements_by_class_name(&self, class_names: &str) -> HtmlCollection {
+        HtmlCollection::new(self, NodeId::root(), class_names_filter(class_names))
+    }
+
+    /// Returns the HTML elements with the value of their name attribute
+    pub fn get_elements_by_name(&self, name: &str) -> HtmlCollection {
+        HtmlCollection::new(self, NodeId::root(), Filter::Name(name.to_owned()))
+    }
+}
+
+/// Collections of an element, which contain the descendants of the element
+impl Element {
+    /// Returns the descendants with the qualified name, or all descendants for "*"
+    pub fn get_elements_by_tag_name(&self, qualified_name: &str) -> HtmlCollection {
+        HtmlCollection::new(
+            &self.document,
+            self.node_id,
+            Filter::TagName(qualified_name.to_owned()),
+        )
+    }
+
+    /// Returns the descendants with the namespace and local name. An empty namespace is the
+    /// null namespace, and "*" matches any namespace or any local name.
+    pub fn get_elements_by_tag_name_ns(
+        &self,
+        namespace: Option<&str>,
+        local_name: &str,
+    ) -> HtmlCollection {
+        HtmlCollection::new(
+            &self.document,
+            self.node_id,
+            tag_name_ns(namespace, local_name),
+        )
+    }
+
+    /// Returns the descendants that have all classes in the space separated class names
+    pub fn get_elements_by_class_name(&self, class_names: &str) -> HtmlCollection {
+        HtmlCollection::new(
+            &self.document,
+            self.node_id,
+            class_names_filter(class_names),
+        )
+    }
+}
+
+fn tag_name_ns(namespace: Option<&str>, local_name: &str) -> Filter {
+    let namespace = namespace.filter(|namespace| !namespace.is_empty());
+    Filter::TagNameNs(namespace.map(str::to_owned), local_name.to_owned())
+}
+
+fn class_names_filter(class_names: &str) -> Filter {
+    let mut classes: Vec<String> = Vec::new();
+    for class in class_tokens(class_names) {
+        if !classes.iter().any(|existing| existing == class) {
+            classes.push(class.to_owned());
+        }
+    }
+
+    Filter::ClassNames(classes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::CharIterator;
+    use crate::html5::node::SVG_NAMESPACE;
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::parser::Html5Parser;
+    use crate::testing::document::parse_document;
+
+    fn ids(document: &DocumentHandle, collection: &HtmlCollection) -> Vec<String> {
+        let doc = document.get();
+        collection
+            .node_ids()
+            .into_iter()
+            .map(|id| match &doc.get_node_by_id(id).unwrap().data {
+                NodeData::Element(element) => element.attributes["id"].clone(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn element(document: &DocumentHandle, id: &str) -> Element {
+        let node_id = document.get().get_node_by_named_id(id).unwrap().id;
+        Element::new(document, node_id).unwrap()
+    }
+
+    #[test]
+    fn elements_by_tag_name() {
+        let document = parse_document(
+            "<!DOCTYPE html><div id=d><p id=p1></p><span id=s><p id=p2></p></span></div><p id=p3></p>\
+             <svg id=svg><foreignObject id=f></foreignObject></svg>",
+        );
+
+        let paragraphs = document.get_elements_by_tag_name("p");
+        assert_eq!(ids(&document, &paragraphs), ["p1", "p2", "p3"]);
+        assert_eq!(document.get_elements_by_tag_name("P").len(), 3);
+
+        // Only HTML elements match case-insensitively
+        assert_eq!(
+            ids(
+                &document,
+                &document.get_elements_by_tag_name("foreignObject")
+            ),
+            ["f"]
+        );
+        assert!(document
+            .get_elements_by_tag_name("foreignobject")
+            .is_empty());
+
+        let div = element(&document, "d");
+        assert_eq!(
+            ids(&document, &div.get_elements_by_tag_name("p")),
+            ["p1", "p2"]
+        );
+        assert_eq!(
+            ids(&document, &div.get_elements_by_tag_name("*")),
+            ["p1", "s", "p2"]
+        );
+        // html, head, body and the elements in the body
+        assert_eq!(document.get_elements_by_tag_name("*").len(), 10);
+    }
+
+    #[test]
+    fn elements_by_tag_name_ns() {
+        let document = parse_document("<!DOCTYPE html><p id=p></p><svg id=svg><g id=g></g></svg>");
+
+        assert_eq!(
+            ids(
+                &document,
+                &document.get_elements_by_tag_name_ns(Some(SVG_NAMESPACE), "*")
+            ),
+            ["svg", "g"]
+        );
+        assert_eq!(
+            ids(
+                &document,
+                &document.get_elements_by_tag_name_ns(Some(HTML_NAMESPACE), "p")
+            ),
+            ["p"]
+        );
+        assert_eq!(
+            ids(
+                &document,
+                &document.get_elements_by_tag_name_ns(Some("*"), "g")
+            ),
+            ["g"]
+        );
+        assert!(document
+            .get_elements_by_tag_name_ns(Some(HTML_NAMESPACE), "g")
+            .is_empty());
+        assert!(document
+            .get_elements_by_tag_name_ns(Some(""), "p")
+            .is_empty());
+        assert!(document.get_elements_by_tag_name_ns(None, "*").is_empty());
+    }
+
+    #[test]
+    fn elements_by_class_name() {
+        let document =
+            parse_document("<!DOCTYPE html><p id=a class='one two'></p><p id=b class='two'></p><p id=c class='One'></p>");
+
+        assert_eq!(
+            ids(&document, &document.get_elements_by_class_name("two")),
+            ["a", "b"]
+        );
+        assert_eq!(
+            ids(
+                &document,
+                &document.get_elements_by_class_name(" two  one ")
+            ),
+            ["a"]
+        );
+        assert_eq!(
+            ids(&document, &document.get_elements_by_class_name("one")),
+            ["a"]
+        );
+        assert!(document.get_elements_by_class_name("one three").is_empty());
+        assert!(document.get_elements_by_class_name(" ").is_empty());
+
+        // Classes are compared case-insensitively in quirks mode. A missing doctype is the one
+        // parse error this document is expected to report.
+        let document = DocumentBuilder::new_document();
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<p id=a class=one></p><p id=b class=ONE></p>", None);
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(document.get().quirks_mode, QuirksMode::Quirks);
+        assert_eq!(
+            ids(&document, &document.get_elements_by_class_name("One")),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn elements_by_name() {
+        let document = parse_document(
+            "<!DOCTYPE html><input id=a name=field><svg><g id=g name=field></g></svg><p id=b name=field></p>",
+        );
+
+        let fields = document.get_elements_by_name("field");
+        assert_eq!(ids(&document, &fields), ["a", "b"]);
+        assert_eq!(fields.named_item("b"), fields.item(1));
+        assert_eq!(fields.named_item("field"), fields.item(0));
+        assert_eq!(fields.named_item(""), None);
+
+        element(&document, "a")
+            .set_attribute("name", "other")
+            .unwrap();
+        assert_eq!(ids(&document, &fields), ["b"]);
+        element(&document, "b").remove_attribute("name").unwrap();
+        assert!(fields.is_empty());
+        assert_eq!(
+            ids(&document, &document.get_elements_by_name("other")),
+            ["a"]
+        );
+    }
+
+    #[test]
+    fn collections_are_live() {
+        let mut document = parse_document(
+            "<!DOCTYPE html><div id=d><p id=a class=x></p></div><template><p></p></template>",
+        );
+        let paragraphs = document.get_elements_by_tag_name("p");
+        let xs = document.get_elements_by_class_name("x");
+        assert_eq!(ids(&document, &paragraphs), ["a"]);
+
+        // Inserted elements are added in tree order
+        let div_id = element(&document, "d").node_id();
+        let p_id = document.create_element("p", div_id, Some(0), HTML_NAMESPACE);
+        document.insert_attribute("id", "b", p_id).unwrap();
+        assert_eq!(ids(&document, &paragraphs), ["b", "a"]);
+        assert_eq!(ids(&document, &xs), ["a"]);
+
+        // Classes follow the class attribute
+        element(&document, "b").class_list().add(&["x"]).unwrap();
+        assert_eq!(ids(&document, &xs), ["b", "a"]);
+        element(&document, "a").set_class_name("y").unwrap();
+        assert_eq!(ids(&document, &xs), ["b"]);
+
+        // Removed elements are no longer descendants of the root
+        let a_id = element(&document, "a").node_id();
+        document.get_mut().remove_child(div_id, a_id).unwrap();
+        assert_eq!(ids(&document, &paragraphs), ["b"]);
+
+        document.get_mut().delete_node(p_id).unwrap();
+        assert!(paragraphs.is_empty());
+        assert!(xs.is_empty());
+        assert!(!document.get().element_index.classes.contains_key("x"));
+
+        document.get_mut().compact();
+        assert!(!document.get().element_index.classes.contains_key("y"));
+        assert!(!document.get().element_index.names.contains_key("p"));
+    }
+}
@@ -0,0 +1,989 @@
+//! Interfaces of specific HTML elements
+//!
+//! Each interface is a view over an [`HtmlElement`] with a given local name, and dereferences
+//! to it for the attributes that all HTML elements share.
+use crate::html5::dom::HtmlElement;
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::{NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentFragment, DocumentHandle};
+use crate::types::Result;
+use std::ops::Deref;
+
+/// Declares a view for HTML elements with the given local name
+macro_rules! html_element_interface {
+    ($(#[$meta:meta])* $interface:ident, $name:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $interface(HtmlElement);
+
+        impl $interface {
+            #[doc = concat!("Returns a view of the node, or None when the node is not a `<", $name, ">` element")]
+            pub fn new(document: &DocumentHandle, node_id: NodeId) -> Option<Self> {
+                HtmlElement::with_name(document, node_id, $name).map(Self)
+            }
+        }
+
+        impl Deref for $interface {
+            type Target = HtmlElement;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+    };
+}
+
+html_element_interface!(
+    /// View of an `<a>` element, see [HTMLAnchorElement](https://html.spec.whatwg.org/multipage/text-level-semantics.html#htmlanchorelement)
+    HtmlAnchorElement,
+    "a"
+);
+html_element_interface!(
+    /// View of an `<img>` element, see [HTMLImageElement](https://html.spec.whatwg.org/multipage/embedded-content.html#htmlimageelement)
+    HtmlImageElement,
+    "img"
+);
+html_element_interface!(
+    /// View of an `<input>` element, see [HTMLInputElement](https://html.spec.whatwg.org/multipage/input.html#htmlinputelement)
+    HtmlInputElement,
+    "input"
+);
+html_element_interface!(
+    /// View of a `<form>` element, see [HTMLFormElement](https://html.spec.whatwg.org/multipage/forms.html#htmlformelement)
+    HtmlFormElement,
+    "form"
+);
+html_element_interface!(
+    /// View of a `<select>` element, see [HTMLSelectElement](https://html.spec.whatwg.org/multipage/form-elements.html#htmlselectelement)
+    HtmlSelectElement,
+    "select"
+);
+html_element_interface!(
+    /// View of an `<option>` element, see [HTMLOptionElement](https://html.spec.whatwg.org/multipage/form-elements.html#htmloptionelement)
+    HtmlOptionElement,
+    "option"
+);
+html_element_interface!(
+    /// View of a `<table>` element, see [HTMLTableElement](https://html.spec.whatwg.org/multipage/tables.html#htmltableelement)
+    HtmlTableElement,
+    "table"
+);
+html_element_interface!(
+    /// View of a `<slot>` element, see [HTMLSlotElement](https://html.spec.whatwg.org/multipage/scripting.html#htmlslotelement)
+    HtmlSlotElement,
+    "slot"
+);
+html_element_interface!(
+    /// View of a `<template>` element, see [HTMLTemplateElement](https://html.spec.whatwg.org/multipage/scripting.html#htmltemplateelement)
+    HtmlTemplateElement,
+    "template"
+);
+
+/// Types of input elements, see [the type attribute](https://html.spec.whatwg.org/multipage/input.html#attr-input-type)
+const INPUT_TYPES: &[&str] = &[
+    "hidden",
+    "text",
+    "search",
+    "tel",
+    "url",
+    "email",
+    "password",
+    "date",
+    "month",
+    "week",
+    "time",
+    "datetime-local",
+    "number",
+    "range",
+    "color",
+    "checkbox",
+    "radio",
+    "file",
+    "submit",
+    "image",
+    "reset",
+    "button",
+];
+
+/// Elements that are listed in the elements of their form owner
+const LISTED_ELEMENTS: &[&str] = &[
+    "button", "fieldset", "input", "object", "output", "select", "textarea",
+];
+
+/// Returns the ids of the HTML element children of the node with one of the given names
+fn children_named(document: &Document, node_id: NodeId, names: &[&str]) -> Vec<NodeId> {
+    let Some(node) = document.get_node_by_id(node_id) else {
+        return Vec::new();
+    };
+
+    node.children
+        .iter()
+        .filter_map(|child| document.get_node_by_id(*child))
+        .filter(|child| {
+            matches!(child.data, NodeData::Element(_))
+                && child.is_namespace(HTML_NAMESPACE)
+                && names.contains(&child.name.as_str())
+        })
+        .map(|child| child.id)
+        .collect()
+}
+
+/// Returns the text of the descendants of the node with ASCII whitespace stripped and collapsed,
+/// leaving out scripts, see [strip and collapse ASCII whitespace](https://infra.spec.whatwg.org/#strip-and-collapse-ascii-whitespace)
+fn collapsed_text(document: &Document, node_id: NodeId) -> String {
+    let mut text = String::new();
+    for node in document.descendants(node_id) {
+        if let NodeData::Text(data) = &node.data {
+            let in_script = document
+                .ancestors(node.id)
+                .take_while(|ancestor| ancestor.id != node_id)
+                .any(|ancestor| ancestor.name == "script");
+            if !in_script {
+                text.push_str(&data.value);
+            }
+        }
+    }
+
+    text.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the [form owner](https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#form-owner)
+/// of a form-associated element
+fn form_owner(element: &HtmlElement) -> Result<Option<NodeId>> {
+    let form_id = element.get_attribute("form")?;
+    let document = element.document().get();
+
+    if let Some(form_id) = form_id {
+        let form = document.get_node_by_named_id(&form_id);
+        return Ok(form
+            .filter(|form| form.name == "form" && form.is_namespace(HTML_NAMESPACE))
+            .map(|form| form.id));
+    }
+
+    Ok(document
+        .ancestors(element.node_id())
+        .find(|ancestor| ancestor.name == "form" && ancestor.is_namespace(HTML_NAMESPACE))
+        .map(|form| form.id))
+}
+
+impl HtmlAnchorElement {
+    /// Returns the value of the href attribute
+    pub fn href(&self) -> Result<String> {
+        self.string_attribute("href")
+    }
+
+    /// Sets the value of the href attribute
+    pub fn set_href(&mut self, href: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("href", href)
+    }
+
+    /// Returns the browsing context in which the link is opened
+    pub fn target(&self) -> Result<String> {
+        self.string_attribute("target")
+    }
+
+    /// Returns the file name for downloading the link target
+    pub fn download(&self) -> Result<String> {
+        self.string_attribute("download")
+    }
+
+    /// Returns the relationship of the link target to the document
+    pub fn rel(&self) -> Result<String> {
+        self.string_attribute("rel")
+    }
+
+    /// Returns the language of the link target
+    pub fn hreflang(&self) -> Result<String> {
+        self.string_attribute("hreflang")
+    }
+
+    /// Returns the MIME type of the link target, from the type attribute
+    pub fn mime_type(&self) -> Result<String> {
+        self.string_attribute("type")
+    }
+
+    /// Returns the text of the link
+    pub fn text(&self) -> String {
+        collapsed_text(&self.document().get(), self.node_id())
+    }
+}
+
+impl HtmlImageElement {
+    /// Returns the value of the src attribute
+    pub fn src(&self) -> Result<String> {
+        self.string_attribute("src")
+    }
+
+    /// Sets the value of the src attribute
+    pub fn set_src(&mut self, src: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("src", src)
+    }
+
+    /// Returns the alternative text of the image
+    pub fn alt(&self) -> Result<String> {
+        self.string_attribute("alt")
+    }
+
+    /// Sets the alternative text of the image
+    pub fn set_alt(&mut self, alt: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("alt", alt)
+    }
+
+    /// Returns the value of the srcset attribute
+    pub fn srcset(&self) -> Result<String> {
+        self.string_attribute("srcset")
+    }
+
+    /// Returns the value of the sizes attribute
+    pub fn sizes(&self) -> Result<String> {
+        self.string_attribute("sizes")
+    }
+
+    /// Returns the CORS setting of the image: "anonymous", "use-credentials" or None
+    pub fn cross_origin(&self) -> Result<Option<String>> {
+        if !self.has_attribute("crossorigin")? {
+            return Ok(None);
+        }
+
+        self.enumerated_attribute(
+            "crossorigin",
+            &["anonymous", "use-credentials"],
+            "",
+            "anonymous",
+        )
+        .map(Some)
+    }
+
+    /// Returns the name of the image map of the image
+    pub fn use_map(&self) -> Result<String> {
+        self.string_attribute("usemap")
+    }
+
+    /// Returns true when the image is part of a server-side image map
+    pub fn is_map(&self) -> Result<bool> {
+        self.bool_attribute("ismap")
+    }
+
+    /// Returns the width from the width attribute, or 0 when it is not set
+    pub fn width(&self) -> Result<u32> {
+        self.unsigned_attribute("width", 0)
+    }
+
+    /// Sets the width attribute
+    pub fn set_width(&mut self, width: u32) -> Result<()> {
+        self.0
+            .element_mut()
+            .set_attribute("width", &width.to_string())
+    }
+
+    /// Returns the height from the height attribute, or 0 when it is not set
+    pub fn height(&self) -> Result<u32> {
+        self.unsigned_attribute("height", 0)
+    }
+
+    /// Sets the height attribute
+    pub fn set_height(&mut self, height: u32) -> Result<()> {
+        self.0
+            .element_mut()
+            .set_attribute("height", &height.to_string())
+    }
+
+    /// Returns the loading mode of the image: "lazy" or "eager"
+    pub fn loading(&self) -> Result<String> {
+        self.enumerated_attribute("loading", &["lazy", "eager"], "eager", "eager")
+    }
+
+    /// Returns the decoding hint of the image: "sync", "async" or "auto"
+    pub fn decoding(&self) -> Result<String> {
+        self.enumerated_attribute("decoding", &["sync", "async", "auto"], "auto", "auto")
+    }
+}
+
+impl HtmlInputElement {
+    /// Returns the type of the input, which is "text" when it is not set or unknown
+    pub fn input_type(&self) -> Result<String> {
+        self.enumerated_attribute("type", INPUT_TYPES, "text", "text")
+    }
+
+    /// Sets the type of the input
+    pub fn set_input_type(&mut self, input_type: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("type", input_type)
+    }
+
+    /// Returns the name of the input
+    pub fn name(&self) -> Result<String> {
+        self.string_attribute("name")
+    }
+
+    /// Sets the name of the input
+    pub fn set_name(&mut self, name: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("name", name)
+    }
+
+    /// Returns the default value of the input, from the value attribute
+    pub fn default_value(&self) -> Result<String> {
+        self.string_attribute("value")
+    }
+
+    /// Sets the default value of the input
+    pub fn set_default_value(&mut self, value: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("value", value)
+    }
+
+    /// Returns true when the checkbox or radio button is checked by default
+    pub fn default_checked(&self) -> Result<bool> {
+        self.bool_attribute("checked")
+    }
+
+    /// Sets whether the checkbox or radio button is checked by default
+    pub fn set_default_checked(&mut self, checked: bool) -> Result<()> {
+        self.0.element_mut().set_bool_attribute("checked", checked)
+    }
+
+    /// Returns true when the input has the disabled attribute
+    pub fn disabled(&self) -> Result<bool> {
+        self.bool_attribute("disabled")
+    }
+
+    /// Sets or removes the disabled attribute
+    pub fn set_disabled(&mut self, disabled: bool) -> Result<()> {
+        self.0
+            .element_mut()
+            .set_bool_attribute("disabled", disabled)
+    }
+
+    /// Returns true when the input is required
+    pub fn required(&self) -> Result<bool> {
+        self.bool_attribute("required")
+    }
+
+    /// Returns true when the input is read-only
+    pub fn read_only(&self) -> Result<bool> {
+        self.bool_attribute("readonly")
+    }
+
+    /// Returns true when the input accepts multiple values
+    pub fn multiple(&self) -> Result<bool> {
+        self.bool_attribute("multiple")
+    }
+
+    /// Returns the placeholder of the input
+    pub fn placeholder(&self) -> Result<String> {
+        self.string_attribute("placeholder")
+    }
+
+    /// Returns the minimum value of the input
+    pub fn min(&self) -> Result<String> {
+        self.string_attribute("min")
+    }
+
+    /// Returns the maximum value of the input
+    pub fn max(&self) -> Result<String> {
+        self.string_attribute("max")
+    }
+
+    /// Returns the step of the input
+    pub fn step(&self) -> Result<String> {
+        self.string_attribute("step")
+    }
+
+    /// Returns the pattern the value of the input must match
+    pub fn pattern(&self) -> Result<String> {
+        self.string_attribute("pattern")
+    }
+
+    /// Returns the maximum length of the value, or -1 when there is none
+    pub fn max_length(&self) -> Result<i32> {
+        Ok(self.integer_attribute("maxlength", -1)?.max(-1))
+    }
+
+    /// Returns the minimum length of the value, or -1 when there is none
+    pub fn min_length(&self) -> Result<i32> {
+        Ok(self.integer_attribute("minlength", -1)?.max(-1))
+    }
+
+    /// Returns the number of characters that are visible in the input, which is 20 by default
+    pub fn size(&self) -> Result<u32> {
+        match self.unsigned_attribute("size", 20)? {
+            0 => Ok(20),
+            size => Ok(size),
+        }
+    }
+
+    /// Returns the autofill hint of the input
+    pub fn autocomplete(&self) -> Result<String> {
+        self.string_attribute("autocomplete")
+    }
+
+    /// Returns the form the input belongs to
+    pub fn form(&self) -> Result<Option<NodeId>> {
+        form_owner(self)
+    }
+}
+
+impl HtmlFormElement {
+    /// Returns the URL the form is submitted to
+    pub fn action(&self) -> Result<String> {
+        self.string_attribute("action")
+    }
+
+    /// Sets the URL the form is submitted to
+    pub fn set_action(&mut self, action: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("action", action)
+    }
+
+    /// Returns the method of submitting the form: "get", "post" or "dialog"
+    pub fn method(&self) -> Result<String> {
+        self.enumerated_attribute("method", &["get", "post", "dialog"], "get", "get")
+    }
+
+    /// Sets the method of submitting the form
+    pub fn set_method(&mut self, method: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("method", method)
+    }
+
+    /// Returns the encoding type of the submitted form data
+    pub fn enctype(&self) -> Result<String> {
+        const URL_ENCODED: &str = "application/x-www-form-urlencoded";
+        self.enumerated_attribute(
+            "enctype",
+            &[URL_ENCODED, "multipart/form-data", "text/plain"],
+            URL_ENCODED,
+            URL_ENCODED,
+        )
+    }
+
+    /// Returns the character encodings used for submitting the form
+    pub fn accept_charset(&self) -> Result<String> {
+        self.string_attribute("accept-charset")
+    }
+
+    /// Returns whether the form fields may be autofilled: "on" or "off"
+    pub fn autocomplete(&self) -> Result<String> {
+        self.enumerated_attribute("autocomplete", &["on", "off"], "on", "on")
+    }
+
+    /// Returns the name of the form
+    pub fn name(&self) -> Result<String> {
+        self.string_attribute("name")
+    }
+
+    /// Returns true when the form is not validated on submission
+    pub fn no_validate(&self) -> Result<bool> {
+        self.bool_attribute("novalidate")
+    }
+
+    /// Returns the browsing context in which the response is shown
+    pub fn target(&self) -> Result<String> {
+        self.string_attribute("target")
+    }
+
+    /// Returns the listed elements of which the form is the form owner, in tree order. Image
+    /// buttons are not included.
+    pub fn elements(&self) -> Result<Vec<NodeId>> {
+        let document = Document::clone(self.document());
+        let candidates: Vec<NodeId> = {
+            let doc = document.get();
+            doc.descendants(NodeId::root())
+                .filter(|node| {
+                    node.is_namespace(HTML_NAMESPACE)
+                        && LISTED_ELEMENTS.contains(&node.name.as_str())
+                })
+                .map(|node| node.id)
+                .collect()
+        };
+
+        let mut elements = Vec::new();
+        for element in candidates
+            .into_iter()
+            .filter_map(|node_id| HtmlElement::new(&document, node_id))
+        {
+            let is_image_button = element.local_name()? == "input"
+                && element
+                    .get_attribute("type")?
+                    .is_some_and(|t| t.eq_ignore_ascii_case("image"));
+            if !is_image_button && form_owner(&element)? == Some(self.node_id()) {
+                elements.push(element.node_id());
+            }
+        }
+
+        Ok(elements)
+    }
+
+    /// Returns the number of elements of the form
+    pub fn length(&self) -> Result<usize> {
+        Ok(self.elements()?.len())
+    }
+}
+
+impl HtmlSelectElement {
+    /// Returns the name of the select
+    pub fn name(&self) -> Result<String> {
+        self.string_attribute("name")
+    }
+
+    /// Returns true when multiple options can be selected
+    pub fn multiple(&self) -> Result<bool> {
+        self.bool_attribute("multiple")
+    }
+
+    /// Returns true when the select has the disabled attribute
+    pub fn disabled(&self) -> Result<bool> {
+        self.bool_attribute("disabled")
+    }
+
+    /// Returns true when the select is required
+    pub fn required(&self) -> Result<bool> {
+        self.bool_attribute("required")
+    }
+
+    /// Returns the number of visible options, or 0 when it is not set
+    pub fn size(&self) -> Result<u32> {
+        self.unsigned_attribute("size", 0)
+    }
+
+    /// Returns the form the select belongs to
+    pub fn form(&self) -> Result<Option<NodeId>> {
+        form_owner(self)
+    }
+
+    /// Returns the [list of options](https://html.spec.whatwg.org/multipage/form-elements.html#concept-select-option-list):
+    /// the option children and the option children of optgroup children, in tree order
+    pub fn options(&self) -> Vec<NodeId> {
+        let document = self.document().get();
+        let mut options = Vec::new();
+        for child in children_named(&document, self.node_id(), &["option", "optgroup"]) {
+            match document.get_node_by_id(child) {
+                Some(node) if node.name == "optgroup" => {
+                    options.extend(children_named(&document, child, &["option"]))
+                }
+                _ => options.push(child),
+            }
+        }
+
+        options
+    }
+
+    /// Returns the number of options
+    pub fn length(&self) -> usize {
+        self.options().len()
+    }
+
+    /// Returns the selected options. Without user interaction, these follow from the selected
+    /// attributes, see [selectedness setting algorithm](https://html.spec.whatwg.org/multipage/form-elements.html#selectedness-setting-algorithm).
+    pub fn selected_options(&self) -> Result<Vec<NodeId>> {
+        let options: Vec<HtmlOptionElement> = self
+            .options()
+            .into_iter()
+            .filter_map(|option| HtmlOptionElement::new(self.document(), option))
+            .collect();
+        let mut selected = Vec::new();
+        for option in &options {
+            if option.default_selected()? {
+                selected.push(option.node_id());
+            }
+        }
+
+        if self.multiple()? || self.size()? > 1 {
+            return Ok(selected);
+        }
+
+        if let Some(last) = selected.last() {
+            return Ok(vec![*last]);
+        }
+        for option in &options {
+            if !option.disabled()? {
+                return Ok(vec![option.node_id()]);
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Returns the index of the first selected option, or -1 when no option is selected
+    pub fn selected_index(&self) -> Result<i32> {
+        let Some(selected) = self.selected_options()?.first().copied() else {
+            return Ok(-1);
+        };
+
+        Ok(self
+            .options()
+            .iter()
+            .position(|option| *option == selected)
+            .map_or(-1, |index| index as i32))
+    }
+
+    /// Returns the value of the first selected option, or an empty string
+    pub fn value(&self) -> Result<String> {
+        match self
+            .selected_options()?
+            .first()
+            .and_then(|option| HtmlOptionElement::new(self.document(), *option))
+        {
+            Some(option) => option.value(),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+impl HtmlOptionElement {
+    /// Returns the select element the option belongs to
+    fn select(&self) -> Option<HtmlSelectElement> {
+        let document = self.document().get();
+        let parent = document.get_node_by_id(self.node_id())?.parent?;
+        let parent_node = document.get_node_by_id(parent)?;
+        let select = match parent_node.name.as_str() {
+            "optgroup" => parent_node.parent?,
+            _ => parent,
+        };
+        drop(document);
+
+        HtmlSelectElement::new(self.document(), select)
+    }
+
+    /// Returns true when the option or its optgroup is disabled
+    pub fn disabled(&self) -> Result<bool> {
+        if self.bool_attribute("disabled")? {
+            return Ok(true);
+        }
+
+        let document = self.document().get();
+        Ok(document
+            .get_node_by_id(self.node_id())
+            .and_then(|node| document.get_node_by_id(node.parent?))
+            .is_some_and(|parent| match &parent.data {
+                NodeData::Element(element) if parent.name == "optgroup" => {
+                    element.attributes.contains_key("disabled")
+                }
+                _ => false,
+            }))
+    }
+
+    /// Sets or removes the disabled attribute
+    pub fn set_disabled(&mut self, disabled: bool) -> Result<()> {
+        self.0
+            .element_mut()
+            .set_bool_attribute("disabled", disabled)
+    }
+
+    /// Returns the label of the option, which is its text when there is no label attribute
+    pub fn label(&self) -> Result<String> {
+        Ok(self.get_attribute("label")?.unwrap_or_else(|| self.text()))
+    }
+
+    /// Returns the value of the option, which is its text when there is no value attribute
+    pub fn value(&self) -> Result<String> {
+        Ok(self.get_attribute("value")?.unwrap_or_else(|| self.text()))
+    }
+
+    /// Sets the value attribute of the option
+    pub fn set_value(&mut self, value: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("value", value)
+    }
+
+    /// Returns the text of the option with whitespace collapsed
+    pub fn text(&self) -> String {
+        collapsed_text(&self.document().get(), self.node_id())
+    }
+
+    /// Returns true when the option is selected by default
+    pub fn default_selected(&self) -> Result<bool> {
+        self.bool_attribute("selected")
+    }
+
+    /// Sets whether the option is selected by default
+    pub fn set_default_selected(&mut self, selected: bool) -> Result<()> {
+        self.0
+            .element_mut()
+            .set_bool_attribute("selected", selected)
+    }
+
+    /// Returns true when the option is selected in its select element
+    pub fn selected(&self) -> Result<bool> {
+        match self.select() {
+            Some(select) => Ok(select.selected_options()?.contains(&self.node_id())),
+            None => self.default_selected(),
+        }
+    }
+
+    /// Returns the index of the option in its select element, or 0 when there is none
+    pub fn index(&self) -> usize {
+        self.select()
+            .and_then(|select| {
+                select
+                    .options()
+                    .iter()
+                    .position(|option| *option == self.node_id())
+            })
+            .unwrap_or(0)
+    }
+}
+
+impl HtmlTableElement {
+    /// Returns the first caption child of the table
+    pub fn caption(&self) -> Option<NodeId> {
+        let document = self.document().get();
+        children_named(&document, self.node_id(), &["caption"])
+            .first()
+            .copied()
+    }
+
+    /// Returns the first thead child of the table
+    pub fn t_head(&self) -> Option<NodeId> {
+        let document = self.document().get();
+        children_named(&document, self.node_id(), &["thead"])
+            .first()
+            .copied()
+    }
+
+    /// Returns the first tfoot child of the table
+    pub fn t_foot(&self) -> Option<NodeId> {
+        let document = self.document().get();
+        children_named(&document, self.node_id(), &["tfoot"])
+            .first()
+            .copied()
+    }
+
+    /// Returns the tbody children of the table
+    pub fn t_bodies(&self) -> Vec<NodeId> {
+        let document = self.document().get();
+        children_named(&document, self.node_id(), &["tbody"])
+    }
+
+    /// Returns the [rows](https://html.spec.whatwg.org/multipage/tables.html#dom-table-rows) of
+    /// the table: the rows of the headers first, then the rows of the bodies and the rows that
+    /// are children of the table, and the rows of the footers last
+    pub fn rows(&self) -> Vec<NodeId> {
+        let document = self.document().get();
+        let sections = children_named(
+            &document,
+            self.node_id(),
+            &["thead", "tbody", "tfoot", "tr"],
+        );
+
+        let rows_in = |section_name: &str| -> Vec<NodeId> {
+            sections
+                .iter()
+                .filter_map(|section| document.get_node_by_id(*section))
+                .flat_map(|section| match section.name.as_str() {
+                    "tr" if section_name == "tbody" => vec![section.id],
+                    name if name == section_name => children_named(&document, section.id, &["tr"]),
+                    _ => Vec::new(),
+                })
+                .collect()
+        };
+
+        let mut rows = rows_in("thead");
+        rows.extend(rows_in("tbody"));
+        rows.extend(rows_in("tfoot"));
+        rows
+    }
+}
+
+impl HtmlSlotElement {
+    /// Returns the name of the slot, which children of the host refer to with their slot attribute
+    pub fn name(&self) -> Result<String> {
+        self.string_attribute("name")
+    }
+
+    /// Sets the name of the slot
+    pub fn set_name(&mut self, name: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("name", name)
+    }
+
+    /// Returns the nodes assigned to the slot. With flatten, nested slots are replaced by their
+    /// assigned nodes, and the children of the slot are returned when nothing is assigned.
+    pub fn assigned_nodes(&self, flatten: bool) -> Vec<NodeId> {
+        self.document()
+            .get()
+            .assigned_nodes(self.node_id(), flatten)
+    }
+
+    /// Returns the elements among the assigned nodes
+    pub fn assigned_elements(&self, flatten: bool) -> Vec<NodeId> {
+        let document = self.document().get();
+        document
+            .assigned_nodes(self.node_id(), flatten)
+            .into_iter()
+            .filter(|node_id| {
+                document
+                    .get_node_by_id(*node_id)
+                    .is_some_and(|node| matches!(node.data, NodeData::Element(_)))
+            })
+            .collect()
+    }
+
+    /// Assigns the nodes to the slot when the shadow tree uses manual slot assignment
+    pub fn assign(&mut self, nodes: &[NodeId]) -> Result<()> {
+        Document::clone(self.document())
+            .get_mut()
+            .assign_slot(self.node_id(), nodes)
+    }
+}
+
+impl HtmlTemplateElement {
+    /// Returns the contents of the template. The fragment shares the inert document that holds
+    /// the contents with the template.
+    pub fn content(&self) -> Option<DocumentFragment> {
+        let document = self.document().get();
+        match &document.get_node_by_id(self.node_id())?.data {
+            NodeData::Element(element) => element.template_contents.clone(),
+            _ => None,
+        }
+    }
+
+    /// Returns the mode of the declarative shadow root: "open", "closed" or empty
+    pub fn shadow_root_mode(&self) -> Result<String> {
+        self.enumerated_attribute("shadowrootmode", &["open", "closed"], "", "")
+    }
+
+    /// Sets the mode of the declarative shadow root
+    pub fn set_shadow_root_mode(&mut self, mode: &str) -> Result<()> {
+        self.0.element_mut().set_attribute("shadowrootmode", mode)
+    }
+
+    /// Returns true when the declarative shadow root delegates focus
+    pub fn shadow_root_delegates_focus(&self) -> Result<bool> {
+        self.bool_attribute("shadowrootdelegatesfocus")
+    }
+
+    /// Returns true when the declarative shadow root is clonable
+    pub fn shadow_root_clonable(&self) -> Result<bool> {
+        self.bool_attribute("shadowrootclonable")
+    }
+
+    /// Returns true when the declarative shadow root is serializable
+    pub fn shadow_root_serializable(&self) -> Result<bool> {
+        self.bool_attribute("shadowrootserializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::document::parse_document;
+
+    fn id(document: &DocumentHandle, id: &str) -> NodeId {
+        document.get().get_node_by_named_id(id).unwrap().id
+    }
+
+    #[test]
+    fn anchor_and_image() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><a id="a" href="/home" rel="next">  Go
+            <b>home</b></a><img id="img" src="a.png" width="20px" height="-3" crossorigin="x">"#,
+        );
+        let mut a = HtmlAnchorElement::new(&document, id(&document, "a")).unwrap();
+        assert_eq!(a.href().unwrap(), "/home");
+        assert_eq!(a.rel().unwrap(), "next");
+        assert_eq!(a.text(), "Go home");
+        a.set_href("/away").unwrap();
+        assert_eq!(a.get_attribute("href").unwrap().as_deref(), Some("/away"));
+        assert!(HtmlImageElement::new(&document, a.node_id()).is_none());
+
+        let img = HtmlImageElement::new(&document, id(&document, "img")).unwrap();
+        assert_eq!(img.src().unwrap(), "a.png");
+        assert_eq!(img.width().unwrap(), 20);
+        assert_eq!(img.height().unwrap(), 0);
+        assert_eq!(img.cross_origin().unwrap().as_deref(), Some("anonymous"));
+        assert_eq!(img.loading().unwrap(), "eager");
+    }
+
+    #[test]
+    fn forms() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><form id="f" method="POST"><input id="i" type="Checkbox" checked maxlength="-5">
+            <input id="img" type="image"><fieldset id="fs"></fieldset></form>
+            <input id="outside"><input id="linked" form="f" size="0"><select id="s" form="f"></select>"#,
+        );
+        let form = HtmlFormElement::new(&document, id(&document, "f")).unwrap();
+        assert_eq!(form.method().unwrap(), "post");
+        assert_eq!(form.enctype().unwrap(), "application/x-www-form-urlencoded");
+        assert_eq!(
+            form.elements().unwrap(),
+            [
+                id(&document, "i"),
+                id(&document, "fs"),
+                id(&document, "linked"),
+                id(&document, "s")
+            ]
+        );
+
+        let input = HtmlInputElement::new(&document, id(&document, "i")).unwrap();
+        assert_eq!(input.input_type().unwrap(), "checkbox");
+        assert!(input.default_checked().unwrap());
+        assert_eq!(input.max_length().unwrap(), -1);
+        assert_eq!(input.size().unwrap(), 20);
+        assert_eq!(input.form().unwrap(), Some(form.node_id()));
+
+        let outside = HtmlInputElement::new(&document, id(&document, "outside")).unwrap();
+        assert_eq!(outside.input_type().unwrap(), "text");
+        assert_eq!(outside.form().unwrap(), None);
+    }
+
+    #[test]
+    fn select_and_options() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><select id="s"><option id="o1" disabled>  One  </option>
+            <optgroup disabled><option id="o2" value="2">Two</option></optgroup>
+            <option id="o3" label="Three">3</option></select>
+            <select id="m" multiple><option selected>a</option><option selected>b</option></select>"#,
+        );
+        let mut select = HtmlSelectElement::new(&document, id(&document, "s")).unwrap();
+        let options = [
+            id(&document, "o1"),
+            id(&document, "o2"),
+            id(&document, "o3"),
+        ];
+        assert_eq!(select.options(), options);
+        // The first option is disabled, and so is the second by its optgroup
+        assert_eq!(select.selected_options().unwrap(), [options[2]]);
+        assert_eq!(select.selected_index().unwrap(), 2);
+        assert_eq!(select.value().unwrap(), "3");
+
+        let mut two = HtmlOptionElement::new(&document, options[1]).unwrap();
+        assert!(two.disabled().unwrap());
+        assert_eq!(two.index(), 1);
+        two.set_default_selected(true).unwrap();
+        assert!(two.selected().unwrap());
+        assert_eq!(select.value().unwrap(), "2");
+
+        let one = HtmlOptionElement::new(&document, options[0]).unwrap();
+        assert_eq!(one.text(), "One");
+        assert_eq!(one.label().unwrap(), "One");
+        assert!(!one.selected().unwrap());
+
+        select = HtmlSelectElement::new(&document, id(&document, "m")).unwrap();
+        assert_eq!(select.selected_options().unwrap().len(), 2);
+        assert_eq!(select.selected_index().unwrap(), 0);
+    }
+
+    #[test]
+    fn table() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><table id="t"><tfoot><tr id="r4"></tr></tfoot><caption>c</caption>
+            <tbody><tr id="r2"></tr></tbody><thead><tr id="r1"></tr></thead>
+            <tr id="r3"></tr></table>"#,
+        );
+        let table = HtmlTableElement::new(&document, id(&document, "t")).unwrap();
+        assert!(table.caption().is_some());
+        assert!(table.t_head().is_some());
+        assert!(table.t_foot().is_some());
+        // The parser wraps the last row in its own tbody
+        assert_eq!(table.t_bodies().len(), 2);
+        let rows: Vec<NodeId> = ["r1", "r2", "r3", "r4"]
+            .iter()
+            .map(|row| id(&document, row))
+            .collect();
+        assert_eq!(table.rows(), rows);
+    }
+
+    #[test]
+    fn template() {
+        let document =
+            parse_document(r#"<!DOCTYPE html><template id="t" shadowrootmode="OPEN"></template>"#);
+        let template = HtmlTemplateElement::new(&document, id(&document, "t")).unwrap();
+        assert_eq!(template.shadow_root_mode().unwrap(), "open");
+        assert!(!template.shadow_root_delegates_focus().unwrap());
+    }
+}
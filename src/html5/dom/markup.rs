@@ -0,0 +1,315 @@
+//! Markup of elements
+//!
+//! Implements [innerHTML](https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml),
+//! [outerHTML](https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-outerhtml)
+//! and [insertAdjacentHTML()](https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-insertadjacenthtml)
+//! on elements. The getters use the [`Html5Serializer`] with its default options.
+//!
+//! Markup is parsed with the [fragment parsing algorithm](https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments),
+//! with the element or its parent as the context element. The parser builds the nodes in a
+//! document of its own, from which they are copied into the document of the element. Like in
+//! browsers, templates in the markup do not attach declarative shadow roots.
+use crate::bytes::CharIterator;
+use crate::html5::dom::Element;
+use crate::html5::node::NodeTrait;
+use crate::html5::node::{Node, NodeData, NodeId, NodeType, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use crate::html5::parser::tree_builder::TreeBuilder;
+use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+use crate::html5::serializer::Html5Serializer;
+use crate::types::{DomError, Result};
+
+impl Element {
+    /// Returns the HTML serialization of the children of the element. For a template element,
+    /// its template contents are serialized.
+    pub fn inner_html(&self) -> String {
+        Html5Serializer::serialize_children(&self.document, self.node_id, None)
+    }
+
+    /// Replaces the children of the element with the nodes parsed from the markup. For a
+    /// template element, its template contents are replaced.
+    pub fn set_inner_html(&mut self, html: &str) -> Result<()> {
+        let is_template = self.is_html()? && self.local_name()? == "template";
+        let contents = if is_template {
+            self.document.get().template_contents(self.node_id).cloned()
+        } else {
+            None
+        };
+        if let Some(mut contents) = contents {
+            let mut inert = contents.contents();
+            let nodes = self.parse_fragment(self.node_id, html, &inert)?;
+            contents.clear();
+            let mut inert = inert.get_mut();
+            for node_id in nodes {
+                inert.attach_node_to_parent(node_id, NodeId::root(), None);
+            }
+            return Ok(());
+        }
+
+        let nodes = self.parse_fragment(self.node_id, html, &self.document)?;
+        let mut doc = self.document.get_mut();
+        let children = doc.dom_node(self.node_id)?.children.clone();
+        for child_id in children {
+            doc.delete_node(child_id)?;
+        }
+        for node_id in nodes {
+            doc.append_child(self.node_id, node_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the HTML serialization of the element and its children
+    pub fn outer_html(&self) -> String {
+        Html5Serializer::serialize_node(&self.document, self.node_id, None)
+    }
+
+    /// Replaces the element with the nodes parsed from the markup. Nothing happens when the
+    /// element has no parent. The element and its subtree are deleted from the document, so
+    /// this handle no longer refers to a node afterwards.
+    pub fn set_outer_html(&mut self, html: &str) -> Result<()> {
+        let Some(parent_id) = self.parent_id()? else {
+            return Ok(());
+        };
+
+        let nodes = self.parse_fragment(parent_id, html, &self.document)?;
+        let mut doc = self.document.get_mut();
+        doc.replace_with(self.node_id, &nodes)?;
+        doc.delete_node(self.node_id)
+    }
+
+    /// Parses the markup and inserts the nodes at the given position, which is one of
+    /// "beforebegin", "afterbegin", "beforeend" or "afterend" (case-insensitive)
+    pub fn insert_adjacent_html(&mut self, position: &str, html: &str) -> Result<()> {
+        let position = position.to_ascii_lowercase();
+        let context_id = match position.as_str() {
+            "beforebegin" | "afterend" => match self.parent_id()? {
+                Some(parent_id) => parent_id,
+                None => {
+                    return Err(DomError::NoModificationAllowed(
+                        "the element has no parent element".into(),
+                    )
+                    .into())
+                }
+            },
+            "afterbegin" | "beforeend" => self.node_id,
+            _ => {
+                return Err(
+                    DomError::Syntax(format!("invalid insert position '{position}'")).into(),
+                )
+            }
+        };
+
+        let nodes = self.parse_fragment(context_id, html, &self.document)?;
+
+        let mut doc = self.document.get_mut();
+        match position.as_str() {
+            "beforebegin" => doc.before(self.node_id, &nodes)?,
+            "afterend" => doc.after(self.node_id, &nodes)?,
+            "afterbegin" => {
+                let first_child = doc.dom_node(self.node_id)?.children.first().copied();
+                for node_id in nodes {
+                    doc.insert_before(self.node_id, node_id, first_child)?;
+                }
+            }
+            _ => {
+                for node_id in nodes {
+                    doc.append_child(self.node_id, node_id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the parent of the element. The children of the document cannot be replaced with
+    /// markup, so that returns an error.
+    fn parent_id(&self) -> Result<Option<NodeId>> {
+        let doc = self.document.get();
+        let Some(parent_id) = doc.dom_node(self.node_id)?.parent else {
+            return Ok(None);
+        };
+
+        if doc.dom_node(parent_id)?.type_of() == NodeType::Document {
+            return Err(DomError::NoModificationAllowed(
+                "the children of the document cannot be set with markup".into(),
+            )
+            .into());
+        }
+
+        Ok(Some(parent_id))
+    }
+
+    /// Parses the markup with the given node as context element, and copies the resulting nodes
+    /// into the given document, which is the document of this element or the inert document of
+    /// its template contents. The copies have no parent. When the node is not an element, or it
+    /// is the html element, a new body element is used as context instead.
+    fn parse_fragment(
+        &self,
+        context_id: NodeId,
+        html: &str,
+        document: &DocumentHandle,
+    ) -> Result<Vec<NodeId>> {
+        let context = self.fragment_context(context_id)?;
+        let fragment = DocumentBuilder::new_document_fragment(context.clone());
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, None);
+        let options = Html5ParserOptions {
+            allow_declarative_shadow_roots: false,
+            ..Default::default()
+        };
+        Html5Parser::parse_fragment(
+            &mut chars,
+            Document::clone(&fragment),
+            &context,
+            Some(options),
+        )?;
+
+        // The root of the fragment document is the html element that holds the parsed nodes
+        let source = fragment.get();
+        let mut document = Document::clone(document);
+        let mut doc = document.get_mut();
+        source
+            .get_root()
+            .children
+            .iter()
+            .map(|&node_id| doc.import_node(&*source, node_id))
+            .collect()
+    }
+
+    /// Returns the context element for parsing markup that is inserted into the given node
+    fn fragment_context(&self, node_id: NodeId) -> Result<Node> {
+        let doc = self.document.get();
+        let node = doc.dom_node(node_id)?;
+        if matches!(node.data, NodeData::Element(_))
+            && !(node.name == "html" && node.is_namespace(HTML_NAMESPACE))
+        {
+            return Ok(node.clone());
+        }
+
+        // The body element is created in a document of its own, as the parser looks up the
+        // context element in its document
+        let mut body_document = DocumentBuilder::new_document();
+        body_document.get_mut().quirks_mode = doc.quirks_mode;
+        let body_id = body_document.create_element("body", NodeId::root(), None, HTML_NAMESPACE);
+        let body = body_document.get().dom_node(body_id)?.clone();
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5::dom::Element;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::testing::document::parse_document;
+    use crate::types::{DomError, Error};
+
+    fn element(document: &DocumentHandle, selectors: &str) -> Element {
+        let node_id = document.get().query_selector(selectors).unwrap().unwrap();
+        Element::new(document, node_id).unwrap()
+    }
+
+    #[test]
+    fn inner_html() {
+        let document =
+            parse_document("<!DOCTYPE html><div id=a><p>old</p></div><table id=t></table>");
+        let mut div = element(&document, "#a");
+        assert_eq!(div.inner_html(), "<p>old</p>");
+        let p = document.get().query_selector("p").unwrap().unwrap();
+
+        div.set_inner_html("<b class=x>bold</b> &amp; text")
+            .unwrap();
+        assert_eq!(div.inner_html(), "<b class=\"x\">bold</b> &amp; text");
+        assert!(document.get().query_selector("p").unwrap().is_none());
+        assert!(document.get().get_node_by_id(p).is_none());
+        assert!(document.get().query_selector("#a > b.x").unwrap().is_some());
+
+        // The element is the context, so rows are parsed as in a table
+        let mut table = element(&document, "#t");
+        table.set_inner_html("<tr><td>cell</td></tr>").unwrap();
+        assert_eq!(table.inner_html(), "<tbody><tr><td>cell</td></tr></tbody>");
+
+        div.set_inner_html("").unwrap();
+        assert_eq!(div.inner_html(), "");
+    }
+
+    #[test]
+    fn inner_html_of_template() {
+        let document = parse_document("<!DOCTYPE html><template id=t><p>old</p></template>");
+        let mut template = element(&document, "#t");
+        assert_eq!(template.inner_html(), "<p>old</p>");
+
+        template.set_inner_html("<span>new</span>").unwrap();
+        assert_eq!(template.inner_html(), "<span>new</span>");
+        let doc = document.get();
+        let contents = doc.template_contents(template.node_id()).unwrap();
+        assert_eq!(contents.children().len(), 1);
+        assert!(contents.query_selector("span").unwrap().is_some());
+        assert!(doc.query_selector("span").unwrap().is_none());
+    }
+
+    #[test]
+    fn outer_html() {
+        let document = parse_document("<!DOCTYPE html><div id=a><b>x</b></div><p id=b>y</p>");
+        let mut div = element(&document, "#a");
+        assert_eq!(div.outer_html(), "<div id=\"a\"><b>x</b></div>");
+        let b = document.get().query_selector("b").unwrap().unwrap();
+
+        div.set_outer_html("<span>one</span>two").unwrap();
+        let body = element(&document, "body");
+        assert_eq!(body.inner_html(), "<span>one</span>two<p id=\"b\">y</p>");
+        assert!(document.get().get_node_by_id(div.node_id()).is_none());
+        assert!(document.get().get_node_by_id(b).is_none());
+        assert!(matches!(div.id(), Err(Error::Dom(DomError::NotFound(_)))));
+
+        let mut html = element(&document, "html");
+        assert!(matches!(
+            html.set_outer_html("<html></html>"),
+            Err(Error::Dom(DomError::NoModificationAllowed(_)))
+        ));
+    }
+
+    #[test]
+    fn insert_adjacent_html() {
+        let document = parse_document("<!DOCTYPE html><div><p id=a>x</p></div>");
+        let mut p = element(&document, "#a");
+
+        p.insert_adjacent_html("beforebegin", "<i>1</i>").unwrap();
+        p.insert_adjacent_html("AfterBegin", "<i>2</i>").unwrap();
+        p.insert_adjacent_html("beforeend", "<i>3</i>").unwrap();
+        p.insert_adjacent_html("afterend", "<i>4</i><i>5</i>")
+            .unwrap();
+
+        let div = element(&document, "div");
+        assert_eq!(
+            div.inner_html(),
+            "<i>1</i><p id=\"a\"><i>2</i>x<i>3</i></p><i>4</i><i>5</i>"
+        );
+
+        assert!(matches!(
+            p.insert_adjacent_html("inside", "<i></i>"),
+            Err(Error::Dom(DomError::Syntax(_)))
+        ));
+        let mut html = element(&document, "html");
+        assert!(matches!(
+            html.insert_adjacent_html("afterend", "<i></i>"),
+            Err(Error::Dom(DomError::NoModificationAllowed(_)))
+        ));
+    }
+
+    #[test]
+    fn declarative_shadow_roots_are_not_attached() {
+        let document = parse_document("<!DOCTYPE html><div id=a></div>");
+        let mut div = element(&document, "#a");
+        div.set_inner_html("<span><template shadowrootmode=open><b>x</b></template></span>")
+            .unwrap();
+
+        let span = element(&document, "span");
+        assert_eq!(document.get().shadow_root(span.node_id()), None);
+        assert_eq!(
+            span.inner_html(),
+            "<template shadowrootmode=\"open\"><b>x</b></template>"
+        );
+    }
+}
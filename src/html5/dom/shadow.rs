@@ -0,0 +1,732 @@
+//! Shadow trees
+//!
+//! Implements [shadow trees](https://dom.spec.whatwg.org/#shadow-trees) on top of the node arena
+//! of a document. A shadow root is a node without a parent whose children form the shadow tree
+//! of its host. Shadow trees are created with [`Document::attach_shadow`], or by the parser for
+//! [declarative shadow roots](https://html.spec.whatwg.org/multipage/scripting.html#attr-template-shadowrootmode).
+//!
+//! The children of a host are assigned to the slots in its shadow tree. Slot assignment is not
+//! stored, but computed when it is asked for, so it is always up to date with the tree. The
+//! [flat tree](https://drafts.csswg.org/css-scoping/#flat-tree), which is used for rendering, is
+//! available through [`Document::flat_tree_children`] and [`Document::flat_tree_descendants`].
+use crate::html5::dom::{ShadowRoot, ShadowRootInit, ShadowRootMode, SlotAssignmentMode};
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use crate::types::{DomError, Result};
+
+/// HTML elements that can be shadow hosts, besides autonomous custom elements
+const SHADOW_HOST_NAMES: &[&str] = &[
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "div",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "main",
+    "nav",
+    "p",
+    "section",
+    "span",
+];
+
+/// Names that match the custom element name production but cannot be used for custom elements
+const RESERVED_CUSTOM_ELEMENT_NAMES: &[&str] = &[
+    "annotation-xml",
+    "color-profile",
+    "font-face",
+    "font-face-src",
+    "font-face-uri",
+    "font-face-format",
+    "font-face-name",
+    "missing-glyph",
+];
+
+/// Returns true when the name is a [valid custom element name](https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name)
+fn is_valid_custom_element_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.contains('-')
+        && name
+            .chars()
+            .all(|c| matches!(c, '-' | '.' | '_' | '0'..='9' | 'a'..='z') || !c.is_ascii())
+        && !RESERVED_CUSTOM_ELEMENT_NAMES.contains(&name)
+}
+
+/// Returns true when an element with the name can have a shadow root, see [valid shadow host name](https://dom.spec.whatwg.org/#valid-shadow-host-name)
+fn is_valid_shadow_host_name(name: &str) -> bool {
+    SHADOW_HOST_NAMES.contains(&name) || is_valid_custom_element_name(name)
+}
+
+/// Returns true when the node is an HTML slot element
+fn is_slot(node: &Node) -> bool {
+    node.name == "slot"
+        && node.is_namespace(HTML_NAMESPACE)
+        && matches!(node.data, NodeData::Element(_))
+}
+
+/// Returns true when the node can be assigned to a slot
+fn is_slottable(node: &Node) -> bool {
+    matches!(node.data, NodeData::Element(_) | NodeData::Text(_))
+}
+
+/// Returns the value of an attribute of an element node
+fn attribute<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    match &node.data {
+        NodeData::Element(element) => element.attributes.get(name).map(String::as_str),
+        _ => None,
+    }
+}
+
+/// Shadow tree methods of the document
+impl Document {
+    /// Attaches a shadow root to the element, see [attach a shadow root](https://dom.spec.whatwg.org/#concept-attach-a-shadow-root).
+    /// Attaching a shadow root to an element with a declarative shadow root of the same mode
+    /// empties and returns the existing shadow root. Returns the id of the shadow root node.
+    pub fn attach_shadow(&mut self, host_id: NodeId, init: ShadowRootInit) -> Result<NodeId> {
+        let host = self.dom_node(host_id)?;
+        let NodeData::Element(element) = &host.data else {
+            return Err(DomError::NotSupported("only elements can be shadow hosts".into()).into());
+        };
+        if !host.is_namespace(HTML_NAMESPACE) || !is_valid_shadow_host_name(element.name()) {
+            return Err(DomError::NotSupported(format!(
+                "<{}> cannot be a shadow host",
+                element.name()
+            ))
+            .into());
+        }
+        let document = Document::clone(&host.document);
+
+        if let Some(shadow_root_id) = self.shadow_root(host_id) {
+            let shadow_root = self.shadow_root_data(shadow_root_id).expect("shadow root");
+            if !shadow_root.declarative || shadow_root.mode != init.mode {
+                return Err(
+                    DomError::NotSupported("element already has a shadow root".into()).into(),
+                );
+            }
+
+            let children = self.dom_node(shadow_root_id)?.children.clone();
+            for child in children {
+                self.detach_node_from_parent(child);
+            }
+            if let Some(NodeData::ShadowRoot(shadow_root)) = self
+                .get_node_by_id_mut(shadow_root_id)
+                .map(|node| &mut node.data)
+            {
+                shadow_root.declarative = false;
+            }
+
+            return Ok(shadow_root_id);
+        }
+
+        let shadow_root = ShadowRoot {
+            mode: init.mode,
+            delegates_focus: init.delegates_focus,
+            slot_assignment: init.slot_assignment,
+            clonable: init.clonable,
+            serializable: init.serializable,
+            declarative: false,
+            host: host_id,
+        };
+        let node = Node::new_shadow_root(&document, shadow_root);
+        let shadow_root_id = self.add_new_node(node);
+
+        if let Some(NodeData::Element(element)) =
+            self.get_node_by_id_mut(host_id).map(|node| &mut node.data)
+        {
+            element.shadow_root = Some(shadow_root_id);
+        }
+
+        Ok(shadow_root_id)
+    }
+
+    /// Returns the shadow root node of the element, whatever its mode. Scripts must not see
+    /// closed shadow roots, which [`Element::shadow_root`](crate::html5::dom::Element::shadow_root)
+    /// takes care of.
+    pub fn shadow_root(&self, host_id: NodeId) -> Option<NodeId> {
+        match &self.get_node_by_id(host_id)?.data {
+            NodeData::Element(element) => element.shadow_root,
+            _ => None,
+        }
+    }
+
+    /// Returns the data of a shadow root node
+    pub fn shadow_root_data(&self, shadow_root_id: NodeId) -> Option<&ShadowRoot> {
+        match &self.get_node_by_id(shadow_root_id)?.data {
+            NodeData::ShadowRoot(shadow_root) => Some(shadow_root),
+            _ => None,
+        }
+    }
+
+    /// Returns the [root](https://dom.spec.whatwg.org/#concept-tree-root) of the node: the
+    /// document node, a shadow root, or the topmost node of a detached subtree
+    pub fn root_of(&self, node_id: NodeId) -> NodeId {
+        self.ancestors(node_id)
+            .last()
+            .map_or(node_id, |ancestor| ancestor.id)
+    }
+
+    /// Returns the shadow root of the shadow tree the node is in, if any
+    pub fn containing_shadow_root(&self, node_id: NodeId) -> Option<NodeId> {
+        let root = self.root_of(node_id);
+        self.shadow_root_data(root).map(|_| root)
+    }
+
+    /// Returns the ancestors of the node, where the host of a shadow root counts as its parent.
+    /// See [host-including inclusive ancestor](https://dom.spec.whatwg.org/#concept-tree-host-including-inclusive-ancestor).
+    pub(crate) fn host_including_ancestors(&self, node_id: NodeId) -> Vec<NodeId> {
+        let mut ancestors = Vec::new();
+        let mut current = node_id;
+        while let Some(node) = self.get_node_by_id(current) {
+            let parent = match &node.data {
+                NodeData::ShadowRoot(shadow_root) => Some(shadow_root.host),
+                _ => node.parent,
+            };
+            match parent {
+                Some(parent) if !ancestors.contains(&parent) => {
+                    ancestors.push(parent);
+                    current = parent;
+                }
+                _ => break,
+            }
+        }
+
+        ancestors
+    }
+
+    /// Returns the node and its descendants, including the shadow trees of all shadow hosts, in
+    /// [shadow-including tree order](https://dom.spec.whatwg.org/#concept-shadow-including-tree-order)
+    pub(crate) fn shadow_including_inclusive_descendants(&self, node_id: NodeId) -> Vec<NodeId> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![node_id];
+        while let Some(id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(id) else {
+                continue;
+            };
+            nodes.push(id);
+            stack.extend(node.children.iter().rev());
+            if let Some(shadow_root_id) = self.shadow_root(id) {
+                stack.push(shadow_root_id);
+            }
+        }
+
+        nodes
+    }
+
+    /// Returns the slot the node is assigned to, see [find a slot](https://dom.spec.whatwg.org/#find-a-slot)
+    pub fn assigned_slot(&self, node_id: NodeId) -> Option<NodeId> {
+        self.find_slot(node_id, false)
+    }
+
+    /// Returns the slot the node is assigned to. When open is set, slots in closed shadow trees
+    /// are not returned.
+    pub(crate) fn find_slot(&self, node_id: NodeId, open: bool) -> Option<NodeId> {
+        let node = self.get_node_by_id(node_id)?;
+        if !is_slottable(node) {
+            return None;
+        }
+        let shadow_root_id = self.shadow_root(node.parent?)?;
+        let shadow_root = self.shadow_root_data(shadow_root_id)?;
+        if open && shadow_root.mode != ShadowRootMode::Open {
+            return None;
+        }
+
+        match shadow_root.slot_assignment {
+            SlotAssignmentMode::Manual => self
+                .descendants(shadow_root_id)
+                .filter(|slot| is_slot(slot))
+                .find(|slot| {
+                    self.manually_assigned_nodes
+                        .get(&slot.id)
+                        .is_some_and(|nodes| nodes.contains(&node_id))
+                })
+                .map(|slot| slot.id),
+            SlotAssignmentMode::Named => {
+                let name = attribute(node, "slot").unwrap_or_default();
+                self.descendants(shadow_root_id)
+                    .find(|slot| {
+                        is_slot(slot) && attribute(slot, "name").unwrap_or_default() == name
+                    })
+                    .map(|slot| slot.id)
+            }
+        }
+    }
+
+    /// Returns the nodes assigned to the slot, see [assignedNodes()](https://html.spec.whatwg.org/multipage/scripting.html#dom-slot-assignednodes).
+    /// With flatten, slots that are assigned to the slot are replaced by their assigned nodes,
+    /// and a slot without assigned nodes by its children.
+    pub fn assigned_nodes(&self, slot_id: NodeId, flatten: bool) -> Vec<NodeId> {
+        if flatten {
+            self.find_flattened_slottables(slot_id)
+        } else {
+            self.find_slottables(slot_id)
+        }
+    }
+
+    /// Returns the nodes assigned to the slot, see [find slottables](https://dom.spec.whatwg.org/#find-slottables)
+    fn find_slottables(&self, slot_id: NodeId) -> Vec<NodeId> {
+        let Some(shadow_root) = self
+            .containing_shadow_root(slot_id)
+            .and_then(|root| self.shadow_root_data(root))
+        else {
+            return Vec::new();
+        };
+        let Some(host) = self.get_node_by_id(shadow_root.host) else {
+            return Vec::new();
+        };
+
+        match shadow_root.slot_assignment {
+            SlotAssignmentMode::Manual => self
+                .manually_assigned_nodes
+                .get(&slot_id)
+                .into_iter()
+                .flatten()
+                .filter(|node_id| host.children.contains(node_id))
+                .copied()
+                .collect(),
+            SlotAssignmentMode::Named => host
+                .children
+                .iter()
+                .filter(|child| self.find_slot(**child, false) == Some(slot_id))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// See [find flattened slottables](https://dom.spec.whatwg.org/#find-flattened-slottables)
+    fn find_flattened_slottables(&self, slot_id: NodeId) -> Vec<NodeId> {
+        if self.containing_shadow_root(slot_id).is_none() {
+            return Vec::new();
+        }
+
+        let mut slottables = self.find_slottables(slot_id);
+        if slottables.is_empty() {
+            slottables = self
+                .get_node_by_id(slot_id)
+                .map(|slot| {
+                    slot.children
+                        .iter()
+                        .filter(|child| self.get_node_by_id(**child).is_some_and(is_slottable))
+                        .copied()
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+
+        let mut result = Vec::new();
+        for node_id in slottables {
+            let nested_slot = self.get_node_by_id(node_id).is_some_and(is_slot)
+                && self.containing_shadow_root(node_id).is_some();
+            if nested_slot {
+                result.extend(self.find_flattened_slottables(node_id));
+            } else {
+                result.push(node_id);
+            }
+        }
+
+        result
+    }
+
+    /// Assigns the nodes to a slot in a shadow tree with manual slot assignment, see [assign()](https://html.spec.whatwg.org/multipage/scripting.html#dom-slot-assign).
+    /// The nodes are taken away from the slots they were assigned to before. Nodes are only
+    /// slotted while they are children of the host.
+    pub fn assign_slot(&mut self, slot_id: NodeId, nodes: &[NodeId]) -> Result<()> {
+        if !is_slot(self.dom_node(slot_id)?) {
+            return Err(DomError::Type(format!("node {slot_id} is not a slot")).into());
+        }
+        for &node_id in nodes {
+            if !is_slottable(self.dom_node(node_id)?) {
+                return Err(DomError::Type(format!(
+                    "node {node_id} is not an element or text node"
+                ))
+                .into());
+            }
+        }
+
+        let mut assigned: Vec<NodeId> = Vec::new();
+        for &node_id in nodes {
+            if !assigned.contains(&node_id) {
+                assigned.push(node_id);
+            }
+        }
+
+        for slot_nodes in self.manually_assigned_nodes.values_mut() {
+            slot_nodes.retain(|node_id| !assigned.contains(node_id));
+        }
+        self.manually_assigned_nodes.insert(slot_id, assigned);
+        self.manually_assigned_nodes
+            .retain(|_, slot_nodes| !slot_nodes.is_empty());
+
+        Ok(())
+    }
+
+    /// Returns the children of the node in the flat tree: the children of the shadow root for a
+    /// shadow host, the assigned nodes (or else the children) for a slot in a shadow tree, and the
+    /// regular children for other nodes
+    pub fn flat_tree_children(&self, node_id: NodeId) -> Vec<NodeId> {
+        if let Some(shadow_root_id) = self.shadow_root(node_id) {
+            return self
+                .get_node_by_id(shadow_root_id)
+                .map(|shadow_root| shadow_root.children.clone())
+                .unwrap_or_default();
+        }
+
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return Vec::new();
+        };
+        if is_slot(node) && self.containing_shadow_root(node_id).is_some() {
+            let assigned = self.find_slottables(node_id);
+            if !assigned.is_empty() {
+                return assigned;
+            }
+        }
+
+        node.children.clone()
+    }
+
+    /// Returns the descendants of the node in the flat tree, in tree order, not including the
+    /// node itself. Children of shadow hosts that are not assigned to a slot are left out.
+    pub fn flat_tree_descendants(&self, node_id: NodeId) -> FlatTreeDescendants<'_> {
+        let mut stack = self.flat_tree_children(node_id);
+        stack.reverse();
+
+        FlatTreeDescendants {
+            document: self,
+            stack,
+        }
+    }
+}
+
+/// Iterator over the descendants of a node in the flat tree
+pub struct FlatTreeDescendants<'a> {
+    document: &'a Document,
+    /// Nodes that are still to be visited, the next one last
+    stack: Vec<NodeId>,
+}
+
+impl<'a> Iterator for FlatTreeDescendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_id = self.stack.pop()?;
+        let mut children = self.document.flat_tree_children(node_id);
+        children.reverse();
+        self.stack.extend(children);
+
+        self.document.get_node_by_id(node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::CharIterator;
+    use crate::html5::dom::elements::HtmlSlotElement;
+    use crate::html5::dom::Element;
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+    use crate::testing::document::parse_document;
+    use crate::types::Error;
+
+    fn id(document: &Document, id: &str) -> NodeId {
+        document.get_node_by_named_id(id).unwrap().id
+    }
+
+    fn names(document: &Document, nodes: impl IntoIterator<Item = NodeId>) -> Vec<String> {
+        nodes
+            .into_iter()
+            .map(|node_id| {
+                let node = document.get_node_by_id(node_id).unwrap();
+                match &node.data {
+                    NodeData::Text(text) => format!("\"{}\"", text.value()),
+                    _ => node.name.clone(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn declarative_shadow_root() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><div id="host"><template shadowrootmode="open" shadowrootclonable><p>shadow
+            <slot id="a" name="a"></slot><slot id="default"><i>fallback</i></slot></p></template
+            ><span slot="a">A</span>text<b>B</b><em slot="missing"></em></div>"#,
+        );
+        let doc = document.get();
+        let host = id(&doc, "host");
+
+        // The template is not inserted into the tree
+        assert_eq!(
+            names(&doc, doc.get_node_by_id(host).unwrap().children.clone()),
+            ["span", "\"text\"", "b", "em"]
+        );
+        let shadow_root_id = doc.shadow_root(host).unwrap();
+        let shadow_root = doc.shadow_root_data(shadow_root_id).unwrap();
+        assert_eq!(shadow_root.mode, ShadowRootMode::Open);
+        assert_eq!(shadow_root.host, host);
+        assert!(shadow_root.declarative);
+        assert!(shadow_root.clonable);
+        assert!(!shadow_root.delegates_focus);
+
+        let children = doc.get_node_by_id(host).unwrap().children.clone();
+        // Elements in the shadow tree are not found by their id in the document
+        assert!(doc.get_node_by_named_id("a").is_none());
+        let slot = |id| {
+            doc.query_selector_from(shadow_root_id, id)
+                .unwrap()
+                .unwrap()
+        };
+        let (a, default) = (slot("#a"), slot("#default"));
+        assert_eq!(doc.containing_shadow_root(a), Some(shadow_root_id));
+        assert_eq!(doc.assigned_nodes(a, false), [children[0]]);
+        assert_eq!(
+            doc.assigned_nodes(default, false),
+            [children[1], children[2]]
+        );
+        assert_eq!(doc.assigned_slot(children[0]), Some(a));
+        assert_eq!(doc.assigned_slot(children[3]), None);
+
+        assert_eq!(
+            names(&doc, doc.flat_tree_descendants(host).map(|node| node.id)),
+            [
+                "p",
+                "\"shadow\n            \"",
+                "slot",
+                "span",
+                "\"A\"",
+                "slot",
+                "\"text\"",
+                "b",
+                "\"B\""
+            ]
+        );
+    }
+
+    #[test]
+    fn declarative_shadow_root_fallback() {
+        let document = parse_document(
+            r#"<!DOCTYPE html><p id="closed"><template shadowrootmode="closed">x</template
+            ><template id="second" shadowrootmode="open">y</template></p
+            ><ul id="list"><template id="ul" shadowrootmode="open">z</template></ul
+            ><div id="invalid"><template id="t" shadowrootmode="sideways"></template></div>"#,
+        );
+        let doc = document.get();
+
+        let closed = id(&doc, "closed");
+        assert!(doc.shadow_root(closed).is_some());
+        assert!(Element::new(&document, closed)
+            .unwrap()
+            .shadow_root()
+            .is_none());
+        // A host can only have one declarative shadow root
+        assert_eq!(
+            doc.get_node_by_id(id(&doc, "second")).unwrap().parent,
+            Some(closed)
+        );
+        // Lists cannot be shadow hosts
+        assert!(doc.shadow_root(id(&doc, "list")).is_none());
+        assert_eq!(
+            doc.get_node_by_id(id(&doc, "ul")).unwrap().parent,
+            Some(id(&doc, "list"))
+        );
+        assert!(doc.shadow_root(id(&doc, "invalid")).is_none());
+        drop(doc);
+
+        let options = Html5ParserOptions {
+            allow_declarative_shadow_roots: false,
+            ..Default::default()
+        };
+        let document = DocumentBuilder::new_document();
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            r#"<div id="host"><template shadowrootmode="open"></template></div>"#,
+            None,
+        );
+        Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options)).unwrap();
+        let doc = document.get();
+        assert!(doc.shadow_root(id(&doc, "host")).is_none());
+    }
+
+    #[test]
+    fn attach_shadow() {
+        let mut document = DocumentBuilder::new_document();
+        let ul = document.create_element("ul", NodeId::root(), None, HTML_NAMESPACE);
+        let custom = document.create_element("my-element", NodeId::root(), None, HTML_NAMESPACE);
+        let reserved = document.create_element("font-face", NodeId::root(), None, HTML_NAMESPACE);
+        let mut doc = document.get_mut();
+
+        let init = ShadowRootInit::new(ShadowRootMode::Open);
+        for host in [ul, reserved] {
+            assert!(matches!(
+                doc.attach_shadow(host, init.clone()),
+                Err(Error::Dom(DomError::NotSupported(_)))
+            ));
+        }
+
+        let shadow_root_id = doc.attach_shadow(custom, init.clone()).unwrap();
+        assert_eq!(doc.shadow_root(custom), Some(shadow_root_id));
+        assert!(doc.get_node_by_id(shadow_root_id).unwrap().parent.is_none());
+        assert!(matches!(
+            doc.attach_shadow(custom, init.clone()),
+            Err(Error::Dom(DomError::NotSupported(_)))
+        ));
+
+        // Shadow roots cannot be inserted, and hosts cannot be inserted into their shadow tree
+        assert!(matches!(
+            doc.append_child(ul, shadow_root_id),
+            Err(Error::Dom(DomError::HierarchyRequest(_)))
+        ));
+        let child = doc.clone_node(ul, false).unwrap();
+        doc.append_child(shadow_root_id, child).unwrap();
+        assert!(matches!(
+            doc.append_child(child, custom),
+            Err(Error::Dom(DomError::HierarchyRequest(_)))
+        ));
+
+        // The shadow tree is kept when compacting, and deleted with its host
+        assert_eq!(doc.compact(), 0);
+        doc.delete_node(custom).unwrap();
+        assert!(doc.get_node_by_id(shadow_root_id).is_none());
+        assert!(doc.get_node_by_id(child).is_none());
+    }
+
+    #[test]
+    fn replace_declarative_shadow_root() {
+        let mut document = parse_document(
+            r#"<!DOCTYPE html><div id="host"><template shadowrootmode="open"><p></p></template></div>"#,
+        );
+        let mut doc = document.get_mut();
+        let host = id(&doc, "host");
+        let shadow_root_id = doc.shadow_root(host).unwrap();
+
+        assert!(doc
+            .attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Closed))
+            .is_err());
+        let attached = doc
+            .attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Open))
+            .unwrap();
+        assert_eq!(attached, shadow_root_id);
+        assert!(doc
+            .get_node_by_id(shadow_root_id)
+            .unwrap()
+            .children
+            .is_empty());
+        assert!(!doc.shadow_root_data(shadow_root_id).unwrap().declarative);
+    }
+
+    #[test]
+    fn manual_slot_assignment() {
+        let mut document = parse_document(
+            r#"<!DOCTYPE html><div id="host"><b id="b"></b><i id="i"></i></div><p id="p"></p>"#,
+        );
+        let (host, b, i, p) = {
+            let doc = document.get();
+            (
+                id(&doc, "host"),
+                id(&doc, "b"),
+                id(&doc, "i"),
+                id(&doc, "p"),
+            )
+        };
+
+        let mut element = Element::new(&document, host).unwrap();
+        let mut init = ShadowRootInit::new(ShadowRootMode::Open);
+        init.slot_assignment = SlotAssignmentMode::Manual;
+        let shadow_root_id = element.attach_shadow(init).unwrap();
+
+        let mut doc = Document::clone(&document);
+        let first = doc.create_element("slot", shadow_root_id, None, HTML_NAMESPACE);
+        let second = doc.create_element("slot", shadow_root_id, None, HTML_NAMESPACE);
+        let mut first_slot = HtmlSlotElement::new(&document, first).unwrap();
+        let mut second_slot = HtmlSlotElement::new(&document, second).unwrap();
+
+        // Without manual assignment nothing is slotted, not even into a slot without a name
+        assert!(first_slot.assigned_nodes(false).is_empty());
+
+        first_slot.assign(&[i, b, p]).unwrap();
+        assert_eq!(first_slot.assigned_nodes(false), [i, b]);
+        assert_eq!(
+            Element::new(&document, b).unwrap().assigned_slot(),
+            Some(first)
+        );
+
+        second_slot.assign(&[b]).unwrap();
+        assert_eq!(first_slot.assigned_nodes(false), [i]);
+        assert_eq!(second_slot.assigned_elements(false), [b]);
+        assert_eq!(document.get().flat_tree_children(host), [first, second]);
+        assert_eq!(document.get().flat_tree_children(second), [b]);
+
+        // Nodes that leave the host are no longer slotted
+        document.get_mut().append_child(p, b).unwrap();
+        assert!(second_slot.assigned_nodes(false).is_empty());
+        assert!(matches!(
+            first_slot.assign(&[shadow_root_id]),
+            Err(Error::Dom(DomError::Type(_)))
+        ));
+    }
+
+    #[test]
+    fn flattened_slots() {
+        // The slot of the outer shadow tree is assigned to a slot of the inner shadow tree
+        let document = parse_document(
+            r#"<!DOCTYPE html><div id="outer"><template shadowrootmode="open"><span id="inner"><template
+            shadowrootmode="open"><slot id="inner-slot"></slot></template><slot id="outer-slot"
+            >fallback</slot></span></template><b>light</b></div>"#,
+        );
+        let doc = document.get();
+        let outer = id(&doc, "outer");
+        let shadow_root_id = doc.shadow_root(outer).unwrap();
+        let inner = doc.get_node_by_id(shadow_root_id).unwrap().children[0];
+        let inner_shadow_root_id = doc.shadow_root(inner).unwrap();
+        let inner_slot = doc.get_node_by_id(inner_shadow_root_id).unwrap().children[0];
+        let outer_slot = doc.get_node_by_id(inner).unwrap().children[0];
+        let light = doc.get_node_by_id(outer).unwrap().children[0];
+
+        assert_eq!(doc.assigned_nodes(inner_slot, false), [outer_slot]);
+        assert_eq!(doc.assigned_nodes(inner_slot, true), [light]);
+        assert_eq!(
+            names(&doc, doc.flat_tree_descendants(outer).map(|node| node.id)),
+            ["span", "slot", "slot", "b", "\"light\""]
+        );
+    }
+
+    #[test]
+    fn clone_shadow_host() {
+        let mut document = parse_document(
+            r#"<!DOCTYPE html><div id="clonable"><template shadowrootmode="open" shadowrootclonable><p></p></template
+            ></div><div id="plain"><template shadowrootmode="open"><p></p></template></div>"#,
+        );
+        let mut doc = document.get_mut();
+        let (clonable, plain) = (id(&doc, "clonable"), id(&doc, "plain"));
+
+        let copy = doc.clone_node(clonable, false).unwrap();
+        let shadow_root_copy = doc.shadow_root(copy).unwrap();
+        assert_ne!(Some(shadow_root_copy), doc.shadow_root(clonable));
+        assert_eq!(doc.shadow_root_data(shadow_root_copy).unwrap().host, copy);
+        assert_eq!(
+            names(
+                &doc,
+                doc.get_node_by_id(shadow_root_copy)
+                    .unwrap()
+                    .children
+                    .clone()
+            ),
+            ["p"]
+        );
+
+        let copy = doc.clone_node(plain, true).unwrap();
+        assert!(doc.shadow_root(copy).is_none());
+        let shadow_root_id = doc.shadow_root(plain).unwrap();
+        assert!(matches!(
+            doc.clone_node(shadow_root_id, true),
+            Err(Error::Dom(DomError::NotSupported(_)))
+        ));
+    }
+}
@@ -0,0 +1,477 @@
+//! Text of a subtree
+//!
+//! Implements [textContent](https://dom.spec.whatwg.org/#dom-node-textcontent) and
+//! [normalize()](https://dom.spec.whatwg.org/#dom-node-normalize) from the DOM, and an
+//! approximation of [innerText](https://html.spec.whatwg.org/multipage/dom.html#the-innertext-idl-attribute).
+//!
+//! The text content is the plain concatenation of all text in the subtree. The inner text is
+//! the text as it would be rendered, which needs a layout that we do not have. Instead, it uses
+//! the default styles of the HTML elements: elements that are not rendered by default and
+//! elements with a `hidden` attribute are skipped, block-level elements start and end on their
+//! own line, and whitespace is collapsed except in preformatted elements.
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::{Node, NodeData, NodeId, NodeTrait, NodeType, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use crate::types::Result;
+
+/// HTML elements that are not rendered by the default styles
+const NOT_RENDERED_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "datalist", "head", "link", "meta", "noembed", "noframes", "param",
+    "rp", "script", "style", "template", "title",
+];
+
+/// HTML elements that are block-level in the default styles
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "legend",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "plaintext",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tr",
+    "ul",
+    "xmp",
+];
+
+/// HTML elements in which whitespace is preserved by the default styles
+const PREFORMATTED_ELEMENTS: &[&str] = &["listing", "plaintext", "pre", "textarea", "xmp"];
+
+/// Returns true when the node is an HTML element with one of the given names
+fn is_html_element_in(node: &Node, names: &[&str]) -> bool {
+    matches!(node.data, NodeData::Element(_))
+        && node.is_namespace(HTML_NAMESPACE)
+        && names.contains(&node.name.as_str())
+}
+
+/// Returns true when the node is an element that is not rendered, so its inner text is empty
+fn is_hidden(node: &Node) -> bool {
+    let NodeData::Element(element) = &node.data else {
+        return false;
+    };
+
+    is_html_element_in(node, NOT_RENDERED_ELEMENTS)
+        || element.attributes.contains_key("hidden")
+        || (node.is_namespace(HTML_NAMESPACE)
+            && node.name == "dialog"
+            && !element.attributes.contains_key("open"))
+}
+
+/// Collects the inner text of a subtree. Whitespace and line breaks are not written right away,
+/// so the ones at the start and end of the text and around line breaks can be dropped.
+struct InnerTextCollector {
+    /// The text collected so far
+    output: String,
+    /// Number of line breaks that are required before the next text
+    pending_line_breaks: usize,
+    /// A collapsed space is written before the next text on the same line
+    pending_space: bool,
+    /// Nothing has been written on the current line yet
+    at_line_start: bool,
+}
+
+impl InnerTextCollector {
+    fn new() -> Self {
+        Self {
+            output: String::new(),
+            pending_line_breaks: 0,
+            pending_space: false,
+            at_line_start: true,
+        }
+    }
+
+    /// Requires at least the given number of line breaks before the next text
+    fn require_line_breaks(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.pending_line_breaks = self.pending_line_breaks.max(count);
+        self.pending_space = false;
+    }
+
+    /// Writes the pending line breaks and space. Line breaks at the start are dropped.
+    fn flush(&mut self) {
+        if self.pending_line_breaks > 0 {
+            if !self.output.is_empty() {
+                self.output.push_str(&"\n".repeat(self.pending_line_breaks));
+                self.at_line_start = true;
+            }
+            self.pending_line_breaks = 0;
+            self.pending_space = false;
+        }
+        if self.pending_space {
+            self.output.push(' ');
+            self.pending_space = false;
+        }
+    }
+
+    /// Writes the text as it is
+    fn push_preserved(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.flush();
+        self.output.push_str(text);
+        self.at_line_start = text.ends_with('\n');
+    }
+
+    /// Writes the text with each run of whitespace collapsed into a single space. Spaces at the
+    /// start of a line are dropped.
+    fn push_collapsed(&mut self, text: &str) {
+        for c in text.chars() {
+            if matches!(c, '\t' | '\n' | '\u{000C}' | '\r' | ' ') {
+                self.pending_space |= !self.at_line_start;
+            } else {
+                self.flush();
+                self.output.push(c);
+                self.at_line_start = false;
+            }
+        }
+    }
+
+    /// Writes a line break, as done for a `<br>` element
+    fn push_line_break(&mut self) {
+        self.pending_space = false;
+        self.flush();
+        self.output.push('\n');
+        self.at_line_start = true;
+    }
+}
+
+/// Text content, see [textContent](https://dom.spec.whatwg.org/#dom-node-textcontent)
+impl Document {
+    /// Returns the text content of the node: the concatenation of all text in an element or a
+    /// fragment, or the data of a text or comment node. Returns None for the document and the
+    /// doctype.
+    pub fn text_content(&self, node_id: NodeId) -> Option<String> {
+        let node = self.get_node_by_id(node_id)?;
+        match &node.data {
+            NodeData::Text(text) => Some(text.value.clone()),
+            NodeData::Comment(comment) => Some(comment.value.clone()),
+            NodeData::Document(_) | NodeData::DocType(_) => None,
+            NodeData::Element(_) | NodeData::ShadowRoot(_) | NodeData::DocumentFragment => Some(
+                self.descendants(node_id)
+                    .filter_map(|descendant| match &descendant.data {
+                        NodeData::Text(text) => Some(text.value.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Sets the text content of the node. The children of an element or a fragment are replaced
+    /// by a single text node, or by nothing when the value is empty. The data of a text or comment
+    /// node is replaced. Nothing happens for the document and the doctype.
+    pub fn set_text_content(&mut self, node_id: NodeId, value: &str) -> Result<()> {
+        let node = self.dom_node(node_id)?;
+        let children = node.children.clone();
+        let handle = Document::clone(&node.document);
+
+        match node.type_of() {
+            NodeType::Text | NodeType::Comment => {
+                self.replace_data(node_id, value.to_string());
+            }
+            NodeType::Element | NodeType::DocumentFragment => {
+                for child_id in children {
+                    self.delete_node(child_id)?;
+                }
+                if !value.is_empty() {
+                    self.add_node(Node::new_text(&handle, value), node_id, None);
+                }
+            }
+            NodeType::Document | NodeType::DocType => {}
+        }
+
+        Ok(())
+    }
+
+    /// Merges adjacent text nodes in the subtree of the node into the first of them, and removes
+    /// empty text nodes, see [normalize()](https://dom.spec.whatwg.org/#dom-node-normalize)
+    pub fn normalize(&mut self, node_id: NodeId) -> Result<()> {
+        self.dom_node(node_id)?;
+
+        let parents: Vec<NodeId> = std::iter::once(node_id)
+            .chain(self.descendants(node_id).map(|node| node.id))
+            .collect();
+        for parent_id in parents {
+            self.normalize_children(parent_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Merges the runs of adjacent text nodes in the children of the parent, and deletes the
+    /// text nodes that were merged or empty
+    fn normalize_children(&mut self, parent_id: NodeId) -> Result<()> {
+        let Some(parent) = self.get_node_by_id(parent_id) else {
+            return Ok(());
+        };
+
+        // Each run is the first text node of the run with the data of the others
+        let mut runs: Vec<(NodeId, String)> = Vec::new();
+        let mut removed = Vec::new();
+        let mut in_run = false;
+        for &child_id in &parent.children {
+            match self.get_node_by_id(child_id).map(|child| &child.data) {
+                Some(NodeData::Text(text)) if text.value.is_empty() => removed.push(child_id),
+                Some(NodeData::Text(text)) => match runs.last_mut() {
+                    Some((_, data)) if in_run => {
+                        data.push_str(&text.value);
+                        removed.push(child_id);
+                    }
+                    _ => {
+                        runs.push((child_id, text.value.clone()));
+                        in_run = true;
+                    }
+                },
+                _ => in_run = false,
+            }
+        }
+
+        for (text_id, data) in runs {
+            if self.text_content(text_id).as_deref() != Some(data.as_str()) {
+                self.replace_data(text_id, data);
+            }
+        }
+        for child_id in removed {
+            self.delete_node(child_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the data of a text or comment node
+    fn replace_data(&mut self, node_id: NodeId, data: String) {
+        let old_value = match self.get_node_by_id_mut(node_id).map(|node| &mut node.data) {
+            Some(NodeData::Text(text)) => std::mem::replace(&mut text.value, data),
+            Some(NodeData::Comment(comment)) => std::mem::replace(&mut comment.value, data),
+            _ => return,
+        };
+
+        self.queue_character_data_record(node_id, old_value);
+    }
+}
+
+/// Inner text, see [innerText](https://html.spec.whatwg.org/multipage/dom.html#the-innertext-idl-attribute)
+impl Document {
+    /// Returns the text of the node as it is rendered with the default styles. Elements that are
+    /// not rendered are left out, block-level elements are put on their own lines (with an empty
+    /// line around paragraphs), table cells are separated by tabs, and whitespace is collapsed
+    /// outside of preformatted elements. Shadow trees are included as they are rendered.
+    pub fn inner_text(&self, node_id: NodeId) -> String {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return String::new();
+        };
+        if is_hidden(node) {
+            return self.text_content(node_id).unwrap_or_default();
+        }
+
+        let mut collector = InnerTextCollector::new();
+        for child_id in self.flat_tree_children(node_id) {
+            self.collect_inner_text(child_id, is_preformatted(node), &mut collector);
+        }
+
+        collector.output
+    }
+
+    /// Adds the rendered text of the node and its flat tree descendants to the collector
+    fn collect_inner_text(
+        &self,
+        node_id: NodeId,
+        preformatted: bool,
+        collector: &mut InnerTextCollector,
+    ) {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return;
+        };
+
+        match &node.data {
+            NodeData::Text(text) if preformatted => collector.push_preserved(&text.value),
+            NodeData::Text(text) => collector.push_collapsed(&text.value),
+            NodeData::Element(_) if is_hidden(node) => {}
+            NodeData::Element(_) if is_html_element_in(node, &["br"]) => {
+                collector.push_line_break()
+            }
+            NodeData::Element(_) => {
+                let line_breaks = if is_html_element_in(node, &["p"]) {
+                    2
+                } else if is_html_element_in(node, BLOCK_ELEMENTS) {
+                    1
+                } else {
+                    0
+                };
+
+                collector.require_line_breaks(line_breaks);
+                let preformatted = preformatted || is_preformatted(node);
+                for child_id in self.flat_tree_children(node_id) {
+                    self.collect_inner_text(child_id, preformatted, collector);
+                }
+                collector.require_line_breaks(line_breaks);
+
+                // Cells are separated by a tab, except for the last cell of a row
+                if is_html_element_in(node, &["td", "th"])
+                    && self.following_siblings(node_id).any(|sibling| {
+                        is_html_element_in(sibling, &["td", "th"]) && !is_hidden(sibling)
+                    })
+                {
+                    collector.push_preserved("\t");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns true when the node is an element in which whitespace is preserved
+fn is_preformatted(node: &Node) -> bool {
+    is_html_element_in(node, PREFORMATTED_ELEMENTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5::node::traversal::Traversable;
+    use crate::html5::node::{Node, NodeData, NodeId};
+    use crate::html5::parser::document::Document;
+    use crate::testing::document::parse_document;
+
+    fn select(document: &Document, selectors: &str) -> NodeId {
+        document.query_selector(selectors).unwrap().unwrap()
+    }
+
+    #[test]
+    fn text_content() {
+        let mut document = parse_document(
+            "<!DOCTYPE html><div id=a>one <b>two</b><!--c--> three</div><p id=b>x</p>",
+        );
+        let mut doc = document.get_mut();
+        let div = select(&doc, "#a");
+        assert_eq!(doc.text_content(div).as_deref(), Some("one two three"));
+        assert_eq!(doc.text_content(NodeId::root()), None);
+
+        let comment = doc
+            .descendants(div)
+            .find(|node| matches!(node.data, NodeData::Comment(_)))
+            .unwrap()
+            .id;
+        assert_eq!(doc.text_content(comment).as_deref(), Some("c"));
+
+        doc.set_text_content(div, "<new>").unwrap();
+        let children = doc.get_node_by_id(div).unwrap().children.clone();
+        assert_eq!(children.len(), 1);
+        assert_eq!(doc.text_content(div).as_deref(), Some("<new>"));
+        assert_eq!(doc.text_content(children[0]).as_deref(), Some("<new>"));
+
+        doc.set_text_content(children[0], "changed").unwrap();
+        assert_eq!(doc.text_content(div).as_deref(), Some("changed"));
+
+        let p = select(&doc, "#b");
+        let old_children = doc.get_node_by_id(p).unwrap().children.clone();
+        doc.set_text_content(p, "").unwrap();
+        assert!(doc.get_node_by_id(p).unwrap().children.is_empty());
+        assert!(old_children
+            .iter()
+            .all(|child_id| doc.get_node_by_id(*child_id).is_none()));
+    }
+
+    #[test]
+    fn normalize() {
+        let mut document = parse_document("<!DOCTYPE html><div><b>x</b></div>");
+        let div = select(&document.get(), "div");
+        let b = select(&document.get(), "b");
+
+        let texts: Vec<NodeId> = ["a", "", "b", "c"]
+            .iter()
+            .map(|value| {
+                let text = Node::new_text(&document, value);
+                document.get_mut().add_node(text, div, None)
+            })
+            .collect();
+        let empty = Node::new_text(&document, "");
+        document.get_mut().add_node(empty, b, None);
+
+        let mut doc = document.get_mut();
+        doc.normalize(div).unwrap();
+
+        assert_eq!(doc.get_node_by_id(div).unwrap().children, [b, texts[0]]);
+        assert_eq!(doc.text_content(texts[0]).as_deref(), Some("abc"));
+        assert!(texts[1..]
+            .iter()
+            .all(|text_id| doc.get_node_by_id(*text_id).is_none()));
+        assert_eq!(doc.get_node_by_id(b).unwrap().children.len(), 1);
+        assert_eq!(doc.text_content(div).as_deref(), Some("xabc"));
+
+        // A node normalizes its subtree through its document, which must not be borrowed
+        assert!(doc.get_node_by_id(div).unwrap().normalize().is_err());
+        let node = doc.get_node_by_id(div).unwrap().clone();
+        drop(doc);
+        let text = Node::new_text(&document, "d");
+        document.get_mut().add_node(text, div, None);
+        node.normalize().unwrap();
+        assert_eq!(
+            document.get().get_node_by_id(div).unwrap().children,
+            [b, texts[0]]
+        );
+        assert_eq!(
+            document.get().text_content(texts[0]).as_deref(),
+            Some("abcd")
+        );
+    }
+
+    #[test]
+    fn inner_text() {
+        let document = parse_document(concat!(
+            "<!DOCTYPE html><head><title>Title</title><style>p { color: red }</style></head>",
+            "<body>  <h1>Heading</h1>\n<p>A   paragraph\nwith <b>bold</b> text<br>and a break.</p>",
+            "<p hidden>Hidden</p><script>var x = 1;</script>",
+            "<ul><li>one</li><li>two</li></ul>",
+            "<table><tr><td>a</td><td>b</td></tr><tr><td>c</td><td>d</td></tr></table>",
+            "<pre>  keep\n  this</pre><div>end <span> of </span> text </div></body>"
+        ));
+        let doc = document.get();
+        let body = select(&doc, "body");
+
+        assert_eq!(
+            doc.inner_text(body),
+            "Heading\n\nA paragraph with bold text\nand a break.\n\none\ntwo\na\tb\nc\td\n  keep\n  this\nend of text"
+        );
+        assert_eq!(doc.inner_text(select(&doc, "title")), "Title");
+        assert_eq!(doc.inner_text(select(&doc, "b")), "bold");
+    }
+}
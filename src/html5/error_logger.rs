@@ -1,7 +1,8 @@
-use crate::bytes::Position;
 use crate::types::ParseError;
+use std::collections::HashSet;
 
 /// Possible parser error enumerated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ParserError {
     AbruptDoctypePublicIdentifier,
     AbruptDoctypeSystemIdentifier,
@@ -56,6 +57,22 @@ pub enum ParserError {
     ExpectedDocTypeButGotChars,
     ExpectedDocTypeButGotStartTag,
     ExpectedDocTypeButGotEndTag,
+    UnexpectedDoctype,
+    UnexpectedStartTag,
+    UnexpectedEndTag,
+    UnexpectedCharacters,
+    UnexpectedEof,
+    MisnestedTags,
+}
+
+/// How serious a parse error is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The input is not conforming, but the result is most likely what the author intended
+    Warning,
+    /// The input is not conforming and the resulting document can differ from what the author
+    /// intended
+    Error,
 }
 
 impl ParserError {
@@ -149,6 +166,36 @@ impl ParserError {
             ParserError::ExpectedDocTypeButGotChars => "expected-doctype-but-got-chars",
             ParserError::ExpectedDocTypeButGotStartTag => "expected-doctype-but-got-start-tag",
             ParserError::ExpectedDocTypeButGotEndTag => "expected-doctype-but-got-end-tag",
+            ParserError::UnexpectedDoctype => "unexpected-doctype",
+            ParserError::UnexpectedStartTag => "unexpected-start-tag",
+            ParserError::UnexpectedEndTag => "unexpected-end-tag",
+            ParserError::UnexpectedCharacters => "unexpected-characters",
+            ParserError::UnexpectedEof => "unexpected-eof",
+            ParserError::MisnestedTags => "misnested-tags",
+        }
+    }
+
+    /// Returns the severity of the error. Warnings are the tokenizer errors where the
+    /// specification recovers by ignoring the offending input (a duplicate attribute, attributes or
+    /// a trailing solidus on an end tag, a solidus in a start tag, an abrupt `<!-->`) or by acting
+    /// as if the missing character was there (whitespace and a character reference semicolon).
+    /// The tree is then the same as for the conforming input. All other errors, including every
+    /// tree construction error, are errors.
+    pub fn severity(&self) -> Severity {
+        match self {
+            ParserError::AbruptClosingOfEmptyComment
+            | ParserError::DuplicateAttribute
+            | ParserError::EndTagWithAttributes
+            | ParserError::EndTagWithTrailingSolidus
+            | ParserError::MissingSemicolonAfterCharacterReference
+            | ParserError::MissingWhitespaceAfterDoctypePublicKeyword
+            | ParserError::MissingWhitespaceAfterDoctypeSystemKeyword
+            | ParserError::MissingWhitespaceBeforeDoctypeName
+            | ParserError::MissingWhitespaceBetweenAttributes
+            | ParserError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers
+            | ParserError::NonVoidHtmlElementStartTagWithTrailingSolidus
+            | ParserError::UnexpectedSolidusInTag => Severity::Warning,
+            _ => Severity::Error,
         }
     }
 }
@@ -157,12 +204,20 @@ impl ParserError {
 pub struct ErrorLogger {
     /// List of errors that occurred during parsing
     errors: Vec<ParseError>,
+    /// Line, column and message of every logged error, so duplicates can be found quickly
+    logged: HashSet<(usize, usize, String)>,
+    /// Maximum number of errors that are collected, if any
+    max_errors: Option<usize>,
 }
 
 impl ErrorLogger {
     /// Creates a new error logger
     pub fn new() -> Self {
-        ErrorLogger { errors: Vec::new() }
+        ErrorLogger {
+            errors: Vec::new(),
+            logged: HashSet::new(),
+            max_errors: None,
+        }
     }
 }
 
@@ -178,23 +233,58 @@ impl ErrorLogger {
         self.errors.clone()
     }
 
+    /// Returns the errors logged so far
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Returns the number of errors logged so far
+    pub(crate) fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Removes all errors logged after the first len errors
+    pub(crate) fn truncate(&mut self, len: usize) {
+        for err in self.errors.drain(len.min(self.errors.len())..) {
+            self.logged.remove(&(err.line, err.col, err.message));
+        }
+    }
+
+    /// Removes all logged errors
+    pub(crate) fn clear(&mut self) {
+        self.errors.clear();
+        self.logged.clear();
+    }
+
+    /// Sets the maximum number of errors that are collected. Any further errors are discarded.
+    pub fn set_max_errors(&mut self, max_errors: Option<usize>) {
+        self.max_errors = max_errors;
+    }
+
+    /// Returns true when the maximum number of errors has been collected
+    pub fn is_full(&self) -> bool {
+        self.max_errors
+            .is_some_and(|max_errors| self.errors.len() >= max_errors)
+    }
+
     /// Adds a new error to the error logger
-    pub fn add_error(&mut self, pos: Position, message: &str) {
+    pub fn add_error(&mut self, error: ParseError) {
+        if self.is_full() {
+            return;
+        }
+
         // Check if the error already exists, if so, don't add it again
-        for err in &self.errors {
-            if err.line == pos.line && err.col == pos.col && err.message == *message {
-                return;
-            }
+        let key = (error.line, error.col, error.message.clone());
+        if !self.logged.insert(key) {
+            return;
         }
 
-        self.errors.push(ParseError {
-            line: pos.line,
-            col: pos.col,
-            offset: pos.offset,
-            message: message.to_string(),
-        });
+        self.errors.push(error);
+    }
 
-        // println!("Parse error ({}/{}): {}", pos.line, pos.col, message);
+    /// Returns the number of errors logged with the given code
+    pub fn count_by_code(&self, code: ParserError) -> usize {
+        self.errors.iter().filter(|err| err.code == code).count()
     }
 }
 
@@ -202,16 +292,29 @@ impl ErrorLogger {
 
 mod tests {
     use super::*;
+    use crate::bytes::{Position, Span};
+    use crate::html5::tokenizer::state::State;
+    use crate::types::ErrorTrigger;
+
+    fn error(pos: Position) -> ParseError {
+        ParseError::new(
+            ParserError::EofInTag,
+            "test",
+            ErrorTrigger::State(State::Data),
+            pos,
+            Span::new(pos, pos),
+        )
+    }
 
     #[test]
     fn test_error_logger() {
         let mut logger = ErrorLogger::new();
 
-        logger.add_error(Position::new(1, 1, 0), "test");
-        logger.add_error(Position::new(1, 1, 0), "test");
-        logger.add_error(Position::new(1, 1, 0), "test");
-        logger.add_error(Position::new(1, 1, 0), "test");
-        logger.add_error(Position::new(1, 1, 0), "test");
+        logger.add_error(error(Position::new(1, 1, 0)));
+        logger.add_error(error(Position::new(1, 1, 0)));
+        logger.add_error(error(Position::new(1, 1, 0)));
+        logger.add_error(error(Position::new(1, 1, 0)));
+        logger.add_error(error(Position::new(1, 1, 0)));
 
         assert_eq!(logger.get_errors().len(), 1);
     }
@@ -220,11 +323,11 @@ mod tests {
     fn test_error_logger2() {
         let mut logger = ErrorLogger::new();
 
-        logger.add_error(Position::new(1, 1, 0), "test");
-        logger.add_error(Position::new(1, 2, 0), "test");
-        logger.add_error(Position::new(1, 3, 0), "test");
-        logger.add_error(Position::new(1, 4, 0), "test");
-        logger.add_error(Position::new(1, 5, 0), "test");
+        logger.add_error(error(Position::new(1, 1, 0)));
+        logger.add_error(error(Position::new(1, 2, 0)));
+        logger.add_error(error(Position::new(1, 3, 0)));
+        logger.add_error(error(Position::new(1, 4, 0)));
+        logger.add_error(error(Position::new(1, 5, 0)));
 
         assert_eq!(logger.get_errors().len(), 5);
     }
@@ -233,15 +336,15 @@ mod tests {
     fn test_error_logger3() {
         let mut logger = ErrorLogger::new();
 
-        logger.add_error(Position::new(1, 1, 0), "test");
-        logger.add_error(Position::new(1, 2, 0), "test");
-        logger.add_error(Position::new(1, 3, 0), "test");
-        logger.add_error(Position::new(1, 4, 0), "test");
-        logger.add_error(Position::new(1, 5, 0), "test");
-        logger.add_error(Position::new(1, 5, 0), "test");
-        logger.add_error(Position::new(1, 5, 0), "test");
-        logger.add_error(Position::new(1, 5, 0), "test");
-        logger.add_error(Position::new(1, 5, 0), "test");
+        logger.add_error(error(Position::new(1, 1, 0)));
+        logger.add_error(error(Position::new(1, 2, 0)));
+        logger.add_error(error(Position::new(1, 3, 0)));
+        logger.add_error(error(Position::new(1, 4, 0)));
+        logger.add_error(error(Position::new(1, 5, 0)));
+        logger.add_error(error(Position::new(1, 5, 0)));
+        logger.add_error(error(Position::new(1, 5, 0)));
+        logger.add_error(error(Position::new(1, 5, 0)));
+        logger.add_error(error(Position::new(1, 5, 0)));
 
         assert_eq!(logger.get_errors().len(), 5);
     }
@@ -250,25 +353,70 @@ mod tests {
     fn test_error_logger4() {
         let mut logger = ErrorLogger::new();
 
-        logger.add_error(Position::new(0, 1, 1), "test");
-        logger.add_error(Position::new(0, 1, 2), "test");
-        logger.add_error(Position::new(0, 1, 3), "test");
-        logger.add_error(Position::new(0, 1, 4), "test");
-        logger.add_error(Position::new(0, 1, 5), "test");
-        logger.add_error(Position::new(0, 1, 5), "test");
-        logger.add_error(Position::new(0, 1, 5), "test");
-        logger.add_error(Position::new(0, 1, 5), "test");
-        logger.add_error(Position::new(0, 1, 5), "test");
-        logger.add_error(Position::new(0, 2, 1), "test");
-        logger.add_error(Position::new(0, 2, 2), "test");
-        logger.add_error(Position::new(0, 2, 3), "test");
-        logger.add_error(Position::new(0, 2, 4), "test");
-        logger.add_error(Position::new(0, 2, 5), "test");
-        logger.add_error(Position::new(0, 2, 5), "test");
-        logger.add_error(Position::new(0, 2, 5), "test");
-        logger.add_error(Position::new(0, 2, 5), "test");
-        logger.add_error(Position::new(0, 2, 5), "test");
+        logger.add_error(error(Position::new(0, 1, 1)));
+        logger.add_error(error(Position::new(0, 1, 2)));
+        logger.add_error(error(Position::new(0, 1, 3)));
+        logger.add_error(error(Position::new(0, 1, 4)));
+        logger.add_error(error(Position::new(0, 1, 5)));
+        logger.add_error(error(Position::new(0, 1, 5)));
+        logger.add_error(error(Position::new(0, 1, 5)));
+        logger.add_error(error(Position::new(0, 1, 5)));
+        logger.add_error(error(Position::new(0, 1, 5)));
+        logger.add_error(error(Position::new(0, 2, 1)));
+        logger.add_error(error(Position::new(0, 2, 2)));
+        logger.add_error(error(Position::new(0, 2, 3)));
+        logger.add_error(error(Position::new(0, 2, 4)));
+        logger.add_error(error(Position::new(0, 2, 5)));
+        logger.add_error(error(Position::new(0, 2, 5)));
+        logger.add_error(error(Position::new(0, 2, 5)));
+        logger.add_error(error(Position::new(0, 2, 5)));
+        logger.add_error(error(Position::new(0, 2, 5)));
 
         assert_eq!(logger.get_errors().len(), 10);
     }
+
+    #[test]
+    fn test_error_codes() {
+        let mut logger = ErrorLogger::new();
+
+        logger.add_error(error(Position::new(0, 1, 1)));
+        logger.add_error(error(Position::new(0, 1, 2)));
+
+        let errors = logger.get_errors();
+        assert_eq!(errors[0].code, ParserError::EofInTag);
+        assert_eq!(errors[0].severity, Severity::Error);
+        assert_eq!(logger.count_by_code(ParserError::EofInTag), 2);
+        assert_eq!(logger.count_by_code(ParserError::DuplicateAttribute), 0);
+        assert_eq!(
+            ParserError::DuplicateAttribute.severity(),
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn test_error_limit() {
+        let mut logger = ErrorLogger::new();
+        logger.set_max_errors(Some(3));
+
+        for col in 1..10 {
+            logger.add_error(error(Position::new(0, 1, col)));
+        }
+
+        assert!(logger.is_full());
+        assert_eq!(logger.get_errors().len(), 3);
+    }
+
+    #[test]
+    fn test_truncate_allows_errors_again() {
+        let mut logger = ErrorLogger::new();
+
+        logger.add_error(error(Position::new(0, 1, 1)));
+        logger.add_error(error(Position::new(0, 1, 2)));
+        logger.truncate(1);
+        assert_eq!(logger.error_count(), 1);
+
+        logger.add_error(error(Position::new(0, 1, 1)));
+        logger.add_error(error(Position::new(0, 1, 2)));
+        assert_eq!(logger.error_count(), 2);
+    }
 }
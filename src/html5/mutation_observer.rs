@@ -0,0 +1,520 @@
+//! Mutation observers
+//!
+//! Records changes to a document in the way of the [MutationObserver](https://dom.spec.whatwg.org/#mutation-observers)
+//! interface. Observers are registered on a document with [`Document::observe`], and collect a
+//! [`MutationRecord`] for every change to the nodes they observe. The records are not delivered
+//! through a callback, but are queued until they are taken with [`Document::take_records`].
+//!
+//! Changes are recorded both for the tree builder and for later edits. Each inserted or removed
+//! node gets its own `childList` record, so replacing a node results in a record for the removal
+//! and one for the insertion.
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::types::{DomError, Result};
+use std::collections::HashMap;
+
+/// Kind of change in a mutation record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationRecordType {
+    /// Children were added or removed
+    ChildList,
+    /// An attribute was set
+    Attributes,
+    /// The value of a text or comment node changed
+    CharacterData,
+}
+
+/// A single change to the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutationRecord {
+    pub record_type: MutationRecordType,
+    /// Node that changed: the parent for child list changes, the element for attribute
+    /// changes and the text or comment node for character data changes.
+    pub target: NodeId,
+    pub added_nodes: Vec<NodeId>,
+    pub removed_nodes: Vec<NodeId>,
+    /// Sibling before the added or removed nodes
+    pub previous_sibling: Option<NodeId>,
+    /// Sibling after the added or removed nodes
+    pub next_sibling: Option<NodeId>,
+    /// Name of the changed attribute
+    pub attribute_name: Option<String>,
+    /// Value of the attribute or character data before the change. Only set when the observer
+    /// asked for old values.
+    pub old_value: Option<String>,
+}
+
+impl MutationRecord {
+    fn new(record_type: MutationRecordType, target: NodeId) -> Self {
+        MutationRecord {
+            record_type,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: None,
+            old_value: None,
+        }
+    }
+}
+
+/// Options for observing a node, see [MutationObserverInit](https://dom.spec.whatwg.org/#dictdef-mutationobserverinit)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MutationObserverInit {
+    /// Observe children being added and removed
+    pub child_list: bool,
+    /// Observe attribute changes. Implied by `attribute_old_value` and `attribute_filter`.
+    pub attributes: bool,
+    /// Observe character data changes. Implied by `character_data_old_value`.
+    pub character_data: bool,
+    /// Also observe all descendants of the node
+    pub subtree: bool,
+    /// Record the old value of changed attributes
+    pub attribute_old_value: bool,
+    /// Record the old value of changed character data
+    pub character_data_old_value: bool,
+    /// Only observe attributes with these names
+    pub attribute_filter: Option<Vec<String>>,
+}
+
+/// Identifies a mutation observer in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MutationObserverId(usize);
+
+/// A node that is observed by an observer
+#[derive(Debug, Clone, PartialEq)]
+struct Registration {
+    observer: MutationObserverId,
+    target: NodeId,
+    options: MutationObserverInit,
+}
+
+/// Observers of a document, with their registrations and queued records
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MutationObservers {
+    next_id: usize,
+    registrations: Vec<Registration>,
+    records: HashMap<MutationObserverId, Vec<MutationRecord>>,
+}
+
+impl MutationObservers {
+    /// Returns true when any node is observed
+    pub fn is_observing(&self) -> bool {
+        !self.registrations.is_empty()
+    }
+
+    /// Forgets everything about the nodes of a document that is parsed again. Only the
+    /// registrations on the document node, which is kept, stay.
+    pub(crate) fn reset(&mut self) {
+        self.registrations.retain(|r| r.target == NodeId::root());
+        for records in self.records.values_mut() {
+            records.clear();
+        }
+    }
+
+    /// Returns the observers that are interested in a change to the target node, and whether
+    /// they want the old value. See [queue a mutation record](https://dom.spec.whatwg.org/#queue-a-mutation-record).
+    fn interested_observers(
+        &self,
+        tree: &impl Traversable,
+        record_type: MutationRecordType,
+        target: NodeId,
+        attribute_name: Option<&str>,
+    ) -> Vec<(MutationObserverId, bool)> {
+        let mut interested: Vec<(MutationObserverId, bool)> = Vec::new();
+
+        let inclusive_ancestors =
+            std::iter::once(target).chain(tree.ancestors(target).map(|node| node.id));
+        for node_id in inclusive_ancestors {
+            for registration in self.registrations.iter().filter(|r| r.target == node_id) {
+                let options = &registration.options;
+                if node_id != target && !options.subtree {
+                    continue;
+                }
+
+                let old_value = match record_type {
+                    MutationRecordType::ChildList if options.child_list => false,
+                    MutationRecordType::Attributes if options.attributes => {
+                        let filtered = options.attribute_filter.as_ref().is_some_and(|filter| {
+                            !filter
+                                .iter()
+                                .any(|name| Some(name.as_str()) == attribute_name)
+                        });
+                        if filtered {
+                            continue;
+                        }
+                        options.attribute_old_value
+                    }
+                    MutationRecordType::CharacterData if options.character_data => {
+                        options.character_data_old_value
+                    }
+                    _ => continue,
+                };
+
+                match interested
+                    .iter_mut()
+                    .find(|(observer, _)| *observer == registration.observer)
+                {
+                    Some((_, wants_old_value)) => *wants_old_value |= old_value,
+                    None => interested.push((registration.observer, old_value)),
+                }
+            }
+        }
+
+        interested
+    }
+
+    fn queue(&mut self, interested: Vec<(MutationObserverId, bool)>, record: MutationRecord) {
+        for (observer, wants_old_value) in interested {
+            let mut record = record.clone();
+            if !wants_old_value {
+                record.old_value = None;
+            }
+            self.records.entry(observer).or_default().push(record);
+        }
+    }
+}
+
+/// Mutation observer methods of the document
+impl Document {
+    /// Creates a new mutation observer that does not observe anything yet
+    pub fn create_mutation_observer(&mut self) -> MutationObserverId {
+        let observers = &mut self.mutation_observers;
+        let id = MutationObserverId(observers.next_id);
+        observers.next_id += 1;
+        observers.records.insert(id, Vec::new());
+        id
+    }
+
+    /// Starts observing the target node. Observing a node again replaces the options of the
+    /// earlier registration. See [observe()](https://dom.spec.whatwg.org/#dom-mutationobserver-observe).
+    pub fn observe(
+        &mut self,
+        observer: MutationObserverId,
+        target: NodeId,
+        mut options: MutationObserverInit,
+    ) -> Result<()> {
+        if options.attribute_old_value || options.attribute_filter.is_some() {
+            options.attributes = true;
+        }
+        if options.character_data_old_value {
+            options.character_data = true;
+        }
+        if !options.child_list && !options.attributes && !options.character_data {
+            return Err(DomError::Type(
+                "one of child_list, attributes or character_data must be set".into(),
+            )
+            .into());
+        }
+        if self.get_node_by_id(target).is_none() {
+            return Err(DomError::NotFound(format!("node {target} does not exist")).into());
+        }
+
+        let observers = &mut self.mutation_observers;
+        if !observers.records.contains_key(&observer) {
+            return Err(DomError::NotFound("unknown mutation observer".into()).into());
+        }
+
+        match observers
+            .registrations
+            .iter_mut()
+            .find(|r| r.observer == observer && r.target == target)
+        {
+            Some(registration) => registration.options = options,
+            None => observers.registrations.push(Registration {
+                observer,
+                target,
+                options,
+            }),
+        }
+
+        Ok(())
+    }
+
+    /// Returns and clears the records that were queued for the observer
+    pub fn take_records(&mut self, observer: MutationObserverId) -> Vec<MutationRecord> {
+        self.mutation_observers
+            .records
+            .get_mut(&observer)
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Stops the observer from observing any node, and drops its queued records
+    pub fn disconnect(&mut self, observer: MutationObserverId) {
+        let observers = &mut self.mutation_observers;
+        observers.registrations.retain(|r| r.observer != observer);
+        if let Some(records) = observers.records.get_mut(&observer) {
+            records.clear();
+        }
+    }
+
+    /// Queues a `childList` record for nodes that were added to or removed from the parent
+    pub(crate) fn queue_child_list_record(
+        &mut self,
+        parent_id: NodeId,
+        added: Option<NodeId>,
+        removed: Option<NodeId>,
+        previous_sibling: Option<NodeId>,
+        next_sibling: Option<NodeId>,
+    ) {
+        if !self.mutation_observers.is_observing() {
+            return;
+        }
+
+        let interested = self.mutation_observers.interested_observers(
+            self,
+            MutationRecordType::ChildList,
+            parent_id,
+            None,
+        );
+
+        let mut record = MutationRecord::new(MutationRecordType::ChildList, parent_id);
+        record.added_nodes.extend(added);
+        record.removed_nodes.extend(removed);
+        record.previous_sibling = previous_sibling;
+        record.next_sibling = next_sibling;
+        self.mutation_observers.queue(interested, record);
+    }
+
+    /// Queues an `attributes` record for an attribute of the element that was set
+    pub(crate) fn queue_attribute_record(
+        &mut self,
+        element_id: NodeId,
+        name: &str,
+        old_value: Option<String>,
+    ) {
+        if !self.mutation_observers.is_observing() {
+            return;
+        }
+
+        let interested = self.mutation_observers.interested_observers(
+            self,
+            MutationRecordType::Attributes,
+            element_id,
+            Some(name),
+        );
+
+        let mut record = MutationRecord::new(MutationRecordType::Attributes, element_id);
+        record.attribute_name = Some(name.to_string());
+        record.old_value = old_value;
+        self.mutation_observers.queue(interested, record);
+    }
+
+    /// Queues a `characterData` record for a text or comment node that changed
+    pub(crate) fn queue_character_data_record(&mut self, node_id: NodeId, old_value: String) {
+        if !self.mutation_observers.is_observing() {
+            return;
+        }
+
+        let interested = self.mutation_observers.interested_observers(
+            self,
+            MutationRecordType::CharacterData,
+            node_id,
+            None,
+        );
+
+        let mut record = MutationRecord::new(MutationRecordType::CharacterData, node_id);
+        record.old_value = Some(old_value);
+        self.mutation_observers.queue(interested, record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::CharIterator;
+    use crate::html5::node::{Node, HTML_NAMESPACE};
+    use crate::html5::parser::document::{DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::parser::Html5Parser;
+    use crate::types::Error;
+
+    fn element(document: &DocumentHandle, name: &str) -> NodeId {
+        let node = Node::new_element(document, name, HashMap::new(), HTML_NAMESPACE);
+        Document::clone(document).get_mut().add_new_node(node)
+    }
+
+    #[test]
+    fn child_list_records() {
+        let mut document = DocumentBuilder::new_document();
+        let (parent, a, b, c) = (
+            element(&document, "div"),
+            element(&document, "a"),
+            element(&document, "b"),
+            element(&document, "c"),
+        );
+
+        let mut doc = document.get_mut();
+        doc.append_child(NodeId::root(), parent).unwrap();
+        let observer = doc.create_mutation_observer();
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        doc.observe(observer, parent, options).unwrap();
+
+        doc.append_child(parent, a).unwrap();
+        doc.append_child(parent, c).unwrap();
+        doc.insert_before(parent, b, Some(c)).unwrap();
+        doc.remove_child(parent, a).unwrap();
+        // Children of the children are not observed without the subtree option
+        doc.append_child(b, a).unwrap();
+
+        let records = doc.take_records(observer);
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| {
+                (
+                    r.added_nodes.clone(),
+                    r.removed_nodes.clone(),
+                    r.previous_sibling,
+                    r.next_sibling,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (vec![a], vec![], None, None),
+                (vec![c], vec![], Some(a), None),
+                (vec![b], vec![], Some(a), Some(c)),
+                (vec![], vec![a], None, Some(b)),
+            ]
+        );
+        assert!(records
+            .iter()
+            .all(|r| r.record_type == MutationRecordType::ChildList && r.target == parent));
+        assert!(doc.take_records(observer).is_empty());
+
+        doc.disconnect(observer);
+        doc.remove_child(parent, b).unwrap();
+        assert!(doc.take_records(observer).is_empty());
+    }
+
+    #[test]
+    fn attribute_records() {
+        let mut document = DocumentBuilder::new_document();
+        let div = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let span = document.create_element("span", div, None, HTML_NAMESPACE);
+
+        let (filtered, all) = {
+            let mut doc = document.get_mut();
+            let filtered = doc.create_mutation_observer();
+            let all = doc.create_mutation_observer();
+            let options = MutationObserverInit {
+                subtree: true,
+                attribute_old_value: true,
+                attribute_filter: Some(vec!["title".into()]),
+                ..Default::default()
+            };
+            doc.observe(filtered, div, options).unwrap();
+            let options = MutationObserverInit {
+                attributes: true,
+                ..Default::default()
+            };
+            doc.observe(all, div, options).unwrap();
+            (filtered, all)
+        };
+
+        document.insert_attribute("title", "one", span).unwrap();
+        document.insert_attribute("title", "two", span).unwrap();
+        document.insert_attribute("id", "main", div).unwrap();
+        document.insert_attribute("class", "big", div).unwrap();
+
+        let mut doc = document.get_mut();
+        let records = doc.take_records(filtered);
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| {
+                (
+                    r.target,
+                    r.attribute_name.as_deref(),
+                    r.old_value.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (span, Some("title"), None),
+                (span, Some("title"), Some("one"))
+            ]
+        );
+
+        let records = doc.take_records(all);
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| {
+                (
+                    r.target,
+                    r.attribute_name.as_deref(),
+                    r.old_value.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [(div, Some("id"), None), (div, Some("class"), None)]
+        );
+    }
+
+    #[test]
+    fn tree_builder_records() {
+        let mut document = DocumentBuilder::new_document();
+        let observer = {
+            let mut doc = document.get_mut();
+            let observer = doc.create_mutation_observer();
+            let options = MutationObserverInit {
+                child_list: true,
+                character_data_old_value: true,
+                subtree: true,
+                ..Default::default()
+            };
+            doc.observe(observer, NodeId::root(), options).unwrap();
+            observer
+        };
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<p>a</x>b</y>c</p>", None);
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let records = document.get_mut().take_records(observer);
+        let added: Vec<_> = records
+            .iter()
+            .filter(|r| r.record_type == MutationRecordType::ChildList)
+            .flat_map(|r| r.added_nodes.clone())
+            .collect();
+        // html, head, body, p and the text node
+        assert_eq!(added.len(), 5);
+
+        let old_values: Vec<_> = records
+            .iter()
+            .filter(|r| r.record_type == MutationRecordType::CharacterData)
+            .map(|r| r.old_value.clone().unwrap())
+            .collect();
+        assert_eq!(old_values, ["a", "ab"]);
+    }
+
+    #[test]
+    fn invalid_options() {
+        let mut document = DocumentBuilder::new_document();
+        let mut doc = document.get_mut();
+        let observer = doc.create_mutation_observer();
+
+        assert!(matches!(
+            doc.observe(observer, NodeId::root(), MutationObserverInit::default()),
+            Err(Error::Dom(DomError::Type(_)))
+        ));
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            doc.observe(observer, NodeId::from(100), options),
+            Err(Error::Dom(DomError::NotFound(_)))
+        ));
+    }
+}
@@ -1,9 +1,12 @@
 use super::parser::document::{Document, DocumentHandle};
+use crate::bytes::Span;
+use crate::html5::dom::ShadowRoot;
 use crate::html5::node::data::comment::CommentData;
 use crate::html5::node::data::doctype::DocTypeData;
 use crate::html5::node::data::document::DocumentData;
 use crate::html5::node::data::element::ElementData;
 use crate::html5::node::data::text::TextData;
+use crate::types::Result;
 use core::fmt::Debug;
 use derive_more::Display;
 use std::collections::HashMap;
@@ -17,6 +20,8 @@ pub const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
 
 pub mod arena;
 pub mod data;
+pub mod selector;
+pub mod traversal;
 
 /// Different types of nodes
 #[derive(Debug, PartialEq)]
@@ -26,6 +31,7 @@ pub enum NodeType {
     Text,
     Comment,
     Element,
+    DocumentFragment,
 }
 
 /// Different type of node data
@@ -41,6 +47,10 @@ pub enum NodeData {
     Comment(CommentData),
     /// Represents an element
     Element(Box<ElementData>),
+    /// Represents the shadow root of a shadow host
+    ShadowRoot(Box<ShadowRoot>),
+    /// Represents a document fragment, like the root of the contents of a template element
+    DocumentFragment,
 }
 
 /// Id used to identify a node
@@ -126,6 +136,11 @@ pub struct Node {
 
     // Returns true when the given node is registered into an arena
     pub is_registered: bool,
+    /// Location in the source document of the start tag of an element, or of the whole text,
+    /// comment or doctype. Nodes that are not created from the source have no location.
+    pub location: Option<Span>,
+    /// Location in the source document of the end tag of an element, if it has one
+    pub end_tag_location: Option<Span>,
 }
 
 impl Node {
@@ -229,6 +244,8 @@ impl Clone for Node {
             data: self.data.clone(),
             document: Document::clone(&self.document),
             is_registered: self.is_registered,
+            location: self.location,
+            end_tag_location: self.end_tag_location,
         }
     }
 }
@@ -245,6 +262,8 @@ impl Node {
             namespace: None,
             document: Document::clone(document),
             is_registered: false,
+            location: None,
+            end_tag_location: None,
         }
     }
 
@@ -263,6 +282,8 @@ impl Node {
             namespace: None,
             document: Document::clone(document),
             is_registered: false,
+            location: None,
+            end_tag_location: None,
         }
     }
 
@@ -287,6 +308,40 @@ impl Node {
             namespace: Some(namespace.into()),
             document: Document::clone(document),
             is_registered: false,
+            location: None,
+            end_tag_location: None,
+        }
+    }
+
+    /// Creates a new shadow root node, which is not attached to its host
+    pub fn new_shadow_root(document: &DocumentHandle, shadow_root: ShadowRoot) -> Self {
+        Node {
+            id: Default::default(),
+            parent: None,
+            children: vec![],
+            data: NodeData::ShadowRoot(Box::new(shadow_root)),
+            name: "".to_string(),
+            namespace: None,
+            document: Document::clone(document),
+            is_registered: false,
+            location: None,
+            end_tag_location: None,
+        }
+    }
+
+    /// Creates a new document fragment node
+    pub fn new_document_fragment(document: &DocumentHandle) -> Self {
+        Node {
+            id: Default::default(),
+            parent: None,
+            children: vec![],
+            data: NodeData::DocumentFragment,
+            name: "".to_string(),
+            namespace: None,
+            document: Document::clone(document),
+            is_registered: false,
+            location: None,
+            end_tag_location: None,
         }
     }
 
@@ -301,6 +356,8 @@ impl Node {
             namespace: None,
             document: Document::clone(document),
             is_registered: false,
+            location: None,
+            end_tag_location: None,
         }
     }
 
@@ -315,6 +372,8 @@ impl Node {
             namespace: None,
             document: Document::clone(document),
             is_registered: false,
+            location: None,
+            end_tag_location: None,
         }
     }
 
@@ -349,6 +408,47 @@ impl Node {
     pub fn is_registered(&self) -> bool {
         self.is_registered
     }
+
+    /// Returns the first descendant of this node that matches the selectors. This borrows the
+    /// document of the node, so it cannot be called while the document is borrowed mutably.
+    pub fn query_selector(&self, selectors: &str) -> Result<Option<NodeId>> {
+        self.document.get().query_selector_from(self.id, selectors)
+    }
+
+    /// Returns all descendants of this node that match the selectors, in tree order
+    pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeId>> {
+        self.document
+            .get()
+            .query_selector_all_from(self.id, selectors)
+    }
+
+    /// Returns true when this node is an element that matches the selectors
+    pub fn matches(&self, selectors: &str) -> Result<bool> {
+        self.document.get().matches(self.id, selectors)
+    }
+
+    /// Returns this node or its closest ancestor that matches the selectors
+    pub fn closest(&self, selectors: &str) -> Result<Option<NodeId>> {
+        self.document.get().closest(self.id, selectors)
+    }
+
+    /// Returns the text content of this node, see [`Document::text_content`]
+    pub fn text_content(&self) -> Option<String> {
+        self.document.get().text_content(self.id)
+    }
+
+    /// Returns the text of this node as it is rendered, see [`Document::inner_text`]
+    pub fn inner_text(&self) -> String {
+        self.document.get().inner_text(self.id)
+    }
+
+    /// Merges adjacent text nodes in the subtree of this node, see [`Document::normalize`]. Fails
+    /// when the document is borrowed, like when this node is a reference into the document.
+    pub fn normalize(&self) -> Result<()> {
+        Document::clone(&self.document)
+            .try_get_mut()?
+            .normalize(self.id)
+    }
 }
 
 pub trait NodeTrait {
@@ -366,6 +466,7 @@ impl NodeTrait for Node {
             NodeData::Text { .. } => NodeType::Text,
             NodeData::Comment { .. } => NodeType::Comment,
             NodeData::Element { .. } => NodeType::Element,
+            NodeData::ShadowRoot { .. } | NodeData::DocumentFragment => NodeType::DocumentFragment,
         }
     }
 }
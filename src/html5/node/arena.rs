@@ -1,9 +1,16 @@
 use crate::html5::node::Node;
+use alloc::rc::Rc;
+use std::cell::Cell;
 use std::collections::HashMap;
 
 use super::NodeId;
 
 /// The node arena is the single source for nodes in a document (or fragment).
+///
+/// Node ids are never reused. Once a node is deleted, its id stays invalid, so a stale id is
+/// detected because the lookup returns `None` instead of silently returning another node. Arenas
+/// can share their ids (see [`NodeArena::share_ids`]), so a node id refers to at most one node in
+/// any of them.
 #[derive(Debug, Clone, PartialEq)]
 pub struct NodeArena {
     /// Current nodes stored as <id, node>
@@ -13,8 +20,8 @@ pub struct NodeArena {
     /// Note that the order of nodes isn't directly needed for functionality, but merely present
     /// for debugging purposes.
     order: Vec<NodeId>,
-    /// Next node ID to use
-    next_id: NodeId,
+    /// Next node ID to use, which is shared with the arenas that share their ids
+    next_id: Rc<Cell<NodeId>>,
 }
 
 impl Clone for NodeId {
@@ -41,7 +48,13 @@ impl NodeArena {
     /// Peek what the next node ID is without incrementing the internal counter.
     /// Used by DocumentTaskQueue for create_element() tasks.
     pub(crate) fn peek_next_id(&self) -> NodeId {
-        self.next_id
+        self.next_id.get()
+    }
+
+    /// Continues the ids of the other arena: from now on, both arenas hand out ids from the same
+    /// counter. Nodes that are already registered keep their id.
+    pub(crate) fn share_ids(&mut self, other: &NodeArena) {
+        self.next_id = Rc::clone(&other.next_id);
     }
 
     /// Gets the node with the given id
@@ -54,14 +67,19 @@ impl NodeArena {
         self.nodes.get_mut(&node_id)
     }
 
+    /// Returns all nodes in the arena, in no particular order
+    pub(crate) fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.nodes.values_mut()
+    }
+
     /// Registered an unregistered node into the arena
     pub fn register_node(&mut self, mut node: Node) -> NodeId {
         if node.is_registered {
             panic!("Node is already attached to an arena");
         }
 
-        let id = self.next_id;
-        self.next_id = id.next();
+        let id = self.next_id.get();
+        self.next_id.set(id.next());
 
         node.is_registered = true;
         node.id = id;
@@ -71,6 +89,26 @@ impl NodeArena {
         id
     }
 
+    /// Removes all nodes for which the predicate returns false. Returns the number of removed
+    /// nodes.
+    pub(crate) fn retain<F>(&mut self, mut keep: F) -> usize
+    where
+        F: FnMut(NodeId) -> bool,
+    {
+        let count = self.nodes.len();
+
+        self.nodes.retain(|&id, _| keep(id));
+        self.order.retain(|id| self.nodes.contains_key(id));
+
+        count - self.nodes.len()
+    }
+
+    /// Releases the memory that was used by removed nodes
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.order.shrink_to_fit();
+    }
+
     /// Prints the list of nodes in sequential order. This makes debugging a bit easier, but should
     /// be removed.
     pub(crate) fn print_nodes(&self) {
@@ -101,7 +139,7 @@ mod tests {
         let id = document.arena.register_node(node);
 
         assert_eq!(document.arena.nodes.len(), 1);
-        assert_eq!(document.arena.next_id, 1.into());
+        assert_eq!(document.arena.peek_next_id(), 1.into());
         assert_eq!(id, NodeId::default());
     }
 
@@ -163,4 +201,45 @@ mod tests {
         assert!(child.is_some());
         assert_eq!(child.unwrap().parent, Some(parent_id));
     }
+
+    #[test]
+    fn retain() {
+        let mut doc = Document::shared();
+        let nodes: Vec<_> = (0..4)
+            .map(|_| Node::new_element(&doc, "node", HashMap::new(), HTML_NAMESPACE))
+            .collect();
+        let node = Node::new_element(&doc, "node", HashMap::new(), HTML_NAMESPACE);
+
+        let mut document = doc.get_mut();
+        let ids: Vec<_> = nodes
+            .into_iter()
+            .map(|node| document.arena.register_node(node))
+            .collect();
+
+        assert_eq!(document.arena.retain(|id| id == ids[1]), 3);
+        assert_eq!(document.arena.count_nodes(), 1);
+        assert_eq!(document.arena.order, [ids[1]]);
+        assert!(document.arena.get_node(ids[1]).is_some());
+
+        // Removed ids are not handed out again
+        let id = document.arena.register_node(node);
+        assert!(!ids.contains(&id));
+        assert!(document.arena.get_node(ids[0]).is_none());
+    }
+
+    #[test]
+    fn share_ids() {
+        let doc = Document::shared();
+        let node = || Node::new_element(&doc, "node", HashMap::new(), HTML_NAMESPACE);
+
+        let mut arena = NodeArena::new();
+        let mut other = NodeArena::new();
+        assert_eq!(arena.register_node(node()), NodeId(0));
+        assert_eq!(other.register_node(node()), NodeId(0));
+
+        other.share_ids(&arena);
+        assert_eq!(other.register_node(node()), NodeId(1));
+        assert_eq!(arena.register_node(node()), NodeId(2));
+        assert_eq!(other.peek_next_id(), NodeId(3));
+    }
 }
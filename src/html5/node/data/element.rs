@@ -24,6 +24,8 @@ pub struct ElementData {
     pub(crate) force_async: bool,
     // Template contents (when it's a template element)
     pub(crate) template_contents: Option<DocumentFragment>,
+    /// Shadow root node when the element is a shadow host
+    pub(crate) shadow_root: Option<NodeId>,
     /// Pointer to the document the node associated with this data is tied to
     pub(crate) document: DocumentHandle,
 }
@@ -44,6 +46,7 @@ impl ElementData {
             classes: ElementClass::new(),
             force_async: false,
             template_contents: None,
+            shadow_root: None,
             document,
         }
     }
@@ -61,6 +64,7 @@ impl ElementData {
             classes: ElementClass::new(),
             force_async: false,
             template_contents: None,
+            shadow_root: None,
             document,
         }
     }
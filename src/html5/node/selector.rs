@@ -0,0 +1,853 @@
+//! Selector matching
+//!
+//! Matches the selector lists from the [`CSS3Parser`](crate::css3::parser::CSS3Parser) against
+//! the nodes of any [`Traversable`] tree, following [Selectors Level 4](https://drafts.csswg.org/selectors/).
+//! Complex selectors are matched from right to left, starting with the compound selector of the
+//! element that is tested.
+//!
+//! Pseudo-classes that depend on user interaction or on a rendered document, like `:hover` or
+//! `:target`, never match.
+use crate::css3::node::{
+    AttributeMatcher, AttributeSelector, Combinator, PseudoClassArgument, PseudoClassSelector,
+    Selector, SelectorList,
+};
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::{Node, NodeData, NodeId, NodeTrait, NodeType, HTML_NAMESPACE};
+use std::collections::HashMap;
+
+/// Attributes whose values are matched case-insensitively in HTML documents, see
+/// [case-sensitivity](https://html.spec.whatwg.org/multipage/semantics-other.html#case-sensitivity-of-selectors)
+const CASE_INSENSITIVE_ATTRIBUTES: [&str; 46] = [
+    "accept",
+    "accept-charset",
+    "align",
+    "alink",
+    "axis",
+    "bgcolor",
+    "charset",
+    "checked",
+    "clear",
+    "codetype",
+    "color",
+    "compact",
+    "declare",
+    "defer",
+    "dir",
+    "direction",
+    "disabled",
+    "enctype",
+    "face",
+    "frame",
+    "hreflang",
+    "http-equiv",
+    "lang",
+    "language",
+    "link",
+    "media",
+    "method",
+    "multiple",
+    "nohref",
+    "noresize",
+    "noshade",
+    "nowrap",
+    "readonly",
+    "rel",
+    "rev",
+    "rules",
+    "scope",
+    "scrolling",
+    "selected",
+    "shape",
+    "target",
+    "text",
+    "type",
+    "valign",
+    "valuetype",
+    "vlink",
+];
+
+/// Elements that can be disabled
+const DISABLEABLE_ELEMENTS: [&str; 7] = [
+    "button", "input", "select", "textarea", "optgroup", "option", "fieldset",
+];
+
+/// Input types that have a text field that can be edited
+const TEXT_INPUT_TYPES: [&str; 13] = [
+    "text",
+    "search",
+    "url",
+    "tel",
+    "email",
+    "password",
+    "date",
+    "month",
+    "week",
+    "time",
+    "datetime-local",
+    "number",
+    "",
+];
+
+/// State of a single match operation
+#[derive(Clone, Copy)]
+struct Context {
+    /// Element that `:scope` matches
+    scope: NodeId,
+    /// Element that the relative selectors of `:has()` are anchored at
+    anchor: Option<NodeId>,
+}
+
+/// Matches selector lists against the nodes of a tree
+pub struct SelectorMatcher<'a, T: Traversable> {
+    tree: &'a T,
+}
+
+impl<'a, T: Traversable> SelectorMatcher<'a, T> {
+    pub fn new(tree: &'a T) -> Self {
+        SelectorMatcher { tree }
+    }
+
+    /// Returns true when the node is an element that matches any of the selectors. The scope is
+    /// the element that `:scope` matches. When the scope is the document node, `:scope` matches
+    /// the root element.
+    pub fn matches(&self, node_id: NodeId, selectors: &SelectorList, scope: NodeId) -> bool {
+        let context = Context {
+            scope,
+            anchor: None,
+        };
+
+        self.element(node_id).is_some() && self.matches_list(selectors.children(), node_id, context)
+    }
+
+    /// Returns all elements in the subtree of the given root that match the selectors, in tree
+    /// order and not including the root itself
+    pub fn select_all(&self, root: NodeId, selectors: &SelectorList) -> Vec<NodeId> {
+        self.tree
+            .descendants(root)
+            .filter(|node| self.matches(node.id, selectors, root))
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Returns the first element in tree order in the subtree of the given root that matches
+    /// the selectors
+    pub fn select_first(&self, root: NodeId, selectors: &SelectorList) -> Option<NodeId> {
+        self.tree
+            .descendants(root)
+            .find(|node| self.matches(node.id, selectors, root))
+            .map(|node| node.id)
+    }
+
+    /// Returns the node itself or its closest ancestor that matches the selectors
+    pub fn closest(&self, node_id: NodeId, selectors: &SelectorList) -> Option<NodeId> {
+        std::iter::once(node_id)
+            .chain(self.tree.ancestors(node_id).map(|node| node.id))
+            .find(|&id| self.matches(id, selectors, node_id))
+    }
+
+    fn element(&self, node_id: NodeId) -> Option<&'a Node> {
+        self.tree
+            .node(node_id)
+            .filter(|node| node.type_of() == NodeType::Element)
+    }
+
+    fn parent_element(&self, node_id: NodeId) -> Option<&'a Node> {
+        let parent = self.tree.node(node_id)?.parent?;
+        self.element(parent)
+    }
+
+    /// Returns the element siblings of the node, including the node itself
+    fn element_siblings(&self, node_id: NodeId) -> Vec<&'a Node> {
+        let Some(parent) = self.tree.node(node_id).and_then(|node| node.parent) else {
+            return self.element(node_id).into_iter().collect();
+        };
+        let Some(parent) = self.tree.node(parent) else {
+            return Vec::new();
+        };
+
+        parent
+            .children
+            .iter()
+            .filter_map(|&child| self.element(child))
+            .collect()
+    }
+
+    fn previous_element_sibling(&self, node_id: NodeId) -> Option<&'a Node> {
+        let siblings = self.element_siblings(node_id);
+        let idx = siblings.iter().position(|node| node.id == node_id)?;
+        idx.checked_sub(1).map(|idx| siblings[idx])
+    }
+
+    /// Matches a selector list, where complex selectors are separated by list combinators
+    fn matches_list(&self, selectors: &[Selector], node_id: NodeId, context: Context) -> bool {
+        selectors
+            .split(|selector| {
+                matches!(
+                    selector,
+                    Selector::Combinator(Combinator::SelectorListCombinator)
+                )
+            })
+            .any(|complex| self.matches_complex(complex, node_id, context))
+    }
+
+    /// Matches a complex selector from right to left
+    fn matches_complex(&self, selectors: &[Selector], node_id: NodeId, context: Context) -> bool {
+        let Some(idx) = selectors.iter().rposition(Selector::is_combinator) else {
+            return self.matches_compound(selectors, node_id, context);
+        };
+
+        if !self.matches_compound(&selectors[idx + 1..], node_id, context) {
+            return false;
+        }
+
+        let Selector::Combinator(combinator) = &selectors[idx] else {
+            return false;
+        };
+        let left = &selectors[..idx];
+
+        // A relative selector starts with a combinator, which relates to the anchor element
+        let matches_left = |id: NodeId| match context.anchor {
+            Some(anchor) if left.is_empty() => id == anchor,
+            _ => !left.is_empty() && self.matches_complex(left, id, context),
+        };
+
+        match combinator {
+            Combinator::DescendantCombinator => self
+                .tree
+                .ancestors(node_id)
+                .filter(|node| node.type_of() == NodeType::Element)
+                .any(|node| matches_left(node.id)),
+            Combinator::ChildCombinator => self
+                .parent_element(node_id)
+                .is_some_and(|parent| matches_left(parent.id)),
+            Combinator::NextSiblingCombinator => self
+                .previous_element_sibling(node_id)
+                .is_some_and(|sibling| matches_left(sibling.id)),
+            Combinator::SubsequentSiblingCombinator => self
+                .element_siblings(node_id)
+                .iter()
+                .take_while(|sibling| sibling.id != node_id)
+                .any(|sibling| matches_left(sibling.id)),
+            // Columns only exist in rendered tables
+            Combinator::ColumnCombinator => false,
+            Combinator::SelectorListCombinator | Combinator::NamespaceSeparator => false,
+        }
+    }
+
+    fn matches_compound(&self, selectors: &[Selector], node_id: NodeId, context: Context) -> bool {
+        let Some(node) = self.element(node_id) else {
+            return false;
+        };
+        let NodeData::Element(element) = &node.data else {
+            return false;
+        };
+        let is_html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+
+        selectors.iter().all(|selector| match selector {
+            Selector::TypeSelector(type_selector) => {
+                type_selector.is_universal()
+                    || node.name == type_selector.name()
+                    || (is_html && node.name.eq_ignore_ascii_case(type_selector.name()))
+            }
+            Selector::IdSelector(id) => {
+                element.attributes.get("id").map(String::as_str) == Some(id.name())
+            }
+            Selector::ClassSelector(class) => element
+                .attributes
+                .get("class")
+                .is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class.name())),
+            Selector::AttributeSelector(attribute) => {
+                matches_attribute(&element.attributes, attribute, is_html)
+            }
+            Selector::PseudoClassSelector(pseudo) => {
+                self.matches_pseudo_class(pseudo, node, context)
+            }
+            Selector::NestingSelector(_) => self.is_scope(node_id, context),
+            // Pseudo-elements are not nodes in the tree, so they never match
+            Selector::PseudoElementSelector(_) => false,
+            Selector::Combinator(_) => false,
+        })
+    }
+
+    fn is_scope(&self, node_id: NodeId, context: Context) -> bool {
+        match self.tree.node(context.scope) {
+            Some(scope) if scope.type_of() == NodeType::Document => self.is_root(node_id),
+            Some(scope) => scope.id == node_id,
+            None => false,
+        }
+    }
+
+    fn is_root(&self, node_id: NodeId) -> bool {
+        self.tree
+            .node(node_id)
+            .and_then(|node| node.parent)
+            .and_then(|parent| self.tree.node(parent))
+            .is_some_and(|parent| parent.type_of() == NodeType::Document)
+    }
+
+    fn matches_pseudo_class(
+        &self,
+        pseudo: &PseudoClassSelector,
+        node: &Node,
+        context: Context,
+    ) -> bool {
+        let argument = pseudo.argument();
+        let attributes = attributes_of(node);
+        let has = |name: &str| attributes.is_some_and(|attributes| attributes.contains_key(name));
+        let is_html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+        let is_html_element = |names: &[&str]| is_html && names.contains(&node.name.as_str());
+
+        match (pseudo.name(), argument) {
+            ("not", Some(PseudoClassArgument::SelectorList(list))) => {
+                !self.matches_list(list.children(), node.id, context)
+            }
+            ("is" | "where", Some(PseudoClassArgument::SelectorList(list))) => {
+                self.matches_list(list.children(), node.id, context)
+            }
+            ("has", Some(PseudoClassArgument::SelectorList(list))) => {
+                self.has(list, node.id, context)
+            }
+            (
+                "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type",
+                Some(PseudoClassArgument::Nth(nth)),
+            ) => {
+                if let Some(selector) = nth.selector() {
+                    if !self.matches_list(selector.children(), node.id, context) {
+                        return false;
+                    }
+                }
+
+                let siblings = self.element_siblings(node.id);
+                let of_type = pseudo.name().ends_with("of-type");
+                let mut candidates = siblings.iter().filter(|sibling| {
+                    if of_type {
+                        return sibling.name == node.name && sibling.namespace == node.namespace;
+                    }
+                    nth.selector().map_or(true, |selector| {
+                        self.matches_list(selector.children(), sibling.id, context)
+                    })
+                });
+
+                let index = if pseudo.name().starts_with("nth-last") {
+                    candidates.rev().position(|sibling| sibling.id == node.id)
+                } else {
+                    candidates.position(|sibling| sibling.id == node.id)
+                };
+                index.is_some_and(|index| nth.value().matches(index + 1))
+            }
+            ("lang", Some(PseudoClassArgument::Identifier(range))) => self
+                .language(node)
+                .is_some_and(|lang| matches_language(&lang, range.name())),
+            ("dir", Some(PseudoClassArgument::Identifier(dir))) => {
+                self.direction(node).eq_ignore_ascii_case(dir.name())
+            }
+            (_, Some(_)) => false,
+
+            ("root", None) => self.is_root(node.id),
+            ("scope", None) => self.is_scope(node.id, context),
+            ("empty", None) => self.children_of(node).all(|child| match &child.data {
+                NodeData::Text(text) => text.value.is_empty(),
+                NodeData::Comment(_) => true,
+                _ => false,
+            }),
+            ("blank", None) => self.children_of(node).all(|child| match &child.data {
+                NodeData::Text(text) => text.value.chars().all(|c| c.is_ascii_whitespace()),
+                NodeData::Comment(_) => true,
+                _ => false,
+            }),
+            ("first-child", None) => {
+                self.element_siblings(node.id).first().map(|n| n.id) == Some(node.id)
+            }
+            ("last-child", None) => {
+                self.element_siblings(node.id).last().map(|n| n.id) == Some(node.id)
+            }
+            ("only-child", None) => self.element_siblings(node.id).len() == 1,
+            ("first-of-type" | "last-of-type" | "only-of-type", None) => {
+                let same_type: Vec<_> = self
+                    .element_siblings(node.id)
+                    .into_iter()
+                    .filter(|sibling| {
+                        sibling.name == node.name && sibling.namespace == node.namespace
+                    })
+                    .collect();
+                match pseudo.name() {
+                    "first-of-type" => same_type.first().map(|n| n.id) == Some(node.id),
+                    "last-of-type" => same_type.last().map(|n| n.id) == Some(node.id),
+                    _ => same_type.len() == 1,
+                }
+            }
+
+            // Links are never visited, as there is no history
+            ("any-link" | "link", None) => is_html_element(&["a", "area"]) && has("href"),
+            ("defined", None) => !is_html || !node.name.contains('-'),
+
+            ("disabled", None) => is_html_element(&DISABLEABLE_ELEMENTS) && self.is_disabled(node),
+            ("enabled", None) => is_html_element(&DISABLEABLE_ELEMENTS) && !self.is_disabled(node),
+            // Without user interaction, the checked and default states are the same
+            ("checked" | "default", None) => {
+                (is_html_element(&["input"])
+                    && ["checkbox", "radio"].contains(&input_type(node).as_str())
+                    && has("checked"))
+                    || (is_html_element(&["option"]) && has("selected"))
+            }
+            ("indeterminate", None) => is_html_element(&["progress"]) && !has("value"),
+            ("required", None) => {
+                is_html_element(&["input", "select", "textarea"]) && has("required")
+            }
+            ("optional", None) => {
+                is_html_element(&["input", "select", "textarea"]) && !has("required")
+            }
+            ("read-write", None) => self.is_read_write(node),
+            ("read-only", None) => !self.is_read_write(node),
+            ("placeholder-shown", None) => {
+                let empty = if is_html_element(&["input"]) {
+                    TEXT_INPUT_TYPES.contains(&input_type(node).as_str())
+                        && attribute(node, "value").map_or(true, str::is_empty)
+                } else {
+                    is_html_element(&["textarea"]) && self.children_of(node).next().is_none()
+                };
+                empty && has("placeholder")
+            }
+            ("valid", None) => {
+                is_html_element(&["input", "select", "textarea"]) && !self.is_invalid(node)
+            }
+            ("invalid", None) => {
+                is_html_element(&["input", "select", "textarea"]) && self.is_invalid(node)
+            }
+            ("in-range", None) => is_html_element(&["input"]) && range_state(node) == Some(true),
+            ("out-of-range", None) => {
+                is_html_element(&["input"]) && range_state(node) == Some(false)
+            }
+
+            // These depend on user interaction, navigation or media playback
+            _ => false,
+        }
+    }
+
+    fn children_of(&self, node: &'a Node) -> impl Iterator<Item = &'a Node> + 'a {
+        let tree = self.tree;
+        node.children
+            .iter()
+            .filter_map(move |&child| tree.node(child))
+    }
+
+    /// Matches the relative selectors of `:has()`, anchored at the given element
+    fn has(&self, list: &SelectorList, node_id: NodeId, context: Context) -> bool {
+        let context = Context {
+            anchor: Some(node_id),
+            ..context
+        };
+
+        // Candidates are the descendants of the anchor, and the following siblings of the anchor
+        // together with their descendants
+        let siblings = self.tree.following_siblings(node_id);
+        let mut candidates =
+            self.tree
+                .descendants(node_id)
+                .map(|node| node.id)
+                .chain(siblings.flat_map(|sibling| {
+                    std::iter::once(sibling.id)
+                        .chain(self.tree.descendants(sibling.id).map(|node| node.id))
+                }));
+
+        candidates.any(|id| self.matches_list(list.children(), id, context))
+    }
+
+    /// Returns the language of the element from the closest `lang` attribute
+    fn language(&self, node: &'a Node) -> Option<String> {
+        std::iter::once(node)
+            .chain(self.tree.ancestors(node.id))
+            .find_map(|node| attribute(node, "xml:lang").or(attribute(node, "lang")))
+            .map(str::to_string)
+    }
+
+    /// Returns the directionality of the element from the closest `dir` attribute
+    fn direction(&self, node: &'a Node) -> &'static str {
+        let dir = std::iter::once(node)
+            .chain(self.tree.ancestors(node.id))
+            .filter_map(|node| attribute(node, "dir"))
+            .find(|dir| dir.eq_ignore_ascii_case("ltr") || dir.eq_ignore_ascii_case("rtl"));
+
+        match dir {
+            Some(dir) if dir.eq_ignore_ascii_case("rtl") => "rtl",
+            _ => "ltr",
+        }
+    }
+
+    /// Returns true when the form control is [actually disabled](https://html.spec.whatwg.org/multipage/semantics-other.html#concept-element-disabled)
+    fn is_disabled(&self, node: &'a Node) -> bool {
+        if attribute(node, "disabled").is_some() {
+            return true;
+        }
+
+        match node.name.as_str() {
+            "option" => self.parent_element(node.id).is_some_and(|parent| {
+                parent.name == "optgroup" && attribute(parent, "disabled").is_some()
+            }),
+            "optgroup" => false,
+            // Controls in a disabled fieldset are disabled, except in its first legend
+            _ => {
+                let mut child = node;
+                for ancestor in self.tree.ancestors(node.id) {
+                    if ancestor.name == "fieldset" && attribute(ancestor, "disabled").is_some() {
+                        let first_legend = ancestor
+                            .children
+                            .iter()
+                            .filter_map(|&id| self.element(id))
+                            .find(|element| element.name == "legend");
+                        return first_legend.map_or(true, |legend| legend.id != child.id);
+                    }
+                    child = ancestor;
+                }
+                false
+            }
+        }
+    }
+
+    fn is_read_write(&self, node: &'a Node) -> bool {
+        if node.namespace.as_deref() != Some(HTML_NAMESPACE) {
+            return false;
+        }
+
+        match node.name.as_str() {
+            "input" => {
+                TEXT_INPUT_TYPES.contains(&input_type(node).as_str())
+                    && attribute(node, "readonly").is_none()
+                    && !self.is_disabled(node)
+            }
+            "textarea" => attribute(node, "readonly").is_none() && !self.is_disabled(node),
+            _ => std::iter::once(node)
+                .chain(self.tree.ancestors(node.id))
+                .find_map(|node| attribute(node, "contenteditable"))
+                .is_some_and(|editable| {
+                    editable.is_empty()
+                        || editable.eq_ignore_ascii_case("true")
+                        || editable.eq_ignore_ascii_case("plaintext-only")
+                }),
+        }
+    }
+
+    /// Returns true when a required control has no value. Other constraints are only checked
+    /// for range inputs.
+    fn is_invalid(&self, node: &'a Node) -> bool {
+        if range_state(node) == Some(false) {
+            return true;
+        }
+        if attribute(node, "required").is_none() || self.is_disabled(node) {
+            return false;
+        }
+
+        match node.name.as_str() {
+            "input" if ["checkbox", "radio"].contains(&input_type(node).as_str()) => {
+                attribute(node, "checked").is_none()
+            }
+            "input" => attribute(node, "value").map_or(true, str::is_empty),
+            "textarea" => self.children_of(node).all(|child| match &child.data {
+                NodeData::Text(text) => text.value.is_empty(),
+                _ => true,
+            }),
+            "select" => !self
+                .tree
+                .descendants(node.id)
+                .any(|option| option.name == "option" && attribute(option, "selected").is_some()),
+            _ => false,
+        }
+    }
+}
+
+fn attributes_of(node: &Node) -> Option<&HashMap<String, String>> {
+    match &node.data {
+        NodeData::Element(element) => Some(&element.attributes),
+        _ => None,
+    }
+}
+
+fn attribute<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    attributes_of(node)?.get(name).map(String::as_str)
+}
+
+/// Returns the lowercased type of an input element
+fn input_type(node: &Node) -> String {
+    attribute(node, "type")
+        .unwrap_or("text")
+        .to_ascii_lowercase()
+}
+
+/// Returns whether the value of a number or range input is within its range, or None when the
+/// input has no range
+fn range_state(node: &Node) -> Option<bool> {
+    if !["number", "range"].contains(&input_type(node).as_str()) {
+        return None;
+    }
+
+    let number =
+        |name: &str| attribute(node, name).and_then(|value| value.trim().parse::<f64>().ok());
+    let (min, max) = (number("min"), number("max"));
+    if min.is_none() && max.is_none() {
+        return None;
+    }
+
+    let Some(value) = number("value") else {
+        return Some(true);
+    };
+
+    Some(min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max))
+}
+
+/// Matches a language tag against a language range with [extended filtering](https://www.rfc-editor.org/rfc/rfc4647#section-3.3.2)
+/// on its first subtag only, like most browsers do
+fn matches_language(lang: &str, range: &str) -> bool {
+    if range == "*" {
+        return !lang.is_empty();
+    }
+
+    lang.eq_ignore_ascii_case(range)
+        || (lang.len() > range.len()
+            && lang.as_bytes()[range.len()] == b'-'
+            && lang[..range.len()].eq_ignore_ascii_case(range))
+}
+
+fn matches_attribute(
+    attributes: &HashMap<String, String>,
+    selector: &AttributeSelector,
+    is_html: bool,
+) -> bool {
+    let name = selector.name.name();
+    let value = attributes.iter().find_map(|(key, value)| {
+        let found = key == name || (is_html && key.eq_ignore_ascii_case(name));
+        found.then_some(value)
+    });
+
+    let Some(value) = value else {
+        return false;
+    };
+    let (Some(matcher), Some(expected)) = (&selector.matcher, &selector.value) else {
+        return true;
+    };
+
+    let ignore_case = match selector.flag.as_ref().map(|flag| flag.name()) {
+        Some("i") => true,
+        Some("s") => false,
+        _ => is_html && CASE_INSENSITIVE_ATTRIBUTES.contains(&name.to_ascii_lowercase().as_str()),
+    };
+    let (value, expected) = if ignore_case {
+        (value.to_lowercase(), expected.value().to_lowercase())
+    } else {
+        (value.clone(), expected.value().to_string())
+    };
+
+    match matcher {
+        AttributeMatcher::EqualityMatch => value == expected,
+        AttributeMatcher::IncludeMatch => {
+            !expected.is_empty()
+                && !expected.contains(|c: char| c.is_ascii_whitespace())
+                && value.split_ascii_whitespace().any(|word| word == expected)
+        }
+        AttributeMatcher::DashMatch => {
+            value == expected || value.starts_with(&format!("{expected}-"))
+        }
+        AttributeMatcher::PrefixMatch => !expected.is_empty() && value.starts_with(&expected),
+        AttributeMatcher::SuffixMatch => !expected.is_empty() && value.ends_with(&expected),
+        AttributeMatcher::SubstringMatch => !expected.is_empty() && value.contains(&expected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5::node::NodeId;
+    use crate::html5::parser::document::Document;
+    use crate::testing::document::parse_document;
+    use crate::types::Error;
+
+    const HTML: &str = r#"<!DOCTYPE html>
+        <html lang="en-US"><body>
+        <div id="main" class="box wide" data-kind="Card">
+          <p id="p1" class="note">one</p>
+          <p id="p2" lang="nl">two <a id="link" href="https://example.com/x.pdf">x</a></p>
+          <span id="s1"></span>
+          <p id="p3" class="note"><!-- comment --></p>
+          <section id="sec" dir="rtl"><p id="p4">four</p></section>
+        </div>
+        <form id="form">
+          <fieldset id="fs" disabled><legend><input id="in-legend"></legend><input id="in-fs" type="checkbox" checked></fieldset>
+          <input id="req" required placeholder="name">
+          <input id="num" type="number" min="1" max="5" value="7" readonly>
+          <textarea id="ta"></textarea>
+        </form>
+        <svg id="svg"><foreignObject id="fo"></foreignObject></svg>
+        <my-element id="custom"></my-element>
+        </body></html>"#;
+
+    /// Returns the id attributes of all elements that match the selectors. Elements without id
+    /// are returned as their tag, like `<html>`.
+    fn select(doc: &Document, selectors: &str) -> Vec<String> {
+        select_from(doc, NodeId::root(), selectors)
+    }
+
+    fn select_from(doc: &Document, root: NodeId, selectors: &str) -> Vec<String> {
+        doc.query_selector_all_from(root, selectors)
+            .unwrap_or_else(|err| panic!("{selectors}: {err}"))
+            .into_iter()
+            .map(|id| {
+                id_of(doc, id)
+                    .unwrap_or_else(|| format!("<{}>", doc.get_node_by_id(id).unwrap().name))
+            })
+            .collect()
+    }
+
+    fn id_of(doc: &Document, node_id: NodeId) -> Option<String> {
+        super::attribute(doc.get_node_by_id(node_id)?, "id").map(str::to_string)
+    }
+
+    fn by_id(doc: &Document, id: &str) -> NodeId {
+        doc.query_selector(&format!("#{id}")).unwrap().unwrap()
+    }
+
+    #[test]
+    fn simple_selectors_and_combinators() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+
+        assert_eq!(select(&doc, "p.note"), ["p1", "p3"]);
+        assert_eq!(select(&doc, "P, SPAN"), ["p1", "p2", "s1", "p3", "p4"]);
+        assert_eq!(select(&doc, "#main > p"), ["p1", "p2", "p3"]);
+        assert_eq!(select(&doc, "#main p"), ["p1", "p2", "p3", "p4"]);
+        assert_eq!(select(&doc, "#p1 + p"), ["p2"]);
+        assert_eq!(select(&doc, "#p2 ~ *"), ["s1", "p3", "sec"]);
+        assert_eq!(select(&doc, ".box.wide > .note ~ span"), ["s1"]);
+        assert_eq!(select(&doc, "div.box:not(.wide)"), Vec::<String>::new());
+
+        // Element names outside the HTML namespace are case-sensitive
+        assert_eq!(select(&doc, "foreignObject"), ["fo"]);
+        assert_eq!(select(&doc, "foreignobject"), Vec::<String>::new());
+        assert_eq!(select(&doc, "*|svg"), ["svg"]);
+    }
+
+    #[test]
+    fn attribute_selectors() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+
+        assert_eq!(select(&doc, "[href]"), ["link"]);
+        assert_eq!(select(&doc, "[HREF$='.pdf']"), ["link"]);
+        assert_eq!(select(&doc, "[href^=https][href*=example]"), ["link"]);
+        assert_eq!(select(&doc, "[class~=wide]"), ["main"]);
+        let html = doc.query_selector("html").unwrap().unwrap();
+        assert_eq!(doc.query_selector_all("[lang|=en]").unwrap(), [html]);
+        assert_eq!(doc.query_selector_all("[lang|=en-u]").unwrap(), []);
+        assert_eq!(select(&doc, "[data-kind=card]").len(), 0);
+        assert_eq!(select(&doc, "[data-kind=card i]"), ["main"]);
+        // The values of some HTML attributes are case-insensitive by default
+        assert_eq!(select(&doc, "[type=CHECKBOX]"), ["in-fs"]);
+        assert_eq!(select(&doc, "[type=CHECKBOX s]").len(), 0);
+        assert_eq!(select(&doc, "[href^='']").len(), 0);
+    }
+
+    #[test]
+    fn structural_pseudo_classes() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+
+        assert_eq!(select(&doc, "#main > :first-child"), ["p1"]);
+        assert_eq!(select(&doc, "#main > :last-child"), ["sec"]);
+        assert_eq!(select(&doc, "#main > p:last-of-type"), ["p3"]);
+        assert_eq!(select(&doc, "#main > :only-of-type"), ["s1", "sec"]);
+        assert_eq!(select(&doc, "p:only-child"), ["p4"]);
+        assert_eq!(
+            select(&doc, "#main > :nth-child(2n+1)"),
+            ["p1", "s1", "sec"]
+        );
+        assert_eq!(select(&doc, "#main > :nth-last-child(-n+2)"), ["p3", "sec"]);
+        assert_eq!(select(&doc, "#main > :nth-child(2 of .note)"), ["p3"]);
+        assert_eq!(select(&doc, "#main > :nth-of-type(even)"), ["p2"]);
+        assert_eq!(
+            select(&doc, "#main > :nth-last-of-type(1)"),
+            ["s1", "p3", "sec"]
+        );
+        assert_eq!(select(&doc, "#main > :empty"), ["s1", "p3"]);
+        assert_eq!(select(&doc, ":root"), ["<html>"]);
+        assert_eq!(
+            doc.query_selector(":root").unwrap(),
+            doc.query_selector("html").unwrap()
+        );
+    }
+
+    #[test]
+    fn logical_pseudo_classes() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+
+        assert_eq!(select(&doc, ":is(#p1, #s1, :unknown)"), ["p1", "s1"]);
+        assert_eq!(
+            select(&doc, "#main > :where(p, span):not(.note)"),
+            ["p2", "s1"]
+        );
+        assert_eq!(select(&doc, "p:has(a)"), ["p2"]);
+        assert_eq!(select(&doc, "#main > :has(> p)"), ["sec"]);
+        assert_eq!(select(&doc, "p:has(+ span)"), ["p2"]);
+        assert_eq!(select(&doc, "p:has(~ section p)"), ["p1", "p2", "p3"]);
+        assert_eq!(select(&doc, "div:has(p:has(a))"), ["main"]);
+        assert_eq!(select(&doc, "p:not(:has(*))"), ["p1", "p3", "p4"]);
+    }
+
+    #[test]
+    fn element_pseudo_classes() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+
+        assert_eq!(select(&doc, ":any-link"), ["link"]);
+        assert_eq!(select(&doc, ":lang(en)").len(), 20);
+        assert_eq!(select(&doc, ":lang(nl)"), ["p2", "link"]);
+        assert_eq!(select(&doc, ":dir(rtl)"), ["sec", "p4"]);
+        assert_eq!(select(&doc, ":defined#custom").len(), 0);
+        assert_eq!(select(&doc, "input:disabled"), ["in-fs"]);
+        assert_eq!(select(&doc, ":enabled"), ["in-legend", "req", "num", "ta"]);
+        assert_eq!(select(&doc, ":checked"), ["in-fs"]);
+        assert_eq!(select(&doc, ":required"), ["req"]);
+        assert_eq!(select(&doc, "form :read-write"), ["in-legend", "req", "ta"]);
+        assert_eq!(select(&doc, ":placeholder-shown"), ["req"]);
+        assert_eq!(select(&doc, ":invalid"), ["req", "num"]);
+        assert_eq!(select(&doc, ":out-of-range"), ["num"]);
+        assert_eq!(select(&doc, ":hover, :focus, :target, :visited").len(), 0);
+        assert_eq!(select(&doc, "p::before").len(), 0);
+    }
+
+    #[test]
+    fn scoped_queries() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+        let main = by_id(&doc, "main");
+
+        assert_eq!(select_from(&doc, main, ":scope > p"), ["p1", "p2", "p3"]);
+        assert_eq!(select_from(&doc, main, "div p"), ["p1", "p2", "p3", "p4"]);
+        assert_eq!(select_from(&doc, main, ":scope div p").len(), 0);
+
+        let link = by_id(&doc, "link");
+        assert!(doc.matches(link, "p > a[href]").unwrap());
+        assert!(!doc.matches(NodeId::root(), "*").unwrap());
+        assert_eq!(doc.closest(link, "p").unwrap(), Some(by_id(&doc, "p2")));
+        assert_eq!(doc.closest(link, ".box").unwrap(), Some(main));
+        assert_eq!(doc.closest(link, "form").unwrap(), None);
+
+        let node = doc.get_node_by_id(main).unwrap();
+        assert_eq!(
+            node.query_selector(".note").unwrap(),
+            Some(by_id(&doc, "p1"))
+        );
+        assert_eq!(node.query_selector_all(":scope > .note").unwrap().len(), 2);
+        assert!(node.matches("div:has(> #s1)").unwrap());
+        assert_eq!(
+            node.closest("body").unwrap(),
+            doc.query_selector("body").unwrap()
+        );
+
+        assert!(matches!(
+            doc.query_selector("p >"),
+            Err(Error::CssSyntax(_))
+        ));
+        assert!(matches!(
+            node.matches(":nth-child(x)"),
+            Err(Error::CssSyntax(_))
+        ));
+    }
+}
@@ -0,0 +1,777 @@
+//! Tree traversal
+//!
+//! Implements the `TreeWalker` and `NodeIterator` interfaces from the
+//! [DOM standard](https://dom.spec.whatwg.org/#traversal), together with plain Rust iterators
+//! over the descendants, ancestors and siblings of a node. Everything works on any
+//! [`Traversable`] node storage, like a [`Document`] or a [`NodeArena`].
+//!
+//! Traversal objects borrow the tree, so the tree cannot change while they are in use. This is
+//! why the node iterator does not need the "pre-removing steps" from the standard.
+use crate::html5::node::arena::NodeArena;
+use crate::html5::node::{Node, NodeId, NodeTrait, NodeType};
+use crate::html5::parser::document::Document;
+
+/// Shows all nodes
+pub const SHOW_ALL: u32 = 0xFFFF_FFFF;
+/// Shows element nodes
+pub const SHOW_ELEMENT: u32 = 0x1;
+/// Shows attribute nodes. Attributes are not stored as nodes, so this has no effect.
+pub const SHOW_ATTRIBUTE: u32 = 0x2;
+/// Shows text nodes
+pub const SHOW_TEXT: u32 = 0x4;
+/// Shows CDATA section nodes. HTML documents do not have these.
+pub const SHOW_CDATA_SECTION: u32 = 0x8;
+/// Shows processing instruction nodes. HTML documents do not have these.
+pub const SHOW_PROCESSING_INSTRUCTION: u32 = 0x40;
+/// Shows comment nodes
+pub const SHOW_COMMENT: u32 = 0x80;
+/// Shows document nodes
+pub const SHOW_DOCUMENT: u32 = 0x100;
+/// Shows doctype nodes
+pub const SHOW_DOCUMENT_TYPE: u32 = 0x200;
+/// Shows document fragment nodes
+pub const SHOW_DOCUMENT_FRAGMENT: u32 = 0x400;
+
+/// Result of a node filter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterResult {
+    /// The node is returned by the traversal
+    Accept,
+    /// The node and its descendants are skipped. Node iterators treat this the same as Skip.
+    Reject,
+    /// The node is skipped, but its descendants are not
+    Skip,
+}
+
+/// Callback that decides which nodes are returned by a traversal
+pub type NodeFilter<'a> = Box<dyn Fn(&Node) -> FilterResult + 'a>;
+
+/// Storage in which nodes can be looked up by their id
+pub trait Traversable {
+    /// Returns the node with the given id
+    fn node(&self, node_id: NodeId) -> Option<&Node>;
+
+    /// Returns all descendants of the node in tree order, not including the node itself
+    fn descendants(&self, node_id: NodeId) -> Descendants<'_>
+    where
+        Self: Sized,
+    {
+        Descendants {
+            tree: self,
+            root: node_id,
+            next: first_child(self, node_id),
+        }
+    }
+
+    /// Returns the ancestors of the node, starting with its parent and ending with the root
+    fn ancestors(&self, node_id: NodeId) -> Ancestors<'_>
+    where
+        Self: Sized,
+    {
+        Ancestors {
+            tree: self,
+            next: self.node(node_id).and_then(|node| node.parent),
+        }
+    }
+
+    /// Returns the siblings after the node in tree order
+    fn following_siblings(&self, node_id: NodeId) -> Siblings<'_>
+    where
+        Self: Sized,
+    {
+        let (siblings, idx) = siblings_of(self, node_id);
+        Siblings {
+            tree: self,
+            ids: siblings.get(idx + 1..).unwrap_or_default().iter(),
+        }
+    }
+
+    /// Returns the siblings before the node in tree order. Use `rev()` to start with the
+    /// sibling closest to the node.
+    fn preceding_siblings(&self, node_id: NodeId) -> Siblings<'_>
+    where
+        Self: Sized,
+    {
+        let (siblings, idx) = siblings_of(self, node_id);
+        Siblings {
+            tree: self,
+            ids: siblings[..idx].iter(),
+        }
+    }
+
+    /// Creates a tree walker for the subtree of the given root
+    fn create_tree_walker<'a>(
+        &'a self,
+        root: NodeId,
+        what_to_show: u32,
+        filter: Option<NodeFilter<'a>>,
+    ) -> TreeWalker<'a>
+    where
+        Self: Sized,
+    {
+        TreeWalker::new(self, root, what_to_show, filter)
+    }
+
+    /// Creates a node iterator for the subtree of the given root
+    fn create_node_iterator<'a>(
+        &'a self,
+        root: NodeId,
+        what_to_show: u32,
+        filter: Option<NodeFilter<'a>>,
+    ) -> NodeIterator<'a>
+    where
+        Self: Sized,
+    {
+        NodeIterator::new(self, root, what_to_show, filter)
+    }
+}
+
+impl Traversable for Document {
+    fn node(&self, node_id: NodeId) -> Option<&Node> {
+        self.get_node_by_id(node_id)
+    }
+}
+
+impl Traversable for NodeArena {
+    fn node(&self, node_id: NodeId) -> Option<&Node> {
+        self.get_node(node_id)
+    }
+}
+
+/// Returns the children of the parent of the node, and the index of the node in them. A node
+/// without a parent is treated as an only child.
+fn siblings_of(tree: &dyn Traversable, node_id: NodeId) -> (&[NodeId], usize) {
+    let siblings = tree
+        .node(node_id)
+        .and_then(|node| node.parent)
+        .and_then(|parent_id| tree.node(parent_id))
+        .map(|parent| parent.children.as_slice())
+        .unwrap_or_default();
+
+    match siblings.iter().position(|id| *id == node_id) {
+        Some(idx) => (siblings, idx),
+        None => (&[], 0),
+    }
+}
+
+fn parent(tree: &dyn Traversable, node_id: NodeId) -> Option<NodeId> {
+    tree.node(node_id).and_then(|node| node.parent)
+}
+
+fn first_child(tree: &dyn Traversable, node_id: NodeId) -> Option<NodeId> {
+    tree.node(node_id)
+        .and_then(|node| node.children.first().copied())
+}
+
+fn last_child(tree: &dyn Traversable, node_id: NodeId) -> Option<NodeId> {
+    tree.node(node_id)
+        .and_then(|node| node.children.last().copied())
+}
+
+fn next_sibling(tree: &dyn Traversable, node_id: NodeId) -> Option<NodeId> {
+    let (siblings, idx) = siblings_of(tree, node_id);
+    siblings.get(idx + 1).copied()
+}
+
+fn previous_sibling(tree: &dyn Traversable, node_id: NodeId) -> Option<NodeId> {
+    let (siblings, idx) = siblings_of(tree, node_id);
+    idx.checked_sub(1).map(|idx| siblings[idx])
+}
+
+/// Returns the node that follows the given node in tree order, without leaving the subtree of
+/// the root
+fn following(tree: &dyn Traversable, node_id: NodeId, root: NodeId) -> Option<NodeId> {
+    if let Some(child_id) = first_child(tree, node_id) {
+        return Some(child_id);
+    }
+
+    let mut current = node_id;
+    while current != root {
+        if let Some(sibling_id) = next_sibling(tree, current) {
+            return Some(sibling_id);
+        }
+        current = parent(tree, current)?;
+    }
+    None
+}
+
+/// Returns the node that precedes the given node in tree order, without leaving the subtree of
+/// the root
+fn preceding(tree: &dyn Traversable, node_id: NodeId, root: NodeId) -> Option<NodeId> {
+    if node_id == root {
+        return None;
+    }
+
+    match previous_sibling(tree, node_id) {
+        Some(mut current) => {
+            while let Some(child_id) = last_child(tree, current) {
+                current = child_id;
+            }
+            Some(current)
+        }
+        None => parent(tree, node_id),
+    }
+}
+
+/// Returns the bit in a whatToShow mask for the type of the node
+fn show_bit(node: &Node) -> u32 {
+    match node.type_of() {
+        NodeType::Element => SHOW_ELEMENT,
+        NodeType::Text => SHOW_TEXT,
+        NodeType::Comment => SHOW_COMMENT,
+        NodeType::Document => SHOW_DOCUMENT,
+        NodeType::DocType => SHOW_DOCUMENT_TYPE,
+        NodeType::DocumentFragment => SHOW_DOCUMENT_FRAGMENT,
+    }
+}
+
+/// Runs the whatToShow mask and the filter callback against the node
+fn filter_node(
+    tree: &dyn Traversable,
+    node_id: NodeId,
+    what_to_show: u32,
+    filter: &Option<NodeFilter>,
+) -> FilterResult {
+    let Some(node) = tree.node(node_id) else {
+        return FilterResult::Reject;
+    };
+
+    if what_to_show & show_bit(node) == 0 {
+        return FilterResult::Skip;
+    }
+
+    match filter {
+        Some(filter) => filter(node),
+        None => FilterResult::Accept,
+    }
+}
+
+/// Walks a subtree in any direction, see https://dom.spec.whatwg.org/#interface-treewalker
+pub struct TreeWalker<'a> {
+    tree: &'a dyn Traversable,
+    root: NodeId,
+    what_to_show: u32,
+    filter: Option<NodeFilter<'a>>,
+    current: NodeId,
+}
+
+impl<'a> TreeWalker<'a> {
+    /// Creates a new tree walker. The current node starts at the root.
+    pub fn new(
+        tree: &'a dyn Traversable,
+        root: NodeId,
+        what_to_show: u32,
+        filter: Option<NodeFilter<'a>>,
+    ) -> Self {
+        TreeWalker {
+            tree,
+            root,
+            what_to_show,
+            filter,
+            current: root,
+        }
+    }
+
+    /// Returns the root of the walker
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Returns the whatToShow mask of the walker
+    pub fn what_to_show(&self) -> u32 {
+        self.what_to_show
+    }
+
+    /// Returns the node the walker is currently at
+    pub fn current_node(&self) -> NodeId {
+        self.current
+    }
+
+    /// Moves the walker to the given node. This node does not have to be accepted by the filter.
+    pub fn set_current_node(&mut self, node_id: NodeId) {
+        self.current = node_id;
+    }
+
+    /// Moves to the closest accepted ancestor of the current node within the root
+    pub fn parent_node(&mut self) -> Option<&'a Node> {
+        let mut node_id = self.current;
+        while node_id != self.root {
+            node_id = parent(self.tree, node_id)?;
+            if self.filter(node_id) == FilterResult::Accept {
+                return self.move_to(node_id);
+            }
+        }
+        None
+    }
+
+    /// Moves to the first accepted child of the current node
+    pub fn first_child(&mut self) -> Option<&'a Node> {
+        self.traverse_children(true)
+    }
+
+    /// Moves to the last accepted child of the current node
+    pub fn last_child(&mut self) -> Option<&'a Node> {
+        self.traverse_children(false)
+    }
+
+    /// Moves to the next accepted sibling of the current node
+    pub fn next_sibling(&mut self) -> Option<&'a Node> {
+        self.traverse_siblings(true)
+    }
+
+    /// Moves to the previous accepted sibling of the current node
+    pub fn previous_sibling(&mut self) -> Option<&'a Node> {
+        self.traverse_siblings(false)
+    }
+
+    /// Moves to the previous accepted node in tree order
+    pub fn previous_node(&mut self) -> Option<&'a Node> {
+        let mut node_id = self.current;
+        while node_id != self.root {
+            let mut sibling = previous_sibling(self.tree, node_id);
+            while let Some(sibling_id) = sibling {
+                node_id = sibling_id;
+                let mut result = self.filter(node_id);
+                while result != FilterResult::Reject {
+                    let Some(child_id) = last_child(self.tree, node_id) else {
+                        break;
+                    };
+                    node_id = child_id;
+                    result = self.filter(node_id);
+                }
+                if result == FilterResult::Accept {
+                    return self.move_to(node_id);
+                }
+                sibling = previous_sibling(self.tree, node_id);
+            }
+
+            if node_id == self.root {
+                return None;
+            }
+            node_id = parent(self.tree, node_id)?;
+            if self.filter(node_id) == FilterResult::Accept {
+                return self.move_to(node_id);
+            }
+        }
+        None
+    }
+
+    /// Moves to the next accepted node in tree order
+    pub fn next_node(&mut self) -> Option<&'a Node> {
+        let mut node_id = self.current;
+        let mut result = FilterResult::Accept;
+        loop {
+            while result != FilterResult::Reject {
+                let Some(child_id) = first_child(self.tree, node_id) else {
+                    break;
+                };
+                node_id = child_id;
+                result = self.filter(node_id);
+                if result == FilterResult::Accept {
+                    return self.move_to(node_id);
+                }
+            }
+
+            let mut temporary = Some(node_id);
+            let mut sibling = None;
+            while let Some(temporary_id) = temporary {
+                if temporary_id == self.root {
+                    return None;
+                }
+                sibling = next_sibling(self.tree, temporary_id);
+                if sibling.is_some() {
+                    break;
+                }
+                temporary = parent(self.tree, temporary_id);
+            }
+
+            node_id = sibling?;
+            result = self.filter(node_id);
+            if result == FilterResult::Accept {
+                return self.move_to(node_id);
+            }
+        }
+    }
+
+    fn filter(&self, node_id: NodeId) -> FilterResult {
+        filter_node(self.tree, node_id, self.what_to_show, &self.filter)
+    }
+
+    fn move_to(&mut self, node_id: NodeId) -> Option<&'a Node> {
+        self.current = node_id;
+        self.tree.node(node_id)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-traverse-children
+    fn traverse_children(&mut self, first: bool) -> Option<&'a Node> {
+        let child = |node_id| {
+            if first {
+                first_child(self.tree, node_id)
+            } else {
+                last_child(self.tree, node_id)
+            }
+        };
+        let sibling = |node_id| {
+            if first {
+                next_sibling(self.tree, node_id)
+            } else {
+                previous_sibling(self.tree, node_id)
+            }
+        };
+
+        let mut node_id = child(self.current)?;
+        loop {
+            match self.filter(node_id) {
+                FilterResult::Accept => return self.move_to(node_id),
+                FilterResult::Skip => {
+                    if let Some(child_id) = child(node_id) {
+                        node_id = child_id;
+                        continue;
+                    }
+                }
+                FilterResult::Reject => {}
+            }
+
+            loop {
+                if let Some(sibling_id) = sibling(node_id) {
+                    node_id = sibling_id;
+                    break;
+                }
+                let parent_id = parent(self.tree, node_id)?;
+                if parent_id == self.root || parent_id == self.current {
+                    return None;
+                }
+                node_id = parent_id;
+            }
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-traverse-siblings
+    fn traverse_siblings(&mut self, next: bool) -> Option<&'a Node> {
+        let child = |node_id| {
+            if next {
+                first_child(self.tree, node_id)
+            } else {
+                last_child(self.tree, node_id)
+            }
+        };
+        let sibling = |node_id| {
+            if next {
+                next_sibling(self.tree, node_id)
+            } else {
+                previous_sibling(self.tree, node_id)
+            }
+        };
+
+        let mut node_id = self.current;
+        if node_id == self.root {
+            return None;
+        }
+
+        loop {
+            let mut sibling_id = sibling(node_id);
+            while let Some(id) = sibling_id {
+                node_id = id;
+                let result = self.filter(node_id);
+                if result == FilterResult::Accept {
+                    return self.move_to(node_id);
+                }
+
+                sibling_id = child(node_id);
+                if result == FilterResult::Reject || sibling_id.is_none() {
+                    sibling_id = sibling(node_id);
+                }
+            }
+
+            node_id = parent(self.tree, node_id)?;
+            if node_id == self.root || self.filter(node_id) == FilterResult::Accept {
+                return None;
+            }
+        }
+    }
+}
+
+/// Iterates a subtree in tree order, see https://dom.spec.whatwg.org/#interface-nodeiterator
+pub struct NodeIterator<'a> {
+    tree: &'a dyn Traversable,
+    root: NodeId,
+    what_to_show: u32,
+    filter: Option<NodeFilter<'a>>,
+    reference: NodeId,
+    pointer_before_reference: bool,
+}
+
+impl<'a> NodeIterator<'a> {
+    /// Creates a new node iterator. The iterator starts before the root.
+    pub fn new(
+        tree: &'a dyn Traversable,
+        root: NodeId,
+        what_to_show: u32,
+        filter: Option<NodeFilter<'a>>,
+    ) -> Self {
+        NodeIterator {
+            tree,
+            root,
+            what_to_show,
+            filter,
+            reference: root,
+            pointer_before_reference: true,
+        }
+    }
+
+    /// Returns the root of the iterator
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Returns the whatToShow mask of the iterator
+    pub fn what_to_show(&self) -> u32 {
+        self.what_to_show
+    }
+
+    /// Returns the node the iterator is positioned at
+    pub fn reference_node(&self) -> NodeId {
+        self.reference
+    }
+
+    /// Returns true when the iterator is positioned before the reference node
+    pub fn pointer_before_reference_node(&self) -> bool {
+        self.pointer_before_reference
+    }
+
+    /// Returns the next accepted node in tree order
+    pub fn next_node(&mut self) -> Option<&'a Node> {
+        self.traverse(true)
+    }
+
+    /// Returns the previous accepted node in tree order
+    pub fn previous_node(&mut self) -> Option<&'a Node> {
+        self.traverse(false)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-nodeiterator-traverse
+    fn traverse(&mut self, next: bool) -> Option<&'a Node> {
+        let mut node_id = self.reference;
+        let mut before = self.pointer_before_reference;
+
+        loop {
+            if next {
+                if before {
+                    before = false;
+                } else {
+                    node_id = following(self.tree, node_id, self.root)?;
+                }
+            } else if before {
+                node_id = preceding(self.tree, node_id, self.root)?;
+            } else {
+                before = true;
+            }
+
+            if filter_node(self.tree, node_id, self.what_to_show, &self.filter)
+                == FilterResult::Accept
+            {
+                break;
+            }
+        }
+
+        self.reference = node_id;
+        self.pointer_before_reference = before;
+        self.tree.node(node_id)
+    }
+}
+
+impl<'a> Iterator for NodeIterator<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_node()
+    }
+}
+
+/// Iterator over the descendants of a node in tree order
+pub struct Descendants<'a> {
+    tree: &'a dyn Traversable,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_id = self.next?;
+        self.next = following(self.tree, node_id, self.root);
+        self.tree.node(node_id)
+    }
+}
+
+/// Iterator over the ancestors of a node, starting with its parent
+pub struct Ancestors<'a> {
+    tree: &'a dyn Traversable,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.tree.node(self.next?)?;
+        self.next = node.parent;
+        Some(node)
+    }
+}
+
+/// Iterator over (some of) the siblings of a node in tree order
+pub struct Siblings<'a> {
+    tree: &'a dyn Traversable,
+    ids: std::slice::Iter<'a, NodeId>,
+}
+
+impl<'a> Iterator for Siblings<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids.next().and_then(|id| self.tree.node(*id))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Siblings<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids.next_back().and_then(|id| self.tree.node(*id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::node::NodeData;
+    use crate::testing::document::parse_document;
+
+    /// Returns a short name for the node: the tag name of elements and the contents of text
+    fn label(node: &Node) -> String {
+        match &node.data {
+            NodeData::Text(text) => format!("#{}", text.value()),
+            NodeData::Comment(comment) => format!("!{}", comment.value()),
+            _ => node.name.clone(),
+        }
+    }
+
+    fn labels<'a>(nodes: impl Iterator<Item = &'a Node>) -> Vec<String> {
+        nodes.map(label).collect()
+    }
+
+    fn find(doc: &Document, name: &str) -> NodeId {
+        doc.descendants(NodeId::root())
+            .find(|node| node.name == name)
+            .expect("node")
+            .id
+    }
+
+    const HTML: &str =
+        "<!DOCTYPE html><div id=a><p>one<!--c--></p><ul><li>x</li><li>y</li></ul>two</div>";
+
+    #[test]
+    fn iterators() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+        let div = find(&doc, "div");
+        let ul = find(&doc, "ul");
+
+        assert_eq!(
+            labels(doc.descendants(div)),
+            ["p", "#one", "!c", "ul", "li", "#x", "li", "#y", "#two"]
+        );
+        assert_eq!(labels(doc.ancestors(ul)), ["div", "body", "html", ""]);
+        assert_eq!(labels(doc.following_siblings(ul)), ["#two"]);
+        assert_eq!(labels(doc.preceding_siblings(ul)), ["p"]);
+        assert_eq!(
+            labels(doc.following_siblings(find(&doc, "p")).rev()),
+            ["#two", "ul"]
+        );
+        assert_eq!(
+            labels(doc.preceding_siblings(NodeId::root())),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn tree_walker() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+        let div = find(&doc, "div");
+
+        let mut walker = doc.create_tree_walker(div, SHOW_ELEMENT, None);
+        let mut visited = vec![];
+        while let Some(node) = walker.next_node() {
+            visited.push(label(node));
+        }
+        assert_eq!(visited, ["p", "ul", "li", "li"]);
+
+        let mut visited = vec![];
+        while let Some(node) = walker.previous_node() {
+            visited.push(label(node));
+        }
+        assert_eq!(visited, ["li", "ul", "p", "div"]);
+
+        assert_eq!(walker.first_child().map(label), Some("p".into()));
+        assert_eq!(walker.next_sibling().map(label), Some("ul".into()));
+        assert_eq!(walker.last_child().map(label), Some("li".into()));
+        assert_eq!(walker.previous_sibling().map(label), Some("li".into()));
+        assert!(walker.previous_sibling().is_none());
+        assert_eq!(walker.parent_node().map(label), Some("ul".into()));
+        assert_eq!(walker.parent_node().map(label), Some("div".into()));
+        assert!(walker.parent_node().is_none());
+    }
+
+    #[test]
+    fn tree_walker_filter() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+        let div = find(&doc, "div");
+
+        // Skipping the ul still shows its children, rejecting the p hides its text
+        let filter: NodeFilter = Box::new(|node| match node.name.as_str() {
+            "ul" => FilterResult::Skip,
+            "p" => FilterResult::Reject,
+            _ => FilterResult::Accept,
+        });
+        let mut walker = doc.create_tree_walker(div, SHOW_ALL, Some(filter));
+
+        let mut visited = vec![];
+        while let Some(node) = walker.next_node() {
+            visited.push(label(node));
+        }
+        assert_eq!(visited, ["li", "#x", "li", "#y", "#two"]);
+
+        walker.set_current_node(div);
+        assert_eq!(walker.first_child().map(label), Some("li".into()));
+        assert_eq!(walker.parent_node().map(label), Some("div".into()));
+    }
+
+    #[test]
+    fn node_iterator() {
+        let document = parse_document(HTML);
+        let doc = document.get();
+        let div = find(&doc, "div");
+
+        let iterator = doc.create_node_iterator(div, SHOW_TEXT | SHOW_COMMENT, None);
+        assert_eq!(labels(iterator), ["#one", "!c", "#x", "#y", "#two"]);
+
+        let filter: NodeFilter = Box::new(|node| {
+            if node.name == "li" {
+                FilterResult::Accept
+            } else {
+                FilterResult::Reject
+            }
+        });
+        let mut iterator = doc.create_node_iterator(div, SHOW_ELEMENT, Some(filter));
+        assert_eq!(iterator.next_node().map(label), Some("li".into()));
+        assert_eq!(iterator.next_node().map(label), Some("li".into()));
+        assert!(iterator.next_node().is_none());
+        assert!(!iterator.pointer_before_reference_node());
+        assert_eq!(iterator.previous_node().map(label), Some("li".into()));
+        assert!(iterator.pointer_before_reference_node());
+        assert_eq!(iterator.previous_node().map(label), Some("li".into()));
+        assert!(iterator.previous_node().is_none());
+    }
+}
@@ -1,24 +1,28 @@
 mod attr_replacements;
 pub mod document;
-mod quirks;
+pub(crate) mod quirks;
 pub mod tree_builder;
 
 // ------------------------------------------------------------
 
 use self::document::DocumentHandle;
 use super::node::NodeId;
-use crate::bytes::CharIterator;
+use crate::bytes::sniffer;
+use crate::bytes::{CharIterator, Confidence, Encoding, Span};
+use crate::html5::dom::{ShadowRootInit, ShadowRootMode, SlotAssignmentMode};
 use crate::html5::error_logger::{ErrorLogger, ParserError};
+use crate::html5::node::traversal::Traversable;
 use crate::html5::node::{Node, NodeData, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
 use crate::html5::parser::attr_replacements::{
     MATHML_ADJUSTMENTS, SVG_ADJUSTMENTS_ATTRIBUTES, SVG_ADJUSTMENTS_TAGS, XML_ADJUSTMENTS,
 };
 use crate::html5::parser::document::{Document, DocumentBuilder, DocumentFragment, DocumentType};
 use crate::html5::parser::quirks::QuirksMode;
+use crate::html5::preload_scanner::{PreloadRequest, PreloadScanner};
 use crate::html5::tokenizer::state::State;
-use crate::html5::tokenizer::token::Token;
+use crate::html5::tokenizer::token::{SpannedToken, Token};
 use crate::html5::tokenizer::{ParserData, Tokenizer, CHAR_REPLACEMENT};
-use crate::types::{ParseError, Result};
+use crate::types::{Error, ErrorTrigger, Limit, ParseError, Result};
 use alloc::rc::Rc;
 use core::cell::RefCell;
 use core::option::Option::Some;
@@ -101,23 +105,36 @@ macro_rules! get_node_by_id {
 macro_rules! current_node {
     ($self:expr) => {{
         let current_node_idx = $self.open_elements.last().unwrap_or_default();
-        $self
-            .document
-            .get()
-            .get_node_by_id(*current_node_idx)
-            .expect("Current node not found")
-            .clone()
+        $self.get_node(*current_node_idx)
     }};
 }
 
 macro_rules! open_elements_get {
     ($self:expr, $idx:expr) => {{
-        $self
-            .document
-            .get()
-            .get_node_by_id($self.open_elements[$idx])
-            .expect("Current node not found")
-            .clone()
+        $self.get_node($self.open_elements[$idx])
+    }};
+}
+
+/// Stops processing the current token when the parser runs in strict mode and a parse error has
+/// been found, so the tree builder does not act on the invalid token
+macro_rules! return_on_strict_error {
+    ($self:expr) => {
+        if $self.strict_error_found() {
+            return;
+        }
+    };
+}
+
+/// Sends a parse error for the current token to the error logger, optionally with an error code.
+/// In strict mode, processing of the token stops right here.
+macro_rules! parse_error {
+    ($self:expr, $message:expr $(,)?) => {{
+        $self.parse_error($message);
+        return_on_strict_error!($self);
+    }};
+    ($self:expr, $code:expr, $message:expr $(,)?) => {{
+        $self.parse_error_with_code($code, $message);
+        return_on_strict_error!($self);
     }};
 }
 
@@ -140,18 +157,63 @@ impl ActiveElement {
     }
 }
 
+#[derive(Clone)]
 pub struct Html5ParserOptions {
     pub scripting_enabled: bool,
+    /// When set, parsing is aborted with an error on the first parse error
+    pub strict: bool,
+    /// Maximum number of parse errors that are collected. Parsing continues when the maximum is
+    /// reached, but any further errors are discarded.
+    pub max_errors: Option<usize>,
+    /// Limits on the resources used while parsing
+    pub limits: Html5ParserLimits,
+    /// Attach templates with a shadowrootmode attribute as shadow roots to their parent element,
+    /// see [allow declarative shadow roots](https://dom.spec.whatwg.org/#document-allow-declarative-shadow-roots)
+    pub allow_declarative_shadow_roots: bool,
+}
+
+/// Limits on the resources the parser may use, to protect against hostile input. Parsing stops
+/// with Error::LimitExceeded when a limit is exceeded. A limit of None means there is no limit.
+#[derive(Clone, Debug, Default)]
+pub struct Html5ParserLimits {
+    /// Maximum depth of the document tree (the document node has depth 0)
+    pub max_tree_depth: Option<usize>,
+    /// Maximum number of nodes in the node arena of the document
+    pub max_node_count: Option<usize>,
+    /// Maximum number of attributes on a single element
+    pub max_attributes: Option<usize>,
+    /// Maximum length in bytes of an attribute name or value
+    pub max_attribute_length: Option<usize>,
+    /// Maximum length in bytes of a text node
+    pub max_text_length: Option<usize>,
+    /// Maximum number of elements on the stack of open elements
+    pub max_open_elements: Option<usize>,
 }
 
 impl Default for Html5ParserOptions {
     fn default() -> Self {
         Html5ParserOptions {
             scripting_enabled: true,
+            strict: false,
+            max_errors: None,
+            limits: Html5ParserLimits::default(),
+            allow_declarative_shadow_roots: true,
         }
     }
 }
 
+/// Status of the parser after it has parsed the input that is currently available
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ParserStatus {
+    /// The whole input has been parsed and the document is complete
+    Finished,
+    /// The input stream ran out and the parser needs more input to continue
+    NeedsInput,
+    /// The parser is paused on a parsing-blocking script that has just been inserted. The script
+    /// can be executed (or skipped) before parsing continues with resume().
+    PendingScript(NodeId),
+}
+
 /// The main parser object
 pub struct Html5Parser<'chars> {
     /// tokenizer object
@@ -166,16 +228,32 @@ pub struct Html5Parser<'chars> {
     parser_cannot_change_mode: bool,
     /// Current token from the tokenizer
     current_token: Token,
+    /// Location of the current token in the input stream
+    current_span: Span,
     /// If true, the current token should be processed again
     reprocess_token: bool,
     /// Stack of open elements
     open_elements: Vec<NodeId>,
+    /// Elements that have been removed from the stack of open elements while processing the
+    /// current token, in the order they were removed
+    popped_elements: Vec<NodeId>,
+    /// Inert documents with the contents of the templates that have been parsed, with the
+    /// template they belong to. Their nodes have ids that are unique across them and the document.
+    template_documents: Vec<(NodeId, DocumentHandle)>,
     /// Current head element
     head_element: Option<NodeId>,
     /// Current form element
     form_element: Option<NodeId>,
     /// If true, scripting is enabled
     scripting_enabled: bool,
+    /// If true, parsing is aborted on the first parse error
+    strict: bool,
+    /// Limits on the resources used while parsing
+    limits: Html5ParserLimits,
+    /// If true, templates with a shadowrootmode attribute are attached as shadow roots
+    allow_declarative_shadow_roots: bool,
+    /// The limit that has been exceeded, if any
+    limit_exceeded: Option<Limit>,
     /// if true, we can insert a frameset
     frameset_ok: bool,
     /// Foster parenting flag
@@ -210,6 +288,13 @@ pub struct Html5Parser<'chars> {
     context_node_id: Option<NodeId>,
     /// Context node document for fragment parsing (we don't want to keep Option<Node> as this clones a whole node
     context_doc: Option<DocumentHandle>,
+    /// Script element that blocks the parser until it has been executed
+    pending_parsing_blocking_script: Option<NodeId>,
+    /// Script element that is being executed by the embedder while the parser is paused
+    running_script: Option<NodeId>,
+    /// Set when a meta element changed the (tentative) encoding. The input must be decoded with
+    /// this encoding and the document must be parsed again.
+    encoding_change: Option<Encoding>,
 }
 
 /// Defines the scopes for in_scope()
@@ -235,6 +320,12 @@ impl<'chars> Html5Parser<'chars> {
         error_logger: Rc<RefCell<ErrorLogger>>,
         options: Option<Html5ParserOptions>,
     ) -> Self {
+        let mut tokenizer = tokenizer;
+        let position = tokenizer.get_position();
+        let options = options.unwrap_or_default();
+        error_logger.borrow_mut().set_max_errors(options.max_errors);
+        tokenizer.set_limits(options.limits.clone());
+
         Html5Parser {
             tokenizer,
             insertion_mode: InsertionMode::Initial,
@@ -242,11 +333,18 @@ impl<'chars> Html5Parser<'chars> {
             template_insertion_mode: vec![],
             parser_cannot_change_mode: false,
             current_token: Token::Eof,
+            current_span: Span::new(position, position),
             reprocess_token: false,
             open_elements: Vec::new(),
+            popped_elements: Vec::new(),
+            template_documents: Vec::new(),
             head_element: None,
             form_element: None,
-            scripting_enabled: options.unwrap_or_default().scripting_enabled,
+            scripting_enabled: options.scripting_enabled,
+            strict: options.strict,
+            limits: options.limits,
+            allow_declarative_shadow_roots: options.allow_declarative_shadow_roots,
+            limit_exceeded: None,
             frameset_ok: true,
             foster_parenting: false,
             script_already_started: false,
@@ -264,6 +362,9 @@ impl<'chars> Html5Parser<'chars> {
             parser_finished: false,
             context_node_id: None,
             context_doc: None,
+            pending_parsing_blocking_script: None,
+            running_script: None,
+            encoding_change: None,
         }
     }
 
@@ -273,6 +374,7 @@ impl<'chars> Html5Parser<'chars> {
         let doc = DocumentBuilder::new_document();
         let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
         let tokenizer = Tokenizer::new(chars, None, error_logger.clone());
+        let position = tokenizer.get_position();
 
         Html5Parser {
             tokenizer,
@@ -281,11 +383,18 @@ impl<'chars> Html5Parser<'chars> {
             template_insertion_mode: vec![],
             parser_cannot_change_mode: false,
             current_token: Token::Eof,
+            current_span: Span::new(position, position),
             reprocess_token: false,
             open_elements: Vec::new(),
+            popped_elements: Vec::new(),
+            template_documents: Vec::new(),
             head_element: None,
             form_element: None,
             scripting_enabled: true,
+            strict: false,
+            limits: Html5ParserLimits::default(),
+            allow_declarative_shadow_roots: true,
+            limit_exceeded: None,
             frameset_ok: true,
             foster_parenting: false,
             script_already_started: false,
@@ -303,6 +412,9 @@ impl<'chars> Html5Parser<'chars> {
             parser_finished: false,
             context_node_id: None,
             context_doc: None,
+            pending_parsing_blocking_script: None,
+            running_script: None,
+            encoding_change: None,
         }
     }
 
@@ -375,7 +487,7 @@ impl<'chars> Html5Parser<'chars> {
                 break;
             }
 
-            node = get_node_by_id!(document, node.parent.unwrap());
+            node = get_node_by_id!(context_node.document, node.parent.unwrap());
         }
 
         // 13. / 14.
@@ -391,28 +503,171 @@ impl<'chars> Html5Parser<'chars> {
         document: DocumentHandle,
         options: Option<Html5ParserOptions>,
     ) -> Result<Vec<ParseError>> {
+        let mut parser = Html5Parser::new_document_parser(chars, document, options);
+        parser.finish()
+    }
+
+    /// Creates a parser that parses a document incrementally. The input stream should be started
+    /// with CharIterator::start_stream(), after which chunks of input can be parsed with feed() as
+    /// they arrive. The document is completed with finish().
+    pub fn new_document_parser(
+        chars: &'chars mut CharIterator,
+        document: DocumentHandle,
+        options: Option<Html5ParserOptions>,
+    ) -> Self {
         // Create a new error logger that will be used in both the tokenizer and the parser
         let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
 
         let tokenizer = Tokenizer::new(chars, None, error_logger.clone());
-        let mut parser = Html5Parser::init(tokenizer, document, error_logger, options);
+        Html5Parser::init(tokenizer, document, error_logger, options)
+    }
 
-        parser.do_parse()
+    /// Appends the given bytes to the input stream and parses as much of the document as possible
+    /// with the input that is available. When the parser is paused on a script, the bytes are only
+    /// appended.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<ParserStatus> {
+        self.tokenizer.chars.append_bytes(bytes);
+        self.run()
+    }
+
+    /// Closes the input stream and parses the remainder of the document. Parsing can still pause on
+    /// scripts, which must be resumed until the parser is finished.
+    pub fn close_input(&mut self) -> Result<ParserStatus> {
+        self.tokenizer.chars.close();
+        self.run()
+    }
+
+    /// Resumes parsing after the pending parsing-blocking script has been executed or skipped.
+    pub fn resume(&mut self) -> Result<ParserStatus> {
+        if self.running_script.take().is_some() {
+            // https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag (steps 12 and 13 of
+            // the pending parsing-blocking script loop)
+            self.script_nesting_level -= 1;
+            if self.script_nesting_level == 0 {
+                self.parser_pause_flag = false;
+            }
+            self.insertion_point = None;
+
+            // A script written by the script we just ran can be pending as well
+            self.handle_pending_script();
+        }
+
+        self.run()
+    }
+
+    /// Inserts the given input into the input stream at the insertion point and parses it. This
+    /// can only be done while a script is running, as the insertion point is undefined otherwise.
+    /// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#document-write-steps
+    pub fn document_write(&mut self, input: &str) -> Result<()> {
+        // 4. We do not support opening a new document, which is needed without an insertion point
+        let Some(insertion_point) = self.insertion_point else {
+            return Err(Error::Parse(
+                "document.write() called without an insertion point".into(),
+            ));
+        };
+
+        // 5.
+        let len = self.tokenizer.chars.insert_str(insertion_point, input);
+        self.insertion_point = Some(insertion_point + len);
+
+        // 6. The inserted characters are parsed until the tokenizer reaches the insertion point, or
+        // until another script pauses the parser.
+        if self.pending_parsing_blocking_script.is_none() {
+            let paused = self.parser_pause_flag;
+            self.parser_pause_flag = false;
+
+            self.tokenizer
+                .chars
+                .set_read_limit(Some(insertion_point + len));
+            let result = self.run();
+            self.tokenizer.chars.set_read_limit(None);
+
+            self.parser_pause_flag |= paused;
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as document_write(), but adds a newline after the input
+    pub fn document_writeln(&mut self, input: &str) -> Result<()> {
+        self.document_write(&format!("{}\n", input))
+    }
+
+    /// Runs the preload scanner over the input that has not been parsed yet. This is useful while
+    /// the parser is paused on a script, so the embedder can fetch sub-resources in the meantime.
+    pub fn preload_scan(&self, scanner: &mut PreloadScanner) -> Vec<PreloadRequest> {
+        scanner.scan(&self.tokenizer.chars.remaining_str())
+    }
+
+    /// Closes the input stream and parses the remainder of the document. Any scripts that would
+    /// pause the parser are skipped. Returns the parse errors found in the whole document.
+    pub fn finish(&mut self) -> Result<Vec<ParseError>> {
+        let mut status = self.close_input()?;
+        while let ParserStatus::PendingScript(_) = status {
+            status = self.resume()?;
+        }
+
+        self.document.get_mut().encoding = self.tokenizer.chars.encoding;
+
+        let result = Ok(self.error_logger.borrow().get_errors().clone());
+        result
     }
 
     /// Internal parser function that does the actual parsing
     fn do_parse(&mut self) -> Result<Vec<ParseError>> {
+        let mut status = self.run()?;
+        while let ParserStatus::PendingScript(_) = status {
+            status = self.resume()?;
+        }
+
+        let result = Ok(self.error_logger.borrow().get_errors().clone());
+        result
+    }
+
+    /// Runs the parser on the input that is currently available. Returns when the document is
+    /// complete, when the input stream runs out and more input is needed, or when the parser is
+    /// paused on a script.
+    fn run(&mut self) -> Result<ParserStatus> {
         let mut dispatcher_mode = DispatcherMode::Html;
 
         loop {
+            // In strict mode, we stop at the first parse error
+            if let Some(error) = self.strict_error() {
+                return Err(error);
+            }
+
             // When the parser is signalled to finish, we break our main parser loop
             if self.parser_finished {
+                // When the encoding changed during parsing, we decode the input again and start over
+                if let Some(encoding) = self.encoding_change.take() {
+                    self.restart(encoding);
+                    continue;
+                }
                 break;
             }
 
             // If reprocess_token is true, we should process the same token again
             if !self.reprocess_token {
-                self.current_token = self.fetch_next_token();
+                // The embedder needs to run the blocking script before we can continue
+                if self.parser_pause_flag {
+                    let script_id = self
+                        .running_script
+                        .or(self.pending_parsing_blocking_script)
+                        .expect("paused without a script");
+                    return Ok(ParserStatus::PendingScript(script_id));
+                }
+
+                self.current_token = match self.fetch_next_token()? {
+                    Some(token) => token,
+                    None => return Ok(ParserStatus::NeedsInput),
+                };
+
+                // The tokenizer may have found an error in the token, which must not be processed
+                // in strict mode
+                if let Some(error) = self.strict_error() {
+                    return Err(error);
+                }
 
                 // If we reprocess a given token, the dispatcher mode should stay the same and
                 // should not be re-evaluated
@@ -421,6 +676,9 @@ impl<'chars> Html5Parser<'chars> {
 
             self.reprocess_token = false;
 
+            // Remember which elements are popped, so we can find the element an end tag closes
+            self.popped_elements.clear();
+
             // Check how we should dispatch the token, and dispatch to the correct function
             match dispatcher_mode {
                 DispatcherMode::Foreign => {
@@ -431,12 +689,57 @@ impl<'chars> Html5Parser<'chars> {
                 }
             }
 
+            self.set_end_tag_location();
+
+            self.check_tree_limits();
+            if let Some(limit) = self.limit_exceeded.take() {
+                return Err(Error::LimitExceeded(limit));
+            }
+
             #[cfg(feature = "debug_parser")]
             self.display_debug_info();
         }
 
-        let result = Ok(self.error_logger.borrow().get_errors().clone());
-        result
+        Ok(ParserStatus::Finished)
+    }
+
+    /// Decodes the input stream again with the given encoding and parses the document from scratch.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+    fn restart(&mut self, encoding: Encoding) {
+        self.tokenizer.chars.force_set_encoding(encoding);
+        self.tokenizer.chars.set_confidence(Confidence::Certain);
+        self.tokenizer.chars.reset();
+        self.tokenizer.reset();
+
+        self.document.reset();
+        self.error_logger.borrow_mut().clear();
+
+        self.insertion_mode = InsertionMode::Initial;
+        self.original_insertion_mode = InsertionMode::Initial;
+        self.template_insertion_mode = vec![];
+        self.parser_cannot_change_mode = false;
+        self.current_token = Token::Eof;
+        self.current_span = Span::new(self.tokenizer.get_position(), self.tokenizer.get_position());
+        self.reprocess_token = false;
+        self.open_elements = Vec::new();
+        self.template_documents = Vec::new();
+        self.head_element = None;
+        self.form_element = None;
+        self.frameset_ok = true;
+        self.foster_parenting = false;
+        self.script_already_started = false;
+        self.pending_table_character_tokens = String::new();
+        self.ack_self_closing = false;
+        self.active_formatting_elements = vec![];
+        self.script_nesting_level = 0;
+        self.parser_pause_flag = false;
+        self.insertion_point = None;
+        self.ignore_lf = false;
+        self.token_queue = vec![];
+        self.parser_finished = false;
+        self.pending_parsing_blocking_script = None;
+        self.running_script = None;
+        self.limit_exceeded = None;
     }
 
     // Process token in foreign content (svg, mathml)
@@ -450,7 +753,11 @@ impl<'chars> Html5Parser<'chars> {
                 return;
             }
             Token::Text(..) if self.current_token.is_null() => {
-                self.parse_error("null character not allowed in foreign content");
+                parse_error!(
+                    self,
+                    ParserError::UnexpectedNullCharacter,
+                    "null character not allowed in foreign content",
+                );
                 self.insert_text_element(&Token::Text(CHAR_REPLACEMENT.to_string()));
             }
             Token::Text(..) if self.current_token.is_empty_or_white() => {
@@ -465,7 +772,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_comment_element(&self.current_token.clone(), None);
             }
             Token::DocType { .. } => {
-                self.parse_error("doctype not allowed in foreign content");
+                parse_error!(self, "doctype not allowed in foreign content");
                 // ignore token
             }
             Token::StartTag { name, .. }
@@ -558,7 +865,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.ack_self_closing = true;
                         handle_as_script_endtag = true;
                     } else {
-                        self.open_elements.pop();
+                        self.pop_open_element();
                         // self.current_token = self.fetch_next_token();
                         self.ack_self_closing = true;
                     }
@@ -573,10 +880,14 @@ impl<'chars> Html5Parser<'chars> {
                 }
 
                 let mut node_idx = self.open_elements.len() - 1;
-                let mut node = get_node_by_id!(self.document, self.open_elements[node_idx]);
+                let mut node = self.get_node(self.open_elements[node_idx]);
 
                 if node.name.to_lowercase() != *name {
-                    self.parse_error("end tag does not match current node");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "end tag does not match current node",
+                    );
                 }
 
                 loop {
@@ -588,7 +899,7 @@ impl<'chars> Html5Parser<'chars> {
                     }
 
                     if node.name.to_lowercase() == *name {
-                        while let Some(node_id) = self.open_elements.pop() {
+                        while let Some(node_id) = self.pop_open_element() {
                             if node_id == node.id {
                                 break;
                             }
@@ -597,7 +908,7 @@ impl<'chars> Html5Parser<'chars> {
                     }
 
                     node_idx -= 1;
-                    node = get_node_by_id!(self.document, self.open_elements[node_idx]);
+                    node = self.get_node(self.open_elements[node_idx]);
 
                     if !node.is_namespace(HTML_NAMESPACE) {
                         continue;
@@ -613,14 +924,15 @@ impl<'chars> Html5Parser<'chars> {
         }
 
         if handle_as_script_endtag {
-            self.open_elements.pop();
+            let script_id = current_node!(self).id;
+            self.pop_open_element();
 
             let old_insertion_point = self.insertion_point;
             self.insertion_point = Some(self.tokenizer.get_position().offset);
 
             self.script_nesting_level += 1;
 
-            // @todo: do script stuff
+            self.prepare_script(script_id);
 
             self.script_nesting_level -= 1;
             if self.script_nesting_level == 0 {
@@ -628,6 +940,8 @@ impl<'chars> Html5Parser<'chars> {
             }
 
             self.insertion_point = old_insertion_point;
+
+            self.handle_pending_script();
         }
     }
 
@@ -673,7 +987,7 @@ impl<'chars> Html5Parser<'chars> {
                             || (sys_identifier.is_some()
                                 && sys_identifier.as_ref().unwrap() != "about:legacy-compat")
                         {
-                            self.parse_error("doctype not allowed in initial insertion mode");
+                            parse_error!(self, "doctype not allowed in initial insertion mode");
                         }
 
                         self.insert_doctype_element(&self.current_token.clone());
@@ -691,19 +1005,31 @@ impl<'chars> Html5Parser<'chars> {
                     }
                     Token::StartTag { .. } => {
                         if !self.is_iframesrcdoc() {
-                            self.parse_error(ParserError::ExpectedDocTypeButGotStartTag.as_str());
+                            parse_error!(
+                                self,
+                                ParserError::ExpectedDocTypeButGotStartTag,
+                                ParserError::ExpectedDocTypeButGotStartTag.as_str(),
+                            );
                         }
                         anything_else = true;
                     }
                     Token::EndTag { .. } => {
                         if !self.is_iframesrcdoc() {
-                            self.parse_error(ParserError::ExpectedDocTypeButGotEndTag.as_str());
+                            parse_error!(
+                                self,
+                                ParserError::ExpectedDocTypeButGotEndTag,
+                                ParserError::ExpectedDocTypeButGotEndTag.as_str(),
+                            );
                         }
                         anything_else = true;
                     }
                     Token::Text(..) => {
                         if !self.is_iframesrcdoc() {
-                            self.parse_error(ParserError::ExpectedDocTypeButGotChars.as_str());
+                            parse_error!(
+                                self,
+                                ParserError::ExpectedDocTypeButGotChars,
+                                ParserError::ExpectedDocTypeButGotChars.as_str(),
+                            );
                         }
                         anything_else = true;
                     }
@@ -724,7 +1050,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 match &self.current_token {
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in before html insertion mode");
+                        parse_error!(self, "doctype not allowed in before html insertion mode");
                     }
                     Token::Comment(..) => {
                         self.insert_comment_element(
@@ -741,7 +1067,7 @@ impl<'chars> Html5Parser<'chars> {
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
-                        self.insert_document_element(&self.current_token.clone());
+                        self.insert_document_element(&self.current_token.clone(), true);
 
                         self.insertion_mode = InsertionMode::BeforeHead;
                     }
@@ -751,7 +1077,7 @@ impl<'chars> Html5Parser<'chars> {
                         anything_else = true;
                     }
                     Token::EndTag { .. } => {
-                        self.parse_error("end tag not allowed in before html insertion mode");
+                        parse_error!(self, "end tag not allowed in before html insertion mode");
                     }
                     _ => {
                         anything_else = true;
@@ -764,7 +1090,7 @@ impl<'chars> Html5Parser<'chars> {
                         is_self_closing: false,
                         attributes: HashMap::new(),
                     };
-                    self.insert_document_element(&token);
+                    self.insert_document_element(&token, false);
 
                     self.insertion_mode = InsertionMode::BeforeHead;
                     self.reprocess_token = true;
@@ -786,7 +1112,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.insert_comment_element(&self.current_token.clone(), None);
                     }
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in before head insertion mode");
+                        parse_error!(self, "doctype not allowed in before head insertion mode");
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
@@ -803,7 +1129,7 @@ impl<'chars> Html5Parser<'chars> {
                         anything_else = true;
                     }
                     Token::EndTag { .. } => {
-                        self.parse_error("end tag not allowed in before head insertion mode");
+                        parse_error!(self, "end tag not allowed in before head insertion mode");
                         // ignore token
                     }
                     _ => {
@@ -811,12 +1137,7 @@ impl<'chars> Html5Parser<'chars> {
                     }
                 }
                 if anything_else {
-                    let token = Token::StartTag {
-                        name: "head".to_string(),
-                        is_self_closing: false,
-                        attributes: HashMap::new(),
-                    };
-                    let node_id = self.insert_html_element(&token);
+                    let node_id = self.insert_implied_html_element("head");
                     self.head_element = Some(node_id);
                     self.insertion_mode = InsertionMode::InHead;
                     self.reprocess_token = true;
@@ -828,7 +1149,10 @@ impl<'chars> Html5Parser<'chars> {
 
                 match &self.current_token {
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in 'head no script' insertion mode");
+                        parse_error!(
+                            self,
+                            "doctype not allowed in 'head no script' insertion mode"
+                        );
                         // ignore token
                         return;
                     }
@@ -865,13 +1189,14 @@ impl<'chars> Html5Parser<'chars> {
                         anything_else = true;
                     }
                     Token::StartTag { name, .. } if name == "head" || name == "noscript" => {
-                        self.parse_error(
+                        parse_error!(
+                            self,
                             "head or noscript tag not allowed in after head insertion mode",
                         );
                         // ignore token
                     }
                     Token::EndTag { .. } => {
-                        self.parse_error("end tag not allowed in after head insertion mode");
+                        parse_error!(self, "end tag not allowed in after head insertion mode");
                         // ignore token
                     }
                     _ => {
@@ -879,7 +1204,10 @@ impl<'chars> Html5Parser<'chars> {
                     }
                 }
                 if anything_else {
-                    self.parse_error("anything else not allowed in after head insertion mode");
+                    parse_error!(
+                        self,
+                        "anything else not allowed in after head insertion mode"
+                    );
 
                     self.pop_check("noscript");
                     self.check_last_element("head");
@@ -904,7 +1232,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.insert_comment_element(&self.current_token.clone(), None);
                     }
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in after head insertion mode");
+                        parse_error!(self, "doctype not allowed in after head insertion mode");
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
@@ -928,7 +1256,7 @@ impl<'chars> Html5Parser<'chars> {
                         ]
                         .contains(&name.as_str()) =>
                     {
-                        self.parse_error("invalid start tag in after head insertion mode");
+                        parse_error!(self, "invalid start tag in after head insertion mode");
 
                         if self.head_element.is_none() {
                             panic!("Head element should not be None");
@@ -939,6 +1267,7 @@ impl<'chars> Html5Parser<'chars> {
                         }
 
                         self.handle_in_head();
+                        return_on_strict_error!(self);
 
                         // Remove the node pointed to by the head element pointer from the stack of open elements (might not be current node at this point)
                         if let Some(node_id) = self.head_element {
@@ -954,11 +1283,11 @@ impl<'chars> Html5Parser<'chars> {
                         anything_else = true;
                     }
                     Token::StartTag { name, .. } if name == "head" => {
-                        self.parse_error("head tag not allowed in after head insertion mode");
+                        parse_error!(self, "head tag not allowed in after head insertion mode");
                         // ignore token
                     }
                     Token::EndTag { .. } => {
-                        self.parse_error("end tag not allowed in after head insertion mode");
+                        parse_error!(self, "end tag not allowed in after head insertion mode");
                         // Ignore token
                     }
                     _ => {
@@ -967,12 +1296,7 @@ impl<'chars> Html5Parser<'chars> {
                 }
 
                 if anything_else {
-                    let token = Token::StartTag {
-                        name: "body".to_string(),
-                        is_self_closing: false,
-                        attributes: HashMap::new(),
-                    };
-                    self.insert_html_element(&token);
+                    self.insert_implied_html_element("body");
 
                     self.insertion_mode = InsertionMode::InBody;
                     self.reprocess_token = true;
@@ -985,21 +1309,21 @@ impl<'chars> Html5Parser<'chars> {
                         self.insert_text_element(&self.current_token.clone());
                     }
                     Token::Eof => {
-                        self.parse_error("eof not allowed in text insertion mode");
+                        parse_error!(self, "eof not allowed in text insertion mode");
 
                         if current_node!(self).name == "script" {
                             self.script_already_started = true;
                         }
-                        self.open_elements.pop();
+                        self.pop_open_element();
                         self.insertion_mode = self.original_insertion_mode;
                         self.reprocess_token = true;
                     }
                     Token::EndTag { name, .. } if name == "script" => {
                         // @todo: If the active speculative HTML parser is null and the JavaScript execution context stack is empty, then perform a microtask checkpoint.
 
-                        let _script = current_node!(self);
+                        let script_id = current_node!(self).id;
 
-                        self.open_elements.pop();
+                        self.pop_open_element();
                         self.insertion_mode = self.original_insertion_mode;
 
                         let old_insertion_point = self.insertion_point;
@@ -1007,7 +1331,7 @@ impl<'chars> Html5Parser<'chars> {
 
                         self.script_nesting_level += 1;
 
-                        // do script stuff
+                        self.prepare_script(script_id);
 
                         self.script_nesting_level -= 1;
                         if self.script_nesting_level == 0 {
@@ -1015,9 +1339,11 @@ impl<'chars> Html5Parser<'chars> {
                         }
 
                         self.insertion_point = old_insertion_point;
+
+                        self.handle_pending_script();
                     }
                     _ => {
-                        self.open_elements.pop();
+                        self.pop_open_element();
                         self.insertion_mode = self.original_insertion_mode;
                     }
                 }
@@ -1030,7 +1356,9 @@ impl<'chars> Html5Parser<'chars> {
                         self.tokenizer.insert_tokens_at_queue_start(tokens);
                     }
                     Token::Text(..) if self.current_token.is_null() => {
-                        self.parse_error(
+                        parse_error!(
+                            self,
+                            ParserError::UnexpectedNullCharacter,
                             "null character not allowed in in table text insertion mode",
                         );
                         // ignore token
@@ -1045,7 +1373,8 @@ impl<'chars> Html5Parser<'chars> {
 
                         for c in self.pending_table_character_tokens.chars() {
                             if !c.is_ascii_whitespace() {
-                                self.parse_error(
+                                parse_error!(
+                                    self,
                                     "non whitespace character in pending table character tokens",
                                 );
                                 process_as_intable_anything_else = true;
@@ -1109,7 +1438,7 @@ impl<'chars> Html5Parser<'chars> {
                             || name == "thead"
                             || name == "tr" =>
                     {
-                        self.parse_error("end tag not allowed in in caption insertion mode");
+                        parse_error!(self, "end tag not allowed in in caption insertion mode");
                         // ignore token
                     }
                     _ => self.handle_in_body(),
@@ -1118,7 +1447,8 @@ impl<'chars> Html5Parser<'chars> {
                 if process_incaption_body {
                     if !self.open_elements_has("caption") {
                         // fragment case
-                        self.parse_error(
+                        parse_error!(
+                            self,
                             "caption end tag not allowed in in caption insertion mode",
                         );
                         // ignore token
@@ -1129,7 +1459,11 @@ impl<'chars> Html5Parser<'chars> {
                     self.generate_implied_end_tags(None, false);
 
                     if current_node!(self).name != "caption" {
-                        self.parse_error("caption end tag not at top of stack");
+                        parse_error!(
+                            self,
+                            ParserError::MisnestedTags,
+                            "caption end tag not at top of stack",
+                        );
                     }
 
                     self.pop_until_named("caption");
@@ -1151,7 +1485,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.insert_comment_element(&self.current_token.clone(), None);
                     }
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in column group insertion mode");
+                        parse_error!(self, "doctype not allowed in column group insertion mode");
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
@@ -1165,7 +1499,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.acknowledge_closing_tag(*is_self_closing);
 
                         self.insert_html_element(&self.current_token.clone());
-                        self.open_elements.pop();
+                        self.pop_open_element();
                     }
                     Token::StartTag { name, .. } if name == "template" => {
                         self.handle_in_head();
@@ -1178,26 +1512,29 @@ impl<'chars> Html5Parser<'chars> {
                     }
                     Token::EndTag { name, .. } if name == "colgroup" => {
                         if current_node!(self).name != "colgroup" {
-                            self.parse_error("colgroup end tag not at top of stack");
+                            parse_error!(self, "colgroup end tag not at top of stack");
                             // ignore token
                             return;
                         }
 
-                        self.open_elements.pop();
+                        self.pop_open_element();
                         self.insertion_mode = InsertionMode::InTable;
                         self.reprocess_token = true;
                     }
                     Token::EndTag { name, .. } if name == "col" => {
-                        self.parse_error("col end tag not allowed in column group insertion mode");
+                        parse_error!(
+                            self,
+                            "col end tag not allowed in column group insertion mode"
+                        );
                         // ignore token
                     }
                     _ => {
                         if current_node!(self).name != "colgroup" {
-                            self.parse_error("colgroup end tag not at top of stack");
+                            parse_error!(self, "colgroup end tag not at top of stack");
                             // ignore token
                             return;
                         }
-                        self.open_elements.pop();
+                        self.pop_open_element();
                         self.insertion_mode = InsertionMode::InTable;
                         self.reprocess_token = true;
                     }
@@ -1213,18 +1550,14 @@ impl<'chars> Html5Parser<'chars> {
                         self.insertion_mode = InsertionMode::InRow;
                     }
                     Token::StartTag { name, .. } if name == "th" || name == "td" => {
-                        self.parse_error(
+                        parse_error!(
+                            self,
                             "th or td tag not allowed in in table body insertion mode",
                         );
 
                         self.clear_stack_back_to_table_body_context();
 
-                        let token = Token::StartTag {
-                            name: "tr".to_string(),
-                            is_self_closing: false,
-                            attributes: HashMap::new(),
-                        };
-                        self.insert_html_element(&token);
+                        self.insert_implied_html_element("tr");
 
                         self.insertion_mode = InsertionMode::InRow;
                         self.reprocess_token = true;
@@ -1233,13 +1566,13 @@ impl<'chars> Html5Parser<'chars> {
                         if name == "tbody" || name == "tfoot" || name == "thead" =>
                     {
                         if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("tbody, tfoot or thead tag not allowed in in table body insertion mode");
+                            parse_error!(self, "tbody, tfoot or thead tag not allowed in in table body insertion mode");
                             // ignore token
                             return;
                         }
 
                         self.clear_stack_back_to_table_body_context();
-                        self.open_elements.pop();
+                        self.pop_open_element();
 
                         self.insertion_mode = InsertionMode::InTable;
                     }
@@ -1251,13 +1584,13 @@ impl<'chars> Html5Parser<'chars> {
                             && !self.is_in_scope("tfoot", HTML_NAMESPACE, Scope::Table)
                             && !self.is_in_scope("thead", HTML_NAMESPACE, Scope::Table)
                         {
-                            self.parse_error("caption, col, colgroup, tbody, tfoot or thead tag not allowed in in table body insertion mode");
+                            parse_error!(self, "caption, col, colgroup, tbody, tfoot or thead tag not allowed in in table body insertion mode");
                             // ignore token
                             return;
                         }
 
                         self.clear_stack_back_to_table_body_context();
-                        self.open_elements.pop();
+                        self.pop_open_element();
 
                         self.insertion_mode = InsertionMode::InTable;
                         self.reprocess_token = true;
@@ -1267,14 +1600,15 @@ impl<'chars> Html5Parser<'chars> {
                             && !self.is_in_scope("tfoot", HTML_NAMESPACE, Scope::Table)
                             && !self.is_in_scope("thead", HTML_NAMESPACE, Scope::Table)
                         {
-                            self.parse_error(
+                            parse_error!(
+                                self,
                                 "table end tag not allowed in in table body insertion mode",
                             );
                             return;
                         }
 
                         self.clear_stack_back_to_table_body_context();
-                        self.open_elements.pop();
+                        self.pop_open_element();
 
                         self.insertion_mode = InsertionMode::InTable;
                         self.reprocess_token = true;
@@ -1285,7 +1619,7 @@ impl<'chars> Html5Parser<'chars> {
                         ]
                         .contains(&name.as_str()) =>
                     {
-                        self.parse_error("end tag not allowed in in table body insertion mode");
+                        parse_error!(self, "end tag not allowed in in table body insertion mode");
                         // ignore token
                     }
                     _ => {
@@ -1305,7 +1639,7 @@ impl<'chars> Html5Parser<'chars> {
                     }
                     Token::EndTag { name, .. } if name == "tr" => {
                         if !self.is_in_scope("tr", HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("tr tag not allowed in in row insertion mode");
+                            parse_error!(self, "tr tag not allowed in in row insertion mode");
                             // ignore token
                             return;
                         }
@@ -1322,7 +1656,7 @@ impl<'chars> Html5Parser<'chars> {
                         .contains(&name.as_str()) =>
                     {
                         if !self.is_in_scope("tr", HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("caption, col, colgroup, tbody, tfoot or thead tag not allowed in in row insertion mode");
+                            parse_error!(self, "caption, col, colgroup, tbody, tfoot or thead tag not allowed in in row insertion mode");
                             // ignore token
                             return;
                         }
@@ -1335,7 +1669,7 @@ impl<'chars> Html5Parser<'chars> {
                     }
                     Token::EndTag { name, .. } if name == "table" => {
                         if !self.is_in_scope("tr", HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("table tag not allowed in in row insertion mode");
+                            parse_error!(self, "table tag not allowed in in row insertion mode");
                             // ignore token
                             return;
                         }
@@ -1350,7 +1684,7 @@ impl<'chars> Html5Parser<'chars> {
                         if name == "tbody" || name == "tfoot" || name == "thead" =>
                     {
                         if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("tbody, tfoot or thead tag not allowed in in table body insertion mode");
+                            parse_error!(self, "tbody, tfoot or thead tag not allowed in in table body insertion mode");
                             // ignore token
                             return;
                         }
@@ -1375,7 +1709,7 @@ impl<'chars> Html5Parser<'chars> {
                             || name == "td"
                             || name == "th" =>
                     {
-                        self.parse_error("end tag not allowed in in row insertion mode");
+                        parse_error!(self, "end tag not allowed in in row insertion mode");
                         // ignore token
                     }
                     _ => self.handle_in_table(),
@@ -1387,14 +1721,21 @@ impl<'chars> Html5Parser<'chars> {
                         let token_name = name.clone();
 
                         if !self.is_in_scope(name.as_str(), HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("th or td tag not allowed in in cell insertion mode");
+                            parse_error!(
+                                self,
+                                "th or td tag not allowed in in cell insertion mode"
+                            );
                             // ignore token
                             return;
                         }
                         self.generate_implied_end_tags(None, false);
 
                         if current_node!(self).name != token_name {
-                            self.parse_error("current node should be th or td");
+                            parse_error!(
+                                self,
+                                ParserError::MisnestedTags,
+                                "current node should be th or td",
+                            );
                         }
 
                         self.pop_until_named(&token_name);
@@ -1414,12 +1755,13 @@ impl<'chars> Html5Parser<'chars> {
                             && !self.is_in_scope("th", HTML_NAMESPACE, Scope::Table)
                         {
                             // fragment case
-                            self.parse_error("caption, col, colgroup, tbody, tfoot or thead tag not allowed in in cell insertion mode");
+                            parse_error!(self, "caption, col, colgroup, tbody, tfoot or thead tag not allowed in in cell insertion mode");
                             // ignore token
                             return;
                         }
 
                         self.close_cell();
+                        return_on_strict_error!(self);
                         self.reprocess_token = true;
                     }
                     Token::EndTag { name, .. }
@@ -1429,7 +1771,7 @@ impl<'chars> Html5Parser<'chars> {
                             || name == "colgroup"
                             || name == "html" =>
                     {
-                        self.parse_error("end tag not allowed in in cell insertion mode");
+                        parse_error!(self, "end tag not allowed in in cell insertion mode");
                         // ignore token
                     }
                     Token::EndTag { name, .. }
@@ -1440,12 +1782,13 @@ impl<'chars> Html5Parser<'chars> {
                             || name == "tr" =>
                     {
                         if !self.is_in_scope(name.as_str(), HTML_NAMESPACE, Scope::Table) {
-                            self.parse_error("tbody, tfoot or thead tag not allowed in in table body insertion mode");
+                            parse_error!(self, "tbody, tfoot or thead tag not allowed in in table body insertion mode");
                             // ignore token
                             return;
                         }
 
                         self.close_cell();
+                        return_on_strict_error!(self);
                         self.reprocess_token = true;
                     }
                     _ => self.handle_in_body(),
@@ -1464,7 +1807,7 @@ impl<'chars> Html5Parser<'chars> {
                             || name == "td"
                             || name == "th" =>
                     {
-                        self.parse_error("caption, table, tbody, tfoot, thead, tr, td or th tag not allowed in in select in table insertion mode");
+                        parse_error!(self, "caption, table, tbody, tfoot, thead, tr, td or th tag not allowed in in select in table insertion mode");
 
                         self.pop_until_named("select");
                         self.reset_insertion_mode();
@@ -1480,7 +1823,7 @@ impl<'chars> Html5Parser<'chars> {
                             || name == "td"
                             || name == "th" =>
                     {
-                        self.parse_error("caption, table, tbody, tfoot, thead, tr, td or th tag not allowed in in select in table insertion mode");
+                        parse_error!(self, "caption, table, tbody, tfoot, thead, tr, td or th tag not allowed in in select in table insertion mode");
 
                         if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Table) {
                             // ignore token
@@ -1512,7 +1855,7 @@ impl<'chars> Html5Parser<'chars> {
                         );
                     }
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in after body insertion mode");
+                        parse_error!(self, "doctype not allowed in after body insertion mode");
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
@@ -1521,7 +1864,8 @@ impl<'chars> Html5Parser<'chars> {
                     Token::EndTag { name, .. } if name == "html" => {
                         if self.is_fragment_case {
                             // fragment case
-                            self.parse_error(
+                            parse_error!(
+                                self,
                                 "html end tag not allowed in after body insertion mode",
                             );
                             // ignore token
@@ -1533,7 +1877,10 @@ impl<'chars> Html5Parser<'chars> {
                         self.stop_parsing();
                     }
                     _ => {
-                        self.parse_error("anything else not allowed in after body insertion mode");
+                        parse_error!(
+                            self,
+                            "anything else not allowed in after body insertion mode"
+                        );
                         self.insertion_mode = InsertionMode::InBody;
                         self.reprocess_token = true;
                     }
@@ -1552,7 +1899,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.insert_comment_element(&self.current_token.clone(), None);
                     }
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in frameset insertion mode");
+                        parse_error!(self, "doctype not allowed in frameset insertion mode");
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
@@ -1564,12 +1911,15 @@ impl<'chars> Html5Parser<'chars> {
                     Token::EndTag { name, .. } if name == "frameset" => {
                         if current_node!(self).name == "html" {
                             // fragment case
-                            self.parse_error("frameset tag not allowed in frameset insertion mode");
+                            parse_error!(
+                                self,
+                                "frameset tag not allowed in frameset insertion mode"
+                            );
                             // ignore token
                             return;
                         }
 
-                        self.open_elements.pop();
+                        self.pop_open_element();
 
                         if !self.is_fragment_case && current_node!(self).name != "frameset" {
                             // fragment case
@@ -1584,19 +1934,19 @@ impl<'chars> Html5Parser<'chars> {
                         self.acknowledge_closing_tag(*is_self_closing);
 
                         self.insert_html_element(&self.current_token.clone());
-                        self.open_elements.pop();
+                        self.pop_open_element();
                     }
                     Token::StartTag { name, .. } if name == "noframes" => {
                         self.handle_in_head();
                     }
                     Token::Eof => {
                         if current_node!(self).name != "html" {
-                            self.parse_error("eof not allowed in frameset insertion mode");
+                            parse_error!(self, "eof not allowed in frameset insertion mode");
                         }
                         self.stop_parsing();
                     }
                     _ => {
-                        self.parse_error("anything else not allowed in frameset insertion mode");
+                        parse_error!(self, "anything else not allowed in frameset insertion mode");
                         // ignore token
                     }
                 }
@@ -1614,7 +1964,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.insert_comment_element(&self.current_token.clone(), None);
                     }
                     Token::DocType { .. } => {
-                        self.parse_error("doctype not allowed in frameset insertion mode");
+                        parse_error!(self, "doctype not allowed in frameset insertion mode");
                         // ignore token
                     }
                     Token::StartTag { name, .. } if name == "html" => {
@@ -1630,7 +1980,8 @@ impl<'chars> Html5Parser<'chars> {
                         self.stop_parsing();
                     }
                     _ => {
-                        self.parse_error(
+                        parse_error!(
+                            self,
                             "anything else not allowed in after frameset insertion mode",
                         );
                         // ignore token
@@ -1658,7 +2009,8 @@ impl<'chars> Html5Parser<'chars> {
                     self.stop_parsing();
                 }
                 _ => {
-                    self.parse_error(
+                    parse_error!(
+                        self,
                         "anything else not allowed in after after body insertion mode",
                     );
                     self.insertion_mode = InsertionMode::InBody;
@@ -1693,7 +2045,8 @@ impl<'chars> Html5Parser<'chars> {
                         self.handle_in_head();
                     }
                     _ => {
-                        self.parse_error(
+                        parse_error!(
+                            self,
                             "anything else not allowed in after after frameset insertion mode",
                         );
                         // ignore token
@@ -1731,12 +2084,33 @@ impl<'chars> Html5Parser<'chars> {
 
             if current_node!(self).name == name && current_node!(self).is_namespace(HTML_NAMESPACE)
             {
-                self.open_elements.pop();
+                self.pop_open_element();
                 break;
             }
 
-            self.open_elements.pop();
+            self.pop_open_element();
+        }
+    }
+
+    /// Returns the document that holds the node: the document that is parsed, or the inert
+    /// document with the contents of a template
+    fn document_of(&self, node_id: NodeId) -> DocumentHandle {
+        if self.document.get().get_node_by_id(node_id).is_some() {
+            return Document::clone(&self.document);
         }
+
+        self.template_documents
+            .iter()
+            .rev()
+            .find(|(_, document)| document.get().get_node_by_id(node_id).is_some())
+            .map(|(_, document)| Document::clone(document))
+            .unwrap_or_else(|| Document::clone(&self.document))
+    }
+
+    /// Returns a copy of the node, which is in the document or in the contents of a template.
+    /// Panics when the node does not exist.
+    fn get_node(&self, node_id: NodeId) -> Node {
+        get_node_by_id!(self.document_of(node_id), node_id)
     }
 
     /// Pops the last element from the open elements until we reach $name
@@ -1747,23 +2121,23 @@ impl<'chars> Html5Parser<'chars> {
             }
 
             if current_node!(self).name == name {
-                self.open_elements.pop();
+                self.pop_open_element();
                 break;
             }
 
-            self.open_elements.pop();
+            self.pop_open_element();
         }
     }
 
     /// Pops the last element from the open elements until we reach any of the elements in $arr
     fn pop_until_any(&mut self, arr: &[&str]) {
         while !self.open_elements.is_empty() {
-            let node_id = self.open_elements.pop();
+            let node_id = self.pop_open_element();
             if node_id.is_none() {
                 break;
             }
 
-            let tag = get_node_by_id!(self.document, node_id.expect("node_id not found")).name;
+            let tag = self.get_node(node_id.expect("node_id not found")).name;
             if arr.contains(&tag.as_str()) {
                 break;
             }
@@ -1772,14 +2146,33 @@ impl<'chars> Html5Parser<'chars> {
 
     /// Remove the given node_id from the open elements stack. Will do nothing when the node_id is not found
     fn open_elements_remove(&mut self, target_node_id: NodeId) {
-        self.open_elements
-            .retain(|&node_id| node_id != target_node_id);
+        let position = self
+            .open_elements
+            .iter()
+            .position(|&node_id| node_id == target_node_id);
+        if let Some(position) = position {
+            self.open_elements_remove_at(position);
+        }
+    }
+
+    /// Removes the element at the given position from the open elements stack
+    fn open_elements_remove_at(&mut self, position: usize) -> NodeId {
+        let node_id = self.open_elements.remove(position);
+        self.popped_elements.push(node_id);
+        node_id
+    }
+
+    /// Pops the current node from the open elements stack
+    fn pop_open_element(&mut self) -> Option<NodeId> {
+        let node_id = self.open_elements.pop()?;
+        self.popped_elements.push(node_id);
+        Some(node_id)
     }
 
     /// Pops the last element from the open elements, and panics if it is not $name
     fn pop_check(&mut self, name: &str) {
-        let node_id = self.open_elements.pop().expect("Open elements is empty");
-        if get_node_by_id!(self.document, node_id).name != name {
+        let node_id = self.pop_open_element().expect("Open elements is empty");
+        if self.get_node(node_id).name != name {
             panic!("{} tag should be popped from open elements", name);
         }
     }
@@ -1787,17 +2180,17 @@ impl<'chars> Html5Parser<'chars> {
     /// Checks if the last element on the open elements is $name, and panics if not
     fn check_last_element(&self, name: &str) {
         let node_id = self.open_elements.last().unwrap_or_default();
-        if get_node_by_id!(self.document, *node_id).name != name {
+        if self.get_node(*node_id).name != name {
             panic!("{name} tag should be last element in open elements");
         }
     }
 
     /// Returns true when the open elements has $name
     fn open_elements_has(&self, name: &str) -> bool {
-        self.open_elements.iter().rev().any(|node_id| {
-            self.document
+        self.open_elements.iter().rev().any(|&node_id| {
+            self.document_of(node_id)
                 .get()
-                .get_node_by_id(*node_id)
+                .get_node_by_id(node_id)
                 .expect("node not found")
                 .name
                 == name
@@ -1809,15 +2202,170 @@ impl<'chars> Html5Parser<'chars> {
         self.error_logger.borrow().get_errors().clone()
     }
 
-    /// Send a parse error to the error logger
+    /// Send a parse error for the current token to the error logger. The error code follows from
+    /// the kind of token that is not expected.
     fn parse_error(&self, message: &str) {
-        self.error_logger
-            .borrow_mut()
-            .add_error(self.tokenizer.get_position(), message);
+        let code = match self.current_token {
+            Token::DocType { .. } => ParserError::UnexpectedDoctype,
+            Token::StartTag { .. } => ParserError::UnexpectedStartTag,
+            Token::EndTag { .. } => ParserError::UnexpectedEndTag,
+            Token::Comment(..) | Token::Text(..) => ParserError::UnexpectedCharacters,
+            Token::Eof => ParserError::UnexpectedEof,
+        };
+        parse_error!(self, code, message);
+    }
+
+    /// Returns the error to stop parsing with in strict mode, once a parse error has been found
+    fn strict_error(&self) -> Option<Error> {
+        if !self.strict {
+            return None;
+        }
+
+        let error = self.error_logger.borrow().errors().first().cloned()?;
+        Some(Error::StrictParse(Box::new(error)))
+    }
+
+    /// Returns true when the parser runs in strict mode and a parse error has been found. Use the
+    /// parse_error! macro to stop processing the current token on the error.
+    fn strict_error_found(&self) -> bool {
+        self.strict && self.error_logger.borrow().error_count() > 0
+    }
+
+    /// Send a parse error with the given code to the error logger
+    fn parse_error_with_code(&self, code: ParserError, message: &str) {
+        self.error_logger.borrow_mut().add_error(ParseError::new(
+            code,
+            message,
+            ErrorTrigger::Token {
+                kind: self.current_token.kind(),
+                name: self.current_token.tag_name().map(str::to_string),
+            },
+            self.tokenizer.get_position(),
+            self.current_span,
+        ));
+    }
+
+    /// Checks the size of the document and the stack of open elements against the limits
+    fn check_tree_limits(&mut self) {
+        if let Some(max) = self.limits.max_open_elements {
+            if self.open_elements.len() > max {
+                self.limit_exceeded = Some(Limit::OpenElements(max));
+            }
+        }
+
+        if let Some(max) = self.limits.max_node_count {
+            let count = self.document.get().arena.count_nodes()
+                + self
+                    .template_documents
+                    .iter()
+                    .map(|(_, document)| document.get().arena.count_nodes())
+                    .sum::<usize>();
+            if count > max {
+                self.limit_exceeded = Some(Limit::NodeCount(max));
+            }
+        }
+    }
+
+    /// Checks the depth of the deepest element in the subtree of the node against the limits. The
+    /// depth follows from the ancestors of the node and not from the stack of open elements, as
+    /// the adoption agency algorithm and foster parenting can nest elements deeper than the stack.
+    pub(crate) fn check_depth_limit(&mut self, node_id: NodeId) {
+        if let Some(max) = self.limits.max_tree_depth {
+            let height = element_height(&self.document_of(node_id).get(), node_id);
+            if self.tree_depth(node_id) + height > max {
+                self.limit_exceeded = Some(Limit::TreeDepth(max));
+            }
+        }
     }
 
-    /// Create a new node that is not connected or attached to the document arena
-    fn create_node(&self, token: &Token, namespace: &str) -> Node {
+    /// Returns the depth of the node in the document tree, on which the document node has depth 0.
+    /// The contents of a template count from the template element they belong to.
+    fn tree_depth(&self, node_id: NodeId) -> usize {
+        let document = self.document.get();
+        if document.get_node_by_id(node_id).is_some() {
+            return document.ancestors(node_id).count();
+        }
+
+        for (template_id, contents) in self.template_documents.iter().rev() {
+            let contents = contents.get();
+            if contents.get_node_by_id(node_id).is_some() {
+                return self.tree_depth(*template_id) + contents.ancestors(node_id).count();
+            }
+        }
+
+        0
+    }
+
+    /// Checks the length of a text node that text has been appended to against the limits. The
+    /// tokenizer already checks the length of the text tokens.
+    pub(crate) fn check_text_limit(&mut self, len: usize) {
+        if let Some(max) = self.limits.max_text_length {
+            if len > max {
+                self.limit_exceeded = Some(Limit::TextLength(max));
+            }
+        }
+    }
+
+    /// Returns the location of the given text, comment or doctype token in the input stream. These
+    /// tokens are (split from) the current token when it is of the same kind. Elements get their
+    /// location from the function that inserts them, as only the caller knows whether the start
+    /// tag is in the input stream.
+    fn token_location(&self, token: &Token) -> Option<Span> {
+        let from_input = matches!(
+            (token, &self.current_token),
+            (Token::Text(..), Token::Text(..))
+                | (Token::Comment(..), Token::Comment(..))
+                | (Token::DocType { .. }, Token::DocType { .. })
+        );
+
+        from_input.then_some(self.current_span)
+    }
+
+    /// Stores the location of the current end tag on the element it closed
+    fn set_end_tag_location(&mut self) {
+        let Token::EndTag { name, .. } = &self.current_token else {
+            return;
+        };
+
+        // The closed element is the topmost element with the same name that has been popped
+        let mut closed = self
+            .popped_elements
+            .iter()
+            .find(|&&id| self.get_node(id).name.eq_ignore_ascii_case(name))
+            .copied();
+
+        // The body and html elements stay open after their end tag, but the insertion mode changes
+        if closed.is_none() && !self.reprocess_token {
+            closed = match (name.as_str(), self.insertion_mode) {
+                ("body", InsertionMode::AfterBody) => self
+                    .open_elements
+                    .iter()
+                    .find(|&&id| self.get_node(id).name == "body")
+                    .copied(),
+                ("html", InsertionMode::AfterAfterBody) => self.open_elements.first().copied(),
+                _ => None,
+            };
+        }
+
+        if let Some(id) = closed {
+            let mut document = self.document_of(id);
+            let mut doc = document.get_mut();
+            let node = doc.get_node_by_id_mut(id).expect("node not found");
+            if node.end_tag_location.is_none() {
+                node.end_tag_location = Some(self.current_span);
+            }
+        }
+    }
+
+    /// Create a new node for the given document that is not connected or attached to its arena
+    fn create_node(&self, document: &DocumentHandle, token: &Token, namespace: &str) -> Node {
+        let mut node = Self::create_node_from_token(document, token, namespace);
+        node.location = self.token_location(token);
+        node
+    }
+
+    /// Creates the node for the given token
+    fn create_node_from_token(document: &DocumentHandle, token: &Token, namespace: &str) -> Node {
         match token {
             Token::DocType {
                 name,
@@ -1825,19 +2373,19 @@ impl<'chars> Html5Parser<'chars> {
                 sys_identifier,
                 ..
             } => Node::new_doctype(
-                &self.document,
+                document,
                 &name.clone().unwrap_or_default(),
                 &pub_identifier.clone().unwrap_or_default(),
                 &sys_identifier.clone().unwrap_or_default(),
             ),
             Token::StartTag {
                 name, attributes, ..
-            } => Node::new_element(&self.document, name, attributes.clone(), namespace),
+            } => Node::new_element(document, name, attributes.clone(), namespace),
             Token::EndTag { name, .. } => {
-                Node::new_element(&self.document, name, HashMap::new(), namespace)
+                Node::new_element(document, name, HashMap::new(), namespace)
             }
-            Token::Comment(value) => Node::new_comment(&self.document, value),
-            Token::Text(value) => Node::new_text(&self.document, value.to_string().as_str()),
+            Token::Comment(value) => Node::new_comment(document, value),
+            Token::Text(value) => Node::new_text(document, value.to_string().as_str()),
             Token::Eof => {
                 panic!("EOF token not allowed");
             }
@@ -1882,7 +2430,7 @@ impl<'chars> Html5Parser<'chars> {
                 return;
             }
 
-            self.open_elements.pop();
+            self.pop_open_element();
         }
     }
 
@@ -2003,7 +2551,7 @@ impl<'chars> Html5Parser<'chars> {
             if ["table", "template", "html"].contains(&current_node!(self).name.as_str()) {
                 return;
             }
-            self.open_elements.pop();
+            self.pop_open_element();
         }
     }
 
@@ -2015,7 +2563,7 @@ impl<'chars> Html5Parser<'chars> {
             {
                 return;
             }
-            self.open_elements.pop();
+            self.pop_open_element();
         }
     }
 
@@ -2026,14 +2574,14 @@ impl<'chars> Html5Parser<'chars> {
             if ["tr", "template", "html"].contains(&val.as_str()) {
                 return;
             }
-            self.open_elements.pop();
+            self.pop_open_element();
         }
     }
 
     /// Checks if the given element is in given scope
     fn is_in_scope(&self, tag: &str, namespace: &str, scope: Scope) -> bool {
         for &node_id in self.open_elements.iter().rev() {
-            let node = get_node_by_id!(self.document, node_id).clone();
+            let node = self.get_node(node_id).clone();
             if node.name == tag && node.is_namespace(namespace) {
                 return true;
             }
@@ -2104,7 +2652,11 @@ impl<'chars> Html5Parser<'chars> {
 
         let tag = current_node!(self).name;
         if tag != "td" && tag != "th" {
-            self.parse_error("current node should be td or th");
+            parse_error!(
+                self,
+                ParserError::MisnestedTags,
+                "current node should be td or th",
+            );
         }
 
         self.pop_until_any(&["td", "th"]);
@@ -2121,7 +2673,11 @@ impl<'chars> Html5Parser<'chars> {
                 self.tokenizer.insert_tokens_at_queue_start(tokens);
             }
             Token::Text(..) if self.current_token.is_null() => {
-                self.parse_error("null character not allowed in in body insertion mode");
+                parse_error!(
+                    self,
+                    ParserError::UnexpectedNullCharacter,
+                    "null character not allowed in in body insertion mode",
+                );
                 // ignore token
             }
             Token::Text(..) => {
@@ -2138,13 +2694,13 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_comment_element(&self.current_token.clone(), None);
             }
             Token::DocType { .. } => {
-                self.parse_error("doctype not allowed in in body insertion mode");
+                parse_error!(self, "doctype not allowed in in body insertion mode");
                 // ignore token
             }
             Token::StartTag {
                 name, attributes, ..
             } if name == "html" => {
-                self.parse_error("html tag not allowed in in body insertion mode");
+                parse_error!(self, "html tag not allowed in in body insertion mode");
 
                 if self.open_elements_has("template") {
                     // ignore token
@@ -2157,13 +2713,18 @@ impl<'chars> Html5Parser<'chars> {
                 let first_node = doc
                     .get_node_by_id_mut(first_node_id)
                     .expect("node not found");
+                let mut added = Vec::new();
                 if let NodeData::Element(element) = &mut first_node.data {
                     for (key, value) in attributes {
                         if !element.attributes.contains_key(key) {
                             element.attributes.insert(key.to_owned(), value.to_owned());
+                            added.push(key);
                         }
                     }
                 };
+                for key in added {
+                    doc.attribute_changed(first_node_id, key, None);
+                }
             }
             Token::StartTag { name, .. }
                 if name == "base"
@@ -2185,7 +2746,7 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag {
                 name, attributes, ..
             } if name == "body" => {
-                self.parse_error("body tag not allowed in in body insertion mode");
+                parse_error!(self, "body tag not allowed in in body insertion mode");
 
                 if self.open_elements.len() == 1
                     || open_elements_get!(self, 1).name != "body"
@@ -2199,7 +2760,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.frameset_ok = false;
 
                 let body_node_id = self.open_elements.iter().find(|node_id| {
-                    let node = get_node_by_id!(self.document, **node_id);
+                    let node = self.get_node(**node_id);
                     node.name == "body" && node.is_namespace(HTML_NAMESPACE)
                 });
 
@@ -2209,17 +2770,22 @@ impl<'chars> Html5Parser<'chars> {
                         .get_node_by_id_mut(*body_node_id)
                         .expect("node not found");
 
+                    let mut added = Vec::new();
                     if let NodeData::Element(element) = &mut body_node.data {
                         for (key, value) in attributes {
                             if !element.attributes.contains_key(key) {
                                 element.attributes.insert(key.to_owned(), value.to_owned());
+                                added.push(key);
                             }
                         }
                     }
+                    for key in added {
+                        doc.attribute_changed(*body_node_id, key, None);
+                    }
                 }
             }
             Token::StartTag { name, .. } if name == "frameset" => {
-                self.parse_error("frameset tag not allowed in in body insertion mode");
+                parse_error!(self, "frameset tag not allowed in in body insertion mode");
 
                 if self.open_elements.len() == 1 || open_elements_get!(self, 1).name != "body" {
                     // ignore token
@@ -2233,7 +2799,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 if self.open_elements.len() > 1 {
                     let second_node_id = self.open_elements[1];
-                    let second_node = get_node_by_id!(self.document, second_node_id);
+                    let second_node = self.get_node(second_node_id);
                     if second_node.parent.is_some() {
                         self.document
                             .get_mut()
@@ -2242,7 +2808,7 @@ impl<'chars> Html5Parser<'chars> {
                 }
 
                 while current_node!(self).name != "html" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2259,7 +2825,7 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::EndTag { name, .. } if name == "body" => {
                 if !self.is_in_scope("body", HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("body end tag not in scope");
+                    parse_error!(self, "body end tag not in scope");
                     // ignore token
                     return;
                 }
@@ -2270,7 +2836,7 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::EndTag { name, .. } if name == "html" => {
                 if !self.is_in_scope("body", HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("body end tag not in scope");
+                    parse_error!(self, "body end tag not in scope");
                     // ignore token
                     return;
                 }
@@ -2309,6 +2875,7 @@ impl<'chars> Html5Parser<'chars> {
             {
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2323,12 +2890,13 @@ impl<'chars> Html5Parser<'chars> {
             {
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 if ["h1", "h2", "h3", "h4", "h5", "h6"].contains(&current_node!(self).name.as_str())
                 {
-                    self.parse_error("h1-h6 not allowed in in body insertion mode");
-                    self.open_elements.pop();
+                    parse_error!(self, "h1-h6 not allowed in in body insertion mode");
+                    self.pop_open_element();
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2336,6 +2904,7 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag { name, .. } if name == "pre" || name == "listing" => {
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2346,13 +2915,14 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::StartTag { name, .. } if name == "form" => {
                 if self.form_element.is_some() && !self.open_elements_has("template") {
-                    self.parse_error("error with template, form shzzl");
+                    parse_error!(self, "error with template, form shzzl");
                     // ignore token
                     return;
                 }
 
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 let node_id = self.insert_html_element(&self.current_token.clone());
@@ -2372,7 +2942,11 @@ impl<'chars> Html5Parser<'chars> {
                         self.generate_implied_end_tags(Some("li"), false);
 
                         if current_node!(self).name != "li" {
-                            self.parse_error("li tag not at top of stack");
+                            parse_error!(
+                                self,
+                                ParserError::MisnestedTags,
+                                "li tag not at top of stack",
+                            );
                         }
 
                         self.pop_until_named("li");
@@ -2388,6 +2962,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2404,7 +2979,11 @@ impl<'chars> Html5Parser<'chars> {
                         self.generate_implied_end_tags(Some(tag.as_str()), false);
 
                         if current_node!(self).name != tag {
-                            self.parse_error("{tag} tag not at top of stack");
+                            parse_error!(
+                                self,
+                                ParserError::MisnestedTags,
+                                "{tag} tag not at top of stack",
+                            );
                         }
 
                         self.pop_until_named(tag.as_str());
@@ -2420,6 +2999,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2427,6 +3007,7 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag { name, .. } if name == "plaintext" => {
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2435,7 +3016,7 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::StartTag { name, .. } if name == "button" => {
                 if self.is_in_scope("button", HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("button tag not allowed in in body insertion mode");
+                    parse_error!(self, "button tag not allowed in in body insertion mode");
                     self.generate_implied_end_tags(None, false);
                     self.pop_until_named("button");
                 }
@@ -2474,7 +3055,7 @@ impl<'chars> Html5Parser<'chars> {
                     || name == "ul" =>
             {
                 if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("end tag not in scope");
+                    parse_error!(self, "end tag not in scope");
                     // ignore token
                     return;
                 }
@@ -2483,7 +3064,11 @@ impl<'chars> Html5Parser<'chars> {
 
                 let cn = current_node!(self);
                 if cn.name != *name {
-                    self.parse_error("end tag not at top of stack");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "end tag not at top of stack",
+                    );
                 }
 
                 self.pop_until_named(name);
@@ -2495,7 +3080,7 @@ impl<'chars> Html5Parser<'chars> {
 
                     if node_id.is_none() || !self.is_in_scope(name, HTML_NAMESPACE, Scope::Regular)
                     {
-                        self.parse_error("end tag not in scope");
+                        parse_error!(self, "end tag not in scope");
                         // ignore token
                         return;
                     }
@@ -2505,16 +3090,24 @@ impl<'chars> Html5Parser<'chars> {
 
                     let cn = current_node!(self);
                     if cn.name != *name {
-                        self.parse_error("end tag not at top of stack");
+                        parse_error!(
+                            self,
+                            ParserError::MisnestedTags,
+                            "end tag not at top of stack",
+                        );
                     }
 
                     if node_id != cn.id {
-                        self.parse_error("end tag not at top of stack");
+                        parse_error!(
+                            self,
+                            ParserError::MisnestedTags,
+                            "end tag not at top of stack",
+                        );
                     }
                     self.open_elements_remove(node_id);
                 } else {
                     if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Regular) {
-                        self.parse_error("end tag not in scope");
+                        parse_error!(self, "end tag not in scope");
                         // ignore token
                         return;
                     }
@@ -2523,7 +3116,11 @@ impl<'chars> Html5Parser<'chars> {
 
                     let cn = current_node!(self);
                     if cn.name != *name {
-                        self.parse_error("end tag not at top of stack");
+                        parse_error!(
+                            self,
+                            ParserError::MisnestedTags,
+                            "end tag not at top of stack",
+                        );
                     }
 
                     self.pop_until_named(name);
@@ -2531,21 +3128,16 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::EndTag { name, .. } if name == "p" => {
                 if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Button) {
-                    self.parse_error("end tag not in scope");
+                    parse_error!(self, "end tag not in scope");
 
-                    let token = Token::StartTag {
-                        name: "p".to_string(),
-                        is_self_closing: false,
-                        attributes: HashMap::new(),
-                    };
-                    self.insert_html_element(&token);
+                    self.insert_implied_html_element("p");
                 }
 
                 self.close_p_element();
             }
             Token::EndTag { name, .. } if name == "li" => {
                 if !self.is_in_scope(name, HTML_NAMESPACE, Scope::ListItem) {
-                    self.parse_error("end tag not in scope");
+                    parse_error!(self, "end tag not in scope");
                     // ignore token
                     return;
                 }
@@ -2553,14 +3145,18 @@ impl<'chars> Html5Parser<'chars> {
                 self.generate_implied_end_tags(Some("li"), false);
 
                 if current_node!(self).name != *name {
-                    self.parse_error("end tag not at top of stack");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "end tag not at top of stack",
+                    );
                 }
 
                 self.pop_until_named(name);
             }
             Token::EndTag { name, .. } if name == "dd" || name == "dt" => {
                 if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("end tag not in scope");
+                    parse_error!(self, "end tag not in scope");
                     // ignore token
                     return;
                 }
@@ -2568,7 +3164,11 @@ impl<'chars> Html5Parser<'chars> {
                 self.generate_implied_end_tags(Some(name), false);
 
                 if current_node!(self).name != *name {
-                    self.parse_error("end tag not at top of stack");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "end tag not at top of stack",
+                    );
                 }
 
                 self.pop_until_named(name);
@@ -2589,12 +3189,16 @@ impl<'chars> Html5Parser<'chars> {
                     self.generate_implied_end_tags(Some(name), false);
 
                     if current_node!(self).name != *name {
-                        self.parse_error("end tag not at top of stack");
+                        parse_error!(
+                            self,
+                            ParserError::MisnestedTags,
+                            "end tag not at top of stack",
+                        );
                     }
 
                     self.pop_until_any(&["h1", "h2", "h3", "h4", "h5", "h6"]);
                 } else {
-                    self.parse_error("end tag not in scope");
+                    parse_error!(self, "end tag not in scope");
                     // ignore token
                 }
             }
@@ -2604,8 +3208,13 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::StartTag { name, .. } if name == "a" => {
                 if let Some(node_id) = self.active_formatting_elements_has_until_marker("a") {
-                    self.parse_error("a tag in active formatting elements");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "a tag in active formatting elements",
+                    );
                     self.adoption_agency_algorithm(&self.current_token.clone());
+                    return_on_strict_error!(self);
 
                     // Remove from lists if not done already by the adoption agency
                     self.open_elements_remove(node_id);
@@ -2640,8 +3249,9 @@ impl<'chars> Html5Parser<'chars> {
                 self.reconstruct_formatting();
 
                 if self.is_in_scope("nobr", HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("nobr tag in scope");
+                    parse_error!(self, ParserError::MisnestedTags, "nobr tag in scope");
                     self.adoption_agency_algorithm(&self.current_token.clone());
+                    return_on_strict_error!(self);
                     self.reconstruct_formatting();
                 }
 
@@ -2665,6 +3275,7 @@ impl<'chars> Html5Parser<'chars> {
                     || name == "u" =>
             {
                 self.adoption_agency_algorithm(&self.current_token.clone());
+                return_on_strict_error!(self);
 
                 #[cfg(feature = "debug_parser")]
                 self.display_debug_info();
@@ -2683,7 +3294,7 @@ impl<'chars> Html5Parser<'chars> {
                 if name == "applet" || name == "marquee" || name == "object" =>
             {
                 if !self.is_in_scope(name, HTML_NAMESPACE, Scope::Regular) {
-                    self.parse_error("end tag not in scope");
+                    parse_error!(self, "end tag not in scope");
                     // ignore token
                     return;
                 }
@@ -2691,7 +3302,11 @@ impl<'chars> Html5Parser<'chars> {
                 self.generate_implied_end_tags(None, false);
 
                 if current_node!(self).name != *name {
-                    self.parse_error("end tag not at top of stack");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "end tag not at top of stack",
+                    );
                 }
 
                 self.pop_until_named(name);
@@ -2702,6 +3317,7 @@ impl<'chars> Html5Parser<'chars> {
                     && self.is_in_scope("p", HTML_NAMESPACE, Scope::Button)
                 {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2714,7 +3330,7 @@ impl<'chars> Html5Parser<'chars> {
                 is_self_closing,
                 ..
             } if name == "br" => {
-                self.parse_error("br end tag not allowed");
+                parse_error!(self, "br end tag not allowed");
                 self.reconstruct_formatting();
 
                 // Remove attributes if any
@@ -2725,7 +3341,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 self.insert_html_element(&br);
 
-                self.open_elements.pop();
+                self.pop_open_element();
                 self.acknowledge_closing_tag(*is_self_closing);
                 self.frameset_ok = false;
             }
@@ -2743,7 +3359,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.reconstruct_formatting();
 
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
 
                 self.acknowledge_closing_tag(*is_self_closing);
                 self.frameset_ok = false;
@@ -2756,7 +3372,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.reconstruct_formatting();
 
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
 
                 self.acknowledge_closing_tag(*is_self_closing);
 
@@ -2772,7 +3388,7 @@ impl<'chars> Html5Parser<'chars> {
                 ..
             } if name == "param" || name == "source" || name == "track" => {
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
 
                 self.acknowledge_closing_tag(*is_self_closing);
             }
@@ -2783,10 +3399,11 @@ impl<'chars> Html5Parser<'chars> {
             } if name == "hr" => {
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
 
                 self.acknowledge_closing_tag(*is_self_closing);
                 self.frameset_ok = false;
@@ -2796,7 +3413,7 @@ impl<'chars> Html5Parser<'chars> {
                 is_self_closing,
                 attributes,
             } if name == "image" => {
-                self.parse_error("image tag not allowed");
+                parse_error!(self, "image tag not allowed");
                 self.current_token = Token::StartTag {
                     name: "img".to_string(),
                     attributes: attributes.clone(),
@@ -2817,6 +3434,7 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag { name, .. } if name == "xmp" => {
                 if self.is_in_scope("p", HTML_NAMESPACE, Scope::Button) {
                     self.close_p_element();
+                    return_on_strict_error!(self);
                 }
 
                 self.reconstruct_formatting();
@@ -2853,7 +3471,7 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::StartTag { name, .. } if name == "optgroup" || name == "option" => {
                 if current_node!(self).name == "option" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 self.reconstruct_formatting();
@@ -2866,7 +3484,7 @@ impl<'chars> Html5Parser<'chars> {
                 }
 
                 if current_node!(self).name != "ruby" {
-                    self.parse_error("rb or rtc not in scope");
+                    parse_error!(self, "rb or rtc not in scope");
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2877,7 +3495,7 @@ impl<'chars> Html5Parser<'chars> {
                 }
 
                 if current_node!(self).name != "rtc" && current_node!(self).name != "ruby" {
-                    self.parse_error("rp or rt not in scope");
+                    parse_error!(self, "rp or rt not in scope");
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -2900,7 +3518,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_foreign_element(&token, MATHML_NAMESPACE);
 
                 if *is_self_closing {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                     self.acknowledge_closing_tag(*is_self_closing);
                 }
             }
@@ -2922,7 +3540,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_foreign_element(&token, SVG_NAMESPACE);
 
                 if *is_self_closing {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                     self.acknowledge_closing_tag(*is_self_closing);
                 }
             }
@@ -2939,7 +3557,7 @@ impl<'chars> Html5Parser<'chars> {
                     || name == "thead"
                     || name == "tr" =>
             {
-                self.parse_error("tag not allowed in in body insertion mode");
+                parse_error!(self, "tag not allowed in in body insertion mode");
                 // ignore token
             }
             Token::StartTag { .. } => {
@@ -2969,7 +3587,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_comment_element(&self.current_token.clone(), None);
             }
             Token::DocType { .. } => {
-                self.parse_error("doctype not allowed in before head insertion mode");
+                parse_error!(self, "doctype not allowed in before head insertion mode");
                 // ignore token
             }
             Token::StartTag { name, .. } if name == "html" => {
@@ -2983,20 +3601,22 @@ impl<'chars> Html5Parser<'chars> {
                 self.acknowledge_closing_tag(*is_self_closing);
 
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
             }
             Token::StartTag {
                 name,
                 is_self_closing,
-                ..
+                attributes,
             } if name == "meta" => {
+                let encoding = self.meta_encoding(attributes);
                 self.acknowledge_closing_tag(*is_self_closing);
 
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
 
-                // @TODO: if active speculative html parser is null then...
-                // we probably want to change the encoding if the element has a charset attribute and the current encoding is "tentative"
+                if let Some(encoding) = encoding {
+                    self.change_encoding(encoding);
+                }
             }
             Token::StartTag { name, .. } if name == "title" => {
                 self.parse_rcdata();
@@ -3013,8 +3633,9 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::StartTag { name, .. } if name == "script" => {
                 let insert_position = self.appropriate_place_insert(None);
-                let node = self.create_node(&self.current_token.clone(), HTML_NAMESPACE);
-                let node_id = self.document.get_mut().add_new_node(node);
+                let mut document = Document::clone(insert_position.document());
+                let node = self.create_node(&document, &self.current_token.clone(), HTML_NAMESPACE);
+                let node_id = document.get_mut().add_new_node(node);
                 self.insert_element_helper(node_id, insert_position);
 
                 // TODO Set the element's parser document to the Document, and set the element's force async to false.
@@ -3040,38 +3661,43 @@ impl<'chars> Html5Parser<'chars> {
                 anything_else = true;
             }
             Token::StartTag { name, .. } if name == "template" => {
-                let node_id = self.insert_html_element(&self.current_token.clone());
+                let token = self.current_token.clone();
+                let (node_id, contents_id) = match self.attach_declarative_shadow_root(&token) {
+                    // The template is not inserted, its contents go into the shadow root
+                    Some(shadow_root_id) => (
+                        self.push_html_element_without_inserting(&token),
+                        shadow_root_id,
+                    ),
+                    None => {
+                        let node_id = self.insert_html_element(&token);
+                        (node_id, node_id)
+                    }
+                };
 
                 self.active_formatting_elements_push_marker();
                 self.frameset_ok = false;
                 self.insertion_mode = InsertionMode::InTemplate;
                 self.template_insertion_mode.push(InsertionMode::InTemplate);
 
-                // Let adjusted insert location
-                // intended parent
-                // document = indented parent's node document
-
-                // check shadow root != none
-                // or allow declarative shadow roots == true
-                // or adjusted current node is not topmost element in stack open elements
-                // then insert html element for token
-                // else:
-                //
-
                 {
-                    let current_node_id = current_node!(self).id;
+                    let mut document = self.document_of(node_id);
+                    let contents = DocumentFragment::new(
+                        Document::clone(&document),
+                        contents_id,
+                        &document.get().arena,
+                    );
+                    self.template_documents.push((node_id, contents.contents()));
 
-                    let doc = Document::clone(&self.document);
-                    let mut doc_mut = self.document.get_mut();
+                    let mut doc_mut = document.get_mut();
                     let node = doc_mut.get_node_by_id_mut(node_id).expect("node not found");
                     if let NodeData::Element(ref mut data) = node.data {
-                        data.template_contents = Some(DocumentFragment::new(doc, current_node_id));
+                        data.template_contents = Some(contents);
                     }
                 }
             }
             Token::EndTag { name, .. } if name == "template" => {
                 if !self.open_elements_has("template") {
-                    self.parse_error("could not find template tag in open element stack");
+                    parse_error!(self, "could not find template tag in open element stack");
                     // ignore token
                     return;
                 }
@@ -3079,7 +3705,11 @@ impl<'chars> Html5Parser<'chars> {
                 self.generate_implied_end_tags(None, true);
 
                 if current_node!(self).name != "template" {
-                    self.parse_error("template end tag not at top of stack");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "template end tag not at top of stack",
+                    );
                 }
 
                 self.pop_until_named("template");
@@ -3088,12 +3718,12 @@ impl<'chars> Html5Parser<'chars> {
                 self.reset_insertion_mode();
             }
             Token::StartTag { name, .. } if name == "head" => {
-                self.parse_error("head tag not allowed in in head insertion mode");
+                parse_error!(self, "head tag not allowed in in head insertion mode");
                 // ignore token
                 return;
             }
             Token::EndTag { .. } => {
-                self.parse_error("end tag not allowed in in head insertion mode");
+                parse_error!(self, "end tag not allowed in in head insertion mode");
                 // ignore token
                 return;
             }
@@ -3108,6 +3738,99 @@ impl<'chars> Html5Parser<'chars> {
         }
     }
 
+    /// Returns the encoding that is declared by the attributes of a meta element, if any
+    fn meta_encoding(&self, attributes: &HashMap<String, String>) -> Option<Encoding> {
+        if let Some(encoding) = attributes
+            .get("charset")
+            .and_then(|v| Encoding::for_label(v))
+        {
+            return Some(encoding);
+        }
+
+        match (attributes.get("http-equiv"), attributes.get("content")) {
+            (Some(http_equiv), Some(content))
+                if http_equiv.eq_ignore_ascii_case("content-type") =>
+            {
+                sniffer::extract_encoding_from_content(content)
+            }
+            _ => None,
+        }
+    }
+
+    /// Changes the encoding of the input stream when we are not yet certain about it. When the
+    /// encoding actually differs, the parser stops so the document can be parsed again.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+    fn change_encoding(&mut self, encoding: Encoding) {
+        // We cannot restart parsing for a meta element that was written by a script, nor once a
+        // script has written into the input stream, as decoding the bytes again would lose the
+        // written characters
+        if self.is_fragment_case
+            || self.script_nesting_level > 0
+            || self.tokenizer.chars.has_inserted_chars()
+            || self.tokenizer.chars.confidence != Confidence::Tentative
+        {
+            return;
+        }
+
+        // 1.
+        let current = self.tokenizer.chars.encoding;
+        if current == Encoding::UTF16BE || current == Encoding::UTF16LE {
+            self.tokenizer.chars.set_confidence(Confidence::Certain);
+            return;
+        }
+
+        // 2. / 3.
+        let encoding = encoding.for_meta();
+
+        // 4.
+        if encoding == current {
+            self.tokenizer.chars.set_confidence(Confidence::Certain);
+            return;
+        }
+
+        // 5. / 6.
+        // We do not check if the bytes decoded so far are the same in both encodings, but always
+        // parse the document again.
+        self.encoding_change = Some(encoding);
+        self.parser_finished = true;
+    }
+
+    /// Prepares a script element that has just been inserted by the parser. We cannot execute
+    /// scripts ourselves, so every script becomes the pending parsing-blocking script which the
+    /// embedder can execute while the parser is paused.
+    /// https://html.spec.whatwg.org/multipage/scripting.html#prepare-the-script-element
+    fn prepare_script(&mut self, script_id: NodeId) {
+        // Scripts inserted by the fragment parser are marked as already started, and scripts are
+        // never executed when scripting is disabled
+        if self.is_fragment_case || !self.scripting_enabled {
+            return;
+        }
+
+        self.pending_parsing_blocking_script = Some(script_id);
+    }
+
+    /// Pauses the parser when there is a pending parsing-blocking script after a script end tag.
+    /// The parser stays paused until resume() is called.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag
+    fn handle_pending_script(&mut self) {
+        if self.pending_parsing_blocking_script.is_none() {
+            return;
+        }
+
+        // A script inserted by document.write() is executed after the script that wrote it
+        if self.script_nesting_level > 0 {
+            self.parser_pause_flag = true;
+            return;
+        }
+
+        // The script is executed by the embedder with the insertion point just before the next
+        // input character. We keep the parser paused until the embedder resumes it.
+        self.running_script = self.pending_parsing_blocking_script.take();
+        self.insertion_point = Some(self.tokenizer.get_position().offset);
+        self.script_nesting_level += 1;
+        self.parser_pause_flag = true;
+    }
+
     /// Handle insertion mode "in_template"
     fn handle_in_template(&mut self) {
         match &self.current_token {
@@ -3177,7 +3900,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.reprocess_token = true;
             }
             Token::EndTag { .. } => {
-                self.parse_error("end tag not allowed in in template insertion mode");
+                parse_error!(self, "end tag not allowed in in template insertion mode");
                 // ignore token
             }
             Token::Eof => {
@@ -3187,7 +3910,7 @@ impl<'chars> Html5Parser<'chars> {
                     return;
                 }
 
-                self.parse_error("eof not allowed in in template insertion mode");
+                parse_error!(self, "eof not allowed in in template insertion mode");
 
                 self.pop_until_named("template");
                 self.active_formatting_elements_clear_until_marker();
@@ -3217,7 +3940,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_comment_element(&self.current_token.clone(), None);
             }
             Token::DocType { .. } => {
-                self.parse_error("doctype not allowed in in table insertion mode");
+                parse_error!(self, "doctype not allowed in in table insertion mode");
                 // ignore token
             }
             Token::StartTag { name, .. } if name == "caption" => {
@@ -3234,12 +3957,7 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag { name, .. } if name == "col" => {
                 self.clear_stack_back_to_table_context();
 
-                let token = Token::StartTag {
-                    name: "colgroup".to_string(),
-                    is_self_closing: false,
-                    attributes: HashMap::new(),
-                };
-                self.insert_html_element(&token);
+                self.insert_implied_html_element("colgroup");
 
                 self.insertion_mode = InsertionMode::InColumnGroup;
                 self.reprocess_token = true;
@@ -3256,18 +3974,13 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag { name, .. } if name == "td" || name == "th" || name == "tr" => {
                 self.clear_stack_back_to_table_context();
 
-                let token = Token::StartTag {
-                    name: "tbody".to_string(),
-                    is_self_closing: false,
-                    attributes: HashMap::new(),
-                };
-                self.insert_html_element(&token);
+                self.insert_implied_html_element("tbody");
 
                 self.insertion_mode = InsertionMode::InTableBody;
                 self.reprocess_token = true;
             }
             Token::StartTag { name, .. } if name == "table" => {
-                self.parse_error("table tag not allowed in in table insertion mode");
+                parse_error!(self, "table tag not allowed in in table insertion mode");
 
                 if !self.open_elements_has("table") {
                     // ignore token
@@ -3280,7 +3993,7 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::EndTag { name, .. } if name == "table" => {
                 if !self.open_elements_has("table") {
-                    self.parse_error("table end tag not allowed in in table insertion mode");
+                    parse_error!(self, "table end tag not allowed in in table insertion mode");
                     // ignore token
                     return;
                 }
@@ -3301,7 +4014,7 @@ impl<'chars> Html5Parser<'chars> {
                     || name == "thead"
                     || name == "tr" =>
             {
-                self.parse_error("end tag not allowed in in table insertion mode");
+                parse_error!(self, "end tag not allowed in in table insertion mode");
                 // ignore token
                 return;
             }
@@ -3323,7 +4036,7 @@ impl<'chars> Html5Parser<'chars> {
                 {
                     anything_else = true;
                 } else {
-                    self.parse_error("input tag not allowed in in table insertion mode");
+                    parse_error!(self, "input tag not allowed in in table insertion mode");
 
                     self.acknowledge_closing_tag(*is_self_closing);
 
@@ -3332,7 +4045,7 @@ impl<'chars> Html5Parser<'chars> {
                 }
             }
             Token::StartTag { name, .. } if name == "form" => {
-                self.parse_error("form tag not allowed in in table insertion mode");
+                parse_error!(self, "form tag not allowed in in table insertion mode");
 
                 if self.open_elements_has("template") || self.form_element.is_some() {
                     // ignore token
@@ -3351,10 +4064,11 @@ impl<'chars> Html5Parser<'chars> {
         }
 
         if anything_else {
-            self.parse_error("anything else not allowed in in table insertion mode");
+            parse_error!(self, "anything else not allowed in in table insertion mode");
 
             self.foster_parenting = true;
             self.handle_in_body();
+            return_on_strict_error!(self);
             self.foster_parenting = false;
         }
     }
@@ -3367,7 +4081,11 @@ impl<'chars> Html5Parser<'chars> {
                 self.tokenizer.insert_tokens_at_queue_start(tokens);
             }
             Token::Text(..) if self.current_token.is_null() => {
-                self.parse_error("null character not allowed in in select insertion mode");
+                parse_error!(
+                    self,
+                    ParserError::UnexpectedNullCharacter,
+                    "null character not allowed in in select insertion mode",
+                );
                 // ignore token
             }
             Token::Text(..) => {
@@ -3377,7 +4095,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.insert_comment_element(&self.current_token.clone(), None);
             }
             Token::DocType { .. } => {
-                self.parse_error("doctype not allowed in in select insertion mode");
+                parse_error!(self, "doctype not allowed in in select insertion mode");
                 // ignore token
             }
             Token::StartTag { name, .. } if name == "html" => {
@@ -3385,18 +4103,18 @@ impl<'chars> Html5Parser<'chars> {
             }
             Token::StartTag { name, .. } if name == "option" => {
                 if current_node!(self).name == "option" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 self.insert_html_element(&self.current_token.clone());
             }
             Token::StartTag { name, .. } if name == "optgroup" => {
                 if current_node!(self).name == "option" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 if current_node!(self).name == "optgroup" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 self.insert_html_element(&self.current_token.clone());
@@ -3406,46 +4124,56 @@ impl<'chars> Html5Parser<'chars> {
                 is_self_closing,
                 ..
             } if name == "hr" => {
+                let is_self_closing = *is_self_closing;
                 if current_node!(self).name == "option" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 if current_node!(self).name == "optgroup" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
-                self.acknowledge_closing_tag(*is_self_closing);
+                self.acknowledge_closing_tag(is_self_closing);
 
                 self.insert_html_element(&self.current_token.clone());
-                self.open_elements.pop();
+                self.pop_open_element();
             }
             Token::EndTag { name, .. } if name == "optgroup" => {
                 if current_node!(self).name == "option"
                     && self.open_elements.len() > 1
                     && open_elements_get!(self, self.open_elements.len() - 2).name == "optgroup"
                 {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
 
                 if current_node!(self).name == "optgroup" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 } else {
-                    self.parse_error("optgroup end tag not allowed in in select insertion mode");
+                    parse_error!(
+                        self,
+                        "optgroup end tag not allowed in in select insertion mode"
+                    );
                     // ignore token
                 }
             }
             Token::EndTag { name, .. } if name == "option" => {
                 if current_node!(self).name == "option" {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 } else {
-                    self.parse_error("option end tag not allowed in in select insertion mode");
+                    parse_error!(
+                        self,
+                        "option end tag not allowed in in select insertion mode"
+                    );
                     // ignore token
                 }
             }
             Token::EndTag { name, .. } if name == "select" => {
                 if !self.is_in_scope("select", HTML_NAMESPACE, Scope::Select) {
                     // fragment case
-                    self.parse_error("select end tag not allowed in in select insertion mode");
+                    parse_error!(
+                        self,
+                        "select end tag not allowed in in select insertion mode"
+                    );
                     // ignore token
                     return;
                 }
@@ -3454,7 +4182,7 @@ impl<'chars> Html5Parser<'chars> {
                 self.reset_insertion_mode();
             }
             Token::StartTag { name, .. } if name == "select" => {
-                self.parse_error("select tag not allowed in in select insertion mode");
+                parse_error!(self, "select tag not allowed in in select insertion mode");
 
                 if !self.is_in_scope("select", HTML_NAMESPACE, Scope::Select) {
                     // fragment case
@@ -3468,7 +4196,8 @@ impl<'chars> Html5Parser<'chars> {
             Token::StartTag { name, .. }
                 if name == "input" || name == "keygen" || name == "textarea" =>
             {
-                self.parse_error(
+                parse_error!(
+                    self,
                     "input, keygen or textarea tag not allowed in in select insertion mode",
                 );
 
@@ -3493,7 +4222,10 @@ impl<'chars> Html5Parser<'chars> {
                 self.handle_in_body();
             }
             _ => {
-                self.parse_error("anything else not allowed in in select insertion mode");
+                parse_error!(
+                    self,
+                    "anything else not allowed in in select insertion mode"
+                );
                 // ignore token
             }
         }
@@ -3510,7 +4242,7 @@ impl<'chars> Html5Parser<'chars> {
             match self.active_formatting_elements[idx] {
                 ActiveElement::Marker => return None,
                 ActiveElement::Node(node_id) => {
-                    if get_node_by_id!(self.document, node_id).name == tag {
+                    if self.get_node(node_id).name == tag {
                         return Some(node_id);
                     }
                 }
@@ -3553,12 +4285,12 @@ impl<'chars> Html5Parser<'chars> {
     fn active_formatting_elements_push(&mut self, node_id: NodeId) {
         let mut matched = 0;
         let mut first_matched = None;
-        let node = get_node_by_id!(self.document, node_id);
+        let node = self.get_node(node_id);
         for entry in self.active_formatting_elements.iter().rev() {
             match entry {
                 ActiveElement::Marker => break,
                 &ActiveElement::Node(id) => {
-                    let current_node = get_node_by_id!(self.document, id);
+                    let current_node = self.get_node(id);
                     if current_node.matches_tag_and_attrs_without_order(&node) {
                         if matched >= 2 {
                             first_matched = Some(id);
@@ -3629,7 +4361,7 @@ impl<'chars> Html5Parser<'chars> {
             }
             let node_id = entry.node_id().expect("node id not found");
 
-            let entry_node = get_node_by_id!(self.document, node_id).clone();
+            let entry_node = self.get_node(node_id).clone();
             let new_node_id = self.insert_element_from_node(entry_node, None);
 
             self.active_formatting_elements[entry_index] = ActiveElement::Node(new_node_id);
@@ -3651,7 +4383,11 @@ impl<'chars> Html5Parser<'chars> {
         self.generate_implied_end_tags(Some("p"), false);
 
         if current_node!(self).name != "p" {
-            self.parse_error("p element not at top of stack");
+            parse_error!(
+                self,
+                ParserError::MisnestedTags,
+                "p element not at top of stack"
+            );
         }
 
         self.pop_until_named("p");
@@ -3748,7 +4484,7 @@ impl<'chars> Html5Parser<'chars> {
         println!("insertion mode  : {:?}", self.insertion_mode);
         print!("Open elements   : [ ");
         for node_id in &self.open_elements {
-            let node = get_node_by_id!(self.document, *node_id);
+            let node = self.get_node(*node_id);
             print!("({}) {}, ", node_id, node.name);
         }
         println!("]");
@@ -3757,7 +4493,7 @@ impl<'chars> Html5Parser<'chars> {
         for elem in &self.active_formatting_elements {
             match elem {
                 ActiveElement::Node(node_id) => {
-                    let node = get_node_by_id!(self.document, *node_id);
+                    let node = self.get_node(*node_id);
                     print!("({}) {}, ", node_id, node.name);
                 }
                 ActiveElement::Marker => {
@@ -3777,35 +4513,39 @@ impl<'chars> Html5Parser<'chars> {
     /// separate function as this is also called during the adoption agency algorithm
     fn handle_in_body_any_other_end_tag(&mut self, tag_name: &str) {
         if self.open_elements.is_empty() {
-            self.parse_error("no open elements");
+            parse_error!(self, "no open elements");
             // ignore token
             return;
         }
 
         for idx in (0..self.open_elements.len()).rev() {
             let node_id = self.open_elements[idx];
-            let node = get_node_by_id!(self.document, node_id).clone();
+            let node = self.get_node(node_id).clone();
 
             if node.name == tag_name {
                 self.generate_implied_end_tags(Some(node.name.as_str()), false);
 
                 // It might be possible that the last item is not our node_id. Emit parse error if so
                 if current_node!(self).id != node.id {
-                    self.parse_error("end tag not at top of stack");
+                    parse_error!(
+                        self,
+                        ParserError::MisnestedTags,
+                        "end tag not at top of stack",
+                    );
                 }
 
                 // Pop until we reach the node.id
                 while current_node!(self).id != node.id {
-                    self.open_elements.pop();
+                    self.pop_open_element();
                 }
                 // Pop node_id as well
-                self.open_elements.pop();
+                self.pop_open_element();
 
                 break;
             }
 
             if node.is_special() {
-                self.parse_error("special node");
+                parse_error!(self, "special node");
                 // ignore token
                 return;
             }
@@ -3832,13 +4572,16 @@ impl<'chars> Html5Parser<'chars> {
     /// Fetches the next token from the tokenizer. However, if the token is a text token AND
     /// it starts with one or more whitespaces, the token is split into 2 tokens: the whitespace part
     /// and the remainder.
-    fn fetch_next_token(&mut self) -> Token {
-        // If there are no tokens to fetch, fetch the next token from the tokenizer
+    fn fetch_next_token(&mut self) -> Result<Option<Token>> {
+        // If there are no tokens to fetch, fetch the next token from the tokenizer. When the
+        // tokenizer needs more input, there is no token yet.
         if self.token_queue.is_empty() {
-            let token = self
-                .tokenizer
-                .next_token(self.parser_data())
-                .expect("tokenizer error");
+            let Some(SpannedToken { token, span, .. }) =
+                self.tokenizer.try_next_spanned_token(self.parser_data())?
+            else {
+                return Ok(None);
+            };
+            self.current_span = span;
 
             if let Token::Text(value) = token {
                 self.token_queue.push(Token::Text(value));
@@ -3847,14 +4590,59 @@ impl<'chars> Html5Parser<'chars> {
                 // }
             } else {
                 // Simply return the token
-                return token;
+                return Ok(Some(token));
             }
         }
 
         let token = self.token_queue.get(0).cloned();
         self.token_queue.remove(0);
 
-        token.expect("no token found")
+        Ok(Some(token.expect("no token found")))
+    }
+
+    /// Attaches a declarative shadow root to the current node when the template start tag has a
+    /// valid shadowrootmode attribute. Returns the shadow root, or None when the template must be
+    /// inserted as a regular element. See the "in head" insertion mode for a template start tag.
+    fn attach_declarative_shadow_root(&mut self, token: &Token) -> Option<NodeId> {
+        let Token::StartTag { attributes, .. } = token else {
+            return None;
+        };
+
+        let mode = match attributes.get("shadowrootmode") {
+            Some(mode) if mode.eq_ignore_ascii_case("open") => ShadowRootMode::Open,
+            Some(mode) if mode.eq_ignore_ascii_case("closed") => ShadowRootMode::Closed,
+            _ => return None,
+        };
+        // The topmost element cannot become a shadow host, which includes the context element
+        // when parsing a fragment
+        if !self.allow_declarative_shadow_roots || self.open_elements.len() < 2 {
+            return None;
+        }
+
+        let host_id = current_node!(self).id;
+        let mut document = self.document_of(host_id);
+        let mut doc = document.get_mut();
+        if doc.shadow_root(host_id).is_some() {
+            return None;
+        }
+
+        let init = ShadowRootInit {
+            mode,
+            delegates_focus: attributes.contains_key("shadowrootdelegatesfocus"),
+            slot_assignment: SlotAssignmentMode::Named,
+            clonable: attributes.contains_key("shadowrootclonable"),
+            serializable: attributes.contains_key("shadowrootserializable"),
+        };
+        // Elements that cannot be shadow hosts get a regular template element instead
+        let shadow_root_id = doc.attach_shadow(host_id, init).ok()?;
+        if let Some(NodeData::ShadowRoot(shadow_root)) = doc
+            .get_node_by_id_mut(shadow_root_id)
+            .map(|node| &mut node.data)
+        {
+            shadow_root.declarative = true;
+        }
+
+        Some(shadow_root_id)
     }
 
     fn get_adjusted_current_node(&self) -> Node {
@@ -3919,7 +4707,7 @@ impl<'chars> Html5Parser<'chars> {
     /// Finds the node where to place an unexpected html tag. This can only be done on a mathml
     /// insertion point, a svg_html insertion point, or at a regular html namespaced node.
     fn process_unexpected_html_tag(&mut self) {
-        self.parse_error("process_unexpected_html_tag");
+        parse_error!(self, "process_unexpected_html_tag");
 
         let mut current_node = current_node!(self);
 
@@ -3927,7 +4715,7 @@ impl<'chars> Html5Parser<'chars> {
             && !current_node.is_html_integration_point()
             && !current_node.is_namespace(HTML_NAMESPACE)
         {
-            self.open_elements.pop();
+            self.pop_open_element();
             if self.open_elements.is_empty() {
                 return;
             }
@@ -4054,11 +4842,32 @@ impl<'chars> Html5Parser<'chars> {
     }
 }
 
+/// Returns how many levels of elements the subtree of the node has below the node
+fn element_height(document: &Document, node_id: NodeId) -> usize {
+    let Some(node) = document.get_node_by_id(node_id) else {
+        return 0;
+    };
+
+    node.children
+        .iter()
+        .filter(|&&child_id| {
+            document
+                .get_node_by_id(child_id)
+                .is_some_and(|child| matches!(child.data, NodeData::Element(_)))
+        })
+        .map(|&child_id| element_height(document, child_id) + 1)
+        .max()
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::bytes::Encoding;
+    use crate::html5::error_logger::Severity;
+    use crate::html5::mutation_observer::MutationObserverInit;
     use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::tokenizer::token::TokenKind;
 
     macro_rules! node_create {
         ($self:expr, $name:expr) => {{
@@ -4367,16 +5176,23 @@ mod test {
         chars.read_from_str(
             "<div id=\"my id\"></div> \
              <div id=\"123\"></div> \
-             <div id=\"\"></div>",
+             <div id=\"\"></div> \
+             <p id=\"123\"></p>",
             Some(Encoding::UTF8),
         );
 
         let document = DocumentBuilder::new_document();
         let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
 
-        assert!(document.get().get_node_by_named_id("my id").is_none());
-        assert!(document.get().get_node_by_named_id("123").is_none());
-        assert!(document.get().get_node_by_named_id("").is_none());
+        // Invalid ids are found as well, only empty ids are not indexed
+        let doc = document.get();
+        assert_eq!(doc.get_node_by_named_id("my id").unwrap().name, "div");
+        assert_eq!(doc.get_node_by_named_id("123").unwrap().name, "div");
+        assert!(doc.get_node_by_named_id("").is_none());
+        let duplicates = doc.duplicate_ids();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].id, "123");
+        assert_eq!(duplicates[0].elements.len(), 2);
     }
 
     #[test]
@@ -4397,4 +5213,566 @@ mod test {
         assert_eq!(div.id, NodeId::from(4));
         assert_eq!(div.name, "div");
     }
+
+    #[test]
+    fn meta_charset_changes_encoding() {
+        // The meta element is placed after the first 1024 bytes, so it will not be found by the
+        // prescan and the encoding can only be changed by the tree builder.
+        let mut bytes = format!("<!--{}-->", " ".repeat(1024)).into_bytes();
+        bytes.extend_from_slice(b"<meta charset=\"iso-8859-2\"><p>\xb1</p>");
+
+        let mut chars = CharIterator::new();
+        chars.read_from_bytes(&bytes, None);
+        assert_eq!(chars.encoding, Encoding::Windows1252);
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        assert_eq!(document.get().encoding, Encoding::ISO8859_2);
+        assert!(chars.is_certain_encoding());
+
+        // The document is parsed again from scratch, so the root holds a single comment and html node
+        let doc_read = document.get();
+        assert_eq!(doc_read.get_root().children.len(), 2);
+        let text = doc_read
+            .descendants(NodeId::root())
+            .find_map(|node| match &node.data {
+                NodeData::Text(text) => Some(text.value().to_string()),
+                _ => None,
+            });
+        assert_eq!(text, Some("\u{105}".to_string()));
+    }
+
+    #[test]
+    fn no_encoding_change_after_document_write() {
+        let mut bytes = b"<script>w()</script>".to_vec();
+        bytes.extend_from_slice(format!("<!--{}-->", " ".repeat(1024)).as_bytes());
+        bytes.extend_from_slice(b"<meta charset=\"iso-8859-2\"><p>\xb1</p>");
+
+        let mut chars = CharIterator::new();
+        chars.read_from_bytes(&bytes, None);
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+        assert!(matches!(
+            parser.close_input().unwrap(),
+            ParserStatus::PendingScript(_)
+        ));
+        parser.document_write("<i id=\"written\"></i>").unwrap();
+        assert_eq!(parser.resume().unwrap(), ParserStatus::Finished);
+
+        // The document is not parsed again, so the written element is kept
+        assert!(document.get().get_node_by_named_id("written").is_some());
+        assert_eq!(chars.encoding, Encoding::Windows1252);
+    }
+
+    #[test]
+    fn parse_streamed_document() {
+        let mut chars = CharIterator::new();
+        chars.start_stream(Some(Encoding::UTF8));
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+
+        assert_eq!(
+            parser.feed(b"<!DOCTYPE html><p id=\"fi").unwrap(),
+            ParserStatus::NeedsInput
+        );
+        assert!(document.get().get_node_by_named_id("first").is_none());
+
+        // The text that is available is already inserted, even without a closing tag
+        parser.feed(b"rst\">caf\xc3").unwrap();
+        assert!(document.get().get_node_by_named_id("first").is_some());
+
+        parser.feed(b"\xa9 &am").unwrap();
+        parser.feed(b"p; bar</p>").unwrap();
+        let errors = parser.finish().unwrap();
+        assert!(errors.is_empty());
+
+        let doc_read = document.get();
+        let p = doc_read.get_node_by_named_id("first").unwrap();
+        let text = doc_read.get_node_by_id(p.children[0]).unwrap();
+        match &text.data {
+            NodeData::Text(text) => assert_eq!(text.value(), "caf\u{e9} & bar"),
+            _ => panic!("expected text node"),
+        }
+    }
+
+    #[test]
+    fn pause_on_scripts() {
+        let mut chars = CharIterator::new();
+        chars.start_stream(Some(Encoding::UTF8));
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+
+        let status = parser
+            .feed(b"<script id=\"one\">a()</script><p id=\"p\"><svg><script id=\"two\"/></svg>")
+            .unwrap();
+        let script_id = document.get().get_node_by_named_id("one").unwrap().id;
+        assert_eq!(status, ParserStatus::PendingScript(script_id));
+        assert!(document.get().get_node_by_named_id("p").is_none());
+
+        // Feeding more input does not resume the parser
+        let status = parser.feed(b"<div id=\"div\"></div>").unwrap();
+        assert_eq!(status, ParserStatus::PendingScript(script_id));
+
+        let status = parser.resume().unwrap();
+        let script_id = document.get().get_node_by_named_id("two").unwrap().id;
+        assert_eq!(status, ParserStatus::PendingScript(script_id));
+        assert!(document.get().get_node_by_named_id("p").is_some());
+
+        assert_eq!(parser.resume().unwrap(), ParserStatus::NeedsInput);
+        assert_eq!(parser.close_input().unwrap(), ParserStatus::Finished);
+        assert!(document.get().get_node_by_named_id("div").is_some());
+    }
+
+    #[test]
+    fn no_pause_without_scripting() {
+        let mut chars = CharIterator::new();
+        chars.start_stream(Some(Encoding::UTF8));
+
+        let document = DocumentBuilder::new_document();
+        let options = Html5ParserOptions {
+            scripting_enabled: false,
+            ..Default::default()
+        };
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), Some(options));
+
+        let status = parser.feed(b"<script>a()</script><p>").unwrap();
+        assert_eq!(status, ParserStatus::NeedsInput);
+        assert_eq!(parser.close_input().unwrap(), ParserStatus::Finished);
+    }
+
+    #[test]
+    fn document_write_inserts_before_next_input() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<body><script id=\"s\">w()</script><p id=\"after\"></p>",
+            None,
+        );
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+
+        assert!(matches!(
+            parser.close_input().unwrap(),
+            ParserStatus::PendingScript(_)
+        ));
+
+        // The written markup is split over two calls, which completes the tag
+        parser.document_write("<b id=\"wri").unwrap();
+        parser.document_write("tten\">x</b>").unwrap();
+        assert!(document.get().get_node_by_named_id("written").is_some());
+        assert!(document.get().get_node_by_named_id("after").is_none());
+
+        assert_eq!(parser.resume().unwrap(), ParserStatus::Finished);
+
+        let doc_read = document.get();
+        let written = doc_read.get_node_by_named_id("written").unwrap();
+        let after = doc_read.get_node_by_named_id("after").unwrap();
+        let body = doc_read.get_node_by_id(written.parent.unwrap()).unwrap();
+        let pos = |id| body.children.iter().position(|c| *c == id).unwrap();
+        assert!(pos(written.id) < pos(after.id));
+    }
+
+    #[test]
+    fn document_write_nested_script() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<script id=\"one\">w()</script><p id=\"after\"></p>", None);
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+        parser.close_input().unwrap();
+
+        // A written script is executed after the script that wrote it has finished
+        parser
+            .document_write("<script id=\"two\">x()</script><i id=\"i\"></i>")
+            .unwrap();
+        assert!(document.get().get_node_by_named_id("i").is_none());
+
+        let script_id = document.get().get_node_by_named_id("two").unwrap().id;
+        assert_eq!(
+            parser.resume().unwrap(),
+            ParserStatus::PendingScript(script_id)
+        );
+        assert_eq!(parser.resume().unwrap(), ParserStatus::Finished);
+        assert!(document.get().get_node_by_named_id("i").is_some());
+        assert!(document.get().get_node_by_named_id("after").is_some());
+    }
+
+    #[test]
+    fn source_locations() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<!DOCTYPE html>\n<p id=\"p\">a<b id=\"b\">b</b>c</p><!-- d --></body>",
+            None,
+        );
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let offsets = |span: Option<Span>| span.map(|span| (span.start.offset, span.end.offset));
+
+        let doc_read = document.get();
+        let root = doc_read.get_root();
+        let doctype = doc_read.get_node_by_id(root.children[0]).unwrap();
+        assert_eq!(offsets(doctype.location), Some((0, 15)));
+
+        let p = doc_read.get_node_by_named_id("p").unwrap();
+        assert_eq!(offsets(p.location), Some((16, 26)));
+        assert_eq!(offsets(p.end_tag_location), Some((43, 47)));
+        let start = p.location.unwrap().start;
+        assert_eq!((start.line, start.col), (2, 1));
+
+        let b = doc_read.get_node_by_named_id("b").unwrap();
+        assert_eq!(offsets(b.location), Some((27, 37)));
+        assert_eq!(offsets(b.end_tag_location), Some((38, 42)));
+
+        let text = doc_read.get_node_by_id(b.children[0]).unwrap();
+        assert_eq!(offsets(text.location), Some((37, 38)));
+
+        // The comment ends up in the body, which has no start tag in the source
+        let body = doc_read.get_node_by_id(p.parent.unwrap()).unwrap();
+        assert!(body.location.is_none());
+        assert_eq!(offsets(body.end_tag_location), Some((57, 64)));
+        let comment = doc_read
+            .get_node_by_id(*body.children.last().unwrap())
+            .unwrap();
+        assert_eq!(offsets(comment.location), Some((47, 57)));
+    }
+
+    #[test]
+    fn synthesized_element_locations() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<p><b>x</p>y</br><table><tr></table>", None);
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc_read = document.get();
+        let location = |selectors: &str| {
+            let node_id = doc_read.query_selector(selectors).unwrap().unwrap();
+            let node = doc_read.get_node_by_id(node_id).unwrap();
+            node.location
+                .map(|span| (span.start.offset, span.end.offset))
+        };
+
+        assert_eq!(location("p > b"), Some((3, 6)));
+        // The formatting element that is reconstructed for the text is not in the input
+        assert_eq!(location("body > b"), None);
+        // The br element is created for the end tag
+        assert_eq!(location("br"), Some((12, 17)));
+        assert_eq!(location("tbody"), None);
+        assert_eq!(location("tr"), Some((24, 28)));
+    }
+
+    #[test]
+    fn template_contents_in_inert_document() {
+        let mut document = DocumentBuilder::new_document();
+        let observer = document.get_mut().create_mutation_observer();
+        let options = MutationObserverInit {
+            child_list: true,
+            subtree: true,
+            ..Default::default()
+        };
+        document
+            .get_mut()
+            .observe(observer, NodeId::root(), options)
+            .unwrap();
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<!DOCTYPE html><template id=t><p id=a>x<b>y</b></p></template>",
+            None,
+        );
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let mut doc = document.get_mut();
+        let template_id = doc.get_node_by_named_id("t").unwrap().id;
+        let contents = doc.template_contents(template_id).unwrap().contents();
+        let contents = contents.get();
+        let p = contents.get_node_by_named_id("a").unwrap();
+        assert_eq!(p.parent, Some(NodeId::root()));
+        assert!(p.end_tag_location.is_some());
+
+        // The contents have never been part of the document
+        assert!(doc.get_node_by_named_id("a").is_none());
+        assert!(doc.get_node_by_id(p.id).is_none());
+        assert_eq!(doc.compact(), 0);
+        let records = doc.take_records(observer);
+        assert!(!records.is_empty());
+        assert!(records
+            .iter()
+            .flat_map(|record| record.added_nodes.iter().chain(&record.removed_nodes))
+            .all(|node_id| doc.get_node_by_id(*node_id).is_some()));
+    }
+
+    #[test]
+    fn structured_parse_errors() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<!DOCTYPE html><p a=1 a=2></b>", None);
+
+        let document = DocumentBuilder::new_document();
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].code, ParserError::DuplicateAttribute);
+        assert_eq!(errors[0].message, "duplicate-attribute");
+        assert_eq!(errors[0].severity, Severity::Warning);
+        assert_eq!(errors[0].trigger, ErrorTrigger::State(State::AttributeName));
+        assert_eq!(errors[0].span.start.offset, 15);
+
+        assert_eq!(errors[1].code, ParserError::UnexpectedEndTag);
+        assert_eq!(errors[1].severity, Severity::Error);
+        assert_eq!(
+            errors[1].trigger,
+            ErrorTrigger::Token {
+                kind: TokenKind::EndTag,
+                name: Some("b".to_string()),
+            }
+        );
+        assert_eq!(
+            (errors[1].span.start.offset, errors[1].span.end.offset),
+            (26, 30)
+        );
+    }
+
+    #[test]
+    fn strict_mode_aborts_on_first_error() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<!DOCTYPE html><p></b><p id=\"after\">", None);
+
+        let document = DocumentBuilder::new_document();
+        let options = Html5ParserOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let result =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+
+        match result {
+            Err(Error::StrictParse(error)) => assert_eq!(error.code, ParserError::UnexpectedEndTag),
+            _ => panic!("expected a strict parse error"),
+        }
+        assert!(document.get().get_node_by_named_id("after").is_none());
+
+        // A valid document parses as usual
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<!DOCTYPE html><p>ok</p>", None);
+        let options = Html5ParserOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let document = DocumentBuilder::new_document();
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options))
+                .unwrap();
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn strict_mode_does_not_process_invalid_token() {
+        let parse_strict = |html: &str| {
+            let mut chars = CharIterator::new();
+            chars.read_from_str(html, None);
+            let document = DocumentBuilder::new_document();
+            let options = Html5ParserOptions {
+                strict: true,
+                ..Default::default()
+            };
+            let result =
+                Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+            assert!(matches!(result, Err(Error::StrictParse(_))));
+            document
+        };
+
+        // Tokenizer error: the start tag with the duplicate attribute is not inserted
+        let document = parse_strict("<!DOCTYPE html><p id=a id=b>");
+        assert!(document.get().get_node_by_named_id("a").is_none());
+
+        // Tree construction error: the h2 that would close the h1 is not inserted
+        let document = parse_strict("<!DOCTYPE html><h1 id=a><h2 id=b>");
+        assert!(document.get().get_node_by_named_id("a").is_some());
+        assert!(document.get().get_node_by_named_id("b").is_none());
+    }
+
+    #[test]
+    fn bounded_error_collection() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(&"</b>".repeat(100), None);
+
+        let document = DocumentBuilder::new_document();
+        let options = Html5ParserOptions {
+            max_errors: Some(5),
+            ..Default::default()
+        };
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options))
+                .unwrap();
+        assert_eq!(errors.len(), 5);
+    }
+
+    #[test]
+    fn resource_limits() {
+        fn parse(input: &str, limits: Html5ParserLimits) -> Result<Vec<ParseError>> {
+            let mut chars = CharIterator::new();
+            chars.read_from_str(input, None);
+
+            let document = DocumentBuilder::new_document();
+            let options = Html5ParserOptions {
+                limits,
+                ..Default::default()
+            };
+            Html5Parser::parse_document(&mut chars, document, Some(options))
+        }
+
+        let nested = "<div>".repeat(50);
+        let limits = Html5ParserLimits {
+            max_tree_depth: Some(20),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(&nested, limits.clone()),
+            Err(Error::LimitExceeded(Limit::TreeDepth(20)))
+        ));
+        assert!(parse(&"<div>".repeat(10), limits).is_ok());
+
+        // The second a element closes the first one, which is removed from the stack of open
+        // elements but stays in the tree as the parent of the table and the caption
+        let input = "<a><table><a><caption>";
+        let limits = Html5ParserLimits {
+            max_open_elements: Some(4),
+            ..Default::default()
+        };
+        assert!(parse(input, limits).is_ok());
+        let limits = Html5ParserLimits {
+            max_tree_depth: Some(4),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(input, limits),
+            Err(Error::LimitExceeded(Limit::TreeDepth(4)))
+        ));
+
+        let limits = Html5ParserLimits {
+            max_open_elements: Some(20),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(&nested, limits),
+            Err(Error::LimitExceeded(Limit::OpenElements(20)))
+        ));
+
+        let limits = Html5ParserLimits {
+            max_node_count: Some(100),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(&"<p>a</p>".repeat(100), limits),
+            Err(Error::LimitExceeded(Limit::NodeCount(100)))
+        ));
+
+        let limits = Html5ParserLimits {
+            max_attributes: Some(2),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse("<p a b c>", limits.clone()),
+            Err(Error::LimitExceeded(Limit::Attributes(2)))
+        ));
+        assert!(parse("<p a b>", limits.clone()).is_ok());
+        // The tokenizer stops before the tag is complete
+        assert!(matches!(
+            parse("<p a b c d", limits),
+            Err(Error::LimitExceeded(Limit::Attributes(2)))
+        ));
+
+        let limits = Html5ParserLimits {
+            max_attribute_length: Some(10),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(&format!("<p a=\"{}\">", "x".repeat(11)), limits.clone()),
+            Err(Error::LimitExceeded(Limit::AttributeLength(10)))
+        ));
+        assert!(matches!(
+            parse(&format!("<p a=\"{}", "x".repeat(1000)), limits),
+            Err(Error::LimitExceeded(Limit::AttributeLength(10)))
+        ));
+
+        let limits = Html5ParserLimits {
+            max_text_length: Some(10),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(&"a".repeat(1000), limits.clone()),
+            Err(Error::LimitExceeded(Limit::TextLength(10)))
+        ));
+        // Text nodes are also checked after adjacent text has been merged
+        assert!(matches!(
+            parse("<p>aaaaaa</x>aaaaaa</p>", limits.clone()),
+            Err(Error::LimitExceeded(Limit::TextLength(10)))
+        ));
+        assert!(parse("<p>aaaa</p>", limits).is_ok());
+    }
+
+    #[test]
+    fn text_location_spans_merged_tokens() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<p id=\"p\">a&amp;b\0c</p>", None);
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc_read = document.get();
+        let p = doc_read.get_node_by_named_id("p").unwrap();
+        assert_eq!(p.children.len(), 1);
+        let text = doc_read.get_node_by_id(p.children[0]).unwrap();
+        let location = text.location.unwrap();
+        assert_eq!((location.start.offset, location.end.offset), (10, 19));
+    }
+
+    #[test]
+    fn preload_scan_while_paused() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<img src=\"parsed.png\"><script>a()</script><base href=\"/static/\"><img src=\"a.png\">",
+            None,
+        );
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+        assert!(matches!(
+            parser.close_input().unwrap(),
+            ParserStatus::PendingScript(_)
+        ));
+
+        let mut scanner = PreloadScanner::new(url::Url::parse("https://example.com/").unwrap());
+        let requests = parser.preload_scan(&mut scanner);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url.as_str(), "https://example.com/static/a.png");
+    }
+
+    #[test]
+    fn document_write_without_insertion_point() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<p>", None);
+
+        let document = DocumentBuilder::new_document();
+        let mut parser =
+            Html5Parser::new_document_parser(&mut chars, Document::clone(&document), None);
+
+        assert!(parser.document_write("<b>").is_err());
+        assert_eq!(parser.close_input().unwrap(), ParserStatus::Finished);
+        assert!(parser.document_write("<b>").is_err());
+    }
 }
@@ -1,18 +1,25 @@
+use crate::bytes::Encoding;
+use crate::css3::parser::CSS3Parser;
+use crate::html5::dom::collection::{tree_position, ElementIndex};
+use crate::html5::dom::{ShadowRootInit, ShadowRootMode};
 use crate::html5::element_class::ElementClass;
+use crate::html5::mutation_observer::MutationObservers;
 use crate::html5::node::arena::NodeArena;
 use crate::html5::node::data::doctype::DocTypeData;
 use crate::html5::node::data::{comment::CommentData, text::TextData};
+use crate::html5::node::selector::SelectorMatcher;
+use crate::html5::node::traversal::Traversable;
 use crate::html5::node::HTML_NAMESPACE;
-use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::node::{Node, NodeData, NodeId, NodeTrait, NodeType};
 use crate::html5::parser::quirks::QuirksMode;
 use crate::html5::parser::tree_builder::TreeBuilder;
 use crate::html5::util::is_valid_id_attribute_value;
-use crate::types::{Error, Result};
+use crate::types::{DomError, Error, Result};
 use alloc::rc::Rc;
 use core::fmt;
 use core::fmt::Debug;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::ops::{Deref, DerefMut};
 
@@ -26,27 +33,23 @@ pub enum DocumentType {
 }
 
 /// Defines a document fragment which can be attached to for instance a <template> element
-#[derive(PartialEq)]
+///
+/// The contents of the fragment live in an inert document of their own, so they are not part of
+/// the document tree: they cannot be found by id or selectors, and changes to them are not seen by
+/// the mutation observers of the document. The root node of the inert document stands for the
+/// fragment itself, and its children are the contents. The parser inserts the contents of a
+/// template element directly into the inert document.
+#[derive(Clone, PartialEq)]
 pub struct DocumentFragment {
-    /// Node elements inside this fragment
-    arena: NodeArena,
+    /// Inert document that holds the contents of this fragment
+    contents: DocumentHandle,
     /// Document handle of the parent
     pub doc: DocumentHandle,
-    /// Host node on which this fragment is attached
+    /// Node in the document the fragment belongs to: the template element itself, or the shadow
+    /// root for a declarative shadow root, whose contents are parsed into the shadow root
     host: NodeId,
 }
 
-impl Clone for DocumentFragment {
-    /// Clones the document fragment
-    fn clone(&self) -> Self {
-        Self {
-            arena: self.arena.clone(),
-            doc: Document::clone(&self.doc),
-            host: self.host,
-        }
-    }
-}
-
 impl Debug for DocumentFragment {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "DocumentFragment")
@@ -54,14 +57,145 @@ impl Debug for DocumentFragment {
 }
 
 impl DocumentFragment {
-    /// Creates a new document fragment and attaches it to "host" node inside "doc"
-    pub(crate) fn new(doc: DocumentHandle, host: NodeId) -> Self {
+    /// Creates a new document fragment and attaches it to "host" node inside "doc". The nodes of
+    /// the contents get ids from the given arena, so they never have the same id as a node in it.
+    pub(crate) fn new(doc: DocumentHandle, host: NodeId, ids: &NodeArena) -> Self {
+        let mut contents = Document::shared();
+        let root = Node::new_document_fragment(&contents);
+        {
+            let mut inert = contents.get_mut();
+            inert.arena.register_node(root);
+            inert.arena.share_ids(ids);
+        }
+
         Self {
-            arena: NodeArena::new(),
+            contents,
             doc,
             host,
         }
     }
+
+    /// Returns the node in the document the fragment belongs to
+    pub(crate) fn host(&self) -> NodeId {
+        self.host
+    }
+
+    /// Returns the inert document that holds the contents. Its root node is the fragment node.
+    pub fn contents(&self) -> DocumentHandle {
+        Document::clone(&self.contents)
+    }
+
+    /// Returns the top-level nodes of the contents
+    pub fn children(&self) -> Vec<NodeId> {
+        self.contents.get().get_root().children.clone()
+    }
+
+    /// Returns true when the fragment has no contents
+    pub fn is_empty(&self) -> bool {
+        self.contents.get().get_root().children.is_empty()
+    }
+
+    /// Returns the first element in the contents that matches the selectors
+    pub fn query_selector(&self, selectors: &str) -> Result<Option<NodeId>> {
+        self.contents.get().query_selector(selectors)
+    }
+
+    /// Returns all elements in the contents that match the selectors, in tree order
+    pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeId>> {
+        self.contents.get().query_selector_all(selectors)
+    }
+
+    /// Copies the fragment with all of its contents, for the host node in the given document. The
+    /// nodes of the copy get ids from the given arena.
+    pub(crate) fn copy(&self, doc: &DocumentHandle, host: NodeId, ids: &NodeArena) -> Self {
+        let copy = Self::new(Document::clone(doc), host, ids);
+
+        let source = self.contents.get();
+        let mut contents = copy.contents();
+        let mut inert = contents.get_mut();
+        for &child_id in &source.get_root().children {
+            if let Ok(child_copy_id) = inert.import_node(&*source, child_id) {
+                inert.attach_node_to_parent(child_copy_id, NodeId::root(), None);
+            }
+        }
+        drop(inert);
+
+        copy
+    }
+
+    /// Deletes all contents
+    pub(crate) fn clear(&mut self) {
+        let mut inert = self.contents.get_mut();
+        for child_id in inert.get_root().children.clone() {
+            _ = inert.delete_node(child_id);
+        }
+    }
+}
+
+/// Copies the node and its descendants from the source tree into the target arena, and returns
+/// the id of the copy, which has no parent. The copies are owned by the given document. Shadow
+/// roots are copied along with their hosts, and template contents along with their template.
+fn copy_tree(
+    source: &dyn Traversable,
+    node_id: NodeId,
+    document: &DocumentHandle,
+    target: &mut NodeArena,
+) -> Option<NodeId> {
+    let node = source.node(node_id)?;
+
+    let mut copy = node.clone();
+    copy.parent = None;
+    copy.children = Vec::new();
+    copy.is_registered = false;
+    copy.document = Document::clone(document);
+    let copy_id = target.register_node(copy);
+
+    let contents_copy = match &node.data {
+        NodeData::Element(element) => element
+            .template_contents
+            .as_ref()
+            .map(|contents| contents.copy(document, copy_id, target)),
+        _ => None,
+    };
+
+    let mut shadow_root = None;
+    if let Some(NodeData::Element(element)) =
+        target.get_node_mut(copy_id).map(|copy| &mut copy.data)
+    {
+        element.set_id(copy_id);
+        element.document = Document::clone(document);
+        shadow_root = element.shadow_root.take();
+        element.template_contents = contents_copy;
+    }
+
+    if let Some(shadow_root_id) = shadow_root {
+        if let Some(shadow_root_copy_id) = copy_tree(source, shadow_root_id, document, target) {
+            if let Some(NodeData::ShadowRoot(shadow_root)) = target
+                .get_node_mut(shadow_root_copy_id)
+                .map(|copy| &mut copy.data)
+            {
+                shadow_root.host = copy_id;
+            }
+            if let Some(NodeData::Element(element)) =
+                target.get_node_mut(copy_id).map(|copy| &mut copy.data)
+            {
+                element.shadow_root = Some(shadow_root_copy_id);
+            }
+        }
+    }
+
+    for &child_id in &node.children {
+        if let Some(child_copy_id) = copy_tree(source, child_id, document, target) {
+            if let Some(child_copy) = target.get_node_mut(child_copy_id) {
+                child_copy.parent = Some(copy_id);
+            }
+            if let Some(copy) = target.get_node_mut(copy_id) {
+                copy.children.push(child_copy_id);
+            }
+        }
+    }
+
+    Some(copy_id)
 }
 
 /// Enum of tasks that can be performed to add or update
@@ -222,17 +356,35 @@ impl DocumentTaskQueue {
     }
 }
 
+/// Elements in the document tree that have the same ID, see [`Document::duplicate_ids`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateId {
+    /// The ID the elements have
+    pub id: String,
+    /// Elements with the ID in tree order. The first one is found by its ID.
+    pub elements: Vec<NodeId>,
+}
+
 /// Defines a document
 #[derive(Debug, PartialEq)]
 pub struct Document {
     /// Holds and owns all nodes in the document
     pub(crate) arena: NodeArena,
-    /// HTML elements with ID (e.g., <div id="myid">)
-    named_id_elements: HashMap<String, NodeId>,
+    /// HTML elements with ID (e.g., <div id="myid">), by ID. Elements that are not in the tree
+    /// are indexed as well, and more than one element can have the same ID.
+    named_id_elements: HashMap<String, HashSet<NodeId>>,
     /// Document type of this document
     pub doctype: DocumentType,
     /// Quirks mode of this document
     pub quirks_mode: QuirksMode,
+    /// Character encoding the document was decoded with
+    pub encoding: Encoding,
+    /// Registered mutation observers and their queued records
+    pub(crate) mutation_observers: MutationObservers,
+    /// Nodes that are manually assigned to slots, by slot
+    pub(crate) manually_assigned_nodes: HashMap<NodeId, Vec<NodeId>>,
+    /// Elements by tag name, class and name attribute, for live collections
+    pub(crate) element_index: ElementIndex,
 }
 
 impl Default for Document {
@@ -243,6 +395,10 @@ impl Default for Document {
             named_id_elements: HashMap::new(),
             doctype: DocumentType::HTML,
             quirks_mode: QuirksMode::NoQuirks,
+            encoding: Encoding::UTF8,
+            mutation_observers: MutationObservers::default(),
+            manually_assigned_nodes: HashMap::new(),
+            element_index: ElementIndex::default(),
         }
     }
 }
@@ -256,6 +412,10 @@ impl Document {
             named_id_elements: HashMap::new(),
             doctype: DocumentType::HTML,
             quirks_mode: QuirksMode::NoQuirks,
+            encoding: Encoding::UTF8,
+            mutation_observers: MutationObservers::default(),
+            manually_assigned_nodes: HashMap::new(),
+            element_index: ElementIndex::default(),
         }
     }
 
@@ -284,29 +444,76 @@ impl Document {
         self.arena.get_node_mut(node_id)
     }
 
-    /// Fetches a node by named id (string) or returns None when no node with this ID is found
+    /// Fetches the first element in tree order with the named id (string), or returns None when
+    /// no element in the document tree has this ID. Elements that are not in the tree, like
+    /// removed elements, are not found. See [getElementById()](https://dom.spec.whatwg.org/#dom-nonelementparentnode-getelementbyid).
     pub fn get_node_by_named_id(&self, named_id: &str) -> Option<&Node> {
-        let node_id = self.named_id_elements.get(named_id)?;
-        self.arena.get_node(*node_id)
+        let node_id = self.first_element_by_named_id(named_id)?;
+        self.arena.get_node(node_id)
     }
 
-    /// Fetches a mutable node by named id (string) or returns None when no node with this ID is found
+    /// Fetches a mutable node by named id (string), like [`Document::get_node_by_named_id`]
     pub fn get_node_by_named_id_mut(&mut self, named_id: &str) -> Option<&mut Node> {
-        let node_id = self.named_id_elements.get(named_id)?;
-        self.arena.get_node_mut(*node_id)
+        let node_id = self.first_element_by_named_id(named_id)?;
+        self.arena.get_node_mut(node_id)
     }
 
-    pub fn add_new_node(&mut self, node: Node) -> NodeId {
-        // if a node contains attributes when adding to the tree,
-        // be sure to handle the special attributes "id" and "class"
-        // which need to by queryable by the DOM
-        let mut node_named_id: Option<String> = None;
-        if let NodeData::Element(element) = &node.data {
-            if let Some(named_id) = element.attributes.get("id") {
-                node_named_id = Some(named_id.clone());
-            }
+    /// Returns all elements in the document tree with the named id, in tree order
+    pub fn elements_by_named_id(&self, named_id: &str) -> Vec<NodeId> {
+        let Some(ids) = self.named_id_elements.get(named_id) else {
+            return Vec::new();
+        };
+
+        let mut elements: Vec<(Vec<usize>, NodeId)> = ids
+            .iter()
+            .filter_map(|&id| Some((tree_position(self, NodeId::root(), id)?, id)))
+            .collect();
+        elements.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        elements.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Returns the IDs that more than one element in the document tree has, ordered by the
+    /// first element with the ID in tree order. As an ID must be unique, these are mistakes in
+    /// the document, like labels or ARIA attributes that refer to the wrong element.
+    pub fn duplicate_ids(&self) -> Vec<DuplicateId> {
+        let mut duplicates: Vec<DuplicateId> = self
+            .named_id_elements
+            .iter()
+            .filter(|(_, ids)| ids.len() > 1)
+            .map(|(id, _)| DuplicateId {
+                id: id.clone(),
+                elements: self.elements_by_named_id(id),
+            })
+            .filter(|duplicate| duplicate.elements.len() > 1)
+            .collect();
+        duplicates.sort_by_cached_key(|duplicate| {
+            tree_position(self, NodeId::root(), duplicate.elements[0])
+        });
+
+        duplicates
+    }
+
+    /// Returns the first element in tree order with the named id. An ID that a single element
+    /// has, which is the common case, only needs a check that the element is in the tree.
+    fn first_element_by_named_id(&self, named_id: &str) -> Option<NodeId> {
+        let ids = self.named_id_elements.get(named_id)?;
+        if ids.len() == 1 {
+            let id = *ids.iter().next()?;
+            let in_tree = self
+                .ancestors(id)
+                .last()
+                .is_some_and(|ancestor| ancestor.id == NodeId::root());
+            return in_tree.then_some(id);
         }
 
+        ids.iter()
+            .filter_map(|&id| Some((tree_position(self, NodeId::root(), id)?, id)))
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, id)| id)
+    }
+
+    pub fn add_new_node(&mut self, node: Node) -> NodeId {
         // Register the node if needed
         let node_id = if !node.is_registered {
             self.arena.register_node(node)
@@ -320,20 +527,53 @@ impl Document {
                 element.set_id(node_id);
             }
         }
-
-        // make named_id (if present) queryable in DOM if it's not mapped already
-        if let Some(node_named_id) = node_named_id {
-            if !self.named_id_elements.contains_key(&node_named_id)
-                && is_valid_id_attribute_value(&node_named_id)
-            {
-                self.named_id_elements
-                    .insert(node_named_id.to_owned(), node_id);
+        // if a node contains attributes when adding to the tree, be sure to index the
+        // attributes that need to be queryable by the DOM, like "id" and "class"
+        if let Some(node) = self.arena.get_node(node_id) {
+            self.element_index.insert(node);
+            if let NodeData::Element(element) = &node.data {
+                update_named_id(
+                    &mut self.named_id_elements,
+                    node_id,
+                    None,
+                    element.attributes.get("id").map(String::as_str),
+                );
             }
         }
 
         node_id
     }
 
+    /// Updates the element index and the id index for an attribute of the element that was set
+    /// or removed, and queues a mutation record for it
+    pub(crate) fn attribute_changed(
+        &mut self,
+        element_id: NodeId,
+        name: &str,
+        old_value: Option<String>,
+    ) {
+        let value = match self.arena.get_node(element_id).map(|node| &node.data) {
+            Some(NodeData::Element(element)) => element.attributes.get(name),
+            _ => None,
+        };
+        self.element_index.attribute_changed(
+            element_id,
+            name,
+            old_value.as_deref(),
+            value.map(String::as_str),
+        );
+        if name == "id" {
+            update_named_id(
+                &mut self.named_id_elements,
+                element_id,
+                old_value.as_deref(),
+                value.map(String::as_str),
+            );
+        }
+
+        self.queue_attribute_record(element_id, name, old_value);
+    }
+
     /// Inserts a node to the parent node at the given position in the children (or none
     /// to add at the end). Will automatically register the node if not done so already
     pub fn add_node(&mut self, node: Node, parent_id: NodeId, position: Option<usize>) -> NodeId {
@@ -373,21 +613,33 @@ impl Document {
             return false;
         }
 
+        let mut siblings = (None, None);
         if let Some(parent_node) = self.get_node_by_id_mut(parent_id) {
             // Make sure position can never be larger than the number of children in the parent
-            if let Some(mut position) = position {
-                if position > parent_node.children.len() {
-                    position = parent_node.children.len();
-                }
-                parent_node.children.insert(position, node_id);
-            } else {
-                // No position given, add to end of the children list
-                parent_node.children.push(node_id);
-            }
+            let position = position
+                .unwrap_or(parent_node.children.len())
+                .min(parent_node.children.len());
+            parent_node.children.insert(position, node_id);
+
+            let children = &parent_node.children;
+            siblings = (
+                position.checked_sub(1).map(|idx| children[idx]),
+                children.get(position + 1).copied(),
+            );
         }
 
         let node = self.arena.get_node_mut(node_id).unwrap();
         node.parent = Some(parent_id);
+        self.element_index.tree_changed();
+
+        let (previous_sibling, next_sibling) = siblings;
+        self.queue_child_list_record(
+            parent_id,
+            Some(node_id),
+            None,
+            previous_sibling,
+            next_sibling,
+        );
 
         true
     }
@@ -400,10 +652,26 @@ impl Document {
             let parent_node = self
                 .get_node_by_id_mut(parent_id)
                 .expect("parent node not found");
+            let position = parent_node.children.iter().position(|&id| id == node_id);
             parent_node.children.retain(|&id| id != node_id);
 
+            let children = &parent_node.children;
+            let previous_sibling = position
+                .and_then(|position| position.checked_sub(1))
+                .map(|idx| children[idx]);
+            let next_sibling = position.and_then(|position| children.get(position).copied());
+
             let node = self.get_node_by_id_mut(node_id).expect("node not found");
             node.parent = None;
+            self.element_index.tree_changed();
+
+            self.queue_child_list_record(
+                parent_id,
+                None,
+                Some(node_id),
+                previous_sibling,
+                next_sibling,
+            );
         }
     }
 
@@ -420,6 +688,625 @@ impl Document {
     }
 }
 
+/// Selector queries, see [scope-match a selectors string](https://dom.spec.whatwg.org/#scope-match-a-selectors-string).
+/// All of these return an error when the selectors cannot be parsed.
+impl Document {
+    /// Returns the first element in the document that matches the selectors
+    pub fn query_selector(&self, selectors: &str) -> Result<Option<NodeId>> {
+        self.query_selector_from(NodeId::root(), selectors)
+    }
+
+    /// Returns all elements in the document that match the selectors, in tree order
+    pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeId>> {
+        self.query_selector_all_from(NodeId::root(), selectors)
+    }
+
+    /// Returns the first descendant of the given node that matches the selectors. `:scope`
+    /// matches the given node.
+    pub fn query_selector_from(&self, node_id: NodeId, selectors: &str) -> Result<Option<NodeId>> {
+        let selectors = CSS3Parser::new().parse_selectors(selectors)?;
+        Ok(SelectorMatcher::new(self).select_first(node_id, &selectors))
+    }
+
+    /// Returns all descendants of the given node that match the selectors, in tree order.
+    /// `:scope` matches the given node.
+    pub fn query_selector_all_from(&self, node_id: NodeId, selectors: &str) -> Result<Vec<NodeId>> {
+        let selectors = CSS3Parser::new().parse_selectors(selectors)?;
+        Ok(SelectorMatcher::new(self).select_all(node_id, &selectors))
+    }
+
+    /// Returns true when the given node is an element that matches the selectors
+    pub fn matches(&self, node_id: NodeId, selectors: &str) -> Result<bool> {
+        let selectors = CSS3Parser::new().parse_selectors(selectors)?;
+        Ok(SelectorMatcher::new(self).matches(node_id, &selectors, node_id))
+    }
+
+    /// Returns the given node or its closest ancestor that matches the selectors
+    pub fn closest(&self, node_id: NodeId, selectors: &str) -> Result<Option<NodeId>> {
+        let selectors = CSS3Parser::new().parse_selectors(selectors)?;
+        Ok(SelectorMatcher::new(self).closest(node_id, &selectors))
+    }
+}
+
+/// DOM mutation methods, see the [mutation algorithms](https://dom.spec.whatwg.org/#mutation-algorithms).
+/// Unlike [`Document::add_node`] and [`Document::relocate`], these make sure the tree stays
+/// valid. Inserted nodes are removed from their current parent first. Removed nodes stay in the
+/// document without a parent, so they can be inserted again, until [`Document::compact`] deletes
+/// them.
+impl Document {
+    /// Appends the node as the last child of the parent
+    pub fn append_child(&mut self, parent_id: NodeId, node_id: NodeId) -> Result<NodeId> {
+        self.insert_before(parent_id, node_id, None)
+    }
+
+    /// Inserts the node before the child, or as the last child of the parent when there is no
+    /// child
+    pub fn insert_before(
+        &mut self,
+        parent_id: NodeId,
+        node_id: NodeId,
+        child_id: Option<NodeId>,
+    ) -> Result<NodeId> {
+        if let Some(child_id) = child_id {
+            if self.dom_node(child_id)?.parent != Some(parent_id) {
+                return Err(DomError::NotFound(format!(
+                    "node {child_id} is not a child of node {parent_id}"
+                ))
+                .into());
+            }
+        }
+
+        // Inserting a node before itself inserts it before its next sibling
+        let reference = match child_id {
+            Some(child_id) if child_id == node_id => {
+                self.next_sibling_excluding(parent_id, Some(child_id), &[node_id])
+            }
+            _ => child_id,
+        };
+
+        self.ensure_pre_insertion_validity(&[node_id], parent_id, reference, None)?;
+        self.insert_nodes(&[node_id], parent_id, reference);
+
+        Ok(node_id)
+    }
+
+    /// Replaces the child of the parent with the node, and returns the child
+    pub fn replace_child(
+        &mut self,
+        parent_id: NodeId,
+        node_id: NodeId,
+        child_id: NodeId,
+    ) -> Result<NodeId> {
+        self.replace_with_nodes(parent_id, child_id, &[node_id])?;
+        Ok(child_id)
+    }
+
+    /// Removes the child from the parent, and returns the child
+    pub fn remove_child(&mut self, parent_id: NodeId, child_id: NodeId) -> Result<NodeId> {
+        if self.dom_node(child_id)?.parent != Some(parent_id) {
+            return Err(DomError::NotFound(format!(
+                "node {child_id} is not a child of node {parent_id}"
+            ))
+            .into());
+        }
+
+        self.detach_node_from_parent(child_id);
+        Ok(child_id)
+    }
+
+    /// Inserts the nodes just before the given node, in the parent of the node. Does nothing
+    /// when the node has no parent.
+    pub fn before(&mut self, node_id: NodeId, nodes: &[NodeId]) -> Result<()> {
+        let Some(parent_id) = self.dom_node(node_id)?.parent else {
+            return Ok(());
+        };
+
+        let viable_previous = self
+            .preceding_siblings(node_id)
+            .rev()
+            .map(|sibling| sibling.id)
+            .find(|id| !nodes.contains(id));
+        let reference = self.next_sibling_excluding(parent_id, viable_previous, nodes);
+
+        self.ensure_pre_insertion_validity(nodes, parent_id, reference, None)?;
+        self.insert_nodes(nodes, parent_id, reference);
+        Ok(())
+    }
+
+    /// Inserts the nodes just after the given node, in the parent of the node. Does nothing
+    /// when the node has no parent.
+    pub fn after(&mut self, node_id: NodeId, nodes: &[NodeId]) -> Result<()> {
+        let Some(parent_id) = self.dom_node(node_id)?.parent else {
+            return Ok(());
+        };
+
+        let reference = self.viable_next_sibling(node_id, nodes);
+
+        self.ensure_pre_insertion_validity(nodes, parent_id, reference, None)?;
+        self.insert_nodes(nodes, parent_id, reference);
+        Ok(())
+    }
+
+    /// Replaces the given node with the nodes. Does nothing when the node has no parent.
+    pub fn replace_with(&mut self, node_id: NodeId, nodes: &[NodeId]) -> Result<()> {
+        let Some(parent_id) = self.dom_node(node_id)?.parent else {
+            return Ok(());
+        };
+
+        if !nodes.contains(&node_id) {
+            return self.replace_with_nodes(parent_id, node_id, nodes);
+        }
+
+        // The node replaces itself, so the nodes are inserted where it was
+        let reference = self.viable_next_sibling(node_id, nodes);
+        self.ensure_pre_insertion_validity(nodes, parent_id, reference, None)?;
+        self.insert_nodes(nodes, parent_id, reference);
+        Ok(())
+    }
+
+    /// Creates a copy of the node without a parent. A deep clone also copies all descendants.
+    pub fn clone_node(&mut self, node_id: NodeId, deep: bool) -> Result<NodeId> {
+        let node = self.dom_node(node_id)?;
+        match node.type_of() {
+            NodeType::Document => {
+                return Err(DomError::NotSupported("cannot clone the document node".into()).into())
+            }
+            NodeType::DocumentFragment => {
+                return Err(DomError::NotSupported("cannot clone a shadow root".into()).into())
+            }
+            _ => {}
+        }
+
+        let children = node.children.clone();
+        let mut copy = node.clone();
+        copy.parent = None;
+        copy.children = Vec::new();
+        copy.is_registered = false;
+        copy.location = None;
+        copy.end_tag_location = None;
+        if let NodeData::Element(element) = &mut copy.data {
+            element.shadow_root = None;
+        }
+
+        let copy_id = self.add_new_node(copy);
+
+        // The template contents are only copied by a deep clone
+        let contents_copy = self.template_contents(node_id).map(|contents| {
+            if deep {
+                contents.copy(&contents.doc, copy_id, &self.arena)
+            } else {
+                DocumentFragment::new(Document::clone(&contents.doc), copy_id, &self.arena)
+            }
+        });
+        if let Some(NodeData::Element(element)) =
+            self.get_node_by_id_mut(copy_id).map(|node| &mut node.data)
+        {
+            element.template_contents = contents_copy;
+        }
+
+        // Only clonable shadow roots are copied along with their host
+        let clonable_shadow_root = self
+            .shadow_root(node_id)
+            .and_then(|shadow_root_id| {
+                Some((shadow_root_id, self.shadow_root_data(shadow_root_id)?))
+            })
+            .filter(|(_, shadow_root)| shadow_root.clonable)
+            .map(|(shadow_root_id, shadow_root)| (shadow_root_id, shadow_root.clone()));
+        if let Some((shadow_root_id, shadow_root)) = clonable_shadow_root {
+            let init = ShadowRootInit {
+                mode: shadow_root.mode,
+                delegates_focus: shadow_root.delegates_focus,
+                slot_assignment: shadow_root.slot_assignment,
+                clonable: true,
+                serializable: shadow_root.serializable,
+            };
+            let shadow_root_copy_id = self.attach_shadow(copy_id, init)?;
+            if let Some(NodeData::ShadowRoot(copy)) = self
+                .get_node_by_id_mut(shadow_root_copy_id)
+                .map(|node| &mut node.data)
+            {
+                copy.declarative = shadow_root.declarative;
+            }
+
+            let shadow_children = self.dom_node(shadow_root_id)?.children.clone();
+            for child_id in shadow_children {
+                let child_copy_id = self.clone_node(child_id, true)?;
+                self.attach_node_to_parent(child_copy_id, shadow_root_copy_id, None);
+            }
+        }
+
+        if deep {
+            for child_id in children {
+                let child_copy_id = self.clone_node(child_id, true)?;
+                self.attach_node_to_parent(child_copy_id, copy_id, None);
+            }
+        }
+
+        Ok(copy_id)
+    }
+
+    /// Deletes the node and all its descendants from the document. Unlike [`Document::remove_child`],
+    /// the nodes are gone for good and their ids become invalid. Ids are never handed out again.
+    pub fn delete_node(&mut self, node_id: NodeId) -> Result<()> {
+        if self.dom_node(node_id)?.type_of() == NodeType::Document {
+            return Err(DomError::NotSupported("cannot delete the document node".into()).into());
+        }
+
+        if let Some(NodeData::ShadowRoot(shadow_root)) =
+            self.get_node_by_id(node_id).map(|node| &node.data)
+        {
+            let host_id = shadow_root.host;
+            if let Some(NodeData::Element(host)) =
+                self.get_node_by_id_mut(host_id).map(|node| &mut node.data)
+            {
+                host.shadow_root = None;
+            }
+        }
+        self.detach_node_from_parent(node_id);
+
+        let deleted: HashSet<NodeId> = self
+            .shadow_including_inclusive_descendants(node_id)
+            .into_iter()
+            .collect();
+        for &id in &deleted {
+            let Some(node) = self.arena.get_node(id) else {
+                continue;
+            };
+            self.element_index.remove(node);
+            if let NodeData::Element(element) = &node.data {
+                update_named_id(
+                    &mut self.named_id_elements,
+                    id,
+                    element.attributes.get("id").map(String::as_str),
+                    None,
+                );
+            }
+        }
+        self.forget_slot_assignments(|id| !deleted.contains(&id));
+        self.arena.retain(|id| !deleted.contains(&id));
+
+        Ok(())
+    }
+
+    /// Deletes all nodes that cannot be reached from the document node, like nodes that have
+    /// been removed from the tree and never inserted again. Returns the number of deleted
+    /// nodes. The ids of the deleted nodes become invalid, so this must not be called while
+    /// nodes are being parsed into the document.
+    pub fn compact(&mut self) -> usize {
+        let reachable: HashSet<NodeId> = self
+            .shadow_including_inclusive_descendants(NodeId::root())
+            .into_iter()
+            .collect();
+
+        self.named_id_elements.retain(|_, ids| {
+            ids.retain(|id| reachable.contains(id));
+            !ids.is_empty()
+        });
+        self.forget_slot_assignments(|id| reachable.contains(&id));
+        self.element_index.retain(|id| reachable.contains(&id));
+
+        let count = self.arena.retain(|id| reachable.contains(&id));
+        self.arena.shrink_to_fit();
+        count
+    }
+
+    /// Removes the manual slot assignments of the nodes that are not kept
+    fn forget_slot_assignments(&mut self, keep: impl Fn(NodeId) -> bool) {
+        self.manually_assigned_nodes.retain(|slot_id, nodes| {
+            nodes.retain(|id| keep(*id));
+            keep(*slot_id) && !nodes.is_empty()
+        });
+    }
+
+    pub(crate) fn dom_node(&self, node_id: NodeId) -> Result<&Node> {
+        self.get_node_by_id(node_id)
+            .ok_or_else(|| DomError::NotFound(format!("node {node_id} does not exist")).into())
+    }
+
+    /// Returns the first sibling after the node that is not one of the given nodes
+    fn viable_next_sibling(&self, node_id: NodeId, nodes: &[NodeId]) -> Option<NodeId> {
+        self.following_siblings(node_id)
+            .map(|sibling| sibling.id)
+            .find(|id| !nodes.contains(id))
+    }
+
+    /// Returns the child of the parent after the given child (or the first child when there is
+    /// none), skipping the excluded nodes
+    fn next_sibling_excluding(
+        &self,
+        parent_id: NodeId,
+        after: Option<NodeId>,
+        excluded: &[NodeId],
+    ) -> Option<NodeId> {
+        let children = &self.get_node_by_id(parent_id)?.children;
+        let start = match after {
+            Some(after) => children.iter().position(|&id| id == after)? + 1,
+            None => 0,
+        };
+
+        children[start..]
+            .iter()
+            .copied()
+            .find(|id| !excluded.contains(id))
+    }
+
+    /// Replaces the child of the parent with the nodes
+    fn replace_with_nodes(
+        &mut self,
+        parent_id: NodeId,
+        child_id: NodeId,
+        nodes: &[NodeId],
+    ) -> Result<()> {
+        if self.dom_node(child_id)?.parent != Some(parent_id) {
+            return Err(DomError::NotFound(format!(
+                "node {child_id} is not a child of node {parent_id}"
+            ))
+            .into());
+        }
+
+        self.ensure_pre_insertion_validity(nodes, parent_id, None, Some(child_id))?;
+        if nodes == [child_id] {
+            return Ok(());
+        }
+
+        let reference = self.viable_next_sibling(child_id, nodes);
+        self.detach_node_from_parent(child_id);
+        self.insert_nodes(nodes, parent_id, reference);
+        Ok(())
+    }
+
+    /// Moves the nodes into the parent, before the reference child or at the end. The reference
+    /// child cannot be one of the nodes.
+    fn insert_nodes(&mut self, nodes: &[NodeId], parent_id: NodeId, reference: Option<NodeId>) {
+        for &node_id in nodes {
+            self.detach_node_from_parent(node_id);
+        }
+
+        let mut position = reference.and_then(|reference| {
+            self.get_node_by_id(parent_id)?
+                .children
+                .iter()
+                .position(|&id| id == reference)
+        });
+
+        for &node_id in nodes {
+            self.attach_node_to_parent(node_id, parent_id, position);
+            position = position.map(|position| position + 1);
+        }
+    }
+
+    /// Checks that inserting the nodes into the parent yields a valid tree, see
+    /// [ensure pre-insertion validity](https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity)
+    /// and [replace](https://dom.spec.whatwg.org/#concept-node-replace). The nodes are inserted
+    /// before the reference child, or in place of the replaced child. More than one node is
+    /// handled like a document fragment.
+    fn ensure_pre_insertion_validity(
+        &self,
+        nodes: &[NodeId],
+        parent_id: NodeId,
+        reference: Option<NodeId>,
+        replaced: Option<NodeId>,
+    ) -> Result<()> {
+        let hierarchy_error =
+            |message: &str| Err(DomError::HierarchyRequest(message.into()).into());
+
+        let parent = self.dom_node(parent_id)?;
+        let parent_type = parent.type_of();
+        if !matches!(
+            parent_type,
+            NodeType::Document | NodeType::DocumentFragment | NodeType::Element
+        ) {
+            return hierarchy_error("only documents, shadow roots and elements can have children");
+        }
+        let parent_ancestors = self.host_including_ancestors(parent_id);
+
+        let mut element_count = 0;
+        let mut has_doctype = false;
+        for &node_id in nodes {
+            let node_type = self.dom_node(node_id)?.type_of();
+
+            if node_id == parent_id || parent_ancestors.contains(&node_id) {
+                return hierarchy_error("a node cannot be inserted into itself or its descendants");
+            }
+
+            match node_type {
+                NodeType::Document => return hierarchy_error("a document cannot be inserted"),
+                NodeType::DocumentFragment => {
+                    return hierarchy_error("a shadow root cannot be inserted")
+                }
+                NodeType::Text if parent_type == NodeType::Document => {
+                    return hierarchy_error("text cannot be a child of the document")
+                }
+                NodeType::DocType if parent_type != NodeType::Document => {
+                    return hierarchy_error("a doctype can only be a child of the document")
+                }
+                NodeType::DocType if nodes.len() > 1 => {
+                    return hierarchy_error(
+                        "a doctype cannot be inserted together with other nodes",
+                    )
+                }
+                NodeType::DocType => has_doctype = true,
+                NodeType::Element => element_count += 1,
+                _ => {}
+            }
+        }
+
+        if parent_type != NodeType::Document || (element_count == 0 && !has_doctype) {
+            return Ok(());
+        }
+        if element_count > 1 {
+            return hierarchy_error("the document can only have one element child");
+        }
+
+        // The nodes end up at this position among the current children
+        let children: Vec<&Node> = parent
+            .children
+            .iter()
+            .filter_map(|&id| self.get_node_by_id(id))
+            .collect();
+        let position = reference
+            .or(replaced)
+            .and_then(|id| children.iter().position(|child| child.id == id))
+            .unwrap_or(children.len());
+        let following = if replaced.is_some() {
+            position + 1
+        } else {
+            position
+        };
+
+        let has_child_of_type = |node_type: NodeType| {
+            children
+                .iter()
+                .any(|child| Some(child.id) != replaced && child.type_of() == node_type)
+        };
+
+        if element_count == 1 {
+            if has_child_of_type(NodeType::Element) {
+                return hierarchy_error("the document already has an element child");
+            }
+            if children[following.min(children.len())..]
+                .iter()
+                .any(|child| child.type_of() == NodeType::DocType)
+            {
+                return hierarchy_error("an element cannot be inserted before the doctype");
+            }
+        } else {
+            if has_child_of_type(NodeType::DocType) {
+                return hierarchy_error("the document already has a doctype");
+            }
+            if children[..position]
+                .iter()
+                .any(|child| child.type_of() == NodeType::Element)
+            {
+                return hierarchy_error("a doctype cannot be inserted after the element");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Template contents, see the [template element](https://html.spec.whatwg.org/multipage/scripting.html#the-template-element).
+/// The contents are not part of the document, but live in the inert document of a
+/// [`DocumentFragment`]. Their nodes have to be copied into the document before they can be
+/// inserted.
+impl Document {
+    /// Returns the template contents of the given template element
+    pub fn template_contents(&self, template_id: NodeId) -> Option<&DocumentFragment> {
+        match &self.get_node_by_id(template_id)?.data {
+            NodeData::Element(element) => element.template_contents.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Copies a node of another tree, like a document or template contents, and all its
+    /// descendants into this document, see [importNode](https://dom.spec.whatwg.org/#dom-document-importnode).
+    /// The copy has no parent.
+    pub fn import_node(&mut self, source: &dyn Traversable, node_id: NodeId) -> Result<NodeId> {
+        if node_id.is_root() {
+            return Err(DomError::NotSupported(
+                "cannot import the document or document fragment node".into(),
+            )
+            .into());
+        }
+
+        let document = Document::clone(&self.get_root().document);
+        let copy_id = copy_tree(source, node_id, &document, &mut self.arena)
+            .ok_or_else(|| DomError::NotFound(format!("node {node_id} does not exist")))?;
+        self.register_imported_nodes(copy_id);
+
+        Ok(copy_id)
+    }
+
+    /// Moves the template contents of the template element into the parent, before the reference
+    /// child or at the end, like inserting the `content` of a template does. The template contents
+    /// are empty afterwards. Returns the ids of the inserted nodes.
+    pub fn adopt_template_contents(
+        &mut self,
+        template_id: NodeId,
+        parent_id: NodeId,
+        reference: Option<NodeId>,
+    ) -> Result<Vec<NodeId>> {
+        let Some(mut contents) = self.template_contents(template_id).cloned() else {
+            return Err(DomError::NotSupported(format!(
+                "node {template_id} is not a template element"
+            ))
+            .into());
+        };
+        if let Some(reference) = reference {
+            if self.dom_node(reference)?.parent != Some(parent_id) {
+                return Err(DomError::NotFound(format!(
+                    "node {reference} is not a child of node {parent_id}"
+                ))
+                .into());
+            }
+        }
+
+        let mut nodes = Vec::new();
+        {
+            let source = contents.contents();
+            let source = source.get();
+            for &child_id in &source.get_root().children {
+                nodes.push(self.import_node(&*source, child_id)?);
+            }
+        }
+
+        if let Err(err) = self.ensure_pre_insertion_validity(&nodes, parent_id, reference, None) {
+            for node_id in nodes {
+                self.delete_node(node_id)?;
+            }
+            return Err(err);
+        }
+        self.insert_nodes(&nodes, parent_id, reference);
+        contents.clear();
+
+        Ok(nodes)
+    }
+
+    /// Indexes the node and its descendants, and makes their ids queryable
+    fn register_imported_nodes(&mut self, node_id: NodeId) {
+        for id in self.shadow_including_inclusive_descendants(node_id) {
+            let Some(node) = self.arena.get_node(id) else {
+                continue;
+            };
+            self.element_index.insert(node);
+            if let NodeData::Element(element) = &node.data {
+                update_named_id(
+                    &mut self.named_id_elements,
+                    id,
+                    None,
+                    element.attributes.get("id").map(String::as_str),
+                );
+            }
+        }
+    }
+}
+
+/// Moves the element in the id index from its old id to its new id. Every non-empty id is
+/// indexed, also ids that are not valid, as getElementById() finds those too.
+fn update_named_id(
+    named_id_elements: &mut HashMap<String, HashSet<NodeId>>,
+    element_id: NodeId,
+    old_value: Option<&str>,
+    value: Option<&str>,
+) {
+    if let Some(old_value) = old_value {
+        if let Some(ids) = named_id_elements.get_mut(old_value) {
+            ids.remove(&element_id);
+            if ids.is_empty() {
+                named_id_elements.remove(old_value);
+            }
+        }
+    }
+
+    if let Some(value) = value.filter(|value| !value.is_empty()) {
+        named_id_elements
+            .entry(value.to_owned())
+            .or_default()
+            .insert(element_id);
+    }
+}
+
 /// Returns true when the parent node has the child node as a child, or if any of the children of
 /// the parent node have the child node as a child.
 fn has_child_recursive(arena: &NodeArena, parent_id: NodeId, child_id: NodeId) -> bool {
@@ -481,6 +1368,16 @@ impl Document {
             NodeData::Document(_) => {
                 _ = writeln!(f, "{}Document", buffer);
             }
+            NodeData::ShadowRoot(shadow_root) => {
+                let mode = match shadow_root.mode {
+                    ShadowRootMode::Open => "open",
+                    ShadowRootMode::Closed => "closed",
+                };
+                _ = writeln!(f, "{}#shadow-root ({})", buffer, mode);
+            }
+            NodeData::DocumentFragment => {
+                _ = writeln!(f, "{}#document-fragment", buffer);
+            }
             NodeData::DocType(DocTypeData {
                 name,
                 pub_identifier,
@@ -518,8 +1415,15 @@ impl Document {
             buffer.push_str("│  ");
         }
 
-        let len = node.children.len();
-        for (i, child) in node.children.iter().enumerate() {
+        // The shadow root of a host is printed as its first child
+        let shadow_root = self.shadow_root(node.id);
+        let children: Vec<NodeId> = shadow_root
+            .into_iter()
+            .chain(node.children.iter().copied())
+            .collect();
+
+        let len = children.len();
+        for (i, child) in children.iter().enumerate() {
             let child = self.arena.get_node(*child).expect("Child not found");
             self.print_tree(child, buffer.clone(), i == len - 1, f);
         }
@@ -571,6 +1475,32 @@ impl DocumentHandle {
         self.0.borrow_mut()
     }
 
+    /// Retrieves a mutable reference to the document, or fails when the document is borrowed
+    pub(crate) fn try_get_mut(&mut self) -> Result<impl DerefMut<Target = Document> + '_> {
+        self.0
+            .try_borrow_mut()
+            .map_err(|_| Error::DocumentTask("the document is already borrowed".into()))
+    }
+
+    /// Removes everything from the document so it can be parsed again. Only the document root
+    /// node is kept. The new nodes continue the ids of the old ones, so ids from before the reset
+    /// do not refer to new nodes.
+    pub(crate) fn reset(&mut self) {
+        let node = Node::new_document(self);
+
+        let mut doc = self.get_mut();
+        let mut arena = NodeArena::new();
+        arena.register_node(node);
+        arena.share_ids(&doc.arena);
+        doc.arena = arena;
+        doc.named_id_elements.clear();
+        doc.doctype = DocumentType::HTML;
+        doc.quirks_mode = QuirksMode::NoQuirks;
+        doc.mutation_observers.reset();
+        doc.manually_assigned_nodes.clear();
+        doc.element_index.clear();
+    }
+
     /// Attaches a node to the parent node at the given position in the children (or none
     /// to add at the end).
     pub fn attach_node_to_parent(
@@ -613,14 +1543,6 @@ impl DocumentHandle {
             )));
         }
 
-        // an ID must be tied to only one element
-        if self.get().named_id_elements.contains_key(value) {
-            return Err(Error::DocumentTask(format!(
-                "ID '{}' already exists in DOM",
-                value
-            )));
-        }
-
         let mut doc = self.get_mut();
         let data = &mut doc
             .get_node_by_id_mut(element_id)
@@ -631,10 +1553,7 @@ impl DocumentHandle {
             .data;
 
         let old_id = if let NodeData::Element(element) = data {
-            let attributes = &mut element.attributes;
-            let old_id = attributes.get("id").map(|v| v.to_owned());
-            attributes.insert("id".into(), value.into());
-            old_id
+            element.attributes.insert("id".into(), value.into())
         } else {
             return Err(Error::DocumentTask(format!(
                 "Node ID {} is not an element",
@@ -642,8 +1561,7 @@ impl DocumentHandle {
             )));
         };
 
-        old_id.map(|id| doc.named_id_elements.remove(&id));
-        doc.named_id_elements.insert(value.to_owned(), element_id);
+        doc.attribute_changed(element_id, "id", old_id);
 
         Ok(())
     }
@@ -656,15 +1574,17 @@ impl DocumentHandle {
                 "Node ID {} not found",
                 element_id
             )))?;
-        if let NodeData::Element(element) = &mut node.data {
+        let old_value = if let NodeData::Element(element) = &mut node.data {
             element.classes = ElementClass::from_string(value);
+            element.attributes.insert("class".into(), value.into())
         } else {
             return Err(Error::DocumentTask(format!(
                 "Node ID {} is not an element",
                 element_id
             )));
-        }
+        };
 
+        doc.attribute_changed(element_id, "class", old_value);
         Ok(())
     }
 
@@ -681,15 +1601,45 @@ impl DocumentHandle {
                 "Node ID {} not found",
                 element_id
             )))?;
-        if let NodeData::Element(element) = &mut node.data {
-            element.attributes.insert(key.to_owned(), value.to_owned());
+        let old_value = if let NodeData::Element(element) = &mut node.data {
+            element.attributes.insert(key.to_owned(), value.to_owned())
         } else {
             return Err(Error::DocumentTask(format!(
                 "Node ID {} is not an element",
                 element_id
             )));
-        }
+        };
 
+        doc.attribute_changed(element_id, key, old_value);
+        Ok(())
+    }
+}
+
+impl DocumentHandle {
+    /// Removes an attribute from an element node. Removing an attribute that is not set does
+    /// nothing. If node is not an element, returns an Err()
+    pub fn remove_attribute(&mut self, key: &str, element_id: NodeId) -> Result<()> {
+        let mut doc = self.get_mut();
+        let node = doc
+            .get_node_by_id_mut(element_id)
+            .ok_or(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                element_id
+            )))?;
+        let NodeData::Element(element) = &mut node.data else {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} is not an element",
+                element_id
+            )));
+        };
+
+        let Some(old_value) = element.attributes.remove(key) else {
+            return Ok(());
+        };
+        if key == "class" {
+            element.classes = ElementClass::new();
+        }
+        doc.attribute_changed(element_id, key, Some(old_value));
         Ok(())
     }
 }
@@ -768,10 +1718,14 @@ impl DocumentBuilder {
 
 #[cfg(test)]
 mod tests {
+    use crate::bytes::CharIterator;
     use crate::html5::node::{NodeTrait, NodeType, HTML_NAMESPACE};
-    use crate::html5::parser::document::{DocumentBuilder, DocumentTaskQueue};
+    use crate::html5::parser::document::{
+        Document, DocumentBuilder, DocumentHandle, DocumentTaskQueue, DuplicateId,
+    };
     use crate::html5::parser::tree_builder::TreeBuilder;
-    use crate::html5::parser::{Node, NodeData, NodeId};
+    use crate::html5::parser::{Html5Parser, Node, NodeData, NodeId};
+    use crate::types::{DomError, Error, Result};
     use std::collections::HashMap;
 
     #[test]
@@ -827,38 +1781,75 @@ mod tests {
     }
 
     #[test]
-    fn duplicate_named_id_elements() {
+    fn reset() {
         let mut document = DocumentBuilder::new_document();
+        let div = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+
+        // ids from before the reset do not resolve to the nodes created after it
+        document.reset();
+        assert!(document.get().get_node_by_id(div).is_none());
+        let p = document.create_element("p", NodeId::root(), None, HTML_NAMESPACE);
+        assert_ne!(p, div);
+        assert!(document.get().get_node_by_id(div).is_none());
+        assert_eq!(document.get().get_root().children, [p]);
+    }
 
-        let div_1 = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
-        let div_2 = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
-
-        // when adding duplicate IDs, our current implementation will prevent duplicates.
-        let mut res = document.insert_attribute("id", "myid", div_1);
-        assert!(res.is_ok());
+    #[test]
+    fn duplicate_named_id_elements() {
+        let mut document = DocumentBuilder::new_document();
 
-        res = document.insert_attribute("id", "myid", div_2);
-        assert!(res.is_err());
-        if let Err(err) = res {
-            assert_eq!(
-                err.to_string(),
-                "document task error: ID 'myid' already exists in DOM"
-            );
-        }
+        let html = document.create_element("html", NodeId::root(), None, HTML_NAMESPACE);
+        let div_1 = document.create_element("div", html, None, HTML_NAMESPACE);
+        let div_2 = document.create_element("div", html, None, HTML_NAMESPACE);
 
+        // an ID can be shared by more than one element, the first one in tree order is found
+        document.insert_attribute("id", "myid", div_2).unwrap();
+        document.insert_attribute("id", "myid", div_1).unwrap();
         assert_eq!(
             document.get().get_node_by_named_id("myid").unwrap().id,
             div_1
         );
+        assert_eq!(
+            document.get().duplicate_ids(),
+            [DuplicateId {
+                id: "myid".into(),
+                elements: vec![div_1, div_2],
+            }]
+        );
 
-        // when div_1's ID changes, "myid" should be removed from the DOM
-        res = document.insert_attribute("id", "newid", div_1);
-        assert!(res.is_ok());
-        assert!(document.get().get_node_by_named_id("myid").is_none());
+        // when div_1's ID changes, "myid" refers to div_2
+        document.insert_attribute("id", "newid", div_1).unwrap();
+        assert_eq!(
+            document.get().get_node_by_named_id("myid").unwrap().id,
+            div_2
+        );
         assert_eq!(
             document.get().get_node_by_named_id("newid").unwrap().id,
             div_1
         );
+        assert!(document.get().duplicate_ids().is_empty());
+
+        // an element inserted before div_2 comes first in tree order
+        let div_3 = document.create_element("div", html, Some(0), HTML_NAMESPACE);
+        document.insert_attribute("id", "myid", div_3).unwrap();
+        assert_eq!(
+            document.get().get_node_by_named_id("myid").unwrap().id,
+            div_3
+        );
+
+        // elements that are not in the tree are not found
+        document.get_mut().remove_child(html, div_3).unwrap();
+        assert_eq!(
+            document.get().get_node_by_named_id("myid").unwrap().id,
+            div_2
+        );
+        document.remove_attribute("id", div_2).unwrap();
+        assert!(document.get().get_node_by_named_id("myid").is_none());
+        assert_eq!(document.get().elements_by_named_id("myid"), []);
+
+        // inserting the element again makes it findable again
+        document.get_mut().append_child(html, div_3).unwrap();
+        assert_eq!(document.get().elements_by_named_id("myid"), [div_3]);
     }
 
     #[test]
@@ -987,7 +1978,7 @@ mod tests {
 
         let doc_read = document.get();
         // validate ID is searchable in dom
-        assert_eq!(*doc_read.named_id_elements.get("myid").unwrap(), p_id);
+        assert_eq!(doc_read.get_node_by_named_id("myid").unwrap().id, p_id);
 
         // validate attribute is applied to underlying element
         let p_node = doc_read.get_node_by_id(p_id).unwrap();
@@ -1019,26 +2010,22 @@ mod tests {
         for error in &errors {
             println!("{}", error);
         }
-        assert_eq!(errors.len(), 6);
+        assert_eq!(errors.len(), 5);
         assert_eq!(
             errors[0],
-            "document task error: ID 'myid' already exists in DOM",
-        );
-        assert_eq!(
-            errors[1],
             "document task error: Node ID 2 is not an element",
         );
-        assert_eq!(errors[2], "document task error: Node ID 42 not found");
+        assert_eq!(errors[1], "document task error: Node ID 42 not found");
         assert_eq!(
-            errors[3],
+            errors[2],
             "document task error: Attribute value 'my id' did not pass validation",
         );
         assert_eq!(
-            errors[4],
+            errors[3],
             "document task error: Attribute value '123' did not pass validation",
         );
         assert_eq!(
-            errors[5],
+            errors[4],
             "document task error: Attribute value '' did not pass validation",
         );
 
@@ -1123,7 +2110,7 @@ mod tests {
         assert_eq!(div_comment_data.value, "comment inside div");
 
         // validate ID is searchable in dom
-        assert_eq!(*doc_read.named_id_elements.get("myid").unwrap(), p_id);
+        assert_eq!(doc_read.get_node_by_named_id("myid").unwrap().id, p_id);
 
         // validate attribute is applied to underlying element
         let p_node = doc_read.get_node_by_id(p_id).unwrap();
@@ -1192,4 +2179,303 @@ mod tests {
         assert!(element.classes.contains("two"));
         assert!(element.classes.contains("three"));
     }
+
+    /// Builds `<!DOCTYPE html><html><body><a/><b/><c/></body></html>` and returns the ids of the
+    /// doctype, html, body, a, b and c nodes
+    fn mutation_document() -> (DocumentHandle, [NodeId; 6]) {
+        let mut document = DocumentBuilder::new_document();
+        let element =
+            |name: &str| Node::new_element(&document, name, HashMap::new(), HTML_NAMESPACE);
+        let (html, body, a, b, c) = (
+            element("html"),
+            element("body"),
+            element("a"),
+            element("b"),
+            element("c"),
+        );
+        let doctype = Node::new_doctype(&document, "html", "", "");
+
+        let mut doc = document.get_mut();
+        let doctype_id = doc.add_node(doctype, NodeId::root(), None);
+        let html_id = doc.add_node(html, NodeId::root(), None);
+        let body_id = doc.add_node(body, html_id, None);
+        let a_id = doc.add_node(a, body_id, None);
+        let b_id = doc.add_node(b, body_id, None);
+        let c_id = doc.add_node(c, body_id, None);
+        drop(doc);
+
+        (document, [doctype_id, html_id, body_id, a_id, b_id, c_id])
+    }
+
+    fn child_names(doc: &Document, node_id: NodeId) -> Vec<String> {
+        doc.get_node_by_id(node_id)
+            .unwrap()
+            .children
+            .iter()
+            .map(|&id| doc.get_node_by_id(id).unwrap().name.clone())
+            .collect()
+    }
+
+    fn is_hierarchy_error<T>(result: Result<T>) -> bool {
+        matches!(result, Err(Error::Dom(DomError::HierarchyRequest(_))))
+    }
+
+    #[test]
+    fn insert_and_remove_children() {
+        let (mut document, [_, _, body, a, b, c]) = mutation_document();
+        let mut doc = document.get_mut();
+
+        assert_eq!(doc.append_child(body, a).unwrap(), a);
+        assert_eq!(child_names(&doc, body), ["b", "c", "a"]);
+
+        doc.insert_before(body, a, Some(b)).unwrap();
+        assert_eq!(child_names(&doc, body), ["a", "b", "c"]);
+
+        // Inserting a node before itself keeps it in place
+        doc.insert_before(body, b, Some(b)).unwrap();
+        assert_eq!(child_names(&doc, body), ["a", "b", "c"]);
+
+        assert_eq!(doc.replace_child(body, c, a).unwrap(), a);
+        assert_eq!(child_names(&doc, body), ["c", "b"]);
+        assert_eq!(doc.get_node_by_id(a).unwrap().parent, None);
+
+        assert_eq!(doc.remove_child(body, b).unwrap(), b);
+        assert_eq!(child_names(&doc, body), ["c"]);
+        assert!(matches!(
+            doc.remove_child(body, b),
+            Err(Error::Dom(DomError::NotFound(_)))
+        ));
+        assert!(matches!(
+            doc.insert_before(body, a, Some(b)),
+            Err(Error::Dom(DomError::NotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn child_node_methods() {
+        let (mut document, [_, _, body, a, b, c]) = mutation_document();
+        let mut doc = document.get_mut();
+
+        doc.before(a, &[c, b]).unwrap();
+        assert_eq!(child_names(&doc, body), ["c", "b", "a"]);
+
+        doc.after(c, &[a, c]).unwrap();
+        assert_eq!(child_names(&doc, body), ["a", "c", "b"]);
+
+        doc.after(b, &[a]).unwrap();
+        assert_eq!(child_names(&doc, body), ["c", "b", "a"]);
+
+        doc.replace_with(b, &[a, b]).unwrap();
+        assert_eq!(child_names(&doc, body), ["c", "a", "b"]);
+
+        doc.replace_with(a, &[]).unwrap();
+        assert_eq!(child_names(&doc, body), ["c", "b"]);
+
+        // Nodes without a parent are left alone
+        doc.before(a, &[b]).unwrap();
+        assert_eq!(child_names(&doc, body), ["c", "b"]);
+    }
+
+    #[test]
+    fn pre_insertion_validity() {
+        let (mut document, [doctype, html, body, a, _, _]) = mutation_document();
+        let text = Node::new_text(&document, "text");
+        let element = Node::new_element(&document, "div", HashMap::new(), HTML_NAMESPACE);
+        let second_doctype = Node::new_doctype(&document, "html", "", "");
+
+        let mut doc = document.get_mut();
+        let text = doc.add_new_node(text);
+        let element = doc.add_new_node(element);
+        let second_doctype = doc.add_new_node(second_doctype);
+        let root = NodeId::root();
+
+        // Cycles
+        assert!(is_hierarchy_error(doc.append_child(a, a)));
+        assert!(is_hierarchy_error(doc.append_child(a, html)));
+        // Parents that cannot have children
+        assert!(is_hierarchy_error(doc.append_child(text, element)));
+        assert!(is_hierarchy_error(doc.append_child(doctype, element)));
+        // The document node itself
+        assert!(is_hierarchy_error(doc.append_child(body, root)));
+        // Children of the document
+        assert!(is_hierarchy_error(doc.append_child(root, text)));
+        assert!(is_hierarchy_error(doc.append_child(root, element)));
+        assert!(is_hierarchy_error(doc.append_child(root, html)));
+        assert!(is_hierarchy_error(doc.append_child(root, second_doctype)));
+        assert!(is_hierarchy_error(doc.append_child(body, second_doctype)));
+        assert!(is_hierarchy_error(doc.after(doctype, &[element, a])));
+
+        // Replacing the element or doctype of the document is allowed
+        assert_eq!(doc.replace_child(root, element, html).unwrap(), html);
+        assert_eq!(
+            doc.replace_child(root, second_doctype, doctype).unwrap(),
+            doctype
+        );
+        assert_eq!(child_names(&doc, root), ["", "div"]);
+
+        // A doctype must come before the element
+        doc.remove_child(root, second_doctype).unwrap();
+        assert!(is_hierarchy_error(doc.append_child(root, doctype)));
+        doc.insert_before(root, doctype, Some(element)).unwrap();
+        assert!(is_hierarchy_error(doc.replace_child(root, html, doctype)));
+        assert!(is_hierarchy_error(doc.insert_before(
+            root,
+            html,
+            Some(doctype)
+        )));
+
+        // Failed insertions leave the tree untouched
+        assert_eq!(doc.get_node_by_id(a).unwrap().parent, Some(body));
+        assert_eq!(doc.get_node_by_id(html).unwrap().parent, None);
+    }
+
+    #[test]
+    fn clone_node() {
+        let (mut document, [_, html, body, a, _, _]) = mutation_document();
+        let text = Node::new_text(&document, "text");
+        let mut doc = document.get_mut();
+        doc.add_node(text, a, None);
+
+        let shallow = doc.clone_node(body, false).unwrap();
+        assert_ne!(shallow, body);
+        assert_eq!(doc.get_node_by_id(shallow).unwrap().name, "body");
+        assert_eq!(doc.get_node_by_id(shallow).unwrap().parent, None);
+        assert!(child_names(&doc, shallow).is_empty());
+
+        let deep = doc.clone_node(body, true).unwrap();
+        assert_eq!(child_names(&doc, deep), ["a", "b", "c"]);
+        let a_copy = doc.get_node_by_id(deep).unwrap().children[0];
+        assert_ne!(a_copy, a);
+        let text_copy = doc.get_node_by_id(a_copy).unwrap().children[0];
+        let NodeData::Text(text) = &doc.get_node_by_id(text_copy).unwrap().data else {
+            panic!("expected a text node");
+        };
+        assert_eq!(text.value(), "text");
+
+        // The original tree is unchanged
+        assert_eq!(child_names(&doc, html), ["body"]);
+        assert_eq!(doc.get_node_by_id(a).unwrap().children.len(), 1);
+
+        assert!(matches!(
+            doc.clone_node(NodeId::root(), true),
+            Err(Error::Dom(DomError::NotSupported(_)))
+        ));
+    }
+
+    #[test]
+    fn delete_node() {
+        let (mut document, [_, html, body, a, b, c]) = mutation_document();
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "inner".to_string());
+        let inner = Node::new_element(&document, "span", attributes, HTML_NAMESPACE);
+
+        let mut doc = document.get_mut();
+        let inner = doc.add_node(inner, a, None);
+        assert!(doc.get_node_by_named_id("inner").is_some());
+
+        doc.delete_node(a).unwrap();
+        assert_eq!(child_names(&doc, body), ["b", "c"]);
+        assert!(doc.get_node_by_id(a).is_none());
+        assert!(doc.get_node_by_id(inner).is_none());
+        assert!(doc.get_node_by_named_id("inner").is_none());
+
+        // Stale ids are detected
+        assert!(matches!(
+            doc.append_child(body, a),
+            Err(Error::Dom(DomError::NotFound(_)))
+        ));
+        assert!(matches!(
+            doc.delete_node(a),
+            Err(Error::Dom(DomError::NotFound(_)))
+        ));
+        assert!(matches!(
+            doc.delete_node(NodeId::root()),
+            Err(Error::Dom(DomError::NotSupported(_)))
+        ));
+
+        doc.delete_node(body).unwrap();
+        assert!(child_names(&doc, html).is_empty());
+        assert!(doc.get_node_by_id(b).is_none());
+        assert!(doc.get_node_by_id(c).is_none());
+        assert_eq!(doc.arena.count_nodes(), 3);
+    }
+
+    #[test]
+    fn compact() {
+        let (mut document, [doctype, html, body, a, b, c]) = mutation_document();
+        let detached = Node::new_element(&document, "div", HashMap::new(), HTML_NAMESPACE);
+
+        let mut doc = document.get_mut();
+        let detached = doc.add_new_node(detached);
+        doc.remove_child(body, b).unwrap();
+        doc.append_child(b, c).unwrap();
+        let copy = doc.clone_node(html, true).unwrap();
+        assert_eq!(doc.arena.count_nodes(), 11);
+
+        // The detached div, b with its child c, and the copy of html, body and a are deleted
+        assert_eq!(doc.compact(), 6);
+        assert_eq!(doc.arena.count_nodes(), 5);
+        for id in [detached, b, c, copy] {
+            assert!(doc.get_node_by_id(id).is_none());
+        }
+        for id in [NodeId::root(), doctype, html, body, a] {
+            assert!(doc.get_node_by_id(id).is_some());
+        }
+
+        assert_eq!(doc.compact(), 0);
+    }
+
+    #[test]
+    fn template_contents() {
+        let mut document = DocumentBuilder::new_document();
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<template><p class=x>one</p><template><b>two</b></template></template><div></div>",
+            None,
+        );
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let mut doc = document.get_mut();
+        let template = doc.query_selector("template").unwrap().unwrap();
+        let target = doc.query_selector("div").unwrap().unwrap();
+
+        // The contents are not part of the document
+        assert!(doc.get_node_by_id(template).unwrap().children.is_empty());
+        assert_eq!(doc.query_selector("p").unwrap(), None);
+
+        let fragment = doc.template_contents(template).unwrap();
+        assert_eq!(fragment.children().len(), 2);
+        let contents = fragment.contents();
+        let contents = contents.get();
+        assert_eq!(child_names(&contents, NodeId::root()), ["p", "template"]);
+        let p = fragment.query_selector("p.x").unwrap().unwrap();
+        // The nodes of the contents have ids of their own
+        assert!(doc.get_node_by_id(p).is_none());
+        // Nested template contents are not part of the outer template contents either
+        assert_eq!(fragment.query_selector("b").unwrap(), None);
+        let nested = fragment.query_selector("template").unwrap().unwrap();
+        let nested_contents = contents.template_contents(nested).unwrap();
+        assert!(nested_contents.query_selector("b").unwrap().is_some());
+        drop(contents);
+
+        let shallow = doc.clone_node(template, false).unwrap();
+        assert!(doc.template_contents(shallow).unwrap().is_empty());
+        let deep = doc.clone_node(template, true).unwrap();
+        assert_eq!(doc.template_contents(deep).unwrap().children().len(), 2);
+
+        let adopted = doc.adopt_template_contents(template, target, None).unwrap();
+        assert_eq!(child_names(&doc, target), ["p", "template"]);
+        assert_eq!(doc.get_node_by_id(target).unwrap().children, adopted);
+        assert!(doc.template_contents(template).unwrap().is_empty());
+        assert!(doc.query_selector("p.x").unwrap().is_some());
+        assert!(!doc.template_contents(adopted[1]).unwrap().is_empty());
+
+        // The clone keeps its own copy of the contents
+        assert_eq!(doc.template_contents(deep).unwrap().children().len(), 2);
+
+        assert!(matches!(
+            doc.adopt_template_contents(target, target, None),
+            Err(Error::Dom(DomError::NotSupported(_)))
+        ));
+    }
 }
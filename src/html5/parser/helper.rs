@@ -1,11 +1,13 @@
+use crate::bytes::Span;
 use crate::html5::element_class::ElementClass;
+use crate::html5::error_logger::ParserError;
 use crate::html5::node::data::text::TextData;
 use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
 use crate::html5::parser::{ActiveElement, Html5Parser, Scope};
 use crate::html5::tokenizer::token::Token;
 use std::collections::HashMap;
 
-use super::document::{Document, DocumentHandle};
+use super::document::{Document, DocumentFragment, DocumentHandle};
 
 const ADOPTION_AGENCY_OUTER_LOOP_DEPTH: usize = 8;
 const ADOPTION_AGENCY_INNER_LOOP_DEPTH: usize = 3;
@@ -23,6 +25,16 @@ pub enum InsertionPositionMode<NodeId> {
     },
 }
 
+impl<NodeId> InsertionPositionMode<NodeId> {
+    /// Returns the document the node is inserted into
+    pub fn document(&self) -> &DocumentHandle {
+        match self {
+            InsertionPositionMode::LastChild { handle, .. }
+            | InsertionPositionMode::Sibling { handle, .. } => handle,
+        }
+    }
+}
+
 pub enum BookMark<NodeId> {
     Replace(NodeId),
     InsertAfter(NodeId),
@@ -46,7 +58,7 @@ impl Html5Parser<'_> {
             .rev()
             .find_map(|(i, &node_id)| {
                 if let ActiveElement::Node(node_id) = node_id {
-                    if get_node_by_id!(self.document, node_id).name == subject {
+                    if self.get_node(node_id).name == subject {
                         Some((i, node_id))
                     } else {
                         None
@@ -63,7 +75,7 @@ impl Html5Parser<'_> {
             .enumerate()
             .skip(format_ele_position)
             .find_map(|(i, &node_id)| {
-                if get_node_by_id!(self.document, node_id).is_special() {
+                if self.get_node(node_id).is_special() {
                     Some((i, node_id))
                 } else {
                     None
@@ -91,6 +103,8 @@ impl Html5Parser<'_> {
     }
 
     pub fn insert_text_helper(&mut self, position: InsertionPositionMode<NodeId>, token: &Token) {
+        let location = self.token_location(token);
+
         match position {
             InsertionPositionMode::Sibling {
                 handle,
@@ -102,22 +116,31 @@ impl Html5Parser<'_> {
                 let position = parent_node.children.iter().position(|&x| x == before);
                 match position {
                     None | Some(0) => {
-                        let node = self.create_node(token, HTML_NAMESPACE);
+                        let node = self.create_node(&doc, token, HTML_NAMESPACE);
                         doc.add_node(node, parent, position);
                     }
                     Some(index) => {
                         let last_node_id = parent_node.children[index - 1];
-                        if let NodeData::Text(TextData { ref mut value, .. }) = doc
-                            .get_mut()
+                        let mut doc_mut = doc.get_mut();
+                        let observed = doc_mut.mutation_observers.is_observing();
+                        let last_node = doc_mut
                             .get_node_by_id_mut(last_node_id)
-                            .expect("node not found")
-                            .data
-                        {
+                            .expect("node not found");
+                        if let NodeData::Text(TextData { ref mut value, .. }) = last_node.data {
+                            let old_value = observed.then(|| value.clone());
                             value.push_str(&token.to_string());
+                            let len = value.len();
+                            extend_location(last_node, location);
+                            if let Some(old_value) = old_value {
+                                doc_mut.queue_character_data_record(last_node_id, old_value);
+                            }
+                            drop(doc_mut);
+                            self.check_text_limit(len);
                             return;
                         };
+                        drop(doc_mut);
 
-                        let node = self.create_node(token, HTML_NAMESPACE);
+                        let node = self.create_node(&doc, token, HTML_NAMESPACE);
                         doc.add_node(node, parent, Some(index));
                     }
                 }
@@ -126,42 +149,72 @@ impl Html5Parser<'_> {
                 let mut doc = handle;
                 let parent_node = get_node_by_id!(doc, parent);
                 if let Some(last_node_id) = parent_node.children.last() {
-                    if let NodeData::Text(TextData { ref mut value, .. }) = self
-                        .document
-                        .get_mut()
+                    let mut doc_mut = doc.get_mut();
+                    let observed = doc_mut.mutation_observers.is_observing();
+                    let last_node = doc_mut
                         .get_node_by_id_mut(*last_node_id)
-                        .expect("node not found")
-                        .data
-                    {
+                        .expect("node not found");
+                    if let NodeData::Text(TextData { ref mut value, .. }) = last_node.data {
+                        let old_value = observed.then(|| value.clone());
                         value.push_str(&token.to_string());
+                        let len = value.len();
+                        extend_location(last_node, location);
+                        if let Some(old_value) = old_value {
+                            doc_mut.queue_character_data_record(*last_node_id, old_value);
+                        }
+                        drop(doc_mut);
+                        self.check_text_limit(len);
                         return;
                     };
-                    let node = self.create_node(token, HTML_NAMESPACE);
+                    drop(doc_mut);
+                    let node = self.create_node(&doc, token, HTML_NAMESPACE);
                     doc.add_node(node, parent, None);
                     return;
                 }
 
-                let node = self.create_node(token, HTML_NAMESPACE);
+                let node = self.create_node(&doc, token, HTML_NAMESPACE);
                 doc.add_node(node, parent, None);
             }
         }
     }
 
+    /// Inserts an HTML element for the current token, which may have been adjusted
     pub fn insert_html_element(&mut self, token: &Token) -> NodeId {
-        self.insert_element_from_token(token, None, Some(HTML_NAMESPACE))
+        self.insert_element_from_token(token, None, Some(HTML_NAMESPACE), true)
+    }
+
+    /// Inserts an HTML element for a start tag token without attributes that is not in the input
+    /// stream, like the implied tbody of a table row. The element gets no location.
+    pub fn insert_implied_html_element(&mut self, name: &str) -> NodeId {
+        let token = Token::StartTag {
+            name: name.to_string(),
+            is_self_closing: false,
+            attributes: HashMap::new(),
+        };
+        self.insert_element_from_token(&token, None, Some(HTML_NAMESPACE), false)
     }
 
+    /// Inserts a foreign element for the current token, which may have been adjusted
     pub fn insert_foreign_element(&mut self, token: &Token, namespace: &str) -> NodeId {
-        self.insert_element_from_token(token, None, Some(namespace))
+        self.insert_element_from_token(token, None, Some(namespace), true)
     }
 
+    /// Inserts an element for the token. Only when the token is (derived from) the current token,
+    /// the element gets the location of the current token.
     pub fn insert_element_from_token(
         &mut self,
         token: &Token,
         override_node: Option<NodeId>,
         namespace: Option<&str>,
+        from_current_token: bool,
     ) -> NodeId {
-        let mut node = self.create_node(token, namespace.unwrap_or(HTML_NAMESPACE));
+        let insert_position = self.appropriate_place_insert(override_node);
+        let mut node = self.create_node(
+            insert_position.document(),
+            token,
+            namespace.unwrap_or(HTML_NAMESPACE),
+        );
+        node.location = from_current_token.then_some(self.current_span);
         // add CSS classes from class attribute in element
         // e.g., <div class="one two three">
         // TODO: this will be refactored later in ElementAttributes to do this
@@ -175,7 +228,7 @@ impl Html5Parser<'_> {
                 }
             }
         }
-        self.insert_element(node, override_node)
+        self.insert_element(node, insert_position)
     }
 
     pub fn insert_element_from_node(
@@ -188,6 +241,9 @@ impl Html5Parser<'_> {
         new_node.children = Vec::new();
         new_node.parent = None;
         new_node.is_registered = false;
+        // The copy is not in the input stream
+        new_node.location = None;
+        new_node.end_tag_location = None;
 
         if let NodeData::Element(ref mut element) = new_node.data {
             if element.attributes.contains_key("class") {
@@ -196,12 +252,38 @@ impl Html5Parser<'_> {
                 }
             }
         }
-        self.insert_element(new_node, override_node)
+        let insert_position = self.appropriate_place_insert(override_node);
+        self.insert_element(new_node, insert_position)
     }
 
-    pub fn insert_element(&mut self, node: Node, override_node: Option<NodeId>) -> NodeId {
-        let node_id = self.document.get_mut().add_new_node(node);
-        let insert_position = self.appropriate_place_insert(override_node);
+    /// Creates an HTML element for the token and pushes it onto the stack of open elements, without
+    /// inserting it into the tree ("only add to element stack" in the specification)
+    pub fn push_html_element_without_inserting(&mut self, token: &Token) -> NodeId {
+        // The element goes in the document of the current node, as it would be inserted there
+        let mut document = self.document_of(current_node!(self).id);
+        let mut node = self.create_node(&document, token, HTML_NAMESPACE);
+        node.location = Some(self.current_span);
+        if let NodeData::Element(ref mut element) = node.data {
+            if let Some(class_string) = element.attributes.get("class") {
+                element.classes = ElementClass::from_string(class_string);
+            }
+        }
+
+        let node_id = document.get_mut().add_new_node(node);
+        self.open_elements.push(node_id);
+        node_id
+    }
+
+    /// Inserts the element at the given position, which has been determined before the element
+    /// was created, so the element is created for the document it is inserted into
+    pub fn insert_element(
+        &mut self,
+        node: Node,
+        insert_position: InsertionPositionMode<NodeId>,
+    ) -> NodeId {
+        let node_id = Document::clone(insert_position.document())
+            .get_mut()
+            .add_new_node(node);
         self.insert_element_helper(node_id, insert_position);
 
         //     if parser not created as part of html fragment parsing algorithm
@@ -209,31 +291,37 @@ impl Html5Parser<'_> {
 
         // push element onto the stack of open elements so that is the new current node
         self.open_elements.push(node_id);
+        self.check_depth_limit(node_id);
 
         // return element
         node_id
     }
 
     pub fn insert_doctype_element(&mut self, token: &Token) {
-        let node = self.create_node(token, HTML_NAMESPACE);
+        let node = self.create_node(&self.document, token, HTML_NAMESPACE);
         self.document.get_mut().add_node(node, NodeId::root(), None);
     }
 
-    pub fn insert_document_element(&mut self, token: &Token) {
-        let node = self.create_node(token, HTML_NAMESPACE);
+    /// Inserts the html element. Only when the token is the current token, the element gets its
+    /// location.
+    pub fn insert_document_element(&mut self, token: &Token, from_current_token: bool) {
+        let mut node = self.create_node(&self.document, token, HTML_NAMESPACE);
+        node.location = from_current_token.then_some(self.current_span);
         let node_id = self.document.get_mut().add_node(node, NodeId::root(), None);
         self.open_elements.push(node_id);
     }
 
     pub fn insert_comment_element(&mut self, token: &Token, insert_position: Option<NodeId>) {
-        let node = self.create_node(token, HTML_NAMESPACE);
         match insert_position {
             Some(position) => {
+                let node = self.create_node(&self.document, token, HTML_NAMESPACE);
                 self.document.get_mut().add_node(node, position, None);
             }
             None => {
-                let node_id = self.document.get_mut().add_new_node(node);
                 let insert_position = self.appropriate_place_insert(None);
+                let mut document = Document::clone(insert_position.document());
+                let node = self.create_node(&document, token, HTML_NAMESPACE);
+                let node_id = document.get_mut().add_new_node(node);
                 self.insert_element_helper(node_id, insert_position);
             }
         }
@@ -259,56 +347,52 @@ impl Html5Parser<'_> {
     ) -> InsertionPositionMode<NodeId> {
         let current_node_id = current_node!(self).id;
         let target_id = override_node.unwrap_or(current_node_id);
-        let target_node = get_node_by_id!(self.document, target_id);
+        let target_node = self.get_node(target_id);
         if !(self.foster_parenting
             && ["table", "tbody", "thead", "tfoot", "tr"].contains(&target_node.name.as_str()))
         {
             if target_node.name == "template" && target_node.is_namespace(HTML_NAMESPACE) {
                 if let NodeData::Element(element) = target_node.data {
                     if let Some(template_contents) = element.template_contents {
-                        return InsertionPositionMode::LastChild {
-                            handle: Document::clone(&template_contents.doc),
-                            parent: target_id,
-                        };
+                        return template_contents_position(target_id, &template_contents);
                     }
                 }
             } else {
                 return InsertionPositionMode::LastChild {
-                    handle: Document::clone(&self.document),
+                    handle: self.document_of(target_id),
                     parent: target_id,
                 };
             }
         }
         let mut iter = self.open_elements.iter().rev().peekable();
         while let Some(node_id) = iter.next() {
-            let node = get_node_by_id!(self.document, *node_id);
+            let node = self.get_node(*node_id);
             if node.name == "template" {
                 if let NodeData::Element(element) = node.data {
                     if let Some(template_contents) = &element.template_contents {
-                        return InsertionPositionMode::LastChild {
-                            handle: Document::clone(&template_contents.doc),
-                            parent: *node_id,
-                        };
+                        return template_contents_position(*node_id, template_contents);
                     }
                 }
             } else if node.name == "table" {
                 if node.parent.is_some() {
                     return InsertionPositionMode::Sibling {
-                        handle: Document::clone(&self.document),
+                        handle: self.document_of(*node_id),
                         parent: node.parent.unwrap(),
                         before: *node_id,
                     };
                 }
                 // TODO has some question? can reached?
+                let parent = *(*iter.peek().unwrap());
                 return InsertionPositionMode::LastChild {
-                    handle: Document::clone(&self.document),
-                    parent: *(*iter.peek().unwrap()),
+                    handle: self.document_of(parent),
+                    parent,
                 };
             }
         }
+        let parent = *self.open_elements.first().unwrap();
         return InsertionPositionMode::LastChild {
-            handle: Document::clone(&self.document),
-            parent: *self.open_elements.first().unwrap(),
+            handle: self.document_of(parent),
+            parent,
         };
     }
 
@@ -328,7 +412,7 @@ impl Html5Parser<'_> {
                 .find_position_in_active_format(&current_node_id)
                 .is_none()
         {
-            self.open_elements.pop();
+            self.pop_open_element();
             return;
         }
 
@@ -353,7 +437,9 @@ impl Html5Parser<'_> {
                     }
                     Some((idx, node_id)) => (idx, node_id),
                 };
-            let format_elem_node = get_node_by_id!(self.document, format_elem_node_id);
+            let format_elem_node = self.get_node(format_elem_node_id);
+            // The nodes that are moved around are all in the document of the formatting element
+            let mut document = self.document_of(format_elem_node_id);
             let format_ele_stack_position = match self
                 .open_elements
                 .iter()
@@ -361,7 +447,7 @@ impl Html5Parser<'_> {
             {
                 // step 4.4
                 None => {
-                    self.parse_error("not found format_element_node in open_elements");
+                    parse_error!(self, "not found format_element_node in open_elements");
                     self.active_formatting_elements.remove(format_elem_idx);
                     return;
                 }
@@ -370,13 +456,17 @@ impl Html5Parser<'_> {
 
             // step 4.5
             if !self.is_in_scope(&format_elem_node.name, HTML_NAMESPACE, Scope::Regular) {
-                self.parse_error("format_element_node not in regular scope");
+                parse_error!(self, "format_element_node not in regular scope");
                 return;
             }
 
             // step 4.6
             if format_elem_node_id != current_node_id {
-                self.parse_error("format_element_node not current_node");
+                parse_error!(
+                    self,
+                    ParserError::MisnestedTags,
+                    "format_element_node not current_node",
+                );
             }
 
             // step 4.7
@@ -384,7 +474,9 @@ impl Html5Parser<'_> {
                 match self.find_further_block_index(format_ele_stack_position) {
                     // step 4.8
                     None => {
-                        self.open_elements.truncate(format_ele_stack_position);
+                        while self.open_elements.len() > format_ele_stack_position {
+                            self.pop_open_element();
+                        }
                         self.active_formatting_elements.remove(format_elem_idx);
                         return;
                     }
@@ -423,31 +515,27 @@ impl Html5Parser<'_> {
                 if inner_loop_counter > ADOPTION_AGENCY_INNER_LOOP_DEPTH {
                     self.find_position_in_active_format(&node_id)
                         .map(|position| self.active_formatting_elements.remove(position));
-                    self.open_elements.remove(node_idx);
+                    self.open_elements_remove_at(node_idx);
                     continue;
                 }
                 // step 4.13.5
                 let node_active_position = match self.find_position_in_active_format(&node_id) {
                     Some(pos) => pos,
                     None => {
-                        self.open_elements.remove(node_idx);
+                        self.open_elements_remove_at(node_idx);
                         continue;
                     }
                 };
 
                 // step 4.13.6
-                let element = get_node_by_id!(self.document, node_id);
+                let element = self.get_node(node_id);
                 let node_attributes = match element.data {
                     NodeData::Element(element) => element.attributes.clone(),
                     _ => HashMap::new(),
                 };
-                let replacement_node = Node::new_element(
-                    &self.document,
-                    &element.name,
-                    node_attributes,
-                    HTML_NAMESPACE,
-                );
-                let replace_node_id = self.document.get_mut().add_new_node(replacement_node);
+                let replacement_node =
+                    Node::new_element(&document, &element.name, node_attributes, HTML_NAMESPACE);
+                let replace_node_id = document.get_mut().add_new_node(replacement_node);
 
                 self.active_formatting_elements[node_active_position] =
                     ActiveElement::Node(replace_node_id);
@@ -462,16 +550,15 @@ impl Html5Parser<'_> {
                 }
 
                 // step 4.13.8
-                self.document.detach_node_from_parent(last_node_id);
-                self.document
-                    .attach_node_to_parent(last_node_id, replace_node_id, None);
+                document.detach_node_from_parent(last_node_id);
+                document.attach_node_to_parent(last_node_id, replace_node_id, None);
 
                 // step 4.13.9
                 last_node_id = node_id;
             }
 
             // step 4.14
-            self.document.detach_node_from_parent(last_node_id);
+            document.detach_node_from_parent(last_node_id);
             let insert_position = self.appropriate_place_insert(Some(common_ancestor));
             self.insert_element_helper(last_node_id, insert_position);
 
@@ -481,26 +568,24 @@ impl Html5Parser<'_> {
                 _ => HashMap::new(),
             };
             let new_format_node: Node = Node::new_element(
-                &self.document,
+                &document,
                 &format_elem_node.name,
                 format_elem_attributes,
                 HTML_NAMESPACE,
             );
 
             // step 4.16
-            let new_node_id = self
-                .document
-                .get_mut()
-                .add_new_node(new_format_node.clone());
-            let further_block_node = get_node_by_id!(self.document, further_block_node_id);
+            let new_node_id = document.get_mut().add_new_node(new_format_node.clone());
+            let further_block_node = self.get_node(further_block_node_id);
             for child in further_block_node.children.iter() {
-                self.document.get_mut().relocate(*child, new_node_id);
+                document.get_mut().relocate(*child, new_node_id);
             }
 
             // step 4.17
-            self.document
+            document
                 .get_mut()
                 .attach_node_to_parent(new_node_id, further_block_node_id, None);
+            self.check_depth_limit(last_node_id);
 
             // step 4.18
             match bookmark_node_id {
@@ -523,7 +608,7 @@ impl Html5Parser<'_> {
             }
 
             // step 4.19
-            self.open_elements.retain(|x| x != &format_elem_node_id);
+            self.open_elements_remove(format_elem_node_id);
             let position = self
                 .find_position_in_open_element(&further_block_node_id)
                 .unwrap();
@@ -531,3 +616,29 @@ impl Html5Parser<'_> {
         }
     }
 }
+
+/// Extends the location of a text node when text from the given location is appended to it
+fn extend_location(node: &mut Node, location: Option<Span>) {
+    if let (Some(node_location), Some(location)) = (node.location.as_mut(), location) {
+        node_location.end = location.end;
+    }
+}
+
+/// Returns where the children of a template element are inserted: at the end of its template
+/// contents, or at the end of the shadow root when it is a declarative shadow root
+fn template_contents_position(
+    template_id: NodeId,
+    template_contents: &DocumentFragment,
+) -> InsertionPositionMode<NodeId> {
+    if template_contents.host() == template_id {
+        InsertionPositionMode::LastChild {
+            handle: template_contents.contents(),
+            parent: NodeId::root(),
+        }
+    } else {
+        InsertionPositionMode::LastChild {
+            handle: Document::clone(&template_contents.doc),
+            parent: template_contents.host(),
+        }
+    }
+}
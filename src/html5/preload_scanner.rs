@@ -0,0 +1,203 @@
+//! Speculative HTML parser (preload scanner)
+//!
+//! While the main parser is blocked on a script, the rest of the input can be scanned for
+//! sub-resources that will most likely be needed later on. The scanner only runs the tokenizer,
+//! so it never touches the document. It does switch the tokenizer into the correct text state for
+//! elements like `<script>` and `<style>`, so their contents are not mistaken for markup.
+use crate::bytes::CharIterator;
+use crate::html5::error_logger::ErrorLogger;
+use crate::html5::tokenizer::state::State;
+use crate::html5::tokenizer::token::Token;
+use crate::html5::tokenizer::{ParserData, Tokenizer};
+use crate::net::request::InitiatorType;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use url::Url;
+
+/// A sub-resource that has been found by the preload scanner
+#[derive(Clone, Debug)]
+pub struct PreloadRequest {
+    /// URL of the resource, resolved against the base URL of the document
+    pub url: Url,
+    /// Type of the element that will fetch the resource
+    pub initiator_type: InitiatorType,
+}
+
+/// Scans (unparsed) input for sub-resources that can be fetched ahead of the parser
+pub struct PreloadScanner {
+    /// URL of the document itself
+    document_url: Url,
+    /// URL found in the first `<base href>` element, if any
+    base_url: Option<Url>,
+}
+
+impl PreloadScanner {
+    /// Creates a new scanner for the document with the given URL
+    pub fn new(document_url: Url) -> Self {
+        PreloadScanner {
+            document_url,
+            base_url: None,
+        }
+    }
+
+    /// Returns the URL that is used to resolve relative URLs
+    pub fn base_url(&self) -> &Url {
+        self.base_url.as_ref().unwrap_or(&self.document_url)
+    }
+
+    /// Sets the base URL, for instance when the main parser already inserted a `<base>` element.
+    /// Only the first base URL is used, as with the frozen base URL of a document.
+    pub fn set_base_url(&mut self, url: Url) {
+        if self.base_url.is_none() {
+            self.base_url = Some(url);
+        }
+    }
+
+    /// Scans the given input and returns all sub-resources that are found, in document order.
+    /// The scanner remembers the base URL between calls, so input can be scanned in chunks.
+    pub fn scan(&mut self, input: &str) -> Vec<PreloadRequest> {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(input, None);
+
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let mut tokenizer = Tokenizer::new(&mut chars, None, error_logger);
+
+        let mut requests = Vec::new();
+        loop {
+            let token = match tokenizer.next_token(ParserData::default()) {
+                Ok(token) => token,
+                Err(_) => break,
+            };
+
+            match token {
+                Token::StartTag {
+                    name, attributes, ..
+                } => {
+                    if let Some(request) = self.process_start_tag(&name, &attributes) {
+                        requests.push(request);
+                    }
+
+                    if let Some(state) = text_state_for(&name) {
+                        tokenizer.set_state(state);
+                    }
+                }
+                Token::Eof => break,
+                _ => {}
+            }
+        }
+
+        requests
+    }
+
+    /// Returns the sub-resource that is fetched by the given start tag, if any
+    fn process_start_tag(
+        &mut self,
+        name: &str,
+        attributes: &HashMap<String, String>,
+    ) -> Option<PreloadRequest> {
+        let (attr, initiator_type) = match name {
+            "base" => {
+                if let Some(url) = attributes
+                    .get("href")
+                    .and_then(|href| self.document_url.join(href).ok())
+                {
+                    self.set_base_url(url);
+                }
+                return None;
+            }
+            "img" => ("src", InitiatorType::Img),
+            "script" => ("src", InitiatorType::Script),
+            "link" => {
+                let rel = attributes.get("rel")?.to_ascii_lowercase();
+                if !rel
+                    .split_ascii_whitespace()
+                    .any(|keyword| keyword == "stylesheet" || keyword == "preload")
+                {
+                    return None;
+                }
+                ("href", InitiatorType::Link)
+            }
+            _ => return None,
+        };
+
+        let value = attributes.get(attr)?;
+        if value.trim().is_empty() {
+            return None;
+        }
+
+        let url = self.base_url().join(value).ok()?;
+        Some(PreloadRequest {
+            url,
+            initiator_type,
+        })
+    }
+}
+
+/// Returns the tokenizer state the tree builder would switch to after the given start tag. We
+/// assume scripting is enabled, as the scanner only runs while a script blocks the parser.
+fn text_state_for(name: &str) -> Option<State> {
+    match name {
+        "title" | "textarea" => Some(State::RCDATA),
+        "style" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => Some(State::RAWTEXT),
+        "script" => Some(State::ScriptData),
+        "plaintext" => Some(State::PLAINTEXT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(input: &str) -> Vec<(String, InitiatorType)> {
+        let mut scanner =
+            PreloadScanner::new(Url::parse("https://example.com/dir/page.html").unwrap());
+        scanner
+            .scan(input)
+            .into_iter()
+            .map(|r| (r.url.to_string(), r.initiator_type))
+            .collect()
+    }
+
+    #[test]
+    fn finds_sub_resources() {
+        let found = scan(
+            r#"<img src="a.png"><link rel="Preload" href="/font.woff2" as="font">
+            <script src="b.js"></script><link rel="icon" href="c.ico">
+            <link rel="alternate stylesheet" href="d.css"><img>"#,
+        );
+
+        assert_eq!(found.len(), 4);
+        assert_eq!(found[0].0, "https://example.com/dir/a.png");
+        assert!(matches!(found[0].1, InitiatorType::Img));
+        assert_eq!(found[1].0, "https://example.com/font.woff2");
+        assert!(matches!(found[1].1, InitiatorType::Link));
+        assert_eq!(found[2].0, "https://example.com/dir/b.js");
+        assert!(matches!(found[2].1, InitiatorType::Script));
+        assert_eq!(found[3].0, "https://example.com/dir/d.css");
+    }
+
+    #[test]
+    fn resolves_against_first_base() {
+        let found = scan(
+            r#"<img src="a.png"><base href="https://cdn.example.org/x/"><base href="/other/">
+            <img src="b.png">"#,
+        );
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0, "https://example.com/dir/a.png");
+        assert_eq!(found[1].0, "https://cdn.example.org/x/b.png");
+    }
+
+    #[test]
+    fn skips_text_contents() {
+        let found = scan(
+            r#"<script>document.write('<img src="no.png">')</script><style>/* <img src="no.png"> */</style>
+            <textarea><img src="no.png"></textarea><img src="yes.png">"#,
+        );
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "https://example.com/dir/yes.png");
+    }
+}
@@ -0,0 +1,1311 @@
+//! HTML serializer
+//!
+//! Turns a document, or the children of a single node, back into HTML by following the
+//! [fragment serialization algorithm](https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments).
+//!
+//! Serializing happens in two steps: the tree is first walked into a flat list of events (start
+//! tags, end tags, text and so on), which are then written out. Options that depend on
+//! neighbouring nodes, like omitting optional tags, only need to look at the previous and next
+//! event in the list.
+use crate::html5::node::data::comment::CommentData;
+use crate::html5::node::data::doctype::DocTypeData;
+use crate::html5::node::data::text::TextData;
+use crate::html5::node::traversal::Traversable;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::parser::quirks::QuirksMode;
+use std::borrow::Cow;
+
+/// Elements that have no end tag and whose children are never serialized
+const VOID_ELEMENTS: [&str; 18] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text contents are serialized without escaping
+const RAW_TEXT_ELEMENTS: [&str; 7] = [
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Elements in which whitespace is kept as-is when whitespace is stripped
+const WHITESPACE_PRESERVING_ELEMENTS: [&str; 3] = ["pre", "textarea", "listing"];
+
+/// Elements that drop a newline directly after their start tag when parsed
+const LEADING_NEWLINE_ELEMENTS: [&str; 3] = ["pre", "textarea", "listing"];
+
+/// Elements that close an open p element when they start
+const P_CLOSING_ELEMENTS: [&str; 32] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Elements in which a p element needs an explicit end tag when it is the last child
+const P_END_TAG_PARENTS: [&str; 7] = ["a", "audio", "del", "ins", "map", "noscript", "video"];
+
+/// Elements that are laid out as blocks. Whitespace between these elements is insignificant, so
+/// it can be removed when minifying or replaced by indentation when pretty printing. Raw text
+/// elements like script and style are not in the list, as they can be found between inline
+/// contents. Whitespace between the children of the head element is insignificant as well.
+const BLOCK_ELEMENTS: [&str; 54] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// How attribute values are quoted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttributeQuoting {
+    /// Always quote attribute values
+    Always,
+    /// Only quote attribute values that cannot be written as an unquoted attribute value
+    /// without a parse error
+    WhenNeeded,
+    /// Only quote attribute values that are empty or contain whitespace, quotes, `=`, `>` or
+    /// `` ` ``, like the html5lib serializer does. Values with a `<` are left unquoted, which
+    /// is a parse error but parses to the same value.
+    Legacy,
+}
+
+/// Options for the serializer. The default options produce the output of the fragment
+/// serialization algorithm from the specification.
+#[derive(Clone, Debug)]
+pub struct Html5SerializerOptions {
+    /// When enabled, the contents of `<noscript>` elements are serialized as raw text
+    pub scripting_enabled: bool,
+    /// How attribute values are quoted
+    pub quote_attr_values: AttributeQuoting,
+    /// Character used to quote attribute values. When not set, a double quote is used unless the
+    /// value contains double quotes and no single quotes.
+    pub quote_char: Option<char>,
+    /// Writes attributes whose value is empty or equal to the attribute name without a value
+    pub minimize_boolean_attributes: bool,
+    /// Writes void elements as `<br />` instead of `<br>`
+    pub use_trailing_solidus: bool,
+    /// Escapes `<` in attribute values
+    pub escape_lt_in_attrs: bool,
+    /// Escapes the contents of raw text elements like `<script>` and `<style>`
+    pub escape_rcdata: bool,
+    /// Leaves out start and end tags that the parser will add back by itself
+    pub omit_optional_tags: bool,
+    /// Collapses runs of whitespace into a single space, except inside `<pre>`, `<textarea>`
+    /// and raw text elements
+    pub strip_whitespace: bool,
+    /// Leaves out text that only consists of whitespace and sits between block-level elements
+    pub strip_block_whitespace: bool,
+    /// Leaves out comments
+    pub strip_comments: bool,
+    /// When set, elements that only contain block-level elements put each child on its own
+    /// line, indented with this string for each level of nesting. Inline content and the
+    /// contents of `<pre>` and `<textarea>` are written as-is.
+    pub indent: Option<String>,
+}
+
+impl Default for Html5SerializerOptions {
+    fn default() -> Self {
+        Html5SerializerOptions {
+            scripting_enabled: true,
+            quote_attr_values: AttributeQuoting::Always,
+            quote_char: Some('"'),
+            minimize_boolean_attributes: false,
+            use_trailing_solidus: false,
+            escape_lt_in_attrs: false,
+            escape_rcdata: false,
+            omit_optional_tags: false,
+            strip_whitespace: false,
+            strip_block_whitespace: false,
+            strip_comments: false,
+            indent: None,
+        }
+    }
+}
+
+impl Html5SerializerOptions {
+    /// Options for human-readable output, with block-level elements indented by two spaces
+    pub fn pretty() -> Self {
+        Html5SerializerOptions {
+            indent: Some("  ".into()),
+            ..Default::default()
+        }
+    }
+
+    /// Options for the smallest output that still parses to an equivalent tree: optional tags,
+    /// comments and insignificant whitespace are left out, and attributes are only quoted when
+    /// needed.
+    pub fn minify() -> Self {
+        Html5SerializerOptions {
+            quote_attr_values: AttributeQuoting::WhenNeeded,
+            quote_char: None,
+            omit_optional_tags: true,
+            strip_whitespace: true,
+            strip_block_whitespace: true,
+            strip_comments: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// A single step in the serialization of a tree. The strings are usually borrowed from the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SerializerEvent<'a> {
+    /// A doctype. Empty identifiers are left out of the output.
+    Doctype {
+        name: Cow<'a, str>,
+        public_id: Cow<'a, str>,
+        system_id: Cow<'a, str>,
+    },
+    /// Start of an element. Void elements in the HTML namespace have no matching end tag.
+    StartTag {
+        namespace: Cow<'a, str>,
+        name: Cow<'a, str>,
+        attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    },
+    /// End of an element
+    EndTag {
+        namespace: Cow<'a, str>,
+        name: Cow<'a, str>,
+    },
+    /// Text contents
+    Text(Cow<'a, str>),
+    /// A comment
+    Comment(Cow<'a, str>),
+}
+
+impl SerializerEvent<'_> {
+    /// Returns the event with its own copy of the strings
+    pub fn into_owned(self) -> SerializerEvent<'static> {
+        let owned = |value: Cow<str>| Cow::Owned(value.into_owned());
+        match self {
+            SerializerEvent::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => SerializerEvent::Doctype {
+                name: owned(name),
+                public_id: owned(public_id),
+                system_id: owned(system_id),
+            },
+            SerializerEvent::StartTag {
+                namespace,
+                name,
+                attributes,
+            } => SerializerEvent::StartTag {
+                namespace: owned(namespace),
+                name: owned(name),
+                attributes: attributes
+                    .into_iter()
+                    .map(|(name, value)| (owned(name), owned(value)))
+                    .collect(),
+            },
+            SerializerEvent::EndTag { namespace, name } => SerializerEvent::EndTag {
+                namespace: owned(namespace),
+                name: owned(name),
+            },
+            SerializerEvent::Text(text) => SerializerEvent::Text(owned(text)),
+            SerializerEvent::Comment(data) => SerializerEvent::Comment(owned(data)),
+        }
+    }
+}
+
+/// Serializes (parts of) a document to HTML
+pub struct Html5Serializer<'a> {
+    /// Options that influence the output
+    options: Html5SerializerOptions,
+    /// Elements that are currently open, as (namespace, name)
+    open_elements: Vec<(&'a str, &'a str)>,
+    /// For each event, how it is laid out when pretty printing
+    layouts: Vec<Layout>,
+    /// Quirks mode of the document that is serialized, as a table only closes a p element in a
+    /// document that is not in quirks mode
+    quirks_mode: QuirksMode,
+    /// The HTML written so far
+    output: String,
+}
+
+impl<'a> Html5Serializer<'a> {
+    /// Serializes the whole document
+    pub fn serialize_document(
+        document: &DocumentHandle,
+        options: Option<Html5SerializerOptions>,
+    ) -> String {
+        Self::serialize_children(document, NodeId::root(), options)
+    }
+
+    /// Serializes the children of the given node, as done for the `innerHTML` of an element.
+    /// For a `<template>` element, its template contents are serialized.
+    pub fn serialize_children(
+        document: &DocumentHandle,
+        node_id: NodeId,
+        options: Option<Html5SerializerOptions>,
+    ) -> String {
+        let doc = document.get();
+        let events = Html5Serializer::events(&doc, node_id);
+        Html5Serializer::write_events(&events, options, doc.quirks_mode)
+    }
+
+    /// Serializes the given node itself, as done for the `outerHTML` of an element
+    pub fn serialize_node(
+        document: &DocumentHandle,
+        node_id: NodeId,
+        options: Option<Html5SerializerOptions>,
+    ) -> String {
+        let doc = document.get();
+        let mut events = Vec::new();
+        walk_node(&*doc, node_id, &mut events);
+        Html5Serializer::write_events(&events, options, doc.quirks_mode)
+    }
+
+    /// Walks the children of the given node and returns the events that describe them in tree
+    /// order. The attributes of elements are sorted by name.
+    pub fn events(document: &Document, node_id: NodeId) -> Vec<SerializerEvent<'_>> {
+        let mut events = Vec::new();
+        if let Some(node) = document.get_node_by_id(node_id) {
+            if is_void_element(node) {
+                return events;
+            }
+            walk_children(document, node, &mut events);
+        }
+        events
+    }
+
+    /// Writes the given events as HTML, as they would be written for a document that is not in
+    /// quirks mode
+    pub fn serialize_events(
+        events: &[SerializerEvent],
+        options: Option<Html5SerializerOptions>,
+    ) -> String {
+        Html5Serializer::write_events(events, options, QuirksMode::NoQuirks)
+    }
+
+    /// Writes the given events of a document in the given quirks mode as HTML
+    fn write_events(
+        events: &[SerializerEvent],
+        options: Option<Html5SerializerOptions>,
+        quirks_mode: QuirksMode,
+    ) -> String {
+        let options = options.unwrap_or_default();
+
+        let stripped;
+        let events = if options.strip_comments || options.strip_block_whitespace {
+            stripped = strip_events(events, &options);
+            &stripped
+        } else {
+            events
+        };
+
+        let layouts = if options.indent.is_some() {
+            layouts(events)
+        } else {
+            vec![Layout::Inline; events.len()]
+        };
+
+        let mut serializer = Html5Serializer {
+            options,
+            open_elements: Vec::new(),
+            layouts,
+            quirks_mode,
+            output: String::new(),
+        };
+
+        for idx in 0..events.len() {
+            serializer.write_event(events, idx);
+        }
+
+        serializer.output
+    }
+
+    fn write_event(&mut self, events: &'a [SerializerEvent], idx: usize) {
+        let event = &events[idx];
+        let prev = idx.checked_sub(1).map(|idx| &events[idx]);
+        let next = events.get(idx + 1);
+
+        let layout = self.layouts[idx];
+
+        match event {
+            SerializerEvent::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => {
+                if layout == Layout::NewLine {
+                    self.write_newline(self.open_elements.len());
+                }
+
+                self.output.push_str("<!DOCTYPE ");
+                self.output.push_str(name);
+                if !public_id.is_empty() {
+                    self.output.push_str(&format!(" PUBLIC \"{public_id}\""));
+                    if !system_id.is_empty() {
+                        self.output.push_str(&format!(" \"{system_id}\""));
+                    }
+                } else if !system_id.is_empty() {
+                    self.output.push_str(&format!(" SYSTEM \"{system_id}\""));
+                }
+                self.output.push('>');
+            }
+            SerializerEvent::StartTag {
+                namespace,
+                name,
+                attributes,
+            } => {
+                let depth = self.open_elements.len();
+
+                let is_void = *namespace == HTML_NAMESPACE && VOID_ELEMENTS.contains(&&**name);
+                if !is_void {
+                    self.open_elements.push((namespace, name));
+                }
+
+                if self.options.omit_optional_tags
+                    && attributes.is_empty()
+                    && *namespace == HTML_NAMESPACE
+                    && is_optional_start_tag(name, prev, next)
+                {
+                    return;
+                }
+
+                if layout == Layout::NewLine {
+                    self.write_newline(depth);
+                }
+
+                self.output.push('<');
+                self.output.push_str(name);
+                for (attr_name, attr_value) in attributes {
+                    self.output.push(' ');
+                    self.output.push_str(attr_name);
+                    self.write_attribute_value(attr_name, attr_value);
+                }
+                if is_void && self.options.use_trailing_solidus {
+                    self.output.push_str(" /");
+                }
+                self.output.push('>');
+
+                // The parser drops a newline directly after these start tags, so one is added to
+                // keep a leading newline of the contents.
+                if *namespace == HTML_NAMESPACE
+                    && LEADING_NEWLINE_ELEMENTS.contains(&&**name)
+                    && matches!(next, Some(SerializerEvent::Text(text)) if text.starts_with('\n'))
+                {
+                    self.output.push('\n');
+                }
+            }
+            SerializerEvent::EndTag { namespace, name } => {
+                if self.open_elements.last() == Some(&(&**namespace, &**name)) {
+                    self.open_elements.pop();
+                }
+
+                if !(self.options.omit_optional_tags
+                    && *namespace == HTML_NAMESPACE
+                    && is_optional_end_tag(name, next, self.quirks_mode))
+                {
+                    if layout == Layout::NewLine {
+                        self.write_newline(self.open_elements.len());
+                    }
+
+                    self.output.push_str("</");
+                    self.output.push_str(name);
+                    self.output.push('>');
+                }
+            }
+            SerializerEvent::Text(text) => {
+                if self.in_raw_text() && !self.options.escape_rcdata {
+                    self.output.push_str(text);
+                    return;
+                }
+
+                if layout == Layout::Omitted {
+                    // The whitespace is replaced by a new line and indentation
+                    return;
+                }
+
+                if self.options.strip_whitespace && !in_preserved_whitespace(&self.open_elements) {
+                    let text = collapse_whitespace(text);
+                    escape_text(&text, &mut self.output);
+                } else {
+                    escape_text(text, &mut self.output);
+                }
+            }
+            SerializerEvent::Comment(data) => {
+                if layout == Layout::NewLine {
+                    self.write_newline(self.open_elements.len());
+                }
+
+                self.output.push_str("<!--");
+                self.output.push_str(data);
+                self.output.push_str("-->");
+            }
+        }
+    }
+
+    fn write_attribute_value(&mut self, name: &str, value: &str) {
+        if self.options.minimize_boolean_attributes
+            && (value.is_empty() || value.eq_ignore_ascii_case(name))
+        {
+            return;
+        }
+
+        self.output.push('=');
+
+        let needs_quotes = match self.options.quote_attr_values {
+            AttributeQuoting::Always => true,
+            AttributeQuoting::WhenNeeded => {
+                value.is_empty()
+                    || value.contains([
+                        '\t', '\n', '\u{000C}', '\r', ' ', '"', '\'', '=', '<', '>', '`',
+                    ])
+            }
+            AttributeQuoting::Legacy => {
+                value.is_empty()
+                    || value.contains(['\t', '\n', '\u{000C}', '\r', ' ', '"', '\'', '=', '>', '`'])
+            }
+        };
+
+        let quote = match self.options.quote_char {
+            Some(quote) => quote,
+            None if value.contains('"') && !value.contains('\'') => '\'',
+            None => '"',
+        };
+
+        if needs_quotes {
+            self.output.push(quote);
+        }
+        for c in value.chars() {
+            match c {
+                '&' => self.output.push_str("&amp;"),
+                '\u{00A0}' => self.output.push_str("&nbsp;"),
+                '<' if self.options.escape_lt_in_attrs => self.output.push_str("&lt;"),
+                '"' if needs_quotes && quote == '"' => self.output.push_str("&quot;"),
+                '\'' if needs_quotes && quote == '\'' => self.output.push_str("&#39;"),
+                _ => self.output.push(c),
+            }
+        }
+        if needs_quotes {
+            self.output.push(quote);
+        }
+    }
+
+    /// Starts a new line, indented for the given depth. Nothing is written at the very start of
+    /// the output.
+    fn write_newline(&mut self, depth: usize) {
+        if self.output.is_empty() {
+            return;
+        }
+        if let Some(indent) = &self.options.indent {
+            self.output.push('\n');
+            self.output.push_str(&indent.repeat(depth));
+        }
+    }
+
+    /// Returns true when text is currently written inside a raw text element
+    fn in_raw_text(&self) -> bool {
+        match self.open_elements.last() {
+            Some((namespace, name)) if *namespace == HTML_NAMESPACE => {
+                RAW_TEXT_ELEMENTS.contains(name)
+                    || (*name == "noscript" && self.options.scripting_enabled)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns true when text inside the given open elements keeps its whitespace
+fn in_preserved_whitespace(open_elements: &[(&str, &str)]) -> bool {
+    open_elements.iter().any(|(namespace, name)| {
+        *namespace == HTML_NAMESPACE
+            && (WHITESPACE_PRESERVING_ELEMENTS.contains(name) || RAW_TEXT_ELEMENTS.contains(name))
+    })
+}
+
+/// Returns true when the text only consists of whitespace
+fn is_whitespace(text: &str) -> bool {
+    text.chars()
+        .all(|c| matches!(c, '\t' | '\n' | '\u{000C}' | '\r' | ' '))
+}
+
+/// Returns true when the event starts or ends a block-level element
+fn is_block_event(event: &SerializerEvent) -> bool {
+    match event {
+        SerializerEvent::StartTag {
+            namespace, name, ..
+        }
+        | SerializerEvent::EndTag { namespace, name } => {
+            *namespace == HTML_NAMESPACE && BLOCK_ELEMENTS.contains(&&**name)
+        }
+        _ => false,
+    }
+}
+
+/// Removes comments and whitespace between block-level elements from the events, depending on
+/// the options
+fn strip_events<'a>(
+    events: &[SerializerEvent<'a>],
+    options: &Html5SerializerOptions,
+) -> Vec<SerializerEvent<'a>> {
+    // Whitespace can be removed when it is surrounded by the edges of a block-level element,
+    // comments or a doctype.
+    let is_boundary = |event: Option<&SerializerEvent>| match event {
+        None | Some(SerializerEvent::Comment(_)) | Some(SerializerEvent::Doctype { .. }) => true,
+        Some(event) => is_block_event(event),
+    };
+
+    let mut open_elements: Vec<(&str, &str)> = Vec::new();
+    let mut stripped = Vec::with_capacity(events.len());
+    for (idx, event) in events.iter().enumerate() {
+        match event {
+            SerializerEvent::StartTag {
+                namespace, name, ..
+            } if !(*namespace == HTML_NAMESPACE && VOID_ELEMENTS.contains(&&**name)) => {
+                open_elements.push((namespace, name));
+            }
+            SerializerEvent::EndTag { namespace, name }
+                if open_elements.last() == Some(&(&**namespace, &**name)) =>
+            {
+                open_elements.pop();
+            }
+            SerializerEvent::Comment(_) if options.strip_comments => continue,
+            SerializerEvent::Text(text)
+                if options.strip_block_whitespace
+                    && is_whitespace(text)
+                    && !in_preserved_whitespace(&open_elements)
+                    && (open_elements.last() == Some(&(HTML_NAMESPACE, "head"))
+                        || (is_boundary(idx.checked_sub(1).map(|idx| &events[idx]))
+                            && is_boundary(events.get(idx + 1)))) =>
+            {
+                continue
+            }
+            _ => {}
+        }
+        stripped.push(event.clone());
+    }
+    stripped
+}
+
+/// How an event is laid out when pretty printing
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Layout {
+    /// Written as-is
+    Inline,
+    /// Written on a new, indented line
+    NewLine,
+    /// Whitespace that is left out, as a new line is started in its place
+    Omitted,
+}
+
+/// Decides for every child of an element where a new line is started when pretty printing. A
+/// new line is started between two children when either of them is block-level and neither is
+/// text, so only whitespace that is insignificant for the rendering is added or replaced.
+fn layouts(events: &[SerializerEvent]) -> Vec<Layout> {
+    struct Frame {
+        /// Whether whitespace may be added and removed between the children
+        flexible: bool,
+        /// Whether the element itself is block-level, so new lines can start at its edges
+        block: bool,
+        /// Whether all children are laid out as blocks, as in the head element
+        block_children: bool,
+        /// Index of the first event and the kind of each child
+        children: Vec<(usize, Child)>,
+    }
+
+    /// Sets the layout of the children of the element and of its end tag, if any
+    fn layout_children(frame: &Frame, end_idx: Option<usize>, layouts: &mut [Layout]) {
+        if !frame.flexible {
+            return;
+        }
+
+        // A comment is laid out as a block when the children around it are blocks
+        let items: Vec<_> = frame
+            .children
+            .iter()
+            .filter(|(_, child)| *child != Child::Whitespace)
+            .collect();
+        let mut blocks = Vec::with_capacity(items.len());
+        for (pos, (_, child)) in items.iter().enumerate() {
+            let block = match child {
+                Child::Block => true,
+                Child::Comment => {
+                    let prev_block = blocks.last().copied().unwrap_or(frame.block);
+                    let next_block = items[pos + 1..]
+                        .iter()
+                        .find(|(_, child)| *child != Child::Comment)
+                        .map_or(frame.block, |(_, child)| *child == Child::Block);
+                    prev_block && next_block
+                }
+                _ => false,
+            };
+            blocks.push(block);
+        }
+
+        // Decide for the gap before each child, and the gap before the end tag, whether a new
+        // line starts there. The edges of the element count as blocks for block-level elements.
+        let breaks_at = |gap: usize| {
+            let before = gap.checked_sub(1).map(|pos| (items[pos].1, blocks[pos]));
+            let after = items.get(gap).map(|item| (item.1, blocks[gap]));
+            match (before, after) {
+                (None, None) => false,
+                (None, Some((_, block))) | (Some((_, block)), None) => frame.block && block,
+                (Some((child, block)), Some((next_child, next_block))) => {
+                    (block || next_block) && child != Child::Text && next_child != Child::Text
+                }
+            }
+        };
+
+        let mut gap = 0;
+        for (idx, child) in &frame.children {
+            if *child == Child::Whitespace {
+                if breaks_at(gap) {
+                    layouts[*idx] = Layout::Omitted;
+                }
+                continue;
+            }
+            if breaks_at(gap) {
+                layouts[*idx] = Layout::NewLine;
+            }
+            gap += 1;
+        }
+        if let Some(end_idx) = end_idx {
+            if breaks_at(gap) {
+                layouts[end_idx] = Layout::NewLine;
+            }
+        }
+    }
+
+    let mut layouts = vec![Layout::Inline; events.len()];
+    let mut stack = vec![Frame {
+        flexible: true,
+        block: true,
+        block_children: false,
+        children: Vec::new(),
+    }];
+
+    for (idx, event) in events.iter().enumerate() {
+        let depth = stack.len();
+        let parent = stack.last_mut().expect("top level frame");
+        match event {
+            SerializerEvent::StartTag {
+                namespace, name, ..
+            } => {
+                let block = is_block_event(event);
+                let child = if block || parent.block_children {
+                    Child::Block
+                } else {
+                    Child::Inline
+                };
+                parent.children.push((idx, child));
+
+                let is_html = *namespace == HTML_NAMESPACE;
+                if !(is_html && VOID_ELEMENTS.contains(&&**name)) {
+                    let flexible = parent.flexible
+                        && is_html
+                        && !WHITESPACE_PRESERVING_ELEMENTS.contains(&&**name)
+                        && !RAW_TEXT_ELEMENTS.contains(&&**name);
+                    stack.push(Frame {
+                        flexible,
+                        block,
+                        block_children: is_html && *name == "head",
+                        children: Vec::new(),
+                    });
+                }
+            }
+            SerializerEvent::EndTag { .. } if depth > 1 => {
+                let frame = stack.pop().expect("element frame");
+                layout_children(&frame, Some(idx), &mut layouts);
+            }
+            SerializerEvent::Text(text) if is_whitespace(text) => {
+                parent.children.push((idx, Child::Whitespace))
+            }
+            SerializerEvent::Text(_) => parent.children.push((idx, Child::Text)),
+            SerializerEvent::Comment(_) | SerializerEvent::Doctype { .. } => {
+                parent.children.push((idx, Child::Comment))
+            }
+            _ => {}
+        }
+    }
+
+    while let Some(frame) = stack.pop() {
+        layout_children(&frame, None, &mut layouts);
+    }
+
+    layouts
+}
+
+/// Kind of a child of an element, for the layout when pretty printing
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Child {
+    Block,
+    Inline,
+    Text,
+    Whitespace,
+    Comment,
+}
+
+/// Adds the events for the given node and its descendants
+fn walk_node<'a>(
+    tree: &'a dyn Traversable,
+    node_id: NodeId,
+    events: &mut Vec<SerializerEvent<'a>>,
+) {
+    let Some(node) = tree.node(node_id) else {
+        return;
+    };
+
+    match &node.data {
+        NodeData::Document(_) | NodeData::ShadowRoot(_) | NodeData::DocumentFragment => {
+            walk_children(tree, node, events);
+        }
+        NodeData::DocType(DocTypeData {
+            name,
+            pub_identifier,
+            sys_identifier,
+        }) => events.push(SerializerEvent::Doctype {
+            name: name.into(),
+            public_id: pub_identifier.into(),
+            system_id: sys_identifier.into(),
+        }),
+        NodeData::Text(TextData { value, .. }) => events.push(SerializerEvent::Text(value.into())),
+        NodeData::Comment(CommentData { value, .. }) => {
+            events.push(SerializerEvent::Comment(value.into()))
+        }
+        NodeData::Element(element) => {
+            let namespace = node.namespace.as_deref().unwrap_or(HTML_NAMESPACE);
+
+            let mut attributes: Vec<(Cow<str>, Cow<str>)> = element
+                .attributes
+                .iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect();
+            attributes.sort();
+
+            events.push(SerializerEvent::StartTag {
+                namespace: namespace.into(),
+                name: (&node.name).into(),
+                attributes,
+            });
+
+            if is_void_element(node) {
+                return;
+            }
+
+            walk_children(tree, node, events);
+
+            events.push(SerializerEvent::EndTag {
+                namespace: namespace.into(),
+                name: (&node.name).into(),
+            });
+        }
+    }
+}
+
+/// Adds the events for the children of the given node. For a template element, these are the
+/// children of its template contents. The contents live in a document of their own, so their
+/// events get their own copy of the strings.
+fn walk_children<'a>(
+    tree: &'a dyn Traversable,
+    node: &'a Node,
+    events: &mut Vec<SerializerEvent<'a>>,
+) {
+    if let NodeData::Element(element) = &node.data {
+        if let Some(contents) = &element.template_contents {
+            let contents = contents.contents();
+            let contents = contents.get();
+            let mut contents_events = Vec::new();
+            walk_node(&*contents, NodeId::root(), &mut contents_events);
+            for event in contents_events {
+                events.push(event.into_owned());
+            }
+        }
+    }
+
+    for child_id in &node.children {
+        walk_node(tree, *child_id, events);
+    }
+}
+
+/// Returns true when the node is a void element, which has no end tag and no serialized children
+fn is_void_element(node: &Node) -> bool {
+    matches!(node.data, NodeData::Element(_))
+        && node.namespace.as_deref().unwrap_or(HTML_NAMESPACE) == HTML_NAMESPACE
+        && VOID_ELEMENTS.contains(&node.name.as_str())
+}
+
+/// Escapes text in the same way as the fragment serialization algorithm does
+fn escape_text(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '\u{00A0}' => output.push_str("&nbsp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+/// Collapses each run of whitespace into a single space
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        if matches!(c, '\t' | '\n' | '\u{000C}' | '\r' | ' ') {
+            if !in_whitespace {
+                result.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            result.push(c);
+            in_whitespace = false;
+        }
+    }
+    result
+}
+
+/// Returns true when the event is text that starts with whitespace
+fn starts_with_whitespace(event: Option<&SerializerEvent>) -> bool {
+    matches!(event, Some(SerializerEvent::Text(text)) if text.starts_with(['\t', '\n', '\u{000C}', '\r', ' ']))
+}
+
+/// Returns true when the start tag of the element may be left out. See
+/// https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+fn is_optional_start_tag(
+    name: &str,
+    prev: Option<&SerializerEvent>,
+    next: Option<&SerializerEvent>,
+) -> bool {
+    match name {
+        // An html element's start tag may be omitted if the first thing inside the html element
+        // is not a comment (or whitespace, which the parser would drop)
+        "html" => {
+            !matches!(next, Some(SerializerEvent::Comment(_))) && !starts_with_whitespace(next)
+        }
+        // A head element's start tag may be omitted if the element is empty, or if the first
+        // thing inside the head element is an element.
+        "head" => match next {
+            Some(SerializerEvent::StartTag { .. }) => true,
+            Some(SerializerEvent::EndTag { name, .. }) => *name == "head",
+            _ => false,
+        },
+        // A body element's start tag may be omitted if the element is empty, or if the first
+        // thing inside the body element is not whitespace or a comment, except if it is an
+        // element that the parser would put in the head.
+        "body" => match next {
+            Some(SerializerEvent::Comment(_)) => false,
+            Some(SerializerEvent::StartTag { name, .. }) => {
+                !["meta", "noscript", "link", "script", "style", "template"].contains(&&**name)
+            }
+            _ => !starts_with_whitespace(next),
+        },
+        // A colgroup element's start tag may be omitted if the first thing inside the colgroup
+        // element is a col element. The end tag of a preceding colgroup is never omitted.
+        "colgroup" => {
+            matches!(next, Some(SerializerEvent::StartTag { name, .. }) if *name == "col")
+        }
+        // A tbody element's start tag may be omitted if the first thing inside the tbody element
+        // is a tr element, and if the element is not immediately preceded by a tbody, thead or
+        // tfoot element whose end tag has been omitted.
+        "tbody" => {
+            if matches!(prev, Some(SerializerEvent::EndTag { name, .. }) if ["tbody", "thead", "tfoot"].contains(&&**name))
+            {
+                return false;
+            }
+            matches!(next, Some(SerializerEvent::StartTag { name, .. }) if *name == "tr")
+        }
+        _ => false,
+    }
+}
+
+/// Returns true when the end tag of the element may be left out. See
+/// https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+fn is_optional_end_tag(
+    name: &str,
+    next: Option<&SerializerEvent>,
+    quirks_mode: QuirksMode,
+) -> bool {
+    // Most end tags can be omitted when there is no more content in the parent element
+    let at_end_of_parent = matches!(next, None | Some(SerializerEvent::EndTag { .. }));
+    let next_start_tag = match next {
+        Some(SerializerEvent::StartTag { name, .. }) => Some(&**name),
+        _ => None,
+    };
+
+    match name {
+        // The end tag of html, head and body may be omitted if the element is not immediately
+        // followed by whitespace or a comment.
+        "html" | "head" | "body" => {
+            !matches!(next, Some(SerializerEvent::Comment(_))) && !starts_with_whitespace(next)
+        }
+        "li" | "optgroup" | "tr" => match next_start_tag {
+            Some(next_name) => next_name == name,
+            None => at_end_of_parent,
+        },
+        "dt" => matches!(next_start_tag, Some("dt" | "dd")),
+        "dd" => match next_start_tag {
+            Some(next_name) => ["dt", "dd"].contains(&next_name),
+            None => at_end_of_parent,
+        },
+        // A p element's end tag may also be omitted at the end of its parent, unless the parent
+        // is an element like a, where the parser would not close the p element. In quirks mode,
+        // a table is put inside the p element instead of closing it.
+        "p" => match (next_start_tag, next) {
+            (Some("table"), _) => quirks_mode != QuirksMode::Quirks,
+            (Some(next_name), _) => P_CLOSING_ELEMENTS.contains(&next_name),
+            (None, Some(SerializerEvent::EndTag { name, .. })) => {
+                !P_END_TAG_PARENTS.contains(&&**name) && !name.contains('-')
+            }
+            (None, next) => next.is_none(),
+        },
+        "option" => match next_start_tag {
+            Some(next_name) => ["option", "optgroup"].contains(&next_name),
+            None => at_end_of_parent,
+        },
+        "rt" | "rp" => match next_start_tag {
+            Some(next_name) => ["rt", "rp"].contains(&next_name),
+            None => at_end_of_parent,
+        },
+        // A colgroup element's end tag may be omitted if the colgroup element is not immediately
+        // followed by whitespace or a comment. We keep it when another colgroup follows, as
+        // that colgroup's start tag could otherwise not be omitted.
+        "colgroup" => match next {
+            Some(SerializerEvent::Comment(_)) => false,
+            Some(SerializerEvent::StartTag { name, .. }) => *name != "colgroup",
+            _ => !starts_with_whitespace(next),
+        },
+        // We never omit the end tag when a tbody follows, so the start tag of that tbody can
+        // be omitted instead.
+        "thead" => matches!(next_start_tag, Some("tbody" | "tfoot")),
+        "tbody" => match next_start_tag {
+            Some(next_name) => ["tbody", "tfoot"].contains(&next_name),
+            None => at_end_of_parent,
+        },
+        "tfoot" => match next_start_tag {
+            Some(next_name) => next_name == "tbody",
+            None => at_end_of_parent,
+        },
+        "td" | "th" => match next_start_tag {
+            Some(next_name) => ["td", "th"].contains(&next_name),
+            None => at_end_of_parent,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::CharIterator;
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::parser::Html5Parser;
+    use crate::testing::document::parse_document;
+
+    fn serialize(html: &str) -> String {
+        let document = parse_document(html);
+        Html5Serializer::serialize_document(&document, None)
+    }
+
+    #[test]
+    fn serialize_document() {
+        assert_eq!(
+            serialize("<!DOCTYPE html><title>a&b</title><p class=x id='y'>Hello<br>world"),
+            "<!DOCTYPE html><html><head><title>a&amp;b</title></head><body><p class=\"x\" id=\"y\">Hello<br>world</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn serialize_raw_text_and_attributes() {
+        assert_eq!(
+            serialize("<!DOCTYPE html><script>if (a < b && c) {}</script><div title='say \"hi\" &amp; bye\u{a0}'>\u{a0}&lt;>"),
+            "<!DOCTYPE html><html><head><script>if (a < b && c) {}</script></head><body><div title=\"say &quot;hi&quot; &amp; bye&nbsp;\">&nbsp;&lt;&gt;</div></body></html>"
+        );
+    }
+
+    #[test]
+    fn serialize_template_and_foreign_elements() {
+        assert_eq!(
+            serialize("<!DOCTYPE html><template><td>cell</template><svg viewBox='0 0 1 1'><path/><foreignObject><b>x</b></foreignObject></svg><math><mi>x</mi></math>"),
+            "<!DOCTYPE html><html><head><template><td>cell</td></template></head><body><svg viewBox=\"0 0 1 1\"><path></path><foreignObject><b>x</b></foreignObject></svg><math><mi>x</mi></math></body></html>"
+        );
+    }
+
+    #[test]
+    fn serialize_children() {
+        let document = parse_document("<!DOCTYPE html><ul><li>one<li>two</ul>");
+
+        let html_id = document.get().get_root().children[1];
+        let body_id = *document
+            .get()
+            .get_node_by_id(html_id)
+            .unwrap()
+            .children
+            .last()
+            .unwrap();
+
+        let options = Html5SerializerOptions {
+            omit_optional_tags: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Html5Serializer::serialize_children(&document, body_id, Some(options)),
+            "<ul><li>one<li>two</ul>"
+        );
+    }
+
+    const ROUND_TRIP_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Test  page</title>
+</head>
+<body>
+<!-- navigation -->
+<ul class="menu">
+  <li><a href="/">Home</a></li>
+  <li><a href="/about us">About <b>us</b></a></li>
+</ul>
+<div id=main>
+<p>Some   <em>inline</em> text.</p>
+<p>Another paragraph</p>
+<a href=x><p>in a link</p></a>
+<pre>
+
+  keep   this
+</pre>
+<form><textarea>  and this  </textarea></form>
+</div>
+<table><tr><td>cell</td><td>cell 2</td></tr></table>
+</body>
+</html>
+"#;
+
+    /// Writes the tree in a form that leaves out differences that do not matter for the
+    /// rendering: comments, whitespace-only text and the length of whitespace runs in text
+    fn normalized(document: &DocumentHandle) -> String {
+        fn walk(document: &Document, node_id: NodeId, depth: usize, output: &mut String) {
+            let node = document.get_node_by_id(node_id).unwrap();
+            match &node.data {
+                NodeData::Text(TextData { value, .. }) if !is_whitespace(value) => {
+                    output.push_str(&format!(
+                        "{}{:?}\n",
+                        " ".repeat(depth),
+                        collapse_whitespace(value)
+                    ));
+                }
+                NodeData::Element(element) => {
+                    let mut attributes: Vec<_> = element.attributes.iter().collect();
+                    attributes.sort();
+                    output.push_str(&format!(
+                        "{}<{} {:?}>\n",
+                        " ".repeat(depth),
+                        node.name,
+                        attributes
+                    ));
+                }
+                NodeData::DocType(DocTypeData { name, .. }) => {
+                    output.push_str(&format!("<!DOCTYPE {name}>\n"));
+                }
+                _ => {}
+            }
+            for child_id in &node.children {
+                walk(document, *child_id, depth + 1, output);
+            }
+        }
+
+        let mut output = String::new();
+        walk(&document.get(), NodeId::root(), 0, &mut output);
+        output
+    }
+
+    #[test]
+    fn pretty_print() {
+        let document = parse_document(ROUND_TRIP_HTML);
+        let pretty =
+            Html5Serializer::serialize_document(&document, Some(Html5SerializerOptions::pretty()));
+
+        assert_eq!(
+            pretty,
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <title>Test  page</title>
+  </head>
+  <body>
+    <!-- navigation -->
+    <ul class="menu">
+      <li><a href="/">Home</a></li>
+      <li><a href="/about us">About <b>us</b></a></li>
+    </ul>
+    <div id="main">
+      <p>Some   <em>inline</em> text.</p>
+      <p>Another paragraph</p>
+      <a href="x"><p>in a link</p></a>
+      <pre>
+
+  keep   this
+</pre>
+      <form><textarea>  and this  </textarea></form>
+    </div>
+    <table>
+      <tbody>
+        <tr>
+          <td>cell</td>
+          <td>cell 2</td>
+        </tr>
+      </tbody>
+    </table>
+  </body>
+</html>"#
+        );
+        assert_eq!(normalized(&parse_document(&pretty)), normalized(&document));
+    }
+
+    #[test]
+    fn pretty_print_mixed_contents() {
+        let document = parse_document(
+            "<!DOCTYPE html><div id=a>text<p>block</p> <b>x</b><script>if (a < b) {}</script> <i>y</i></div>",
+        );
+        let pretty = Html5Serializer::serialize_children(
+            &document,
+            NodeId::root(),
+            Some(Html5SerializerOptions::pretty()),
+        );
+
+        // New lines only replace the whitespace next to the block, and the script stays inline.
+        // The inline contents at the end of the div are not followed by a new line.
+        assert!(pretty.contains(concat!(
+            "  <body>\n",
+            "    <div id=\"a\">text<p>block</p>\n",
+            "      <b>x</b><script>if (a < b) {}</script> <i>y</i></div>\n",
+            "  </body>"
+        )));
+        assert_eq!(normalized(&parse_document(&pretty)), normalized(&document));
+    }
+
+    #[test]
+    fn minify() {
+        let document = parse_document(ROUND_TRIP_HTML);
+        let minified =
+            Html5Serializer::serialize_document(&document, Some(Html5SerializerOptions::minify()));
+
+        assert_eq!(
+            minified,
+            concat!(
+                "<!DOCTYPE html><html lang=en><meta charset=utf-8><title>Test page</title>",
+                "<ul class=menu><li><a href=/>Home</a><li><a href=\"/about us\">About <b>us</b></a></ul>",
+                "<div id=main><p>Some <em>inline</em> text.<p>Another paragraph</p> ",
+                "<a href=x><p>in a link</p></a> <pre>\n\n  keep   this\n</pre>",
+                "<form><textarea>  and this  </textarea></form></div>",
+                "<table><tr><td>cell<td>cell 2</table>"
+            )
+        );
+        assert_eq!(
+            normalized(&parse_document(&minified)),
+            normalized(&document)
+        );
+    }
+
+    #[test]
+    fn minify_in_quirks_mode() {
+        fn parse_quirks_document(html: &str) -> DocumentHandle {
+            let mut chars = CharIterator::new();
+            chars.read_from_str(html, None);
+            let document = DocumentBuilder::new_document();
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+            assert_eq!(document.get().quirks_mode, QuirksMode::Quirks);
+            document
+        }
+
+        // Without a doctype, the table would end up inside the p element
+        let document = parse_quirks_document("<p>x</p><table><tr><td>a</td></tr></table>");
+        let minified =
+            Html5Serializer::serialize_document(&document, Some(Html5SerializerOptions::minify()));
+
+        assert_eq!(minified, "<p>x</p><table><tr><td>a</table>");
+        assert_eq!(
+            normalized(&parse_quirks_document(&minified)),
+            normalized(&document)
+        );
+    }
+}
@@ -5,12 +5,13 @@ mod character_reference;
 mod replacement_tables;
 
 use crate::bytes::Bytes::{self, *};
-use crate::bytes::{CharIterator, Position};
+use crate::bytes::{CharIterator, Position, Span};
 use crate::html5::error_logger::{ErrorLogger, ParserError};
 use crate::html5::node::HTML_NAMESPACE;
+use crate::html5::parser::Html5ParserLimits;
 use crate::html5::tokenizer::state::State;
-use crate::html5::tokenizer::token::Token;
-use crate::types::{Error, Result};
+use crate::html5::tokenizer::token::{SpannedToken, Token};
+use crate::types::{Error, ErrorTrigger, Limit, ParseError, Result};
 use std::cell::{Ref, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -43,16 +44,67 @@ pub struct Tokenizer<'stream> {
     /// Temporary buffer
     pub temporary_buffer: String,
     /// Queue of emitted tokens. Needed because we can generate multiple tokens during iteration
-    pub token_queue: Vec<Token>,
+    pub token_queue: Vec<SpannedToken>,
     /// The last emitted start token (or empty if none)
     pub last_start_token: String,
     /// Error logger to log errors to
     pub error_logger: Rc<RefCell<ErrorLogger>>,
+    /// Last point in a text state where we can safely suspend when the input stream runs out
+    text_checkpoint: Option<TextCheckpoint>,
+    /// Position where the text that is currently consumed starts
+    text_start: Position,
+    /// Position where the markup (tag, comment etc) that is currently tokenized starts
+    markup_start: Position,
+    /// Position where the current attribute starts
+    current_attr_start: Position,
+    /// Position directly after the current attribute
+    current_attr_end: Position,
+    /// Spans of the attributes of the current token
+    current_attr_spans: HashMap<String, Span>,
+    /// Span of the last token that has been returned
+    last_span: Span,
+    /// True when the iterator has returned the EOF token
+    eof_emitted: bool,
+    /// Limits on the attributes and text of the tokens
+    limits: Html5ParserLimits,
+}
+
+/// Tokenizer state at the start of a token. When an unfinished input stream runs out in the middle
+/// of a token, the tokenizer rolls back to this state and reads the token again once more input
+/// has been appended.
+struct Checkpoint {
+    state: State,
+    position: Position,
+    consumed: String,
+    current_token: Option<Token>,
+    current_attr_name: String,
+    current_attr_value: String,
+    current_attrs: HashMap<String, String>,
+    temporary_buffer: String,
+    last_start_token: String,
+    text_start: Position,
+    markup_start: Position,
+    current_attr_spans: HashMap<String, Span>,
+    error_count: usize,
+}
+
+/// Position in one of the text states (data, rcdata etc). All text that has been consumed up to
+/// this point can be emitted when the input stream runs out, instead of waiting for more input.
+struct TextCheckpoint {
+    state: State,
+    position: Position,
+    consumed_len: usize,
+    error_count: usize,
 }
 
 impl<'stream> Tokenizer<'stream> {
+    /// Inserts tokens at the start of the queue. The tokens are split from the last returned
+    /// token, so they get the span of that token.
     pub(crate) fn insert_tokens_at_queue_start(&mut self, first_tokens: Vec<Token>) {
-        let mut new_queue = first_tokens.clone();
+        let mut new_queue: Vec<SpannedToken> = first_tokens
+            .into_iter()
+            .map(|token| SpannedToken::new(token, self.last_span))
+            .collect();
         new_queue.extend(self.token_queue.iter().cloned());
 
         self.token_queue = new_queue;
@@ -106,6 +158,8 @@ impl<'stream> Tokenizer<'stream> {
         opts: Option<Options>,
         error_logger: Rc<RefCell<ErrorLogger>>,
     ) -> Self {
+        let position = chars.position;
+
         return Tokenizer {
             chars,
             state: opts.as_ref().map_or(State::Data, |o| o.initial_state),
@@ -118,23 +172,183 @@ impl<'stream> Tokenizer<'stream> {
             current_attrs: HashMap::new(),
             temporary_buffer: String::new(),
             error_logger,
+            text_checkpoint: None,
+            text_start: position,
+            markup_start: position,
+            current_attr_start: position,
+            current_attr_end: position,
+            current_attr_spans: HashMap::new(),
+            last_span: Span::new(position, position),
+            eof_emitted: false,
+            limits: Html5ParserLimits::default(),
         };
     }
 
+    /// Resets the tokenizer to its initial state, so the input stream can be tokenized again
+    pub(crate) fn reset(&mut self) {
+        self.state = State::Data;
+        self.last_start_token.clear();
+        self.consumed.clear();
+        self.current_token = None;
+        self.token_queue.clear();
+        self.current_attr_name.clear();
+        self.current_attr_value.clear();
+        self.current_attrs.clear();
+        self.temporary_buffer.clear();
+        self.text_checkpoint = None;
+        self.current_attr_spans.clear();
+        self.eof_emitted = false;
+
+        let position = self.chars.position;
+        self.text_start = position;
+        self.markup_start = position;
+        self.last_span = Span::new(position, position);
+    }
+
+    /// Sets the limits on the number and length of attributes and the length of text. Tokenizing
+    /// stops with Error::LimitExceeded as soon as a token exceeds a limit.
+    pub(crate) fn set_limits(&mut self, limits: Html5ParserLimits) {
+        self.limits = limits;
+    }
+
     /// Returns the current position in the stream (with line/col number and position)
-    pub(crate) fn get_position(&self) -> Position {
+    pub fn get_position(&self) -> Position {
         self.chars.position
     }
 
-    /// Retrieves the next token from the input stream or Token::EOF when the end is reached
+    /// Retrieves the next token from the input stream or Token::EOF when the end is reached. Note
+    /// that Token::EOF is also returned when the input of a stream that is not yet closed runs
+    /// out. Use try_next_token() to tell these apart.
     pub fn next_token(&mut self, parser_data: ParserData) -> Result<Token> {
-        self.consume_stream(parser_data)?;
+        Ok(self.try_next_token(parser_data)?.unwrap_or(Token::Eof))
+    }
+
+    /// Retrieves the next token from the input stream, or None when the input stream is not yet
+    /// closed and more input is needed to complete the next token. Tokenizing continues where it
+    /// left off once more input has been appended to the stream.
+    pub fn try_next_token(&mut self, parser_data: ParserData) -> Result<Option<Token>> {
+        Ok(self
+            .try_next_spanned_token(parser_data)?
+            .map(|spanned| spanned.token))
+    }
+
+    /// Same as try_next_token(), but also returns the span of the token and, for start tags, the
+    /// spans of its attributes in the input stream.
+    pub fn try_next_spanned_token(
+        &mut self,
+        parser_data: ParserData,
+    ) -> Result<Option<SpannedToken>> {
+        if self.token_queue.is_empty() && !self.chars.is_complete() {
+            let checkpoint = self.checkpoint();
+            self.text_checkpoint = None;
+
+            self.consume_stream(parser_data)?;
+
+            if self.chars.needs_input() {
+                self.rollback(checkpoint);
+            }
+        } else {
+            self.consume_stream(parser_data)?;
+        }
 
         if self.token_queue.is_empty() {
-            return Ok(Token::Eof);
+            if self.chars.is_complete() {
+                let position = self.chars.position;
+                return Ok(Some(SpannedToken::new(
+                    Token::Eof,
+                    Span::new(position, position),
+                )));
+            }
+            return Ok(None);
+        }
+
+        let spanned = self.token_queue.remove(0);
+        self.last_span = spanned.span;
+
+        // The last attribute is only stored when the start tag is emitted
+        if let (Some(max), Token::StartTag { attributes, .. }) =
+            (self.limits.max_attributes, &spanned.token)
+        {
+            if attributes.len() > max {
+                return Err(Error::LimitExceeded(Limit::Attributes(max)));
+            }
+        }
+
+        Ok(Some(spanned))
+    }
+
+    /// Saves the current state of the tokenizer
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            state: self.state,
+            position: self.chars.position,
+            consumed: self.consumed.clone(),
+            current_token: self.current_token.clone(),
+            current_attr_name: self.current_attr_name.clone(),
+            current_attr_value: self.current_attr_value.clone(),
+            current_attrs: self.current_attrs.clone(),
+            temporary_buffer: self.temporary_buffer.clone(),
+            last_start_token: self.last_start_token.clone(),
+            text_start: self.text_start,
+            markup_start: self.markup_start,
+            current_attr_spans: self.current_attr_spans.clone(),
+            error_count: self.error_logger.borrow().error_count(),
+        }
+    }
+
+    /// Undoes everything that has been tokenized since the given checkpoint, because the input ran
+    /// out halfway. When we passed through a text state, we only roll back to that point and emit
+    /// the text consumed so far.
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.last_start_token = checkpoint.last_start_token;
+
+        if let Some(text_checkpoint) = self.text_checkpoint.take() {
+            // Consumed text has either been emitted as the first token, or is still in the buffer
+            let mut text = match self.token_queue.first().map(|spanned| &spanned.token) {
+                Some(Token::Text(value)) => value.clone(),
+                _ => std::mem::take(&mut self.consumed),
+            };
+            text.truncate(text_checkpoint.consumed_len);
+
+            self.state = text_checkpoint.state;
+            self.chars.seek(text_checkpoint.position);
+            self.error_logger
+                .borrow_mut()
+                .truncate(text_checkpoint.error_count);
+
+            self.consumed.clear();
+            self.current_token = None;
+            self.current_attr_name.clear();
+            self.current_attr_value.clear();
+            self.current_attrs.clear();
+            self.temporary_buffer.clear();
+            self.current_attr_spans.clear();
+            self.token_queue.clear();
+
+            if !text.is_empty() {
+                let span = Span::new(self.text_start, text_checkpoint.position);
+                self.token_queue
+                    .push(SpannedToken::new(Token::Text(text), span));
+            }
+            return;
         }
 
-        Ok(self.token_queue.remove(0))
+        self.state = checkpoint.state;
+        self.chars.seek(checkpoint.position);
+        self.error_logger
+            .borrow_mut()
+            .truncate(checkpoint.error_count);
+
+        self.consumed = checkpoint.consumed;
+        self.current_token = checkpoint.current_token;
+        self.current_attr_name = checkpoint.current_attr_name;
+        self.current_attr_value = checkpoint.current_attr_value;
+        self.current_attrs = checkpoint.current_attrs;
+        self.temporary_buffer = checkpoint.temporary_buffer;
+        self.text_start = checkpoint.text_start;
+        self.markup_start = checkpoint.markup_start;
+        self.current_attr_spans = checkpoint.current_attr_spans;
+        self.token_queue.clear();
     }
 
     /// Returns the error logger
@@ -142,8 +356,9 @@ impl<'stream> Tokenizer<'stream> {
         self.error_logger.borrow()
     }
 
-    /// Sets the tokenizer state to a new state
-    pub(crate) fn set_state(&mut self, state: State) {
+    /// Sets the tokenizer state to a new state. This is normally done by the tree builder, for
+    /// instance after a script start tag.
+    pub fn set_state(&mut self, state: State) {
         self.state = state;
     }
 
@@ -155,6 +370,48 @@ impl<'stream> Tokenizer<'stream> {
                 return Ok(());
             }
 
+            self.check_limits()?;
+
+            // Remember where the text states start, so we can suspend there when the input of the
+            // stream runs out
+            if !self.chars.is_complete()
+                && !self.chars.needs_input()
+                && matches!(
+                    self.state,
+                    State::Data
+                        | State::RCDATA
+                        | State::RAWTEXT
+                        | State::ScriptData
+                        | State::PLAINTEXT
+                )
+            {
+                self.text_checkpoint = Some(TextCheckpoint {
+                    state: self.state,
+                    position: self.chars.position,
+                    consumed_len: self.consumed.len(),
+                    error_count: self.error_logger.borrow().error_count(),
+                });
+            }
+
+            // Keep track of where text and markup start, so we can emit spans for the tokens. Any
+            // markup starts with a '<' that is read in one of these states.
+            if matches!(
+                self.state,
+                State::Data
+                    | State::RCDATA
+                    | State::RAWTEXT
+                    | State::ScriptData
+                    | State::PLAINTEXT
+                    | State::ScriptDataEscaped
+                    | State::ScriptDataEscapedDash
+                    | State::ScriptDataEscapedDashDash
+            ) {
+                self.markup_start = self.chars.position;
+                if self.consumed.is_empty() {
+                    self.text_start = self.chars.position;
+                }
+            }
+
             match self.state {
                 State::Data => {
                     let c = self.read_char();
@@ -966,6 +1223,7 @@ impl<'stream> Tokenizer<'stream> {
                     }
                 }
                 State::BeforeAttributeName => {
+                    let start = self.chars.position;
                     let c = self.read_char();
                     match c {
                         Ch(CHAR_TAB | CHAR_LF | CHAR_FF | CHAR_SPACE) => {
@@ -979,6 +1237,7 @@ impl<'stream> Tokenizer<'stream> {
                             self.parse_error(ParserError::UnexpectedEqualsSignBeforeAttributeName);
 
                             self.store_and_clear_current_attribute();
+                            self.current_attr_start = start;
                             self.current_attr_name.push(c.into());
 
                             self.state = State::AttributeName;
@@ -986,6 +1245,7 @@ impl<'stream> Tokenizer<'stream> {
                         _ => {
                             // Store an existing attribute if any and clear
                             self.store_and_clear_current_attribute();
+                            self.current_attr_start = start;
 
                             self.chars.unread();
                             self.state = State::AttributeName;
@@ -993,6 +1253,7 @@ impl<'stream> Tokenizer<'stream> {
                     }
                 }
                 State::AttributeName => {
+                    self.current_attr_end = self.chars.position;
                     let c = self.read_char();
                     match c {
                         Ch(CHAR_TAB | CHAR_LF | CHAR_FF | CHAR_SPACE | '/' | '>') | Eof => {
@@ -1044,6 +1305,7 @@ impl<'stream> Tokenizer<'stream> {
                         _ => {
                             self.store_and_clear_current_attribute();
                             self.chars.unread();
+                            self.current_attr_start = self.chars.position;
                             self.state = State::AttributeName;
                         }
                     }
@@ -1075,7 +1337,10 @@ impl<'stream> Tokenizer<'stream> {
                 State::AttributeValueDoubleQuoted => {
                     let c = self.read_char();
                     match c {
-                        Ch('"') => self.state = State::AfterAttributeValueQuoted,
+                        Ch('"') => {
+                            self.current_attr_end = self.chars.position;
+                            self.state = State::AfterAttributeValueQuoted;
+                        }
                         Ch('&') => {
                             self.consume_character_reference(Some(Ch('"')), true);
                         }
@@ -1095,7 +1360,10 @@ impl<'stream> Tokenizer<'stream> {
                 State::AttributeValueSingleQuoted => {
                     let c = self.read_char();
                     match c {
-                        Ch('\'') => self.state = State::AfterAttributeValueQuoted,
+                        Ch('\'') => {
+                            self.current_attr_end = self.chars.position;
+                            self.state = State::AfterAttributeValueQuoted;
+                        }
                         Ch('&') => {
                             self.consume_character_reference(Some(Ch('\'')), true);
                         }
@@ -1113,6 +1381,7 @@ impl<'stream> Tokenizer<'stream> {
                     }
                 }
                 State::AttributeValueUnquoted => {
+                    self.current_attr_end = self.chars.position;
                     let c = self.read_char();
                     match c {
                         Ch(CHAR_TAB | CHAR_LF | CHAR_FF | CHAR_SPACE) => {
@@ -2099,13 +2368,57 @@ impl<'stream> Tokenizer<'stream> {
         // If there is any consumed data, emit this first as a text token
         if self.has_consumed_data() {
             let value = self.get_consumed_str().to_string();
+            let span = Span::new(self.text_start, self.markup_start);
 
-            self.token_queue.push(Token::Text(value.to_string()));
+            self.token_queue
+                .push(SpannedToken::new(Token::Text(value), span));
 
             self.clear_consume_buffer();
         }
 
-        self.token_queue.push(token);
+        let span = match &token {
+            Token::Eof => Span::new(self.chars.position, self.chars.position),
+            _ => Span::new(self.markup_start, self.chars.position),
+        };
+
+        let attribute_spans = match &token {
+            Token::StartTag { .. } => std::mem::take(&mut self.current_attr_spans),
+            Token::EndTag { .. } => {
+                self.current_attr_spans.clear();
+                HashMap::new()
+            }
+            _ => HashMap::new(),
+        };
+
+        self.token_queue.push(SpannedToken {
+            token,
+            span,
+            attribute_spans,
+        });
+    }
+
+    /// Checks the text and attributes that are consumed so far against the limits, so tokenizing
+    /// stops before a huge token has been read completely
+    fn check_limits(&self) -> Result<()> {
+        if let Some(max) = self.limits.max_text_length {
+            if self.consumed.len() > max {
+                return Err(Error::LimitExceeded(Limit::TextLength(max)));
+            }
+        }
+
+        if let Some(max) = self.limits.max_attribute_length {
+            if self.current_attr_name.len() > max || self.current_attr_value.len() > max {
+                return Err(Error::LimitExceeded(Limit::AttributeLength(max)));
+            }
+        }
+
+        if let Some(max) = self.limits.max_attributes {
+            if self.current_attrs.len() > max {
+                return Err(Error::LimitExceeded(Limit::Attributes(max)));
+            }
+        }
+
+        Ok(())
     }
 
     // Consumes the given character
@@ -2151,13 +2464,18 @@ impl<'stream> Tokenizer<'stream> {
     }
 
     /// Creates a parser log error message
-    pub(crate) fn parse_error(&mut self, message: ParserError) {
+    pub(crate) fn parse_error(&mut self, code: ParserError) {
         // The previous position is where the error occurred
         let pos = self.chars.get_previous_position();
-
-        self.error_logger
-            .borrow_mut()
-            .add_error(pos, message.as_str());
+        let span = Span::new(self.markup_start, self.chars.position);
+
+        self.error_logger.borrow_mut().add_error(ParseError::new(
+            code,
+            code.as_str(),
+            ErrorTrigger::State(self.state),
+            pos,
+            span,
+        ));
     }
 
     /// Set is_closing_tag in current token
@@ -2220,6 +2538,10 @@ impl<'stream> Tokenizer<'stream> {
         if !self.current_attr_name.is_empty()
             && !self.current_attrs.contains_key(&self.current_attr_name)
         {
+            self.current_attr_spans.insert(
+                self.current_attr_name.clone(),
+                Span::new(self.current_attr_start, self.current_attr_end),
+            );
             self.current_attrs.insert(
                 self.current_attr_name.clone(),
                 self.current_attr_value.clone(),
@@ -2253,3 +2575,113 @@ impl<'stream> Tokenizer<'stream> {
         }
     }
 }
+
+/// Iterates over all tokens in the input stream together with their spans, up to and including
+/// the EOF token. The iterator stops early when the input stream is not yet closed and more input
+/// is needed. A tokenizer error is returned as the last item. Tokens are read as if they are found
+/// in HTML content, so the caller is responsible for switching the state (with set_state()) after
+/// elements like `<script>` or `<textarea>`.
+impl Iterator for Tokenizer<'_> {
+    type Item = Result<SpannedToken>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.eof_emitted {
+            return None;
+        }
+
+        match self.try_next_spanned_token(ParserData::default()) {
+            Ok(Some(spanned)) => {
+                if spanned.token.is_eof() {
+                    self.eof_emitted = true;
+                }
+                Some(Ok(spanned))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                self.eof_emitted = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(input: &str) -> Vec<SpannedToken> {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(input, None);
+
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        Tokenizer::new(&mut chars, None, error_logger)
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    /// Returns the start and end offsets of the given span
+    fn offsets(span: Span) -> (usize, usize) {
+        (span.start.offset, span.end.offset)
+    }
+
+    #[test]
+    fn token_spans() {
+        let tokens = tokenize("<!DOCTYPE html>\n<p class=x>a &amp; b</p><!-- c --><br/>");
+
+        let spans: Vec<_> = tokens.iter().map(|spanned| offsets(spanned.span)).collect();
+        assert_eq!(
+            spans,
+            vec![
+                (0, 15),
+                (15, 16),
+                (16, 27),
+                (27, 36),
+                (36, 40),
+                (40, 50),
+                (50, 55),
+                (55, 55),
+            ]
+        );
+        assert!(tokens.last().unwrap().token.is_eof());
+
+        // Line and column numbers are available as well
+        let span = tokens[2].span;
+        assert_eq!((span.start.line, span.start.col), (2, 1));
+        assert_eq!((span.end.line, span.end.col), (2, 12));
+    }
+
+    #[test]
+    fn attribute_spans() {
+        let tokens = tokenize("text<a href=\"x\" hidden data-x=y title='t'></a>");
+        assert_eq!(offsets(tokens[0].span), (0, 4));
+        assert!(tokens[0].attribute_spans.is_empty());
+
+        let start_tag = &tokens[1];
+        assert!(start_tag.token.is_start_tag("a"));
+        assert_eq!(offsets(start_tag.span), (4, 42));
+
+        let spans = &start_tag.attribute_spans;
+        assert_eq!(offsets(spans["href"]), (7, 15));
+        assert_eq!(offsets(spans["hidden"]), (16, 22));
+        assert_eq!(offsets(spans["data-x"]), (23, 31));
+        assert_eq!(offsets(spans["title"]), (32, 41));
+
+        assert!(tokens[2].attribute_spans.is_empty());
+        assert!(tokens[3].token.is_eof());
+        assert_eq!(tokens.len(), 4);
+    }
+
+    #[test]
+    fn script_data_spans() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<script><!-- a < b --></script>", None);
+
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let mut tokenizer = Tokenizer::new(&mut chars, None, error_logger);
+
+        assert_eq!(offsets(tokenizer.next().unwrap().unwrap().span), (0, 8));
+        tokenizer.set_state(State::ScriptData);
+        assert_eq!(offsets(tokenizer.next().unwrap().unwrap().span), (8, 22));
+        assert_eq!(offsets(tokenizer.next().unwrap().unwrap().span), (22, 31));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::bytes::Span;
 use crate::html5::tokenizer::CHAR_NUL;
 use std::collections::HashMap;
 
@@ -30,6 +31,38 @@ pub enum Token {
     Eof,
 }
 
+/// The kinds of tokens, without their contents
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    DocType,
+    StartTag,
+    EndTag,
+    Comment,
+    Text,
+    Eof,
+}
+
+/// A token together with its location in the input stream
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    /// Span of the whole token
+    pub span: Span,
+    /// Spans of the attributes of a start tag, by attribute name. Empty for other tokens.
+    pub attribute_spans: HashMap<String, Span>,
+}
+
+impl SpannedToken {
+    /// Creates a spanned token without attribute spans
+    pub fn new(token: Token, span: Span) -> Self {
+        Self {
+            token,
+            span,
+            attribute_spans: HashMap::new(),
+        }
+    }
+}
+
 impl Token {
     /// Returns true when there is a mixture of white and non-white and \0 characters in the token
     pub(crate) fn is_mixed(&self) -> bool {
@@ -67,6 +100,26 @@ impl Token {
 }
 
 impl Token {
+    /// Returns the kind of the token
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::DocType { .. } => TokenKind::DocType,
+            Token::StartTag { .. } => TokenKind::StartTag,
+            Token::EndTag { .. } => TokenKind::EndTag,
+            Token::Comment(..) => TokenKind::Comment,
+            Token::Text(..) => TokenKind::Text,
+            Token::Eof => TokenKind::Eof,
+        }
+    }
+
+    /// Returns the tag name of a start or end tag token
+    pub fn tag_name(&self) -> Option<&str> {
+        match self {
+            Token::StartTag { name, .. } | Token::EndTag { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
     /// Returns true when any of the characters in the token are null
     pub fn is_null(&self) -> bool {
         if let Token::Text(value) = self {
@@ -1,4 +1,7 @@
 //! Testing harness and utilities for testing the engine
+pub mod document;
+pub mod encoding;
+pub mod serializer;
 pub mod tokenizer;
 pub mod tree_construction;
 
@@ -0,0 +1,20 @@
+//! Documents for unit tests
+use crate::bytes::CharIterator;
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use crate::html5::parser::Html5Parser;
+
+/// Parses the HTML into a new document. Panics when the parser fails or reports a parse error,
+/// so a mistake in the markup of a test does not go unnoticed.
+pub fn parse_document(html: &str) -> DocumentHandle {
+    let mut chars = CharIterator::new();
+    chars.read_from_str(html, None);
+
+    let document = DocumentBuilder::new_document();
+    let errors = Html5Parser::parse_document(&mut chars, Document::clone(&document), None)
+        .expect("parser failed");
+    if let Some(error) = errors.first() {
+        panic!("parse error in {html:?}: {error}");
+    }
+
+    document
+}
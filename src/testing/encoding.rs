@@ -0,0 +1,143 @@
+use super::FIXTURE_ROOT;
+use crate::bytes::{CharIterator, Encoding};
+use crate::html5::parser::document::{Document, DocumentBuilder};
+use crate::html5::parser::Html5Parser;
+use crate::types::{Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const ENCODING_PATH: &str = "encoding";
+
+/// Holds a single encoding test
+#[derive(Debug, PartialEq, Clone)]
+pub struct Test {
+    /// Raw bytes of the document
+    pub data: Vec<u8>,
+    /// Expected encoding label as found in the test file
+    pub encoding: String,
+}
+
+impl Test {
+    /// Returns the expected encoding
+    pub fn expected_encoding(&self) -> Result<Encoding> {
+        Encoding::for_label(&self.encoding).ok_or(Error::Test(format!(
+            "unknown encoding label {}",
+            self.encoding
+        )))
+    }
+
+    /// Returns a human-readable version of the data of this test
+    pub fn description(&self) -> String {
+        String::from_utf8_lossy(&self.data).to_string()
+    }
+
+    /// Returns the encoding of the document after parsing the raw bytes of the test. This includes
+    /// encodings declared by meta elements that are only found by the tree builder.
+    pub fn parsed_encoding(&self) -> Encoding {
+        let mut chars = CharIterator::new();
+        chars.read_from_bytes(&self.data, None);
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let encoding = document.get().encoding;
+        encoding
+    }
+
+    pub fn assert_valid(&self) {
+        assert_eq!(
+            self.parsed_encoding(),
+            self.expected_encoding().expect("expected encoding"),
+            "encoding mismatch for: {}",
+            self.description()
+        );
+    }
+}
+
+/// Holds all tests as found in the given fixture file
+pub struct FixtureFile {
+    pub tests: Vec<Test>,
+}
+
+pub fn fixture_from_filename(filename: &str) -> Result<FixtureFile> {
+    let path = PathBuf::from(FIXTURE_ROOT)
+        .join(ENCODING_PATH)
+        .join(filename);
+    fixture_from_path(&path)
+}
+
+pub fn fixture_from_path<P>(path: &P) -> Result<FixtureFile>
+where
+    P: AsRef<Path>,
+{
+    let contents = fs::read(path)?;
+    Ok(FixtureFile {
+        tests: parse_fixture(&contents)?,
+    })
+}
+
+/// Splits the raw contents of an encoding fixture file into separate tests. The data of a test can
+/// be in any encoding, so we cannot read the fixture as a string.
+fn parse_fixture(contents: &[u8]) -> Result<Vec<Test>> {
+    let mut tests = vec![];
+
+    for chunk in split(contents, b"#data\n").into_iter().skip(1) {
+        let parts = split(chunk, b"\n#encoding\n");
+        if parts.len() != 2 {
+            return Err(Error::Test("missing #encoding section".into()));
+        }
+
+        let encoding = String::from_utf8_lossy(parts[1])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        tests.push(Test {
+            data: parts[0].to_vec(),
+            encoding,
+        });
+    }
+
+    Ok(tests)
+}
+
+/// Splits the haystack on each occurrence of the needle
+fn split<'a>(haystack: &'a [u8], needle: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut pos = 0;
+
+    while pos + needle.len() <= haystack.len() {
+        if &haystack[pos..pos + needle.len()] == needle {
+            parts.push(&haystack[start..pos]);
+            pos += needle.len();
+            start = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    parts.push(&haystack[start..]);
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fixture_file() {
+        let tests = parse_fixture(
+            b"#data\n<meta charset=\"iso8859-2\">\n#encoding\niso-8859-2\n\n#data\n<p>\xff</p>\n#encoding\nwindows-1252\n",
+        )
+        .unwrap();
+
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].data, b"<meta charset=\"iso8859-2\">");
+        assert_eq!(tests[0].encoding, "iso-8859-2");
+        assert_eq!(tests[1].data, b"<p>\xff</p>");
+        assert_eq!(tests[1].expected_encoding().unwrap(), Encoding::Windows1252);
+    }
+}
@@ -0,0 +1,206 @@
+use super::FIXTURE_ROOT;
+use crate::html5::node::HTML_NAMESPACE;
+use crate::html5::serializer::{
+    AttributeQuoting, Html5Serializer, Html5SerializerOptions, SerializerEvent,
+};
+use crate::types::{Error, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SERIALIZER_PATH: &str = "serializer";
+
+/// Holds a single serializer test. The input is a list of tokens in the format of the html5lib
+/// test suite, for instance `["StartTag", namespace, name, attributes]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Test {
+    pub description: String,
+    pub input: Vec<Vec<Value>>,
+    /// Any of these outputs is accepted
+    pub expected: Vec<String>,
+    #[serde(default)]
+    pub options: HashMap<String, Value>,
+}
+
+/// Owned version of a serializer event, as read from the fixture
+enum InputToken {
+    Doctype(String, String, String),
+    StartTag(String, String, Vec<(String, String)>),
+    EndTag(String, String),
+    Characters(String),
+    Comment(String),
+}
+
+impl Test {
+    /// Returns true when the test depends on features the serializer does not have. Injecting
+    /// a meta charset element depends on the encoding of the output, while we serialize to a
+    /// string.
+    pub fn is_supported(&self) -> bool {
+        !self.options.contains_key("inject_meta_charset")
+    }
+
+    /// Returns the serializer options for this test. The tests assume the default options of the
+    /// html5lib serializer, which differ from the defaults of our serializer.
+    pub fn serializer_options(&self) -> Html5SerializerOptions {
+        let flag = |name: &str, default: bool| {
+            self.options
+                .get(name)
+                .and_then(Value::as_bool)
+                .unwrap_or(default)
+        };
+
+        Html5SerializerOptions {
+            quote_attr_values: if flag("quote_attr_values", false) {
+                AttributeQuoting::Always
+            } else {
+                AttributeQuoting::Legacy
+            },
+            quote_char: self
+                .options
+                .get("quote_char")
+                .and_then(Value::as_str)
+                .and_then(|quote| quote.chars().next()),
+            minimize_boolean_attributes: flag("minimize_boolean_attributes", true),
+            use_trailing_solidus: flag("use_trailing_solidus", false),
+            escape_lt_in_attrs: flag("escape_lt_in_attrs", false),
+            escape_rcdata: flag("escape_rcdata", false),
+            omit_optional_tags: flag("omit_optional_tags", true),
+            strip_whitespace: flag("strip_whitespace", false),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the output of the serializer for the input tokens of this test
+    pub fn serialize(&self) -> Result<String> {
+        let tokens = self
+            .input
+            .iter()
+            .map(|token| parse_token(token))
+            .collect::<Result<Vec<_>>>()?;
+
+        let events: Vec<SerializerEvent> = tokens
+            .iter()
+            .map(|token| match token {
+                InputToken::Doctype(name, public_id, system_id) => SerializerEvent::Doctype {
+                    name: name.into(),
+                    public_id: public_id.into(),
+                    system_id: system_id.into(),
+                },
+                InputToken::StartTag(namespace, name, attributes) => SerializerEvent::StartTag {
+                    namespace: namespace.into(),
+                    name: name.into(),
+                    attributes: attributes
+                        .iter()
+                        .map(|(name, value)| (name.into(), value.into()))
+                        .collect(),
+                },
+                InputToken::EndTag(namespace, name) => SerializerEvent::EndTag {
+                    namespace: namespace.into(),
+                    name: name.into(),
+                },
+                InputToken::Characters(text) => SerializerEvent::Text(text.into()),
+                InputToken::Comment(data) => SerializerEvent::Comment(data.into()),
+            })
+            .collect();
+
+        Ok(Html5Serializer::serialize_events(
+            &events,
+            Some(self.serializer_options()),
+        ))
+    }
+
+    pub fn assert_valid(&self) {
+        let output = self.serialize().expect("serialize");
+        assert!(
+            self.expected.contains(&output),
+            "serializer mismatch for '{}': got {:?}, expected one of {:?}",
+            self.description,
+            output,
+            self.expected
+        );
+    }
+}
+
+/// Converts a token from the fixture into an input token
+fn parse_token(token: &[Value]) -> Result<InputToken> {
+    let str_at = |idx: usize| -> Result<String> {
+        token
+            .get(idx)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or(Error::Test(format!("invalid token {token:?}")))
+    };
+    // Doctype identifiers are optional
+    let optional_str_at = |idx: usize| {
+        token
+            .get(idx)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    match str_at(0)?.as_str() {
+        "Doctype" => Ok(InputToken::Doctype(
+            str_at(1)?,
+            optional_str_at(2),
+            optional_str_at(3),
+        )),
+        "StartTag" => Ok(InputToken::StartTag(
+            str_at(1)?,
+            str_at(2)?,
+            parse_attributes(token.get(3))?,
+        )),
+        // Empty tags are void elements in the HTML namespace
+        "EmptyTag" => Ok(InputToken::StartTag(
+            HTML_NAMESPACE.to_string(),
+            str_at(1)?,
+            parse_attributes(token.get(2))?,
+        )),
+        "EndTag" => Ok(InputToken::EndTag(str_at(1)?, str_at(2)?)),
+        "Characters" => Ok(InputToken::Characters(str_at(1)?)),
+        "Comment" => Ok(InputToken::Comment(str_at(1)?)),
+        kind => Err(Error::Test(format!("unknown token type {kind}"))),
+    }
+}
+
+/// Attributes are either a list of `{namespace, name, value}` objects, or an empty object
+fn parse_attributes(value: Option<&Value>) -> Result<Vec<(String, String)>> {
+    let Some(Value::Array(attributes)) = value else {
+        return Ok(Vec::new());
+    };
+
+    attributes
+        .iter()
+        .map(|attribute| {
+            let name = attribute.get("name").and_then(Value::as_str);
+            let value = attribute.get("value").and_then(Value::as_str);
+            match (name, value) {
+                (Some(name), Some(value)) => Ok((name.to_string(), value.to_string())),
+                _ => Err(Error::Test(format!("invalid attribute {attribute}"))),
+            }
+        })
+        .collect()
+}
+
+/// Holds all tests as found in the given fixture file
+#[derive(Debug, Deserialize)]
+pub struct FixtureFile {
+    pub tests: Vec<Test>,
+}
+
+pub fn fixture_from_filename(filename: &str) -> Result<FixtureFile> {
+    let path = PathBuf::from(FIXTURE_ROOT)
+        .join(SERIALIZER_PATH)
+        .join(filename);
+    fixture_from_path(&path)
+}
+
+pub fn fixture_from_path<P>(path: &P) -> Result<FixtureFile>
+where
+    P: AsRef<Path>,
+{
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
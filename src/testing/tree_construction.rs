@@ -134,7 +134,10 @@ impl Harness {
             document = DocumentBuilder::new_document();
         };
 
-        let options = Html5ParserOptions { scripting_enabled };
+        let options = Html5ParserOptions {
+            scripting_enabled,
+            ..Default::default()
+        };
 
         let mut chars = CharIterator::new();
         chars.read_from_str(self.test.spec_data(), None);
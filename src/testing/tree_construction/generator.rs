@@ -1,3 +1,4 @@
+use crate::html5::node::traversal::Traversable;
 use crate::html5::node::{Node, NodeData, NodeTrait, NodeType, HTML_NAMESPACE};
 use crate::html5::node::{MATHML_NAMESPACE, SVG_NAMESPACE, XLINK_NAMESPACE, XMLNS_NAMESPACE};
 use crate::html5::parser::document::DocumentHandle;
@@ -15,12 +16,17 @@ impl TreeOutputGenerator {
 
     /// Generates a tree
     pub fn generate(&self) -> Vec<String> {
-        self.output_treeline(self.document.get().get_root(), 0)
+        let doc = self.document.get();
+        self.output_treeline(&*doc, doc.get_root(), 0)
     }
 
     /// Generates an array of indented tree line and its children. Note that text lines can have newlines in them
-    fn output_treeline(&self, node: &Node, indent_level: usize) -> Vec<String> {
-        let mut indent_level = indent_level;
+    fn output_treeline(
+        &self,
+        tree: &dyn Traversable,
+        node: &Node,
+        indent_level: usize,
+    ) -> Vec<String> {
         let mut output = Vec::new();
 
         // We can skip the document node, as it is always the root node (either a document node, or
@@ -51,18 +57,34 @@ impl TreeOutputGenerator {
                 }
             }
 
-            // Template tags have an extra "content" node in the test tree ouput
+            // Template tags have an extra "content" node in the test tree ouput, which holds the
+            // template contents
             if node.name == "template" && node.is_namespace(HTML_NAMESPACE) {
                 output.push(format!("| {}content", "  ".repeat(indent_level)));
-                indent_level += 1;
+
+                if let NodeData::Element(element) = &node.data {
+                    if let Some(contents) = &element.template_contents {
+                        let contents = contents.contents();
+                        let contents = contents.get();
+                        for child_id in contents.get_root().children.iter() {
+                            let child_node =
+                                contents.get_node_by_id(*child_id).expect("node not found");
+
+                            output.append(&mut self.output_treeline(
+                                &*contents,
+                                child_node,
+                                indent_level + 2,
+                            ));
+                        }
+                    }
+                }
             }
         }
 
         for child_id in node.children.iter() {
-            let doc = self.document.get();
-            let child_node = doc.get_node_by_id(*child_id).expect("node not found");
+            let child_node = tree.node(*child_id).expect("node not found");
 
-            output.append(&mut self.output_treeline(child_node, indent_level + 1));
+            output.append(&mut self.output_treeline(tree, child_node, indent_level + 1));
         }
 
         output
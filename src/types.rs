@@ -1,11 +1,24 @@
 //! Error results that can be returned from the engine
+use crate::bytes::{Position, Span};
+use crate::html5::error_logger::{ParserError, Severity};
+use crate::html5::tokenizer::state::State;
+use crate::html5::tokenizer::token::TokenKind;
 use thiserror::Error;
 
 /// Parser error that defines an error (message) on the given position
 #[derive(Debug, PartialEq, Clone)]
 pub struct ParseError {
-    /// Parse error message
+    /// Error code
+    pub code: ParserError,
+    /// Parse error message. This is the error code for tokenizer errors, and a more detailed
+    /// message for tree construction errors.
     pub message: String,
+    /// Severity of the error
+    pub severity: Severity,
+    /// Tokenizer state or token that triggered the error
+    pub trigger: ErrorTrigger,
+    /// Span in the input stream of the token that triggered the error
+    pub span: Span,
     /// Line number (1-based) of the error
     pub line: usize,
     // Column (1-based) on line of the error
@@ -14,6 +27,110 @@ pub struct ParseError {
     pub offset: usize,
 }
 
+impl ParseError {
+    /// Creates a new parse error at the given position
+    pub fn new(
+        code: ParserError,
+        message: &str,
+        trigger: ErrorTrigger,
+        pos: Position,
+        span: Span,
+    ) -> Self {
+        ParseError {
+            code,
+            message: message.to_string(),
+            severity: code.severity(),
+            trigger,
+            span,
+            line: pos.line,
+            col: pos.col,
+            offset: pos.offset,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}:{}", self.message, self.line, self.col)
+    }
+}
+
+/// What triggered a parse error
+#[derive(Debug, PartialEq, Clone)]
+pub enum ErrorTrigger {
+    /// The tokenizer found an error in the given state
+    State(State),
+    /// The tree builder found an error while processing a token of the given kind. The name is
+    /// the tag name of start and end tags. The token itself is found at the span of the error.
+    Token {
+        kind: TokenKind,
+        name: Option<String>,
+    },
+}
+
+/// A parser limit that has been exceeded, together with the maximum that was set
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    /// The document tree got deeper than the maximum depth
+    TreeDepth(usize),
+    /// The node arena held more nodes than the maximum
+    NodeCount(usize),
+    /// An element had more attributes than the maximum
+    Attributes(usize),
+    /// An attribute name or value was longer than the maximum number of bytes
+    AttributeLength(usize),
+    /// A text node was longer than the maximum number of bytes
+    TextLength(usize),
+    /// The stack of open elements held more elements than the maximum
+    OpenElements(usize),
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::TreeDepth(max) => write!(f, "tree deeper than {max} levels"),
+            Limit::NodeCount(max) => write!(f, "more than {max} nodes"),
+            Limit::Attributes(max) => write!(f, "more than {max} attributes on an element"),
+            Limit::AttributeLength(max) => write!(f, "attribute longer than {max} bytes"),
+            Limit::TextLength(max) => write!(f, "text longer than {max} bytes"),
+            Limit::OpenElements(max) => write!(f, "more than {max} open elements"),
+        }
+    }
+}
+
+/// Errors from the DOM mutation methods. These are named after the [DOMException](https://webidl.spec.whatwg.org/#idl-DOMException-error-names)
+/// names that the DOM standard throws.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum DomError {
+    /// The operation would yield an incorrect node tree
+    #[error("hierarchy request error: {0}")]
+    HierarchyRequest(String),
+
+    /// The node was not found where it was expected
+    #[error("not found error: {0}")]
+    NotFound(String),
+
+    /// The operation is not supported
+    #[error("not supported error: {0}")]
+    NotSupported(String),
+
+    /// The arguments of the operation are invalid
+    #[error("type error: {0}")]
+    Type(String),
+
+    /// The string did not match the expected pattern
+    #[error("syntax error: {0}")]
+    Syntax(String),
+
+    /// The string contains invalid characters
+    #[error("invalid character error: {0}")]
+    InvalidCharacter(String),
+
+    /// The object can not be modified
+    #[error("no modification allowed error: {0}")]
+    NoModificationAllowed(String),
+}
+
 /// Serious errors and errors from third-party libraries
 #[derive(Error, Debug)]
 pub enum Error {
@@ -26,6 +143,18 @@ pub enum Error {
     #[error("parse error: {0}")]
     Parse(String),
 
+    #[error("strict parse error: {0}")]
+    StrictParse(Box<ParseError>),
+
+    #[error("parser limit exceeded: {0}")]
+    LimitExceeded(Limit),
+
+    #[error("css syntax error: {0}")]
+    CssSyntax(String),
+
+    #[error("dom error: {0}")]
+    Dom(#[from] DomError),
+
     #[error("utf8 conversion error: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
 
@@ -0,0 +1,14 @@
+use gosub_engine::testing::encoding::fixture_from_filename;
+use test_case::test_case;
+
+// See tests/data/html5lib-tests/encoding/ for other test files.
+#[test_case("tests1.dat")]
+#[test_case("tests2.dat")]
+#[test_case("test-yahoo-jp.dat")]
+fn encoding(filename: &str) {
+    let fixture_file = fixture_from_filename(filename).expect("fixture");
+
+    for test in fixture_file.tests {
+        test.assert_valid();
+    }
+}
@@ -1,5 +1,7 @@
 extern crate regex;
 extern crate serde_derive;
 
+mod encoding;
+mod serializer;
 mod tokenizer;
 mod tree_construction;